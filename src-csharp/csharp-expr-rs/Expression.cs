@@ -3,6 +3,7 @@ using System.Collections.Generic;
 using System.Collections.ObjectModel;
 using System.Linq;
 using System.Runtime.ExceptionServices;
+using System.Runtime.InteropServices;
 using System.Text;
 
 namespace csharp_expr_rs
@@ -55,22 +56,30 @@ namespace csharp_expr_rs
 
     /// <summary>
     /// If non sealed, implement the proper disposable pattern !
+    /// An instance can be executed from many threads at once.
     /// </summary>
     public sealed class Expression : IDisposable
     {
         private readonly FFIExpressionHandle _expressionHandle;
-        private readonly HashSet<string> _identifiers;
+        private readonly string _expression;
 
         public Expression(string expression)
-            : this(PrepareExpression(expression))
-        { }
+            : this(expression, ExpressionOptions.None)
+        {
+        }
+
+        public Expression(string expression, ExpressionOptions options)
+            : this(PrepareExpression(expression, options))
+        {
+            _expression = expression;
+        }
 
         [HandleProcessCorruptedStateExceptions]
-        static (FFIExpressionHandle _expressionHandle, HashSet<string> _identifiers, bool isDeterministic) PrepareExpression(string expression)
+        static (FFIExpressionHandle _expressionHandle, string[] _identifiers, bool isDeterministic) PrepareExpression(string expression, ExpressionOptions options)
         {
             try
             {
-                var FFIResultPointer = Native.ffi_parse_and_prepare_expr(expression);
+                var FFIResultPointer = Native.ffi_parse_and_prepare_expr_with_options(expression, options);
                 if (FFIResultPointer.is_error)
                 {
                     var errorMsg = FFIResultPointer.GetError().AsStringAndDispose();
@@ -78,11 +87,9 @@ namespace csharp_expr_rs
                 }
 
                 var expressionHandle = FFIResultPointer.GetContent();
-                var identifiers = new HashSet<string>(
-                    Native.ffi_get_identifiers(expressionHandle)
-                        .AsStringAndDispose()
-                        .Split(new[] { '|' }, StringSplitOptions.RemoveEmptyEntries)
-                    );
+                var identifiers = Native.ffi_get_identifiers(expressionHandle)
+                    .AsStringAndDispose()
+                    .Split(new[] { '|' }, StringSplitOptions.RemoveEmptyEntries);
                 var isDeterministic = Native.ffi_is_deterministic(expressionHandle);
 
                 return (expressionHandle, identifiers, isDeterministic);
@@ -93,25 +100,68 @@ namespace csharp_expr_rs
             }
         }
 
-        internal Expression((FFIExpressionHandle expressionHandle, HashSet<string> identifiers, bool isDeterministic) preparedExpression)
+        internal Expression((FFIExpressionHandle expressionHandle, string[] identifiers, bool isDeterministic) preparedExpression)
         {
             _expressionHandle = preparedExpression.expressionHandle;
-            _identifiers = preparedExpression.identifiers;
-            Identifiers = _identifiers.ToArray();
+            Identifiers = preparedExpression.identifiers;
             IsDeterministic = preparedExpression.isDeterministic;
         }
 
+        /// <summary>
+        /// In the order they first appear in the expression, which is the one of the values of ExecuteBySlots
+        /// </summary>
         public string[] Identifiers { get; }
         public bool IsDeterministic { get; }
 
         readonly FFIIdentifierKeyValue[] _emptyValues = new FFIIdentifierKeyValue[0];
 
-        public (bool is_error, string content) Execute(IReadOnlyDictionary<string, string> identifierValues)
+        /// <summary>
+        /// The content is the error message, or the typed result : null, string, bool, long, decimal, DateTime, TimeSpan,
+        /// an object[] or a Dictionary&lt;string, object&gt; of them
+        /// </summary>
+        public (bool is_error, object content) Execute(IReadOnlyDictionary<string, string> identifierValues)
             => Execute((IEnumerable<KeyValuePair<string, string>>)identifierValues);
 
+        public (bool is_error, object content) Execute(IEnumerable<KeyValuePair<string, string>> identifierValues)
+        {
+            var (is_error, content, _, _) = ExecuteWithErrorSpan(identifierValues);
+            return (is_error, content);
+        }
+
+        /// <summary>
+        /// The identifier values are typed : null, string, bool, int, long, decimal, double, DateTime, TimeSpan or an enumerable of them, other types are passed as strings
+        /// </summary>
+        public (bool is_error, object content) Execute(IReadOnlyDictionary<string, object> identifierValues)
+            => Execute((IEnumerable<KeyValuePair<string, object>>)identifierValues);
+
+        public (bool is_error, object content) Execute(IEnumerable<KeyValuePair<string, object>> identifierValues)
+        {
+            var (is_error, content, _, _) = ExecuteWithErrorSpan(identifierValues);
+            return (is_error, content);
+        }
+
+        /// <summary>
+        /// On error, also gives the part of the expression that failed, as chars, the length is 0 when unknown
+        /// </summary>
+        public (bool is_error, object content, int error_start, int error_length) ExecuteWithErrorSpan(IEnumerable<KeyValuePair<string, string>> identifierValues)
+            => ExecuteWithErrorSpan(identifierValues?.Select(kv => new KeyValuePair<string, object>(kv.Key, kv.Value ?? string.Empty)));
+
+        public (bool is_error, object content, int error_start, int error_length) ExecuteWithErrorSpan(IEnumerable<KeyValuePair<string, object>> identifierValues)
+            => ExecuteWithErrorSpan(identifierValues, MissingIdentifierPolicy.Error);
+
+        /// <summary>
+        /// Same as Execute, an identifier without value gives what the policy says, defaultValue is typed as the identifier values
+        /// </summary>
+        public (bool is_error, object content) Execute(IEnumerable<KeyValuePair<string, object>> identifierValues, MissingIdentifierPolicy missingPolicy, object defaultValue = null)
+        {
+            var (is_error, content, _, _) = ExecuteWithErrorSpan(identifierValues, missingPolicy, defaultValue);
+            return (is_error, content);
+        }
+
         [HandleProcessCorruptedStateExceptions]
-        public (bool is_error, string content) Execute(IEnumerable<KeyValuePair<string, string>> identifierValues)
+        public (bool is_error, object content, int error_start, int error_length) ExecuteWithErrorSpan(IEnumerable<KeyValuePair<string, object>> identifierValues, MissingIdentifierPolicy missingPolicy, object defaultValue = null)
         {
+            var allocations = new List<IntPtr>();
             try
             {
                 var idValues = _emptyValues;
@@ -119,13 +169,154 @@ namespace csharp_expr_rs
                 if (identifierValues != null)
                 {
                     idValues = identifierValues
-                        .Select(kv => new FFIIdentifierKeyValue { key = kv.Key, value = kv.Value ?? string.Empty })
+                        .Select(kv => new FFIIdentifierKeyValue { key = kv.Key, value = FFIValue.Alloc(kv.Value, allocations) })
                         .ToArray();
                 }
 
-                var result = Native.ffi_exec_expr(_expressionHandle, idValues, (UIntPtr)idValues.Length);
-                var stringResult = result.GetContent().AsStringAndDispose();
-                return (result.is_error, stringResult);
+                var missing = new FFIMissingIdentifier { policy = missingPolicy, default_value = FFIValue.Alloc(defaultValue, allocations) };
+
+                var result = Native.ffi_exec_expr_value_with_missing(_expressionHandle, idValues, (UIntPtr)idValues.Length, missing);
+                var content = result.TakeContent();
+                var (errorStart, errorLength) = ToCharsSpan((int)result.error_start, (int)result.error_end);
+                return (result.is_error, content, errorStart, errorLength);
+            }
+            catch (Exception ex)
+            {
+                throw new ExpressionInvokeException(ex.Message, ex);
+            }
+            finally
+            {
+                allocations.ForEach(NativeUtf8.Free);
+            }
+        }
+
+        /// <summary>
+        /// The identifier values are given without their names, one per identifier in the order of Identifiers,
+        /// typed as for Execute
+        /// </summary>
+        public (bool is_error, object content) ExecuteBySlots(IReadOnlyList<object> slotValues)
+            => ExecuteBySlots(slotValues, MissingIdentifierPolicy.Error);
+
+        /// <summary>
+        /// Same as ExecuteBySlots, an identifier without value gives what the policy says
+        /// </summary>
+        [HandleProcessCorruptedStateExceptions]
+        public (bool is_error, object content) ExecuteBySlots(IReadOnlyList<object> slotValues, MissingIdentifierPolicy missingPolicy, object defaultValue = null)
+        {
+            var allocations = new List<IntPtr>();
+            try
+            {
+                var values = (slotValues ?? new object[0])
+                    .Select(v => FFIValue.Alloc(v, allocations))
+                    .ToArray();
+
+                var missing = new FFIMissingIdentifier { policy = missingPolicy, default_value = FFIValue.Alloc(defaultValue, allocations) };
+
+                var result = Native.ffi_exec_expr_slots_with_missing(_expressionHandle, values, (UIntPtr)values.Length, missing);
+                return (result.is_error, result.TakeContent());
+            }
+            catch (Exception ex)
+            {
+                throw new ExpressionInvokeException(ex.Message, ex);
+            }
+            finally
+            {
+                allocations.ForEach(NativeUtf8.Free);
+            }
+        }
+
+        /// <summary>
+        /// Executes the expression for each row, the values of an identifier are given as a column of rowsCount typed values.
+        /// An error on a row doesn't stop the others.
+        /// </summary>
+        public (bool is_error, object content)[] ExecuteMany(IReadOnlyDictionary<string, IReadOnlyList<object>> columns, int rowsCount)
+            => ExecuteMany(columns, rowsCount, MissingIdentifierPolicy.Error);
+
+        /// <summary>
+        /// Same as ExecuteMany, an identifier without value gives what the policy says
+        /// </summary>
+        [HandleProcessCorruptedStateExceptions]
+        public (bool is_error, object content)[] ExecuteMany(IReadOnlyDictionary<string, IReadOnlyList<object>> columns, int rowsCount, MissingIdentifierPolicy missingPolicy, object defaultValue = null)
+        {
+            if (rowsCount < 0)
+                throw new ArgumentOutOfRangeException(nameof(rowsCount));
+            var valueSize = Marshal.SizeOf<FFIValue>();
+            var allocations = new List<IntPtr>();
+            var buffers = new List<IntPtr>();
+            try
+            {
+                var ffiColumns = (columns ?? new Dictionary<string, IReadOnlyList<object>>())
+                    .Select(kv =>
+                    {
+                        if (kv.Value.Count < rowsCount)
+                            throw new ArgumentException($"The column '{kv.Key}' has {kv.Value.Count} values for {rowsCount} rows", nameof(columns));
+                        var buffer = Marshal.AllocHGlobal(valueSize * Math.Max(rowsCount, 1));
+                        buffers.Add(buffer);
+                        for (var row = 0; row < rowsCount; row++)
+                        {
+                            Marshal.StructureToPtr(FFIValue.Alloc(kv.Value[row], allocations), buffer + row * valueSize, false);
+                        }
+                        return new FFIIdentifierColumn { key = kv.Key, values = buffer, values_len = (UIntPtr)rowsCount };
+                    })
+                    .ToArray();
+
+                var missing = new FFIMissingIdentifier { policy = missingPolicy, default_value = FFIValue.Alloc(defaultValue, allocations) };
+
+                var results = new FFIExecValueResult[rowsCount];
+                Native.ffi_exec_expr_batch_with_missing(_expressionHandle, ffiColumns, (UIntPtr)ffiColumns.Length, (UIntPtr)rowsCount, results, missing);
+                return results.Select(r => (r.is_error, r.TakeContent())).ToArray();
+            }
+            catch (ArgumentException)
+            {
+                throw;
+            }
+            catch (Exception ex)
+            {
+                throw new ExpressionInvokeException(ex.Message, ex);
+            }
+            finally
+            {
+                allocations.ForEach(NativeUtf8.Free);
+                buffers.ForEach(Marshal.FreeHGlobal);
+            }
+        }
+
+        /// <summary>
+        /// The type of the result and the definite type errors, without executing the expression.
+        /// The identifiers without declared type can be anything.
+        /// </summary>
+        [HandleProcessCorruptedStateExceptions]
+        public (ExpressionType resultType, ExpressionDiagnostic[] diagnostics) InferType(IReadOnlyDictionary<string, ExpressionType> identifierTypes)
+        {
+            try
+            {
+                var types = (identifierTypes ?? new Dictionary<string, ExpressionType>())
+                    .Select(kv => new FFIIdentifierType { key = kv.Key, identifier_type = kv.Value })
+                    .ToArray();
+                var result = Native.ffi_infer_type(_expressionHandle, types, (UIntPtr)types.Length);
+                try
+                {
+                    if (result.is_error)
+                        throw new ExpressionInvokeException(FFIStringHandle.ReadString(result.error));
+                    var diagnosticSize = Marshal.SizeOf<FFIDiagnostic>();
+                    var diagnostics = Enumerable.Range(0, (int)result.diagnostics_len)
+                        .Select(i => Marshal.PtrToStructure<FFIDiagnostic>(result.diagnostics + i * diagnosticSize))
+                        .Select(d =>
+                        {
+                            var (start, length) = ToCharsSpan((int)d.start, (int)d.end);
+                            return new ExpressionDiagnostic(d.kind, FFIStringHandle.ReadString(d.message), start, length);
+                        })
+                        .ToArray();
+                    return (result.result_type, diagnostics);
+                }
+                finally
+                {
+                    Native.ffi_free_type_result(result);
+                }
+            }
+            catch (ExpressionInvokeException)
+            {
+                throw;
             }
             catch (Exception ex)
             {
@@ -133,6 +324,17 @@ namespace csharp_expr_rs
             }
         }
 
+        // the native side counts UTF-8 bytes
+        (int start, int length) ToCharsSpan(int startByte, int endByte)
+        {
+            if (_expression == null || endByte <= startByte)
+                return (0, 0);
+            var bytes = Encoding.UTF8.GetBytes(_expression);
+            var start = Encoding.UTF8.GetCharCount(bytes, 0, startByte);
+            var length = Encoding.UTF8.GetCharCount(bytes, startByte, endByte - startByte);
+            return (start, length);
+        }
+
         public void Dispose()
         {
             _expressionHandle.Dispose();
@@ -0,0 +1,36 @@
+﻿using System;
+
+namespace csharp_expr_rs
+{
+    public enum DiagnosticKind
+    {
+        UnknownFunction = 0,
+        WrongArity = 1,
+        TypeMismatch = 2,
+        /// <summary>
+        /// A part of the expression without identifiers that fails each time it's executed
+        /// </summary>
+        AlwaysFails = 3,
+    }
+
+    /// <summary>
+    /// A mistake found in the expression, Start and Length are in chars, the Length is 0 when the location is unknown
+    /// </summary>
+    public sealed class ExpressionDiagnostic
+    {
+        public ExpressionDiagnostic(DiagnosticKind kind, string message, int start, int length)
+        {
+            Kind = kind;
+            Message = message;
+            Start = start;
+            Length = length;
+        }
+
+        public DiagnosticKind Kind { get; }
+        public string Message { get; }
+        public int Start { get; }
+        public int Length { get; }
+
+        public override string ToString() => Message;
+    }
+}
@@ -0,0 +1,25 @@
+﻿using System;
+
+namespace csharp_expr_rs
+{
+    /// <summary>
+    /// How an expression is prepared, the values can be combined
+    /// </summary>
+    [Flags]
+    public enum ExpressionOptions : uint
+    {
+        None = 0,
+        /// <summary>
+        /// The strict mode : identifiers only match the exact same names, `Amount` and `amount` are two identifiers
+        /// </summary>
+        CaseSensitiveIdentifiers = 1,
+        /// <summary>
+        /// The expressions calling unknown functions or with a wrong parameters count are rejected, instead of failing when executed
+        /// </summary>
+        Strict = 2,
+        /// <summary>
+        /// The expression is compiled to a bytecode once prepared, faster to execute many times
+        /// </summary>
+        Compiled = 4,
+    }
+}
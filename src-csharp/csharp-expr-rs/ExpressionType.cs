@@ -0,0 +1,22 @@
+﻿using System;
+
+namespace csharp_expr_rs
+{
+    /// <summary>
+    /// The types known before the execution, a Number is a long or a decimal, an Array an object[], a Record a dictionary of objects by name.
+    /// A Lambda, like `x => x > 10`, is never a value, only a parameter of a function.
+    /// </summary>
+    public enum ExpressionType
+    {
+        Any = 0,
+        Null = 1,
+        Text = 2,
+        Boolean = 3,
+        Number = 4,
+        Date = 5,
+        TimeSpan = 6,
+        Array = 7,
+        Lambda = 8,
+        Record = 9,
+    }
+}
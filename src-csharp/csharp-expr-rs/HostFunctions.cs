@@ -0,0 +1,88 @@
+﻿using System;
+using System.Collections.Concurrent;
+using System.Collections.Generic;
+using System.Runtime.InteropServices;
+
+namespace csharp_expr_rs
+{
+    /// <summary>
+    /// .net functions callable from the expressions prepared after their registration.
+    /// Those expressions call the function registered under the name when they're executed :
+    /// once it's unregistered, executing them is an error.
+    /// The values are null, string, bool, long, decimal, DateTime, TimeSpan, an object[] or a Dictionary&lt;string, object&gt; of them.
+    /// A function can also return any other dictionary of objects by name as a record, and any other enumerable as an array.
+    /// </summary>
+    public static class HostFunctions
+    {
+        // the native side only keeps the function pointers, the delegates must stay alive while they can be called.
+        // A replaced or unregistered one may still be running on another thread, as the native side calls a copy
+        // of the pointer taken before, so they're all kept, this never shrinks.
+        static readonly ConcurrentQueue<FFIHostFunction> _callbacks = new ConcurrentQueue<FFIHostFunction>();
+
+        /// <summary>
+        /// Replaces the function already registered under the name, if any.
+        /// Each registration keeps its delegate alive until the process ends, see Unregister.
+        /// </summary>
+        /// <param name="maxParams">Negative for no maximum</param>
+        public static void Register(string name, bool isDeterministic, int minParams, int maxParams, Func<object[], object> function)
+        {
+            FFIHostFunction callback = (userData, args, argsLen, result) =>
+            {
+                try
+                {
+                    var values = new object[(int)argsLen];
+                    var size = Marshal.SizeOf<FFIValue>();
+                    for (var i = 0; i < values.Length; i++)
+                    {
+                        values[i] = Marshal.PtrToStructure<FFIValue>(args + i * size).ToObject();
+                    }
+                    SetResult(result, function(values));
+                }
+                catch (Exception ex)
+                {
+                    Native.ffi_host_result_set_error(result, ex.Message);
+                }
+            };
+            // kept before the native side can call it
+            _callbacks.Enqueue(callback);
+            if (!Native.ffi_register_function(name, isDeterministic, minParams, maxParams, callback, IntPtr.Zero))
+                throw new ArgumentException($"The function '{name}' couldn't be registered", nameof(name));
+        }
+
+        /// <summary>
+        /// The expressions executed afterwards fail to call it. The calls already running on other threads
+        /// may still be inside it, so its delegate is never released : registering and unregistering
+        /// functions again and again keeps all their delegates in memory.
+        /// </summary>
+        public static bool Unregister(string name)
+        {
+            return Native.ffi_unregister_function(name);
+        }
+
+        /// <summary>
+        /// The functions callable now, built-in and registered ones, as a JSON array sorted by name.
+        /// Each one has its name, aliases, category, description, parameters with their type and default, and its return type.
+        /// </summary>
+        public static string ListAsJson()
+        {
+            using (var json = Native.ffi_list_functions())
+            {
+                return json.AsString();
+            }
+        }
+
+        static void SetResult(IntPtr result, object value)
+        {
+            // the native side copies the text and the items before returning
+            var allocations = new List<IntPtr>();
+            try
+            {
+                Native.ffi_host_result_set_value(result, FFIValue.Alloc(value, allocations));
+            }
+            finally
+            {
+                allocations.ForEach(NativeUtf8.Free);
+            }
+        }
+    }
+}
@@ -0,0 +1,20 @@
+﻿using System;
+
+namespace csharp_expr_rs
+{
+    /// <summary>
+    /// What an identifier gives when no value was passed for it
+    /// </summary>
+    public enum MissingIdentifierPolicy
+    {
+        /// <summary>
+        /// The execution fails, as by default
+        /// </summary>
+        Error = 0,
+        Null = 1,
+        /// <summary>
+        /// The default value given with the policy
+        /// </summary>
+        Default = 2,
+    }
+}
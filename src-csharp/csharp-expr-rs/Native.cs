@@ -1,4 +1,7 @@
 ﻿using System;
+using System.Collections;
+using System.Collections.Generic;
+using System.Linq;
 using System.Runtime.CompilerServices;
 using System.Runtime.InteropServices;
 using System.Text;
@@ -17,6 +20,8 @@ namespace csharp_expr_rs
         public static extern FFIParseResult ffi_parse_and_prepare_expr([MarshalAs(UnmanagedType.LPUTF8Str)] string expression);
         [DllImport(LIB_NAME)]
         public static extern void ffi_free_expr(IntPtr ptr);
+        [DllImport(LIB_NAME)]
+        public static extern FFIParseResult ffi_parse_and_prepare_expr_with_options([MarshalAs(UnmanagedType.LPUTF8Str)] string expression, ExpressionOptions options);
 
         [DllImport(LIB_NAME)]
         public static extern FFIStringHandle ffi_get_identifiers(FFIExpressionHandle ptr);
@@ -26,8 +31,164 @@ namespace csharp_expr_rs
 
         [DllImport(LIB_NAME, CharSet = CharSet.Ansi)]
         public static extern FFIExecResult ffi_exec_expr(FFIExpressionHandle ptr, FFIIdentifierKeyValue[] identifier_values, UIntPtr identifier_values_len);
+        [DllImport(LIB_NAME, CharSet = CharSet.Ansi)]
+        public static extern FFIExecValueResult ffi_exec_expr_value(FFIExpressionHandle ptr, FFIIdentifierKeyValue[] identifier_values, UIntPtr identifier_values_len);
+        [DllImport(LIB_NAME, CharSet = CharSet.Ansi)]
+        public static extern FFIExecValueResult ffi_exec_expr_value_with_missing(FFIExpressionHandle ptr, FFIIdentifierKeyValue[] identifier_values, UIntPtr identifier_values_len, FFIMissingIdentifier missing);
+        [DllImport(LIB_NAME)]
+        public static extern FFIExecValueResult ffi_exec_expr_slots(FFIExpressionHandle ptr, FFIValue[] slot_values, UIntPtr slot_values_len);
+        [DllImport(LIB_NAME)]
+        public static extern FFIExecValueResult ffi_exec_expr_slots_with_missing(FFIExpressionHandle ptr, FFIValue[] slot_values, UIntPtr slot_values_len, FFIMissingIdentifier missing);
+        [DllImport(LIB_NAME)]
+        public static extern UIntPtr ffi_exec_expr_batch(FFIExpressionHandle ptr, FFIIdentifierColumn[] columns, UIntPtr columns_len, UIntPtr rows_len, [In, Out] FFIExecValueResult[] results);
+        [DllImport(LIB_NAME)]
+        public static extern UIntPtr ffi_exec_expr_batch_with_missing(FFIExpressionHandle ptr, FFIIdentifierColumn[] columns, UIntPtr columns_len, UIntPtr rows_len, [In, Out] FFIExecValueResult[] results, FFIMissingIdentifier missing);
         [DllImport(LIB_NAME)]
         public static extern void ffi_free_cstring(IntPtr ptr);
+        [DllImport(LIB_NAME)]
+        public static extern void ffi_free_value(FFIValue value);
+
+        [DllImport(LIB_NAME)]
+        public static extern FFITypeResult ffi_infer_type(FFIExpressionHandle ptr, FFIIdentifierType[] identifier_types, UIntPtr identifier_types_len);
+        [DllImport(LIB_NAME)]
+        public static extern void ffi_free_type_result(FFITypeResult result);
+        [DllImport(LIB_NAME)]
+        public static extern FFIStringHandle ffi_list_functions();
+
+        [DllImport(LIB_NAME)]
+        [return: MarshalAs(UnmanagedType.I1)]
+        public static extern bool ffi_register_function([MarshalAs(UnmanagedType.LPUTF8Str)] string name, [MarshalAs(UnmanagedType.I1)] bool deterministic, int min_params, int max_params, FFIHostFunction callback, IntPtr user_data);
+        [DllImport(LIB_NAME)]
+        [return: MarshalAs(UnmanagedType.I1)]
+        public static extern bool ffi_unregister_function([MarshalAs(UnmanagedType.LPUTF8Str)] string name);
+        [DllImport(LIB_NAME)]
+        public static extern void ffi_host_result_set_value(IntPtr result, FFIValue value);
+        [DllImport(LIB_NAME)]
+        public static extern void ffi_host_result_set_error(IntPtr result, [MarshalAs(UnmanagedType.LPUTF8Str)] string message);
+    }
+
+    [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+    internal delegate void FFIHostFunction(IntPtr user_data, IntPtr args, UIntPtr args_len, IntPtr result);
+
+    internal enum FFIValueKind : int
+    {
+        Null = 0,
+        Str = 1,
+        Boolean = 2,
+        Int = 3,
+        Num = 4,
+        Date = 5,
+        TimeSpan = 6,
+        Array = 7,
+        Record = 8,
+    }
+
+    /// <summary>
+    /// Only the fields of its kind are meaningful : boolean for Boolean, int for Int and for the ticks of Date and TimeSpan,
+    /// text for Str, the decimal parts for Num, as given by decimal.GetBits, and items for Array and Record.
+    /// The items of a Record are its fields, each one as its name, a Str, followed by its value.
+    /// </summary>
+    [StructLayout(LayoutKind.Sequential)]
+    internal struct FFIValue
+    {
+        public FFIValueKind kind;
+        [MarshalAs(UnmanagedType.I1)]
+        public bool boolean;
+        public long @int;
+        public int decimal_lo;
+        public int decimal_mid;
+        public int decimal_hi;
+        public int decimal_flags;
+        public IntPtr text;
+        public IntPtr items;
+        public UIntPtr items_len;
+
+        /// <summary>
+        /// The text and the items, if any, are not freed. An array is an object[], a record a Dictionary&lt;string, object&gt;.
+        /// </summary>
+        public object ToObject()
+        {
+            switch (kind)
+            {
+                case FFIValueKind.Str: return FFIStringHandle.ReadString(text);
+                case FFIValueKind.Boolean: return boolean;
+                case FFIValueKind.Int: return @int;
+                case FFIValueKind.Num: return new decimal(new[] { decimal_lo, decimal_mid, decimal_hi, decimal_flags });
+                case FFIValueKind.Date: return new DateTime(@int);
+                case FFIValueKind.TimeSpan: return new TimeSpan(@int);
+                case FFIValueKind.Array:
+                    {
+                        var size = Marshal.SizeOf<FFIValue>();
+                        var values = new object[(int)items_len];
+                        for (var i = 0; i < values.Length; i++)
+                        {
+                            values[i] = Marshal.PtrToStructure<FFIValue>(items + i * size).ToObject();
+                        }
+                        return values;
+                    }
+                case FFIValueKind.Record:
+                    {
+                        var size = Marshal.SizeOf<FFIValue>();
+                        var fields = new Dictionary<string, object>();
+                        for (var i = 0; i + 1 < (int)items_len; i += 2)
+                        {
+                            var name = (string)Marshal.PtrToStructure<FFIValue>(items + i * size).ToObject();
+                            fields[name] = Marshal.PtrToStructure<FFIValue>(items + (i + 1) * size).ToObject();
+                        }
+                        return fields;
+                    }
+                default: return null;
+            }
+        }
+
+        /// <summary>
+        /// The texts and the items are allocated for the native side to read during a call,
+        /// they are added to allocations, to free with NativeUtf8.Free afterwards.
+        /// A string is a text, a dictionary of objects by name a record, as an ExpandoObject, and any other enumerable an array.
+        /// </summary>
+        public static FFIValue Alloc(object value, List<IntPtr> allocations)
+        {
+            switch (value)
+            {
+                case null: return new FFIValue { kind = FFIValueKind.Null };
+                case bool b: return new FFIValue { kind = FFIValueKind.Boolean, boolean = b };
+                case int i: return new FFIValue { kind = FFIValueKind.Int, @int = i };
+                case long l: return new FFIValue { kind = FFIValueKind.Int, @int = l };
+                case decimal d: return FromDecimal(d);
+                case double d: return FromDecimal((decimal)d);
+                case DateTime dt: return new FFIValue { kind = FFIValueKind.Date, @int = dt.Ticks };
+                case TimeSpan ts: return new FFIValue { kind = FFIValueKind.TimeSpan, @int = ts.Ticks };
+                case string s: return AllocText(s, allocations);
+                case IDictionary<string, object> fields: return AllocItems(FFIValueKind.Record, fields.SelectMany(f => new[] { f.Key, f.Value }).ToArray(), allocations);
+                case IEnumerable items: return AllocItems(FFIValueKind.Array, items.Cast<object>().ToArray(), allocations);
+                default: return AllocText(Convert.ToString(value, System.Globalization.CultureInfo.InvariantCulture), allocations);
+            }
+        }
+
+        static FFIValue AllocText(string text, List<IntPtr> allocations)
+        {
+            var ptr = NativeUtf8.Alloc(text);
+            allocations.Add(ptr);
+            return new FFIValue { kind = FFIValueKind.Str, text = ptr };
+        }
+
+        static FFIValue AllocItems(FFIValueKind kind, object[] items, List<IntPtr> allocations)
+        {
+            var size = Marshal.SizeOf<FFIValue>();
+            var buffer = Marshal.AllocHGlobal(size * Math.Max(items.Length, 1));
+            allocations.Add(buffer);
+            for (var i = 0; i < items.Length; i++)
+            {
+                Marshal.StructureToPtr(Alloc(items[i], allocations), buffer + i * size, false);
+            }
+            return new FFIValue { kind = kind, items = buffer, items_len = (UIntPtr)items.Length };
+        }
+
+        static FFIValue FromDecimal(decimal d)
+        {
+            var bits = decimal.GetBits(d);
+            return new FFIValue { kind = FFIValueKind.Num, decimal_lo = bits[0], decimal_mid = bits[1], decimal_hi = bits[2], decimal_flags = bits[3] };
+        }
     }
 
     [StructLayout(LayoutKind.Sequential)]
@@ -35,8 +196,61 @@ namespace csharp_expr_rs
     {
         [MarshalAs(UnmanagedType.LPUTF8Str)]
         public string key;
+        public FFIValue value;
+    }
+
+    /// <summary>
+    /// default_value is only read by the Default policy
+    /// </summary>
+    [StructLayout(LayoutKind.Sequential)]
+    internal struct FFIMissingIdentifier
+    {
+        public MissingIdentifierPolicy policy;
+        public FFIValue default_value;
+    }
+
+    /// <summary>
+    /// The values of one identifier for all the rows of a batch, values points to an unmanaged array of values_len FFIValue
+    /// </summary>
+    [StructLayout(LayoutKind.Sequential)]
+    internal struct FFIIdentifierColumn
+    {
+        [MarshalAs(UnmanagedType.LPUTF8Str)]
+        public string key;
+        public IntPtr values;
+        public UIntPtr values_len;
+    }
+
+    [StructLayout(LayoutKind.Sequential)]
+    internal struct FFIIdentifierType
+    {
         [MarshalAs(UnmanagedType.LPUTF8Str)]
-        public string value;
+        public string key;
+        public ExpressionType identifier_type;
+    }
+
+    [StructLayout(LayoutKind.Sequential)]
+    internal struct FFIDiagnostic
+    {
+        public DiagnosticKind kind;
+        public IntPtr message;
+        // UTF-8 bytes offsets, both are 0 when unknown
+        public UIntPtr start;
+        public UIntPtr end;
+    }
+
+    /// <summary>
+    /// Freed with ffi_free_type_result, with the strings of its diagnostics
+    /// </summary>
+    [StructLayout(LayoutKind.Sequential)]
+    internal struct FFITypeResult
+    {
+        [MarshalAs(UnmanagedType.I1)]
+        public bool is_error;
+        public IntPtr error;
+        public ExpressionType result_type;
+        public IntPtr diagnostics;
+        public UIntPtr diagnostics_len;
     }
 
     [StructLayout(LayoutKind.Sequential)]
@@ -59,10 +273,64 @@ namespace csharp_expr_rs
         [MarshalAs(UnmanagedType.I1)]
         public bool is_error;
         public IntPtr content;
+        // UTF-8 bytes offsets of the failing part of the expression, both are 0 when unknown
+        public UIntPtr error_start;
+        public UIntPtr error_end;
 
         public FFIStringHandle GetContent() => new FFIStringHandle(content);
     }
 
+    [StructLayout(LayoutKind.Sequential)]
+    internal unsafe struct FFIExecValueResult
+    {
+        [MarshalAs(UnmanagedType.I1)]
+        public bool is_error;
+        public IntPtr error;
+        public FFIValue value;
+        // UTF-8 bytes offsets of the failing part of the expression, both are 0 when unknown
+        public UIntPtr error_start;
+        public UIntPtr error_end;
+
+        /// <summary>
+        /// The error message or the value, their native strings and items are freed
+        /// </summary>
+        public object TakeContent()
+        {
+            if (is_error)
+                return new FFIStringHandle(error).AsStringAndDispose();
+            if (value.kind == FFIValueKind.Str)
+                return new FFIStringHandle(value.text).AsStringAndDispose();
+            if (value.kind == FFIValueKind.Array || value.kind == FFIValueKind.Record)
+            {
+                var items = value.ToObject();
+                Native.ffi_free_value(value);
+                return items;
+            }
+            return value.ToObject();
+        }
+    }
+
+    /// <summary>
+    /// UTF-8 strings allocated on the .net side, for the native side to read during a call
+    /// </summary>
+    internal static class NativeUtf8
+    {
+        public static IntPtr Alloc(string text)
+        {
+            var bytes = Encoding.UTF8.GetBytes(text);
+            var ptr = Marshal.AllocHGlobal(bytes.Length + 1);
+            Marshal.Copy(bytes, 0, ptr, bytes.Length);
+            Marshal.WriteByte(ptr, bytes.Length, 0);
+            return ptr;
+        }
+
+        public static void Free(IntPtr ptr)
+        {
+            if (ptr != IntPtr.Zero)
+                Marshal.FreeHGlobal(ptr);
+        }
+    }
+
     internal class FFIStringHandle : SafeHandle
     {
         public FFIStringHandle() : base(IntPtr.Zero, true) { }
@@ -70,13 +338,23 @@ namespace csharp_expr_rs
 
         public override bool IsInvalid => false;
 
-        public string AsString()
+        /// <summary>
+        /// The native side gives no string when it failed unexpectedly
+        /// </summary>
+        public string AsString() => handle == IntPtr.Zero
+            ? throw new InvalidOperationException("The native library failed to give a string")
+            : ReadString(handle);
+
+        /// <summary>
+        /// Reads a native UTF-8 string, without taking its ownership
+        /// </summary>
+        public static string ReadString(IntPtr ptr)
         {
             int len = 0;
-            while (Marshal.ReadByte(handle, len) != 0)
+            while (Marshal.ReadByte(ptr, len) != 0)
             { ++len; }
             byte[] buffer = new byte[len];
-            Marshal.Copy(handle, buffer, 0, buffer.Length);
+            Marshal.Copy(ptr, buffer, 0, buffer.Length);
             return Encoding.UTF8.GetString(buffer);
         }
 
@@ -0,0 +1,456 @@
+use crate::expressions::*;
+use crate::functions::{binary_operator_on_values, convert_operand, f_operators, f_unary_operators, index_value, member_value, operand_conversion, result_to_bool, unary_operator_on_value, OperandConversion};
+use std::fmt;
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::Arc;
+
+// A prepared expression compiled to a stack based bytecode, for the expressions executed many times.
+// The functions, identifiers and constants are resolved once by `compile_expr` to slots of the program,
+// and `exec_compiled_expr` gives the same results and errors as `exec_expr`.
+// `ExprAndIdentifiers::compile` keeps it with the prepared expression, as `PREPARE_COMPILED` does for the ffi.
+// The parameters of a function stay expressions, as the function decides when to execute them (`Iif`, `And`...),
+// and so are the operators not implemented by `f_operators` / `f_unary_operators`.
+#[derive(Default)]
+pub struct CompiledExpr {
+    code: Vec<Instruction>,
+    constants: VecSharedExpr,
+    // by slot, as prepared
+    identifiers: Vec<String>,
+    functions: Vec<Arc<FunctionImpl>>,
+    parameters: Vec<VecSharedExpr>,
+    expressions: VecSharedExpr,
+    // the names of the fields read by `Member`
+    members: Vec<String>,
+    // the instructions of each parsed expression, the innermost first
+    spans: Vec<(Range<usize>, Span)>,
+    max_stack: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Instruction {
+    Constant(usize),            // pushes the constant
+    Identifier(usize),          // pushes the value of the identifier at the slot
+    Call(usize, usize),         // pushes the result of the function, called with the parameters
+    Expression(usize),          // pushes the result of the expression, for what has no instruction
+    Convert(OperandConversion), // changes the value on top, like the built-in operators do for their operands
+    Binary(AssocOp),            // pops the right then the left operands, pushes the result
+    Unary(UnaryOp),             // changes the value on top
+    Jump(usize),                // to the instruction
+    JumpIfFalse(usize),         // pops the condition
+    JumpIfBoolean(bool, usize), // keeps the boolean on top when it is the expected one, pops it otherwise
+    JumpIfNotNull(usize),       // keeps the value on top when it is not null, pops it otherwise
+    Array(usize),               // pops the items, pushes the array of them
+    Index,                      // pops the index then the array, pushes the item
+    Member(usize),              // changes the record on top to its field
+}
+
+impl fmt::Debug for CompiledExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompiledExpr").field("code", &self.code).field("identifiers", &self.identifiers).finish()
+    }
+}
+
+pub fn compile_expr(expr: &SharedExpr) -> CompiledExpr {
+    let mut compiler = Compiler::default();
+    compiler.compile(expr);
+    compiler.compiled
+}
+
+#[derive(Default)]
+struct Compiler {
+    compiled: CompiledExpr,
+    depth: usize,
+}
+
+impl Compiler {
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        match instruction {
+            Instruction::Constant(_) | Instruction::Identifier(_) | Instruction::Call(_, _) | Instruction::Expression(_) => self.depth += 1,
+            Instruction::Binary(_) | Instruction::JumpIfFalse(_) | Instruction::Index => self.depth -= 1,
+            Instruction::Array(count) => self.depth = self.depth + 1 - count,
+            _ => {}
+        }
+        self.compiled.max_stack = self.compiled.max_stack.max(self.depth);
+        self.compiled.code.push(instruction);
+        self.compiled.code.len() - 1
+    }
+
+    // the jump emitted at `at` goes to the next instruction
+    fn patch_jump(&mut self, at: usize) {
+        let target = self.compiled.code.len();
+        self.compiled.code[at] = match self.compiled.code[at] {
+            Instruction::Jump(_) => Instruction::Jump(target),
+            Instruction::JumpIfFalse(_) => Instruction::JumpIfFalse(target),
+            Instruction::JumpIfBoolean(expected, _) => Instruction::JumpIfBoolean(expected, target),
+            Instruction::JumpIfNotNull(_) => Instruction::JumpIfNotNull(target),
+            instruction => instruction,
+        };
+    }
+
+    fn expression(&mut self, expr: &SharedExpr) {
+        self.compiled.expressions.push(Arc::clone(expr));
+        self.emit(Instruction::Expression(self.compiled.expressions.len() - 1));
+    }
+
+    fn compile(&mut self, expr: &SharedExpr) {
+        match expr.as_ref() {
+            Expr::Failed(_) => self.expression(expr),
+            e if e.is_constant() => {
+                self.compiled.constants.push(Arc::clone(expr));
+                self.emit(Instruction::Constant(self.compiled.constants.len() - 1));
+            }
+            Expr::PreparedIdentifier(name, slot) => {
+                if self.compiled.identifiers.len() <= *slot {
+                    self.compiled.identifiers.resize(*slot + 1, String::new());
+                }
+                self.compiled.identifiers[*slot] = name.clone();
+                self.emit(Instruction::Identifier(*slot));
+            }
+            Expr::PreparedFunctionCall(_, parameters, fnc) => {
+                let slot = match self.compiled.functions.iter().position(|f| Arc::ptr_eq(f, fnc)) {
+                    Some(slot) => slot,
+                    None => {
+                        self.compiled.functions.push(Arc::clone(fnc));
+                        self.compiled.functions.len() - 1
+                    }
+                };
+                self.compiled.parameters.push(parameters.clone());
+                self.emit(Instruction::Call(slot, self.compiled.parameters.len() - 1));
+            }
+            Expr::PreparedBinaryOperator(left, right, op, op_impl) if op_impl.implementation_type() == type_of(&f_operators) => match op {
+                AssocOp::LAnd | AssocOp::LOr => {
+                    self.compile(left);
+                    self.emit(Instruction::Convert(OperandConversion::Bool));
+                    let jump = self.emit(Instruction::JumpIfBoolean(*op == AssocOp::LOr, 0));
+                    self.depth -= 1;
+                    self.compile(right);
+                    self.emit(Instruction::Convert(OperandConversion::Bool));
+                    self.patch_jump(jump);
+                }
+                AssocOp::NullCoalescing => {
+                    self.compile(left);
+                    let jump = self.emit(Instruction::JumpIfNotNull(0));
+                    self.depth -= 1;
+                    self.compile(right);
+                    self.patch_jump(jump);
+                }
+                _ => {
+                    self.compile(left);
+                    self.emit(Instruction::Convert(operand_conversion(*op)));
+                    self.compile(right);
+                    self.emit(Instruction::Convert(operand_conversion(*op)));
+                    self.emit(Instruction::Binary(*op));
+                }
+            },
+            Expr::PreparedUnaryOperator(operand, op, op_impl) if op_impl.implementation_type() == type_of(&f_unary_operators) => {
+                self.compile(operand);
+                self.emit(Instruction::Unary(*op));
+            }
+            Expr::Ternary(condition, when_true, when_false) => {
+                self.compile(condition);
+                let to_false = self.emit(Instruction::JumpIfFalse(0));
+                self.compile(when_true);
+                let to_end = self.emit(Instruction::Jump(0));
+                self.depth -= 1;
+                self.patch_jump(to_false);
+                self.compile(when_false);
+                self.patch_jump(to_end);
+            }
+            Expr::Array(items) => {
+                for item in items {
+                    self.compile(item);
+                }
+                self.emit(Instruction::Array(items.len()));
+            }
+            Expr::Index(e, index) => {
+                self.compile(e);
+                self.compile(index);
+                self.emit(Instruction::Index);
+            }
+            Expr::Member(e, name) => {
+                self.compile(e);
+                self.compiled.members.push(name.clone());
+                self.emit(Instruction::Member(self.compiled.members.len() - 1));
+            }
+            Expr::Spanned(e, span) => {
+                let start = self.compiled.code.len();
+                self.compile(e);
+                self.compiled.spans.push((start..self.compiled.code.len(), *span));
+            }
+            _ => self.expression(expr),
+        }
+    }
+}
+
+pub fn exec_compiled_expr(compiled: &CompiledExpr, values: &IdentifierValues) -> ExprFuncResult {
+    let identifiers = compiled.identifiers.iter().enumerate().map(|(slot, name)| values.get_slot(slot, name)).collect::<Vec<_>>();
+    let mut stack = Vec::with_capacity(compiled.max_stack);
+    let mut pc = 0;
+    while let Some(instruction) = compiled.code.get(pc) {
+        if let Err(err) = exec_instruction(compiled, *instruction, &mut pc, &identifiers, values, &mut stack) {
+            return Err(locate_error(compiled, err, pc));
+        }
+    }
+    Ok(stack.pop().unwrap_or(ExprResult::Null))
+}
+
+// like `Expr::Spanned` does, the error is located on the innermost parsed expression
+fn locate_error(compiled: &CompiledExpr, err: ExecError, pc: usize) -> ExecError {
+    match compiled.spans.iter().find(|(range, _)| range.contains(&pc)) {
+        Some((_, span)) => err.or_span(*span),
+        None => err,
+    }
+}
+
+// `pc` is only moved when the instruction succeeds
+fn exec_instruction(compiled: &CompiledExpr, instruction: Instruction, pc: &mut usize, identifiers: &[Option<&IdentifierValueGetter>], values: &IdentifierValues, stack: &mut Vec<ExprResult>) -> Result<(), ExecError> {
+    let mut next = *pc + 1;
+    match instruction {
+        Instruction::Constant(slot) => stack.push(exec_expr(&compiled.constants[slot], values)?),
+        Instruction::Identifier(slot) => match identifiers[slot] {
+            Some(get_value) => stack.push(get_value()?),
+            None => stack.push(values.missing_value(&compiled.identifiers[slot])?),
+        },
+        Instruction::Call(function, parameters) => match compiled.functions[function](&compiled.parameters[parameters], values)? {
+            ExprResult::NonExecuted(expr) => stack.push(exec_expr(&expr, values)?),
+            result => stack.push(result),
+        },
+        Instruction::Expression(slot) => stack.push(exec_expr(&compiled.expressions[slot], values)?),
+        Instruction::Convert(conversion) => {
+            let value = convert_operand(stack.pop().unwrap(), conversion)?;
+            stack.push(value);
+        }
+        Instruction::Binary(op) => {
+            let right = stack.pop().unwrap();
+            let left = stack.pop().unwrap();
+            stack.push(binary_operator_on_values(op, left, right)?);
+        }
+        Instruction::Unary(op) => {
+            let value = unary_operator_on_value(op, stack.pop().unwrap())?;
+            stack.push(value);
+        }
+        Instruction::Jump(target) => next = target,
+        Instruction::JumpIfFalse(target) => {
+            if !result_to_bool(&stack.pop().unwrap()) {
+                next = target;
+            }
+        }
+        Instruction::JumpIfBoolean(expected, target) => match stack.last() {
+            Some(ExprResult::Boolean(b)) if *b == expected => next = target,
+            _ => {
+                stack.pop();
+            }
+        },
+        Instruction::JumpIfNotNull(target) => match stack.last() {
+            Some(ExprResult::Null) => {
+                stack.pop();
+            }
+            _ => next = target,
+        },
+        Instruction::Array(count) => {
+            let items = stack.drain(stack.len() - count..).collect();
+            stack.push(ExprResult::Array(Rc::new(items)));
+        }
+        Instruction::Index => {
+            let index = stack.pop().unwrap();
+            let array = stack.pop().unwrap();
+            stack.push(index_value(array, index)?);
+        }
+        Instruction::Member(member) => {
+            let value = member_value(stack.pop().unwrap(), &compiled.members[member], values.case())?;
+            stack.push(value);
+        }
+    }
+    *pc = next;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::FunctionRegistry;
+    use chrono::NaiveDate;
+    use rust_decimal_macros::*;
+    use test_case::test_case;
+
+    fn values() -> IdentifierValues {
+        let mut values = IdentifierValues::new();
+        values.insert("i".into(), Box::new(|| Ok(ExprResult::Int(3))));
+        values.insert("n".into(), Box::new(|| Ok(ExprResult::Num(dec!(2.50)))));
+        values.insert("s".into(), Box::new(|| Ok(ExprResult::Str(Rc::new("abc".to_string())))));
+        values.insert("e".into(), Box::new(|| Ok(ExprResult::Str(Rc::new(String::new())))));
+        values.insert("t".into(), Box::new(|| Ok(ExprResult::Str(Rc::new(" TRUE ".to_string())))));
+        values.insert("c".into(), Box::new(|| Ok(ExprResult::Str(Rc::new("1,5".to_string())))));
+        values.insert("b".into(), Box::new(|| Ok(ExprResult::Boolean(true))));
+        values.insert("z".into(), Box::new(|| Ok(ExprResult::Int(0))));
+        values.insert("big".into(), Box::new(|| Ok(ExprResult::Int(i64::MAX))));
+        values.insert("nul".into(), Box::new(|| Ok(ExprResult::Null)));
+        values.insert("d".into(), Box::new(|| Ok(ExprResult::Date(NaiveDate::from_ymd_opt(2020, 2, 29).unwrap().and_hms_opt(10, 30, 0).unwrap()))));
+        values.insert("fails".into(), Box::new(|| Err("The host failed".into())));
+        values.insert(
+            "r".into(),
+            Box::new(|| {
+                let owner = ExprResult::Record(Rc::new(vec![("Name".to_string(), ExprResult::Str(Rc::new("Ann".to_string())))]));
+                let tags = ExprResult::Array(Rc::new(vec![ExprResult::Str(Rc::new("a".to_string())), ExprResult::Str(Rc::new("b".to_string()))]));
+                Ok(ExprResult::Record(Rc::new(vec![
+                    ("Price".to_string(), ExprResult::Int(12)),
+                    ("Tags".to_string(), tags),
+                    ("Owner".to_string(), owner),
+                ])))
+            }),
+        );
+        values
+    }
+
+    fn debug_result(result: ExprFuncResult) -> Result<String, ExecError> {
+        result.map(|r| format!("{:?}", r))
+    }
+
+    // the tree and the bytecode executions, with and without the constant folding
+    fn exec_both_ways(expression: &str, operators: SharedBinaryOperatorsImpl, unary_operators: SharedUnaryOperatorsImpl) -> Result<String, ExecError> {
+        let registry = FunctionRegistry::builtins();
+        let values = values();
+        let parsed = parse_expr(expression).unwrap();
+        let (_, prepared) = prepare_expr(
+            Arc::new(parsed.clone()),
+            registry.functions(),
+            &mut IdentifierSlots::default(),
+            Arc::clone(&operators),
+            Arc::clone(&unary_operators),
+        );
+        let folded = prepare_expr_and_identifiers(parsed, &registry, operators, unary_operators).expr;
+
+        let expected = debug_result(exec_expr(&prepared, &values));
+        assert_eq!(debug_result(exec_compiled_expr(&compile_expr(&prepared), &values)), expected, "{}", expression);
+        assert_eq!(debug_result(exec_expr(&folded, &values)), expected, "{} folded", expression);
+        assert_eq!(debug_result(exec_compiled_expr(&compile_expr(&folded), &values)), expected, "{} folded", expression);
+        expected
+    }
+
+    fn exec_both_ways_with_builtins(expression: &str) -> Result<String, ExecError> {
+        exec_both_ways(expression, Arc::new(f_operators), Arc::new(f_unary_operators))
+    }
+
+    #[test_case("1 + 2 * 3" => Ok("Int(7)".to_string()))]
+    #[test_case("i * n - 1" => Ok("Num(6.50)".to_string()))]
+    #[test_case("big + 1" => Ok("Num(9223372036854775808)".to_string()))]
+    #[test_case("-i + +c" => Ok("Num(-1.5)".to_string()))]
+    #[test_case("i / 2 > 1 && s == \"abc\"" => Ok("Boolean(true)".to_string()))]
+    #[test_case("z == 1 || t" => Ok("Boolean(true)".to_string()))]
+    #[test_case("!b || fails" => Err(ExecError { message: "The host failed".to_string(), span: Some(Span { start: 6, end: 11 }) }))]
+    #[test_case("b && missing" => Err(ExecError { message: "Unable to find value for identifier named 'missing'".to_string(), span: Some(Span { start: 5, end: 12 }) }))]
+    #[test_case("false && missing" => Ok("Boolean(false)".to_string()))]
+    #[test_case("nul ?? e ?? s" => Ok("Str(\"\")".to_string()))]
+    #[test_case("nul ?? nul" => Ok("Null".to_string()))]
+    #[test_case("i > 2 ? (n > 3 ? \"big\" : \"medium\") : missing" => Ok("Str(\"medium\")".to_string()))]
+    #[test_case("$\"{s} is {i + 1:N2} on {d:yyyy-MM-dd}, {nul}!\"" => Ok("Str(\"abc is 4.00 on 2020-02-29, !\")".to_string()))]
+    #[test_case("$\"\"" => Ok("Str(\"\")".to_string()))]
+    #[test_case("Iif(i > 2, Upper(s), missing) + \"\"" => Err(ExecError { message: "The value 'ABC' is not a number.".to_string(), span: Some(Span { start: 0, end: 34 }) }))]
+    #[test_case("1 + (2 * s)" => Err(ExecError { message: "The value 'abc' is not a number.".to_string(), span: Some(Span { start: 4, end: 11 }) }))]
+    #[test_case("n / z" => Err(ExecError { message: "Couldn't divide 2.50 by 0".to_string(), span: Some(Span { start: 0, end: 5 }) }))]
+    #[test_case("Len(Left(s)) + 1" => Err(ExecError { message: "Function Left should have exactly 2 parameters".to_string(), span: Some(Span { start: 4, end: 11 }) }))]
+    #[test_case("$\"{i:Z9}\"" => Err(ExecError { message: "The format 'Z9' is not supported".to_string(), span: Some(Span { start: 0, end: 9 }) }))]
+    #[test_case("Unknown(1) ?? 2" => Err(ExecError { message: "Unable to find the function named 'Unknown'".to_string(), span: Some(Span { start: 0, end: 10 }) }))]
+    #[test_case("FirstNotNull(nul, Iif(b, d, 1))" => Ok("Date(2020-02-29T10:30:00)".to_string()))]
+    #[test_case("DateDiffDays(d, \"2020-01-01\") % 7 == 2" => Ok("Boolean(false)".to_string()))]
+    #[test_case("[i, s, [n]][2][0] * 2" => Ok("Num(5.00)".to_string()))]
+    #[test_case("Split(s, \"b\")[i - 2]" => Ok("Str(\"c\")".to_string()))]
+    #[test_case("new[] { i, s }[i]" => Err(ExecError { message: "The index 3 is out of the 2 items".to_string(), span: Some(Span { start: 0, end: 17 }) }))]
+    #[test_case("[1, i, 5].Where(x => x > i - 1).Sum(x => x * n)" => Ok("Num(20.00)".to_string()))]
+    #[test_case("[i].Select(i => i * s)" => Err(ExecError { message: "The value 'abc' is not a number.".to_string(), span: Some(Span { start: 16, end: 21 }) }))]
+    #[test_case("r.Price * 2 + r.Tags.Count()" => Ok("Int(26)".to_string()))]
+    #[test_case("r.owner.NAME == \"Ann\" ? r.Tags[1] : r.Tags[0]" => Ok("Str(\"b\")".to_string()))]
+    #[test_case("1 + r.Owner.Age" => Err(ExecError { message: "The record '{ Name = Ann }' has no field 'Age'".to_string(), span: Some(Span { start: 4, end: 15 }) }))]
+    #[test_case("s.Length" => Err(ExecError { message: "The value 'abc' is not a record, it has no field 'Length'".to_string(), span: Some(Span { start: 0, end: 8 }) }))]
+    fn same_results_as_the_tree(expression: &str) -> Result<String, ExecError> {
+        exec_both_ways_with_builtins(expression)
+    }
+
+    #[test]
+    fn same_results_for_every_operator() {
+        let operands = [
+            "i",
+            "n",
+            "s",
+            "e",
+            "t",
+            "c",
+            "b",
+            "z",
+            "big",
+            "nul",
+            "d",
+            "fails",
+            "missing",
+            "1",
+            "0",
+            "-1.5",
+            "\"2\"",
+            "null",
+            "true",
+            "Len(s)",
+            "Divide(1, z)",
+            "(b ? s : n)",
+        ];
+        let operators = ["+", "-", "*", "/", "%", "==", "!=", ">", ">=", "<", "<=", "&&", "||", "??"];
+        for left in operands.iter() {
+            for unary in ["!", "-", "+"].iter() {
+                exec_both_ways_with_builtins(&format!("{}{}", unary, left)).ok();
+            }
+            for op in operators.iter() {
+                for right in operands.iter() {
+                    exec_both_ways_with_builtins(&format!("{} {} {}", left, op, right)).ok();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn host_operators_are_executed_as_expressions() {
+        fn concat_op(l: SharedExpr, r: SharedExpr, op: AssocOp, values: &IdentifierValues) -> ExprFuncResult {
+            Ok(ExprResult::Str(Rc::new(format!("({} {} {})", exec_expr(&l, values)?, op, exec_expr(&r, values)?))))
+        }
+        fn keep_op(e: SharedExpr, _: UnaryOp, values: &IdentifierValues) -> ExprFuncResult {
+            exec_expr(&e, values)
+        }
+        let result = exec_both_ways("-i * 2 + (missing ?? 3)", Arc::new(concat_op), Arc::new(keep_op));
+        assert_eq!(result.unwrap_err().span, Some(Span { start: 10, end: 17 }));
+        assert_eq!(exec_both_ways("-i * 2 + (nul ?? 3)", Arc::new(concat_op), Arc::new(keep_op)), Ok("Str(\"((3 * 2) + ( ?? 3))\")".to_string()));
+    }
+
+    #[test]
+    fn missing_identifiers() {
+        let expr = prepare_expr_and_identifiers(parse_expr("IsNull(x) ? i : x * 2").unwrap(), &FunctionRegistry::builtins(), Arc::new(f_operators), Arc::new(f_unary_operators));
+        let compiled = compile_expr(&expr.expr);
+        for missing in [MissingIdentifier::Error, MissingIdentifier::Null, MissingIdentifier::Default(ExprResult::Int(4))].iter() {
+            let (tree_values, compiled_values) = (values().with_missing_identifier(missing.clone()), values().with_missing_identifier(missing.clone()));
+            assert_eq!(debug_result(exec_compiled_expr(&compiled, &compiled_values)), debug_result(exec_expr(&expr.expr, &tree_values)), "{:?}", missing);
+        }
+    }
+
+    #[test_case("a + 1" => "CompiledExpr { code: [Identifier(0), Convert(Number), Constant(0), Convert(Number), Binary(+)], identifiers: [\"a\"] }")]
+    #[test_case("a ? b : a" => "CompiledExpr { code: [Identifier(0), JumpIfFalse(4), Identifier(1), Jump(5), Identifier(0)], identifiers: [\"a\", \"b\"] }")]
+    #[test_case("a || Len(b)" => "CompiledExpr { code: [Identifier(0), Convert(Bool), JumpIfBoolean(true, 5), Call(0, 0), Convert(Bool)], identifiers: [\"a\"] }")]
+    #[test_case("[a, 1][0]" => "CompiledExpr { code: [Identifier(0), Constant(0), Array(2), Constant(1), Index], identifiers: [\"a\"] }")]
+    #[test_case("a.b.c" => "CompiledExpr { code: [Identifier(0), Member(0), Member(1)], identifiers: [\"a\"] }")]
+    fn compiled_code(expression: &str) -> String {
+        let expr = prepare_expr_and_identifiers(parse_expr(expression).unwrap(), &FunctionRegistry::builtins(), Arc::new(f_operators), Arc::new(f_unary_operators));
+        format!("{:?}", compile_expr(&expr.expr))
+    }
+
+    #[test]
+    fn execute_from_many_threads() {
+        let expr = prepare_expr_and_identifiers(parse_expr("$\"{Upper(s)}-{i * 2}\"").unwrap(), &FunctionRegistry::builtins(), Arc::new(f_operators), Arc::new(f_unary_operators));
+        let compiled = Arc::new(compile_expr(&expr.expr));
+        let threads = (0..4)
+            .map(|_| {
+                let compiled = Arc::clone(&compiled);
+                std::thread::spawn(move || exec_compiled_expr(&compiled, &values()).map(|r| r.to_string()))
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), Ok("ABC-6".to_string()));
+        }
+    }
+}
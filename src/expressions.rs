@@ -1,32 +1,140 @@
+use crate::bytecode::{compile_expr, exec_compiled_expr, CompiledExpr};
 use crate::parsing::*;
+pub use crate::parsing::{ExpectedToken, ParseError, ParseErrorKind};
+use crate::registry::{FunctionMetadata, FunctionRegistry};
+use crate::types::FunctionSignature;
 use std::fmt::Display;
 
 use chrono::prelude::*;
 use chrono::Duration;
-use nom::error::ErrorKind;
 use rust_decimal::prelude::*;
+use std::any::TypeId;
+use std::borrow::Borrow;
+use std::cell::RefCell;
 use std::cmp;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::ops::Add;
 use std::ops::AddAssign;
+use std::panic::{self, AssertUnwindSafe};
 use std::rc::Rc;
 use std::sync::Arc;
 use unicase::UniCase;
 
-pub type RcExpr = Rc<Expr>;
-pub type VecRcExpr = Vec<RcExpr>;
-pub type SliceRcExpr = [RcExpr];
-pub type ExprFuncResult = Result<ExprResult, String>;
-pub type FunctionImpl = dyn Fn(&SliceRcExpr, &IdentifierValues) -> ExprFuncResult;
-pub type FunctionImplList = HashMap<UniCase<String>, (FunctionDeterminism, Rc<FunctionImpl>)>;
-pub type IdentifierValueGetter = dyn Fn() -> Rc<String>;
-pub type IdentifierValues = HashMap<String, Box<IdentifierValueGetter>>;
+// A prepared expression is Send + Sync, so it can be executed from many threads at once :
+// the tree is shared with Arc and the functions and operators have to be Send + Sync.
+// The identifier values and the results are per execution, they stay on the executing thread.
+pub type SharedExpr = Arc<Expr>;
+pub type VecSharedExpr = Vec<SharedExpr>;
+pub type SliceSharedExpr = [SharedExpr];
+#[deprecated(note = "the expressions are shared with Arc, use SharedExpr")]
+pub type RcExpr = SharedExpr;
+#[deprecated(note = "the expressions are shared with Arc, use VecSharedExpr")]
+pub type VecRcExpr = VecSharedExpr;
+#[deprecated(note = "the expressions are shared with Arc, use SliceSharedExpr")]
+pub type SliceRcExpr = SliceSharedExpr;
+pub type ExprFuncResult = Result<ExprResult, ExecError>;
+pub type FunctionImpl = dyn Fn(&SliceSharedExpr, &IdentifierValues) -> ExprFuncResult + Send + Sync;
+pub type FunctionImplList = HashMap<UniCase<String>, FunctionDefinition>;
+pub type IdentifierValueGetter = dyn Fn() -> ExprFuncResult;
 pub type ExprDecimal = Decimal;
 
-pub trait BinaryOperatorsImpl: Fn(RcExpr, RcExpr, AssocOp, &IdentifierValues) -> ExprFuncResult {}
-impl<T> BinaryOperatorsImpl for T where T: Fn(RcExpr, RcExpr, AssocOp, &IdentifierValues) -> ExprFuncResult {}
-pub type BinaryOperatorsImplRc = Rc<dyn BinaryOperatorsImpl>;
+// `implementation_type` tells the built-in operators apart from the host ones, see `bytecode::compile_expr`
+pub trait BinaryOperatorsImpl: Fn(SharedExpr, SharedExpr, AssocOp, &IdentifierValues) -> ExprFuncResult + Send + Sync {
+    fn implementation_type(&self) -> TypeId;
+}
+impl<T> BinaryOperatorsImpl for T
+where
+    T: Fn(SharedExpr, SharedExpr, AssocOp, &IdentifierValues) -> ExprFuncResult + Send + Sync + 'static,
+{
+    fn implementation_type(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+}
+pub type SharedBinaryOperatorsImpl = Arc<dyn BinaryOperatorsImpl>;
+#[deprecated(note = "the operators are shared with Arc, use SharedBinaryOperatorsImpl")]
+pub type BinaryOperatorsImplRc = SharedBinaryOperatorsImpl;
+
+pub trait UnaryOperatorsImpl: Fn(SharedExpr, UnaryOp, &IdentifierValues) -> ExprFuncResult + Send + Sync {
+    fn implementation_type(&self) -> TypeId;
+}
+impl<T> UnaryOperatorsImpl for T
+where
+    T: Fn(SharedExpr, UnaryOp, &IdentifierValues) -> ExprFuncResult + Send + Sync + 'static,
+{
+    fn implementation_type(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+}
+pub type SharedUnaryOperatorsImpl = Arc<dyn UnaryOperatorsImpl>;
+#[deprecated(note = "the operators are shared with Arc, use SharedUnaryOperatorsImpl")]
+pub type UnaryOperatorsImplRc = SharedUnaryOperatorsImpl;
+
+// the type to compare with `implementation_type`, as in `type_of(&f_operators)`
+pub fn type_of<T: 'static>(_: &T) -> TypeId {
+    TypeId::of::<T>()
+}
+
+// A function as the expressions can call it
+#[derive(Clone)]
+pub struct FunctionDefinition {
+    pub determinism: FunctionDeterminism,
+    pub arity: FunctionArity,
+    pub signature: FunctionSignature,
+    pub implementation: Arc<FunctionImpl>,
+    pub metadata: FunctionMetadata,
+}
+
+impl FunctionDefinition {
+    pub fn new(determinism: FunctionDeterminism, arity: FunctionArity, signature: FunctionSignature, implementation: Arc<FunctionImpl>) -> FunctionDefinition {
+        FunctionDefinition {
+            determinism,
+            arity,
+            signature,
+            implementation,
+            metadata: FunctionMetadata::default(),
+        }
+    }
+
+    pub fn with_metadata(self, metadata: FunctionMetadata) -> FunctionDefinition {
+        FunctionDefinition { metadata, ..self }
+    }
+
+    pub fn is_optional_parameter(&self, index: usize) -> bool {
+        index >= self.arity.min
+    }
+
+    pub fn is_variadic(&self) -> bool {
+        self.arity.max.is_none()
+    }
+}
+
+// How many parameters a function accepts, checked when preparing an expression.
+// `max` is `None` for no maximum, the default accepts any count.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FunctionArity {
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+impl FunctionArity {
+    pub fn exactly(count: usize) -> FunctionArity {
+        FunctionArity { min: count, max: Some(count) }
+    }
+
+    pub fn between(min: usize, max: usize) -> FunctionArity {
+        FunctionArity { min, max: Some(max) }
+    }
+
+    pub fn at_least(min: usize) -> FunctionArity {
+        FunctionArity { min, max: None }
+    }
+
+    pub fn accepts(&self, count: usize) -> bool {
+        count >= self.min && self.max.is_none_or(|max| count <= max)
+    }
+}
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -74,6 +182,7 @@ pub enum AssocOp {
     NotEqual,
     Greater,
     GreaterEqual,
+    NullCoalescing,
 }
 
 impl fmt::Display for AssocOp {
@@ -92,8 +201,30 @@ impl fmt::Display for AssocOp {
             AssocOp::NotEqual => write!(f, "!="),
             AssocOp::Greater => write!(f, ">"),
             AssocOp::GreaterEqual => write!(f, ">="),
+            AssocOp::NullCoalescing => write!(f, "??"),
+        }
+    }
+}
+
+impl AssocOp {
+    /// Binding power of the operator, following the C# operators precedence table.
+    /// see : https://docs.microsoft.com/en-us/dotnet/csharp/language-reference/operators/#operator-precedence
+    pub fn precedence(&self) -> u8 {
+        match self {
+            AssocOp::Multiply | AssocOp::Divide | AssocOp::Modulus => 7,
+            AssocOp::Add | AssocOp::Subtract => 6,
+            AssocOp::Less | AssocOp::LessEqual | AssocOp::Greater | AssocOp::GreaterEqual => 5,
+            AssocOp::Equal | AssocOp::NotEqual => 4,
+            AssocOp::LAnd => 3,
+            AssocOp::LOr => 2,
+            AssocOp::NullCoalescing => 1,
         }
     }
+
+    // `a ?? b ?? c` is `a ?? (b ?? c)`, every other binary operator is left associative
+    pub fn is_right_associative(&self) -> bool {
+        matches!(self, AssocOp::NullCoalescing)
+    }
 }
 
 impl fmt::Debug for AssocOp {
@@ -102,18 +233,55 @@ impl fmt::Debug for AssocOp {
     }
 }
 
+// Unary operators always bind tighter than the binary ones
+#[derive(PartialEq, Copy, Clone)]
+pub enum UnaryOp {
+    Not,
+    Negate,
+    Plus,
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        match self {
+            UnaryOp::Not => write!(f, "!"),
+            UnaryOp::Negate => write!(f, "-"),
+            UnaryOp::Plus => write!(f, "+"),
+        }
+    }
+}
+
+impl fmt::Debug for UnaryOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        self::Display::fmt(self, f)
+    }
+}
+
 #[repr(C)]
 #[derive(Clone)]
 pub enum Expr {
-    Str(String),                                                                  // "text"
-    Boolean(bool),                                                                // true | false
-    Num(ExprDecimal),                                                             // 123.45
-    Null,                                                                         // null
-    Identifier(String),                                                           // varToto
-    FunctionCall(UniCase<String>, VecRcExpr),                                     // func(42, "text")
-    PreparedFunctionCall(UniCase<String>, VecRcExpr, Rc<FunctionImpl>),           // func(42, "text") + *func()
-    BinaryOperator(RcExpr, RcExpr, AssocOp),                                      // 32 + 10
-    PreparedBinaryOperator(RcExpr, RcExpr, AssocOp, Rc<dyn BinaryOperatorsImpl>), // 32 + 10 + *operators()
+    Str(String),                                                                        // "text"
+    Boolean(bool),                                                                      // true | false
+    Num(ExprDecimal),                                                                   // 123.45
+    Int(i64),                                                                           // 123
+    Null,                                                                               // null
+    Identifier(String),                                                                 // varToto
+    PreparedIdentifier(String, usize),                                                  // varToto + its slot
+    FunctionCall(UniCase<String>, VecSharedExpr),                                       // func(42, "text")
+    PreparedFunctionCall(UniCase<String>, VecSharedExpr, Arc<FunctionImpl>),            // func(42, "text") + *func()
+    BinaryOperator(SharedExpr, SharedExpr, AssocOp),                                    // 32 + 10
+    PreparedBinaryOperator(SharedExpr, SharedExpr, AssocOp, SharedBinaryOperatorsImpl), // 32 + 10 + *operators()
+    UnaryOperator(SharedExpr, UnaryOp),                                                 // !true
+    PreparedUnaryOperator(SharedExpr, UnaryOp, SharedUnaryOperatorsImpl),               // -42 + *operators()
+    Ternary(SharedExpr, SharedExpr, SharedExpr),                                        // a > 3 ? "big" : "small"
+    Array(VecSharedExpr),                                                               // new[] { 1, 2, 3 } | [1, 2, 3]
+    Index(SharedExpr, SharedExpr),                                                      // items[0]
+    Member(SharedExpr, String),                                                         // x.Price, a field of a record
+    Lambda(String, SharedExpr),                                                         // x => x > 10, only a parameter of a function
+    Spanned(SharedExpr, Span),                                                          // where the inner expression was parsed
+    Date(NaiveDateTime),                                                                // only from the constant folding
+    TimeSpan(Duration),                                                                 // only from the constant folding
+    Failed(ExecError),                                                                  // a constant that failed to fold, the error is returned when executed
 }
 
 #[derive(Clone, Debug)]
@@ -121,19 +289,333 @@ pub enum ExprResult {
     Str(Rc<String>),
     Boolean(bool),
     Num(ExprDecimal),
+    Int(i64),
     Date(NaiveDateTime),
     TimeSpan(Duration),
     Null,
+    Array(Rc<Vec<ExprResult>>),
+    // the named fields of a value given by the host, in their order, read by `x.Price`
+    Record(Rc<Vec<(String, ExprResult)>>),
 
-    NonExecuted(RcExpr),
+    NonExecuted(SharedExpr),
 }
 
 #[repr(C)]
 #[derive(Debug)]
 pub struct ExprAndIdentifiers {
-    pub expr: RcExpr,
-    pub identifiers_names: HashSet<String>,
+    pub expr: SharedExpr,
+    // by slot, in the order they first appear in the expression
+    pub identifiers_names: Vec<String>,
     pub determinism: FunctionDeterminism,
+    // the one to give to `IdentifierValues::with_case` or `from_slots_with_case`
+    pub identifier_case: IdentifierCase,
+    // the calls that would fail for sure once executed, see `diagnose_expr`
+    pub diagnostics: Vec<Diagnostic>,
+    // the bytecode of `expr` once compiled, executed instead of it by `exec_prepared_expr`
+    pub compiled: Option<CompiledExpr>,
+}
+
+impl ExprAndIdentifiers {
+    // for the expressions executed many times, see `bytecode::compile_expr`
+    pub fn compile(self) -> ExprAndIdentifiers {
+        let compiled = compile_expr(&self.expr);
+        ExprAndIdentifiers { compiled: Some(compiled), ..self }
+    }
+}
+
+// How the identifier names are matched, to each other for their slots and to the names of their values
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IdentifierCase {
+    // `Amount` and `amount` are the same identifier, like the function names
+    #[default]
+    Insensitive,
+    // the strict mode, only the exact same names match
+    Sensitive,
+}
+
+impl IdentifierCase {
+    pub fn same_names(self, a: &str, b: &str) -> bool {
+        match self {
+            IdentifierCase::Insensitive => UniCase::new(a) == UniCase::new(b),
+            IdentifierCase::Sensitive => a == b,
+        }
+    }
+}
+
+// The identifiers found while preparing an expression, a name matching a previous one gets its slot
+#[derive(Debug, Default)]
+pub struct IdentifierSlots {
+    names: Vec<String>,
+    case: IdentifierCase,
+    // the parameters of the lambdas being prepared, the innermost last
+    parameters: Vec<String>,
+}
+
+impl IdentifierSlots {
+    pub fn new(case: IdentifierCase) -> IdentifierSlots {
+        IdentifierSlots { case, ..IdentifierSlots::default() }
+    }
+
+    // the first casing found is the one kept
+    pub fn slot(&mut self, name: &str) -> usize {
+        let case = self.case;
+        let position = self.names.iter().position(|n| case.same_names(n, name));
+        position.unwrap_or_else(|| {
+            self.names.push(name.to_string());
+            self.names.len() - 1
+        })
+    }
+
+    // a lambda parameter has no slot, its value is given by the function calling the lambda
+    pub fn is_parameter(&self, name: &str) -> bool {
+        self.parameters.iter().any(|p| self.case.same_names(p, name))
+    }
+
+    pub fn into_names(self) -> Vec<String> {
+        self.names
+    }
+}
+
+// What an identifier without value gives when executed
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum MissingIdentifier {
+    #[default]
+    Error,
+    Null,
+    Default(ExprResult),
+}
+
+// The values of the identifiers for one execution, by name, or by the slot of `ExprAndIdentifiers::identifiers_names`.
+// A value by slot is found without hashing the name, it is the one used when both are given.
+// The lambda parameters are on top of them, see `with_parameter`.
+#[derive(Default)]
+pub struct IdentifierValues {
+    by_name: ValuesByName,
+    by_slot: Vec<Box<IdentifierValueGetter>>,
+    missing: MissingIdentifier,
+    // the innermost last, as the lambda bodies are executed by the functions taking them
+    parameters: RefCell<Vec<(String, ExprResult)>>,
+}
+
+enum ValuesByName {
+    Insensitive(HashMap<InsensitiveName, Box<IdentifierValueGetter>>),
+    Sensitive(HashMap<String, Box<IdentifierValueGetter>>),
+}
+
+impl Default for ValuesByName {
+    fn default() -> Self {
+        ValuesByName::Insensitive(HashMap::new())
+    }
+}
+
+impl ValuesByName {
+    fn case(&self) -> IdentifierCase {
+        match self {
+            ValuesByName::Insensitive(_) => IdentifierCase::Insensitive,
+            ValuesByName::Sensitive(_) => IdentifierCase::Sensitive,
+        }
+    }
+}
+
+// A name compared as UniCase does, which can be looked up with a borrowed `InsensitiveStr`,
+// so that getting a value doesn't allocate the name
+struct InsensitiveName(String);
+
+#[repr(transparent)]
+struct InsensitiveStr(str);
+
+impl InsensitiveStr {
+    fn new(name: &str) -> &InsensitiveStr {
+        // same layout, thanks to `repr(transparent)`
+        unsafe { &*(name as *const str as *const InsensitiveStr) }
+    }
+}
+
+impl Borrow<InsensitiveStr> for InsensitiveName {
+    fn borrow(&self) -> &InsensitiveStr {
+        InsensitiveStr::new(&self.0)
+    }
+}
+
+impl PartialEq for InsensitiveStr {
+    fn eq(&self, other: &Self) -> bool {
+        UniCase::new(&self.0) == UniCase::new(&other.0)
+    }
+}
+
+impl Eq for InsensitiveStr {}
+
+impl Hash for InsensitiveStr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        UniCase::new(&self.0).hash(state)
+    }
+}
+
+impl PartialEq for InsensitiveName {
+    fn eq(&self, other: &Self) -> bool {
+        let (name, other): (&InsensitiveStr, &InsensitiveStr) = (self.borrow(), other.borrow());
+        name == other
+    }
+}
+
+impl Eq for InsensitiveName {}
+
+impl Hash for InsensitiveName {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let name: &InsensitiveStr = self.borrow();
+        name.hash(state)
+    }
+}
+
+impl IdentifierValues {
+    pub fn new() -> IdentifierValues {
+        IdentifierValues::default()
+    }
+
+    pub fn with_case(case: IdentifierCase) -> IdentifierValues {
+        let by_name = match case {
+            IdentifierCase::Insensitive => ValuesByName::Insensitive(HashMap::new()),
+            IdentifierCase::Sensitive => ValuesByName::Sensitive(HashMap::new()),
+        };
+        IdentifierValues { by_name, ..IdentifierValues::default() }
+    }
+
+    // the values in the order of `ExprAndIdentifiers::identifiers_names`
+    pub fn from_slots(values: Vec<Box<IdentifierValueGetter>>) -> IdentifierValues {
+        IdentifierValues::from_slots_with_case(values, IdentifierCase::default())
+    }
+
+    // the case matches the names completing the slots and the lambda parameters, as when the expression was prepared
+    pub fn from_slots_with_case(values: Vec<Box<IdentifierValueGetter>>, case: IdentifierCase) -> IdentifierValues {
+        IdentifierValues {
+            by_slot: values,
+            ..IdentifierValues::with_case(case)
+        }
+    }
+
+    pub fn with_missing_identifier(self, missing: MissingIdentifier) -> IdentifierValues {
+        IdentifierValues { missing, ..self }
+    }
+
+    // replaces the value of the same name, as matched with the case of these values
+    pub fn insert(&mut self, name: String, get_value: Box<IdentifierValueGetter>) {
+        match &mut self.by_name {
+            ValuesByName::Insensitive(values) => {
+                let name = InsensitiveName(name);
+                values.remove(&name);
+                values.insert(name, get_value);
+            }
+            ValuesByName::Sensitive(values) => {
+                values.insert(name, get_value);
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&IdentifierValueGetter> {
+        match &self.by_name {
+            ValuesByName::Insensitive(values) => values.get(InsensitiveStr::new(name)),
+            ValuesByName::Sensitive(values) => values.get(name),
+        }
+        .map(|get_value| get_value.as_ref())
+    }
+
+    pub fn get_slot(&self, slot: usize, name: &str) -> Option<&IdentifierValueGetter> {
+        match self.by_slot.get(slot) {
+            Some(get_value) => Some(get_value.as_ref()),
+            None => self.get(name),
+        }
+    }
+
+    // Executes `f` with the value of a lambda parameter, which hides the other values of the same name until `f` returns.
+    // `Where(items, x => x > 10)` executes its lambda body with each item as `x`.
+    pub fn with_parameter<T, F: FnOnce() -> T>(&self, name: &str, value: ExprResult, f: F) -> T {
+        self.parameters.borrow_mut().push((name.to_string(), value));
+        let result = f();
+        self.parameters.borrow_mut().pop();
+        result
+    }
+
+    // how the fields of the records are matched, as the identifiers are
+    pub fn case(&self) -> IdentifierCase {
+        self.by_name.case()
+    }
+
+    pub fn parameter(&self, name: &str) -> Option<ExprResult> {
+        let case = self.by_name.case();
+        self.parameters.borrow().iter().rev().find(|(n, _)| case.same_names(n, name)).map(|(_, value)| value.clone())
+    }
+
+    // the result of an identifier without value, as decided by `with_missing_identifier`
+    pub fn missing_value(&self, name: &str) -> ExprFuncResult {
+        match &self.missing {
+            MissingIdentifier::Error => Err(format!("Unable to find value for identifier named '{}'", name).into()),
+            MissingIdentifier::Null => Ok(ExprResult::Null),
+            MissingIdentifier::Default(value) => Ok(value.clone()),
+        }
+    }
+}
+
+// Byte offsets of a parsed expression in its source, end excluded
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+// An execution error, located on the innermost parsed expression that failed
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExecError {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl ExecError {
+    pub(crate) fn or_span(self, span: Span) -> ExecError {
+        ExecError { span: self.span.or(Some(span)), ..self }
+    }
+}
+
+impl From<String> for ExecError {
+    fn from(message: String) -> Self {
+        ExecError { message, span: None }
+    }
+}
+
+impl From<&str> for ExecError {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+impl Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+// A mistake found when preparing an expression, located on the innermost parsed expression containing it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    UnknownFunction = 0,
+    WrongArity = 1,
+    // a parameter or an operand that fails to change to the expected type, see `types::infer_expr_type`
+    TypeMismatch = 2,
+    // a constant sub-expression that fails each time it's executed
+    AlwaysFails = 3,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
 }
 
 impl fmt::Debug for Expr {
@@ -142,13 +624,25 @@ impl fmt::Debug for Expr {
             Expr::Str(x) => write!(f, "Str({:?})", x),
             Expr::Boolean(x) => write!(f, "Boolean({:?})", x),
             Expr::Num(x) => write!(f, "Num({:?})", x),
+            Expr::Int(x) => write!(f, "Int({:?})", x),
             Expr::Null => write!(f, "Null"),
-            // Expr::Array(x) => write!(f, "Array({:?})", x),
+            Expr::Array(x) => write!(f, "Array({:?})", x),
+            Expr::Index(e, i) => write!(f, "{:?}[{:?}]", e, i),
+            Expr::Member(e, m) => write!(f, "{:?}.{}", e, m),
+            Expr::Lambda(p, b) => write!(f, "Lambda({:?},{:?})", p, b),
             Expr::Identifier(x) => write!(f, "Identifier({:?})", x),
+            Expr::PreparedIdentifier(x, slot) => write!(f, "PreparedIdentifier({:?},{})", x, slot),
             Expr::FunctionCall(s, x) => write!(f, "FunctionCall({:?},{:?})", s, x),
             Expr::PreparedFunctionCall(s, x, _) => write!(f, "PreparedFunctionCall({:?},{:?})", s, x),
             Expr::BinaryOperator(l, r, o) => write!(f, "{:?} {:?} {:?}", l, o, r),
             Expr::PreparedBinaryOperator(l, r, o, _) => write!(f, "{:?} {:?} {:?}", l, o, r),
+            Expr::UnaryOperator(e, o) => write!(f, "{:?}{:?}", o, e),
+            Expr::PreparedUnaryOperator(e, o, _) => write!(f, "{:?}{:?}", o, e),
+            Expr::Ternary(c, t, e) => write!(f, "{:?} ? {:?} : {:?}", c, t, e),
+            Expr::Spanned(e, s) => write!(f, "{:?}@{}..{}", e, s.start, s.end),
+            Expr::Date(x) => write!(f, "Date({:?})", x),
+            Expr::TimeSpan(x) => write!(f, "TimeSpan({:?})", x),
+            Expr::Failed(x) => write!(f, "Failed({:?})", x.message),
         }
     }
 }
@@ -159,13 +653,27 @@ impl cmp::PartialEq for Expr {
             (Expr::Str(x_a), Expr::Str(x_b)) => x_a == x_b,
             (Expr::Boolean(x_a), Expr::Boolean(x_b)) => x_a == x_b,
             (Expr::Num(x_a), Expr::Num(x_b)) => x_a == x_b,
-            // (Expr::Array(x_a), Expr::Array(x_b)) => x_a == x_b,
+            (Expr::Int(x_a), Expr::Int(x_b)) => x_a == x_b,
+            (Expr::Array(x_a), Expr::Array(x_b)) => x_a == x_b,
+            (Expr::Index(e_a, i_a), Expr::Index(e_b, i_b)) => e_a == e_b && i_a == i_b,
+            (Expr::Member(e_a, m_a), Expr::Member(e_b, m_b)) => e_a == e_b && m_a == m_b,
+            (Expr::Lambda(p_a, b_a), Expr::Lambda(p_b, b_b)) => p_a == p_b && b_a == b_b,
             (Expr::Identifier(x_a), Expr::Identifier(x_b)) => x_a == x_b,
+            (Expr::PreparedIdentifier(x_a, slot_a), Expr::PreparedIdentifier(x_b, slot_b)) => x_a == x_b && slot_a == slot_b,
             (Expr::BinaryOperator(left_a, right_a, op_a), Expr::BinaryOperator(left_b, right_b, op_b)) => left_a == left_b && right_a == right_b && op_a == op_b,
             (Expr::PreparedBinaryOperator(left_a, right_a, op_a, _), Expr::PreparedBinaryOperator(left_b, right_b, op_b, _)) => left_a == left_b && right_a == right_b && op_a == op_b,
             (Expr::FunctionCall(n_a, p_a), Expr::FunctionCall(n_b, p_b)) => n_a == n_b && p_a == p_b,
             (Expr::PreparedFunctionCall(n_a, p_a, _), Expr::PreparedFunctionCall(n_b, p_b, _)) => n_a == n_b && p_a == p_b,
+            (Expr::UnaryOperator(e_a, op_a), Expr::UnaryOperator(e_b, op_b)) => e_a == e_b && op_a == op_b,
+            (Expr::PreparedUnaryOperator(e_a, op_a, _), Expr::PreparedUnaryOperator(e_b, op_b, _)) => e_a == e_b && op_a == op_b,
+            (Expr::Ternary(c_a, t_a, e_a), Expr::Ternary(c_b, t_b, e_b)) => c_a == c_b && t_a == t_b && e_a == e_b,
+            // the location is not part of the expression
+            (Expr::Spanned(e_a, _), b) => e_a.as_ref() == b,
+            (a, Expr::Spanned(e_b, _)) => a == e_b.as_ref(),
             (Expr::Null, Expr::Null) => true,
+            (Expr::Date(x_a), Expr::Date(x_b)) => x_a == x_b,
+            (Expr::TimeSpan(x_a), Expr::TimeSpan(x_b)) => x_a == x_b,
+            (Expr::Failed(x_a), Expr::Failed(x_b)) => x_a == x_b,
             _ => false,
         }
     }
@@ -177,8 +685,13 @@ impl cmp::PartialEq for ExprResult {
             (ExprResult::Str(x_a), ExprResult::Str(x_b)) => x_a == x_b,
             (ExprResult::Boolean(x_a), ExprResult::Boolean(x_b)) => x_a == x_b,
             (ExprResult::Num(x_a), ExprResult::Num(x_b)) => x_a == x_b,
+            (ExprResult::Int(x_a), ExprResult::Int(x_b)) => x_a == x_b,
+            (ExprResult::Int(x_a), ExprResult::Num(x_b)) => ExprDecimal::from(*x_a) == *x_b,
+            (ExprResult::Num(x_a), ExprResult::Int(x_b)) => *x_a == ExprDecimal::from(*x_b),
             (ExprResult::Date(x_a), ExprResult::Date(x_b)) => x_a == x_b,
             (ExprResult::TimeSpan(x_a), ExprResult::TimeSpan(x_b)) => x_a == x_b,
+            (ExprResult::Array(x_a), ExprResult::Array(x_b)) => x_a == x_b,
+            (ExprResult::Record(x_a), ExprResult::Record(x_b)) => x_a == x_b,
             (ExprResult::Null, ExprResult::Null) => true, // should be false ? => implemented in the `f_are_equals` function
             _ => false,
         }
@@ -191,13 +704,25 @@ impl Display for Expr {
             Expr::Str(s) => write!(f, "{}", s),
             Expr::Boolean(b) => write!(f, "{}", b),
             Expr::Num(n) => write!(f, "{}", n),
+            Expr::Int(n) => write!(f, "{}", n),
             Expr::Null => write!(f, ""),
-            // Expr::Array(_) => write!(f, "Array"),
+            Expr::Array(items) => write!(f, "[{}]", items.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", ")),
+            Expr::Index(e, i) => write!(f, "{}[{}]", e, i),
+            Expr::Member(e, m) => write!(f, "{}.{}", e, m),
+            Expr::Lambda(p, b) => write!(f, "{} => {}", p, b),
             Expr::Identifier(i) => write!(f, "@{}", i),
+            Expr::PreparedIdentifier(i, _) => write!(f, "@{}", i),
             Expr::FunctionCall(_, _) => write!(f, "FunctionCall"),
             Expr::PreparedFunctionCall(_, _, _) => write!(f, "PreparedFunctionCall"),
             Expr::BinaryOperator(l, r, o) => write!(f, "{} {} {}", l, o, r),
             Expr::PreparedBinaryOperator(l, r, o, _) => write!(f, "{} {} {}", l, o, r),
+            Expr::UnaryOperator(e, o) => write!(f, "{}{}", o, e),
+            Expr::PreparedUnaryOperator(e, o, _) => write!(f, "{}{}", o, e),
+            Expr::Ternary(c, t, e) => write!(f, "{} ? {} : {}", c, t, e),
+            Expr::Spanned(e, _) => write!(f, "{}", e),
+            Expr::Date(d) => write!(f, "{}", ExprResult::Date(*d)),
+            Expr::TimeSpan(t) => write!(f, "{}", ExprResult::TimeSpan(*t)),
+            Expr::Failed(e) => write!(f, "{}", e),
         }
     }
 }
@@ -212,6 +737,7 @@ impl Display for ExprResult {
             ExprResult::Str(s) => write!(f, "{}", s),
             ExprResult::Boolean(b) => write!(f, "{}", b),
             ExprResult::Num(n) => write!(f, "{}", n),
+            ExprResult::Int(n) => write!(f, "{}", n),
             ExprResult::Date(d) => write!(f, "{:02}/{:02}/{:02} {:02}:{:02}:{:02}", d.month(), d.day(), d.year(), d.hour(), d.minute(), d.second()),
             ExprResult::TimeSpan(d) => {
                 let sign = if *d < Duration::zero() { "-" } else { "" };
@@ -229,11 +755,41 @@ impl Display for ExprResult {
                 }
             }
             ExprResult::Null => write!(f, ""),
+            ExprResult::Array(items) => write!(f, "[{}]", items.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", ")),
+            // as the anonymous types of C#
+            ExprResult::Record(fields) => write!(f, "{{ {} }}", fields.iter().map(|(n, v)| format!("{} = {}", n, v)).collect::<Vec<_>>().join(", ")),
             ExprResult::NonExecuted(rc_expr) => write!(f, "{:?}", rc_expr),
         }
     }
 }
 
+impl Expr {
+    // A value known before the execution, or its failure
+    pub fn is_constant(&self) -> bool {
+        match self {
+            Expr::Array(items) => items.iter().all(|i| i.is_constant()),
+            Expr::Spanned(e, _) => e.is_constant(),
+            e => matches!(e, Expr::Str(_) | Expr::Boolean(_) | Expr::Num(_) | Expr::Int(_) | Expr::Null | Expr::Date(_) | Expr::TimeSpan(_) | Expr::Failed(_)),
+        }
+    }
+
+    // The parsed expression itself, whatever its location
+    pub fn without_span(&self) -> &Expr {
+        match self {
+            Expr::Spanned(e, _) => e.without_span(),
+            e => e,
+        }
+    }
+
+    // Where the expression was parsed, if known
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Expr::Spanned(_, span) => Some(*span),
+            _ => None,
+        }
+    }
+}
+
 impl ExprResult {
     pub fn is_final(&self) -> bool {
         match self {
@@ -251,79 +807,307 @@ impl ExprResult {
     }
 }
 
-pub fn parse_expr(expression: &str) -> Result<Expr, String> {
-    let expr = expr::<(&str, ErrorKind)>(expression);
-    match expr {
-        Ok((rest, expr)) => match rest.len() {
-            0 => Ok(expr),
-            _ => Err(format!("Unable to parse the rest of the expression '{:?}'", rest)),
-        },
-        Err(err_kind) => Err(format!("{:?}", err_kind)),
-    }
+pub fn parse_expr(expression: &str) -> Result<Expr, ParseError> {
+    expr(expression)
 }
 
-pub fn prepare_expr_and_identifiers(expr: Expr, funcs: &FunctionImplList, operators: BinaryOperatorsImplRc) -> ExprAndIdentifiers {
-    let mut identifiers = HashSet::<String>::new();
-    let (determinism, expr) = prepare_expr(Rc::new(expr), funcs, &mut identifiers, operators);
+pub fn prepare_expr_and_identifiers(expr: Expr, registry: &FunctionRegistry, operators: SharedBinaryOperatorsImpl, unary_operators: SharedUnaryOperatorsImpl) -> ExprAndIdentifiers {
+    prepare_expr_and_identifiers_with_case(expr, registry, operators, unary_operators, IdentifierCase::default())
+}
+
+pub fn prepare_expr_and_identifiers_with_case(
+    expr: Expr,
+    registry: &FunctionRegistry,
+    operators: SharedBinaryOperatorsImpl,
+    unary_operators: SharedUnaryOperatorsImpl,
+    identifier_case: IdentifierCase,
+) -> ExprAndIdentifiers {
+    let mut identifiers = IdentifierSlots::new(identifier_case);
+    let expr = Arc::new(expr);
+    let diagnostics = diagnose_expr(&expr, registry.functions());
+    let (determinism, expr) = prepare_expr(expr, registry.functions(), &mut identifiers, operators, unary_operators);
     ExprAndIdentifiers {
-        expr,
-        identifiers_names: identifiers,
+        expr: fold_constants(&expr, registry.functions()),
+        identifiers_names: identifiers.into_names(),
         determinism,
+        identifier_case,
+        diagnostics,
+        compiled: None,
+    }
+}
+
+// Executes the bytecode of the prepared expression when it was compiled, its tree otherwise
+pub fn exec_prepared_expr(expr: &ExprAndIdentifiers, values: &IdentifierValues) -> ExprFuncResult {
+    match &expr.compiled {
+        Some(compiled) => exec_compiled_expr(compiled, values),
+        None => exec_expr(&expr.expr, values),
+    }
+}
+
+// The definition of a prepared call : the built-in one the parser bound it to, as for the interpolated strings, or the one named so
+pub fn prepared_call_definition<'a>(funcs: &'a FunctionImplList, name: &UniCase<String>, implementation: &Arc<FunctionImpl>) -> Option<&'a FunctionDefinition> {
+    crate::functions::interpolation_definition(implementation).or_else(|| funcs.get(name))
+}
+
+// The unknown functions and the calls with a wrong parameters count, in the order they appear in the expression.
+// The expression can still be prepared and executed, these calls only fail if they are executed.
+pub fn diagnose_expr(expr: &SharedExpr, funcs: &FunctionImplList) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    diagnose_expr_into(expr, funcs, None, &mut diagnostics);
+    diagnostics
+}
+
+fn diagnose_expr_into(expr: &SharedExpr, funcs: &FunctionImplList, span: Option<Span>, diagnostics: &mut Vec<Diagnostic>) {
+    match expr.as_ref() {
+        Expr::FunctionCall(name, parameters) | Expr::PreparedFunctionCall(name, parameters, _) => {
+            let definition = match expr.as_ref() {
+                Expr::PreparedFunctionCall(_, _, implementation) => prepared_call_definition(funcs, name, implementation),
+                _ => funcs.get(name),
+            };
+            match definition {
+                None => diagnostics.push(Diagnostic {
+                    kind: DiagnosticKind::UnknownFunction,
+                    message: format!("Unable to find the function named '{}'", name),
+                    span,
+                }),
+                Some(definition) if !definition.arity.accepts(parameters.len()) => diagnostics.push(Diagnostic {
+                    kind: DiagnosticKind::WrongArity,
+                    message: crate::functions::assert_params_count(parameters, definition.arity.min, definition.arity.max, name).unwrap_err(),
+                    span,
+                }),
+                Some(_) => {}
+            }
+            for p in parameters.iter() {
+                diagnose_expr_into(p, funcs, span, diagnostics);
+            }
+        }
+        Expr::BinaryOperator(left, right, _) | Expr::PreparedBinaryOperator(left, right, _, _) => {
+            diagnose_expr_into(left, funcs, span, diagnostics);
+            diagnose_expr_into(right, funcs, span, diagnostics);
+        }
+        Expr::UnaryOperator(operand, _) | Expr::PreparedUnaryOperator(operand, _, _) => diagnose_expr_into(operand, funcs, span, diagnostics),
+        Expr::Ternary(condition, when_true, when_false) => {
+            for e in [condition, when_true, when_false].iter() {
+                diagnose_expr_into(e, funcs, span, diagnostics);
+            }
+        }
+        Expr::Array(items) => {
+            for item in items {
+                diagnose_expr_into(item, funcs, span, diagnostics);
+            }
+        }
+        Expr::Index(e, index) => {
+            diagnose_expr_into(e, funcs, span, diagnostics);
+            diagnose_expr_into(index, funcs, span, diagnostics);
+        }
+        Expr::Member(e, _) => diagnose_expr_into(e, funcs, span, diagnostics),
+        Expr::Lambda(_, body) => diagnose_expr_into(body, funcs, span, diagnostics),
+        Expr::Spanned(e, span) => diagnose_expr_into(e, funcs, Some(*span), diagnostics),
+        Expr::Str(_) | Expr::Boolean(_) | Expr::Num(_) | Expr::Int(_) | Expr::Null | Expr::Identifier(_) | Expr::PreparedIdentifier(_, _) => {}
+        Expr::Date(_) | Expr::TimeSpan(_) | Expr::Failed(_) => {}
     }
 }
 
-pub fn prepare_expr_list(exprs: &SliceRcExpr, funcs: &FunctionImplList, identifiers: &mut HashSet<String>, operators: BinaryOperatorsImplRc) -> (FunctionDeterminism, VecRcExpr) {
-    let mut list = VecRcExpr::with_capacity(exprs.len());
+pub fn prepare_expr_list(
+    exprs: &SliceSharedExpr,
+    funcs: &FunctionImplList,
+    identifiers: &mut IdentifierSlots,
+    operators: SharedBinaryOperatorsImpl,
+    unary_operators: SharedUnaryOperatorsImpl,
+) -> (FunctionDeterminism, VecSharedExpr) {
+    let mut list = VecSharedExpr::with_capacity(exprs.len());
     let mut total_determinist = FunctionDeterminism::default();
     for p in exprs.iter() {
-        let (determinism, prepared) = prepare_expr(Rc::clone(p), funcs, identifiers, Rc::clone(&operators));
+        let (determinism, prepared) = prepare_expr(Arc::clone(p), funcs, identifiers, Arc::clone(&operators), Arc::clone(&unary_operators));
         list.push(prepared);
         total_determinist += determinism;
     }
     (total_determinist, list)
 }
 
-pub fn prepare_expr(expr: RcExpr, funcs: &FunctionImplList, identifiers: &mut HashSet<String>, operators: BinaryOperatorsImplRc) -> (FunctionDeterminism, RcExpr) {
+pub fn prepare_expr(expr: SharedExpr, funcs: &FunctionImplList, identifiers: &mut IdentifierSlots, operators: SharedBinaryOperatorsImpl, unary_operators: SharedUnaryOperatorsImpl) -> (FunctionDeterminism, SharedExpr) {
     match expr.as_ref() {
-        Expr::Identifier(name) => {
-            identifiers.insert(name.clone());
-            (FunctionDeterminism::Deterministic, expr)
-        }
+        Expr::Identifier(name) | Expr::PreparedIdentifier(name, _) if identifiers.is_parameter(name) => (FunctionDeterminism::Deterministic, SharedExpr::new(Expr::Identifier(name.clone()))),
+        // an identifier prepared again gets the slot of these identifiers
+        Expr::Identifier(name) | Expr::PreparedIdentifier(name, _) => (FunctionDeterminism::Deterministic, SharedExpr::new(Expr::PreparedIdentifier(name.clone(), identifiers.slot(name)))),
         Expr::FunctionCall(name, parameters) => match &funcs.get(&name) {
+            // the call fails when executed, so the functions never check their parameters count themselves
+            Some(fnc) if !fnc.arity.accepts(parameters.len()) => {
+                let (params_determinism, _) = prepare_expr_list(parameters, funcs, identifiers, operators, unary_operators);
+                let message = crate::functions::assert_params_count(parameters, fnc.arity.min, fnc.arity.max, name).unwrap_err();
+                (fnc.determinism + params_determinism, Arc::new(Expr::Failed(message.into())))
+            }
             Some(fnc) => {
-                let (params_determinism, prepared_list) = prepare_expr_list(parameters, funcs, identifiers, operators);
-                (fnc.0 + params_determinism, Rc::new(Expr::PreparedFunctionCall(name.clone(), prepared_list, Rc::clone(&fnc.1))))
+                let (params_determinism, prepared_list) = prepare_expr_list(parameters, funcs, identifiers, operators, unary_operators);
+                (
+                    fnc.determinism + params_determinism,
+                    Arc::new(Expr::PreparedFunctionCall(name.clone(), prepared_list, Arc::clone(&fnc.implementation))),
+                )
             }
             None => (FunctionDeterminism::default(), expr),
         },
         Expr::BinaryOperator(left, right, op) => {
-            let left_prepared = prepare_expr(Rc::clone(left), funcs, identifiers, Rc::clone(&operators));
-            let right_prepared = prepare_expr(Rc::clone(right), funcs, identifiers, Rc::clone(&operators));
+            let left_prepared = prepare_expr(Arc::clone(left), funcs, identifiers, Arc::clone(&operators), Arc::clone(&unary_operators));
+            let right_prepared = prepare_expr(Arc::clone(right), funcs, identifiers, Arc::clone(&operators), Arc::clone(&unary_operators));
             (
                 (left_prepared.0 + right_prepared.0),
-                RcExpr::new(Expr::PreparedBinaryOperator(left_prepared.1, right_prepared.1, *op, Rc::clone(&operators))),
+                SharedExpr::new(Expr::PreparedBinaryOperator(left_prepared.1, right_prepared.1, *op, Arc::clone(&operators))),
             )
         }
+        Expr::UnaryOperator(operand, op) => {
+            let (determinism, prepared) = prepare_expr(Arc::clone(operand), funcs, identifiers, operators, Arc::clone(&unary_operators));
+            (determinism, SharedExpr::new(Expr::PreparedUnaryOperator(prepared, *op, unary_operators)))
+        }
+        Expr::Ternary(condition, when_true, when_false) => {
+            let (determinism, mut prepared) = prepare_expr_list(&[Arc::clone(condition), Arc::clone(when_true), Arc::clone(when_false)], funcs, identifiers, operators, unary_operators);
+            let when_false = prepared.pop().unwrap();
+            let when_true = prepared.pop().unwrap();
+            let condition = prepared.pop().unwrap();
+            (determinism, SharedExpr::new(Expr::Ternary(condition, when_true, when_false)))
+        }
+        Expr::Array(items) => {
+            let (determinism, prepared) = prepare_expr_list(items, funcs, identifiers, operators, unary_operators);
+            (determinism, SharedExpr::new(Expr::Array(prepared)))
+        }
+        Expr::Index(e, index) => {
+            let (determinism, mut prepared) = prepare_expr_list(&[Arc::clone(e), Arc::clone(index)], funcs, identifiers, operators, unary_operators);
+            let index = prepared.pop().unwrap();
+            let e = prepared.pop().unwrap();
+            (determinism, SharedExpr::new(Expr::Index(e, index)))
+        }
+        Expr::Member(e, name) => {
+            let (determinism, prepared) = prepare_expr(Arc::clone(e), funcs, identifiers, operators, unary_operators);
+            (determinism, SharedExpr::new(Expr::Member(prepared, name.clone())))
+        }
+        Expr::Lambda(parameter, body) => {
+            identifiers.parameters.push(parameter.clone());
+            let (determinism, prepared) = prepare_expr(Arc::clone(body), funcs, identifiers, operators, unary_operators);
+            identifiers.parameters.pop();
+            (determinism, SharedExpr::new(Expr::Lambda(parameter.clone(), prepared)))
+        }
+        Expr::Spanned(e, span) => {
+            let (determinism, prepared) = prepare_expr(Arc::clone(e), funcs, identifiers, operators, unary_operators);
+            (determinism, SharedExpr::new(Expr::Spanned(prepared, *span)))
+        }
         Expr::Str(_) => (FunctionDeterminism::Deterministic, expr),
         Expr::Boolean(_) => (FunctionDeterminism::Deterministic, expr),
         Expr::Num(_) => (FunctionDeterminism::Deterministic, expr),
+        Expr::Int(_) => (FunctionDeterminism::Deterministic, expr),
         Expr::Null => (FunctionDeterminism::Deterministic, expr),
-        Expr::PreparedFunctionCall(_, _, _) => unreachable!(),
+        Expr::Date(_) | Expr::TimeSpan(_) | Expr::Failed(_) => (FunctionDeterminism::Deterministic, expr),
+        // bound by the parser, as the calls of the interpolated strings
+        Expr::PreparedFunctionCall(name, parameters, implementation) => {
+            let determinism = prepared_call_definition(funcs, name, implementation).map_or(FunctionDeterminism::default(), |f| f.determinism);
+            let (params_determinism, prepared_list) = prepare_expr_list(parameters, funcs, identifiers, operators, unary_operators);
+            (determinism + params_determinism, Arc::new(Expr::PreparedFunctionCall(name.clone(), prepared_list, Arc::clone(implementation))))
+        }
         Expr::PreparedBinaryOperator(_, _, _, _) => unreachable!(),
+        Expr::PreparedUnaryOperator(_, _, _) => unreachable!(),
     }
 }
 
-pub fn exec_expr<'a>(expr: &'a RcExpr, values: &'a IdentifierValues) -> Result<ExprResult, String> {
+// Executes once the deterministic sub-expressions without identifiers, and replaces them with their value.
+// A failure is kept as an `Expr::Failed`, so it only happens if the sub-expression is executed, as before folding.
+pub fn fold_constants(expr: &SharedExpr, funcs: &FunctionImplList) -> SharedExpr {
+    let (folded, is_constant) = match expr.as_ref() {
+        Expr::PreparedFunctionCall(name, parameters, fnc) => {
+            let parameters = parameters.iter().map(|p| fold_constants(p, funcs)).collect::<VecSharedExpr>();
+            let is_deterministic = prepared_call_definition(funcs, name, fnc).is_some_and(|f| f.determinism == FunctionDeterminism::Deterministic);
+            let is_constant = is_deterministic && parameters.iter().all(|p| p.is_constant());
+            (SharedExpr::new(Expr::PreparedFunctionCall(name.clone(), parameters, Arc::clone(fnc))), is_constant)
+        }
+        Expr::PreparedBinaryOperator(left, right, op, op_impl) => {
+            let (left, right) = (fold_constants(left, funcs), fold_constants(right, funcs));
+            let is_constant = left.is_constant() && right.is_constant();
+            (SharedExpr::new(Expr::PreparedBinaryOperator(left, right, *op, Arc::clone(op_impl))), is_constant)
+        }
+        Expr::PreparedUnaryOperator(operand, op, op_impl) => {
+            let operand = fold_constants(operand, funcs);
+            let is_constant = operand.is_constant();
+            (SharedExpr::new(Expr::PreparedUnaryOperator(operand, *op, Arc::clone(op_impl))), is_constant)
+        }
+        Expr::Ternary(condition, when_true, when_false) => {
+            let (condition, when_true, when_false) = (fold_constants(condition, funcs), fold_constants(when_true, funcs), fold_constants(when_false, funcs));
+            let is_constant = condition.is_constant() && when_true.is_constant() && when_false.is_constant();
+            (SharedExpr::new(Expr::Ternary(condition, when_true, when_false)), is_constant)
+        }
+        // an array of constants is already a constant
+        Expr::Array(items) => return SharedExpr::new(Expr::Array(items.iter().map(|i| fold_constants(i, funcs)).collect())),
+        Expr::Index(e, index) => {
+            let (e, index) = (fold_constants(e, funcs), fold_constants(index, funcs));
+            let is_constant = e.is_constant() && index.is_constant();
+            (SharedExpr::new(Expr::Index(e, index)), is_constant)
+        }
+        Expr::Member(e, name) => {
+            let e = fold_constants(e, funcs);
+            let is_constant = e.is_constant();
+            (SharedExpr::new(Expr::Member(e, name.clone())), is_constant)
+        }
+        // executed with the value of its parameter, a lambda is never a constant
+        Expr::Lambda(parameter, body) => return SharedExpr::new(Expr::Lambda(parameter.clone(), fold_constants(body, funcs))),
+        // a folded constant keeps its location, for the type errors and the errors of the functions using it
+        Expr::Spanned(e, span) => {
+            let e = fold_constants(e, funcs);
+            return match e.as_ref() {
+                Expr::Failed(err) => SharedExpr::new(Expr::Failed(err.clone().or_span(*span))),
+                _ => SharedExpr::new(Expr::Spanned(e, *span)),
+            };
+        }
+        _ => return Arc::clone(expr),
+    };
+    if !is_constant {
+        return folded;
+    }
+    // the functions run while preparing, a panic of one of them is only a failure of the constant
+    let result = panic::catch_unwind(AssertUnwindSafe(|| exec_expr(&folded, &IdentifierValues::new()))).unwrap_or_else(|payload| Err(format!("Unexpected failure : {}", panic_reason(payload.as_ref())).into()));
+    match result {
+        Ok(result) => constant_expr(&result).unwrap_or(folded),
+        Err(e) => SharedExpr::new(Expr::Failed(e)),
+    }
+}
+
+// The message given to `panic!`, when it's a text
+pub fn panic_reason(payload: &(dyn std::any::Any + Send)) -> String {
+    match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(reason), _) => reason.to_string(),
+        (None, Some(reason)) => reason.clone(),
+        (None, None) => "unknown reason".to_string(),
+    }
+}
+
+// The constant giving this result when executed, if any
+fn constant_expr(result: &ExprResult) -> Option<SharedExpr> {
+    let expr = match result {
+        ExprResult::Str(s) => Expr::Str(s.to_string()),
+        ExprResult::Boolean(b) => Expr::Boolean(*b),
+        ExprResult::Num(n) => Expr::Num(*n),
+        ExprResult::Int(i) => Expr::Int(*i),
+        ExprResult::Null => Expr::Null,
+        ExprResult::Date(d) => Expr::Date(*d),
+        ExprResult::TimeSpan(t) => Expr::TimeSpan(*t),
+        ExprResult::Array(items) => Expr::Array(items.iter().map(constant_expr).collect::<Option<_>>()?),
+        ExprResult::Record(_) | ExprResult::NonExecuted(_) => return None,
+    };
+    Some(SharedExpr::new(expr))
+}
+
+pub fn exec_expr<'a>(expr: &'a SharedExpr, values: &'a IdentifierValues) -> Result<ExprResult, ExecError> {
     match expr.as_ref() {
         Expr::Str(s) => Ok(ExprResult::Str(Rc::new(s.clone()))),
         Expr::Boolean(b) => Ok(ExprResult::Boolean(*b)),
         Expr::Num(f) => Ok(ExprResult::Num(*f)),
+        Expr::Int(i) => Ok(ExprResult::Int(*i)),
         Expr::Null => Ok(ExprResult::Null),
-        Expr::Identifier(name) => match &values.get(name) {
-            Some(s) => Ok(ExprResult::Str(s())),
-            None => Err(format!("Unable to find value for identifier named '{}'", name)),
+        Expr::Identifier(name) => match (values.parameter(name), values.get(name)) {
+            (Some(value), _) => Ok(value),
+            (None, Some(get_value)) => get_value(),
+            (None, None) => values.missing_value(name),
+        },
+        Expr::PreparedIdentifier(name, slot) => match values.get_slot(*slot, name) {
+            Some(get_value) => get_value(),
+            None => values.missing_value(name),
         },
-        Expr::FunctionCall(name, _parameters) => Err(format!("Unable to find the function named '{}'", name)),
+        Expr::FunctionCall(name, _parameters) => Err(format!("Unable to find the function named '{}'", name).into()),
         Expr::PreparedFunctionCall(_, parameters, fnc) => {
             let call_result = fnc(&parameters, &values)?;
             if let ExprResult::NonExecuted(expr) = call_result {
@@ -332,8 +1116,26 @@ pub fn exec_expr<'a>(expr: &'a RcExpr, values: &'a IdentifierValues) -> Result<E
                 Ok(call_result)
             }
         }
-        Expr::BinaryOperator(_, _, _) => Err(format!("No operators implementation")),
-        Expr::PreparedBinaryOperator(left, right, op, op_impl) => op_impl(Rc::clone(left), Rc::clone(right), *op, values),
+        Expr::BinaryOperator(_, _, _) => Err("No operators implementation".into()),
+        Expr::PreparedBinaryOperator(left, right, op, op_impl) => op_impl(Arc::clone(left), Arc::clone(right), *op, values),
+        Expr::UnaryOperator(_, _) => Err("No unary operators implementation".into()),
+        Expr::PreparedUnaryOperator(operand, op, op_impl) => op_impl(Arc::clone(operand), *op, values),
+        // only the chosen branch is executed, like the `Iif` function
+        Expr::Ternary(condition, when_true, when_false) => {
+            if crate::functions::exec_expr_to_bool(condition, values)? {
+                exec_expr(when_true, values)
+            } else {
+                exec_expr(when_false, values)
+            }
+        }
+        Expr::Array(items) => Ok(ExprResult::Array(Rc::new(items.iter().map(|i| exec_expr(i, values)).collect::<Result<_, _>>()?))),
+        Expr::Index(e, index) => crate::functions::index_value(exec_expr(e, values)?, exec_expr(index, values)?),
+        Expr::Member(e, name) => crate::functions::member_value(exec_expr(e, values)?, name, values.case()),
+        Expr::Lambda(_, _) => Err("A lambda is only a parameter of a function, like 'Where(items, x => x > 10)'".into()),
+        Expr::Spanned(e, span) => exec_expr(e, values).map_err(|err| err.or_span(*span)),
+        Expr::Date(d) => Ok(ExprResult::Date(*d)),
+        Expr::TimeSpan(t) => Ok(ExprResult::TimeSpan(*t)),
+        Expr::Failed(e) => Err(e.clone()),
     }
 }
 
@@ -347,7 +1149,7 @@ mod tests {
     use test_case::test_case;
     macro_rules! rc_expr_str {
         ( $x:expr ) => {
-            Rc::new(Expr::Str($x.to_string()))
+            SharedExpr::new(Expr::Str($x.to_string()))
         };
     }
     macro_rules! exprresult_str {
@@ -357,7 +1159,7 @@ mod tests {
     }
     macro_rules! rc_expr_num {
         ( $x:expr ) => {
-            Rc::new(Expr::Num(dec!($x)))
+            SharedExpr::new(Expr::Num(dec!($x)))
         };
     }
     macro_rules! exprresult_num {
@@ -367,7 +1169,7 @@ mod tests {
     }
     macro_rules! rc_expr_null {
         () => {
-            Rc::new(Expr::Null)
+            SharedExpr::new(Expr::Null)
         };
     }
 
@@ -377,48 +1179,140 @@ mod tests {
     #[test_case("knownFunc(test2, test3, test2, test3)" => vec!["test2", "test3"])]
     #[test_case("unknownFunc(test7)" => Vec::<String>::new())]
     #[test_case("knownFunc(test2, test3, test3, knownFunc(test4, test5), test6, test5)" => vec!["test2", "test3", "test4", "test5", "test6"])]
+    #[test_case("$\"Hello {name}, you are {age + 1}\"" => vec!["age", "name"])]
+    #[test_case("$\"{knownFunc(test8):N0} {{test9}}\"" => vec!["test8"])]
     fn prepare_expr_and_identifiers_detection(expression: &str) -> Vec<String> {
         let expr = parse_expr(expression).unwrap();
-        let mut funcs = FunctionImplList::new();
-        funcs.insert(
-            UniCase::new("knownFunc".to_string()),
-            (FunctionDeterminism::Deterministic, Rc::new(|_v: &SliceRcExpr, _: &IdentifierValues| Ok(exprresult_num!(42)))),
-        );
-        let expr = prepare_expr_and_identifiers(expr, &funcs, Rc::new(null_op));
-        let mut result = expr.identifiers_names.iter().cloned().collect::<Vec<String>>();
+        let funcs = FunctionRegistry::new().with_function("knownFunc", Deterministic, |_v: &SliceSharedExpr, _: &IdentifierValues| Ok(exprresult_num!(42)));
+        let expr = prepare_expr_and_identifiers(expr, &funcs, Arc::new(null_op), Arc::new(null_unary_op));
+        let mut result = expr.identifiers_names;
         result.sort();
         result
     }
 
     #[test]
-    fn execute_one_expression() {
-        let mut funcs = FunctionImplList::new();
-        funcs.insert(
-            UniCase::new("first".to_string()),
-            (
-                FunctionDeterminism::Deterministic,
-                Rc::new(|v: &SliceRcExpr, _: &IdentifierValues| v.first().map_or_else(|| Err("There was no first value.".to_string()), |x| Ok(ExprResult::NonExecuted(x.clone())))),
-            ),
+    fn identifiers_by_slot() {
+        let expr = prepare_expr_and_identifiers(parse_expr("b + a * b - c").unwrap(), &FunctionRegistry::builtins(), Arc::new(f_operators), Arc::new(f_unary_operators));
+        assert_eq!(expr.identifiers_names, vec!["b", "a", "c"]);
+
+        let mut values = IdentifierValues::from_slots(vec![Box::new(|| Ok(ExprResult::Int(2))), Box::new(|| Ok(ExprResult::Int(10)))]);
+        // the slots come first, the names complete them
+        values.insert("b".into(), Box::new(|| Ok(ExprResult::Int(100))));
+        values.insert("c".into(), Box::new(|| Ok(ExprResult::Int(1))));
+        assert_eq!(exec_expr(&expr.expr, &values).map(|r| r.to_string()), Ok("21".to_string()));
+    }
+
+    #[test_case(IdentifierCase::Insensitive => (vec!["Amount".to_string()], Ok("30".to_string())))]
+    #[test_case(IdentifierCase::Sensitive => (vec!["Amount".to_string(), "amount".to_string(), "AMOUNT".to_string()], Err("Unable to find value for identifier named 'Amount'".to_string())))]
+    fn identifiers_case(case: IdentifierCase) -> (Vec<String>, Result<String, String>) {
+        let expr = prepare_expr_and_identifiers_with_case(
+            parse_expr("Amount + amount * AMOUNT").unwrap(),
+            &FunctionRegistry::builtins(),
+            Arc::new(f_operators),
+            Arc::new(f_unary_operators),
+            case,
         );
+        let mut values = IdentifierValues::with_case(case);
+        values.insert("amount".into(), Box::new(|| Ok(ExprResult::Int(2))));
+        values.insert("AMOUNT".into(), Box::new(|| Ok(ExprResult::Int(5))));
+        (expr.identifiers_names, exec_expr(&expr.expr, &values).map(|r| r.to_string()).map_err(|e| e.message))
+    }
 
-        funcs.insert(
-            UniCase::new("forty_two".to_string()),
-            (FunctionDeterminism::Deterministic, Rc::new(|_v: &SliceRcExpr, _: &IdentifierValues| Ok(exprresult_num!(42)))),
+    #[test_case(IdentifierCase::Insensitive => Ok("0".to_string()))]
+    #[test_case(IdentifierCase::Sensitive => Ok("2".to_string()))]
+    fn lambda_parameters_case(case: IdentifierCase) -> Result<String, String> {
+        // the inner `X` hides the outer `x` only when the case is ignored
+        let expr = prepare_expr_and_identifiers_with_case(
+            parse_expr("items.Count(x => items.Any(X => x > X))").unwrap(),
+            &FunctionRegistry::builtins(),
+            Arc::new(f_operators),
+            Arc::new(f_unary_operators),
+            case,
         );
-        funcs.insert(
-            UniCase::new("forty_two_str".to_string()),
-            (FunctionDeterminism::Deterministic, Rc::new(|_v: &SliceRcExpr, _: &IdentifierValues| Ok(exprresult_str!(Rc::new("42".to_string()))))),
+        let items = ExprResult::Array(Rc::new(vec![ExprResult::Int(1), ExprResult::Int(2), ExprResult::Int(3)]));
+        let values = IdentifierValues::from_slots_with_case(vec![Box::new(move || Ok(items.clone()))], case);
+        exec_expr(&expr.expr, &values).map(|r| r.to_string()).map_err(|e| e.message)
+    }
+
+    #[test_case("items.Where(x => x.Price > 10).Sum(x => x.Price)", IdentifierCase::Insensitive => Ok("27.5".to_string()))]
+    #[test_case("items.OrderBy(x => x.name).Select(x => x.Name)", IdentifierCase::Insensitive => Ok("[bolt, nut, screw]".to_string()))]
+    #[test_case("items.OrderBy(x => x.name).Select(x => x.Name)", IdentifierCase::Sensitive => Err("The record '{ Name = nut, Price = 8 }' has no field 'name'".to_string()))]
+    #[test_case("items[0]", IdentifierCase::Insensitive => Ok("{ Name = nut, Price = 8 }".to_string()))]
+    #[test_case("items.Price", IdentifierCase::Insensitive => Err("The value '[{ Name = nut, Price = 8 }, { Name = screw, Price = 12.5 }, { Name = bolt, Price = 15 }]' is not a record, it has no field 'Price'".to_string()))]
+    fn record_fields(expression: &str, case: IdentifierCase) -> Result<String, String> {
+        let expr = prepare_expr_and_identifiers_with_case(parse_expr(expression).unwrap(), &FunctionRegistry::builtins(), Arc::new(f_operators), Arc::new(f_unary_operators), case);
+        let item = |name: &str, price: ExprResult| ExprResult::Record(Rc::new(vec![("Name".to_string(), exprresult_str!(Rc::new(name.to_string()))), ("Price".to_string(), price)]));
+        let items = ExprResult::Array(Rc::new(vec![item("nut", ExprResult::Int(8)), item("screw", exprresult_num!(12.5)), item("bolt", ExprResult::Int(15))]));
+        let values = IdentifierValues::from_slots_with_case(vec![Box::new(move || Ok(items.clone()))], case);
+        exec_expr(&expr.expr, &values).map(|r| r.to_string()).map_err(|e| e.message)
+    }
+
+    #[test_case("ÉTÉ" => true)]
+    #[test_case("ete" => false)]
+    fn insensitive_names(name: &str) -> bool {
+        let mut values = IdentifierValues::with_case(IdentifierCase::Insensitive);
+        values.insert("été".into(), Box::new(|| Ok(ExprResult::Null)));
+        values.get(name).is_some()
+    }
+
+    #[test_case("Left(\"abc\", 1) + Iif(true, Now(), Concat())" => Vec::<String>::new())]
+    #[test_case("Left(\"abc\")" => vec!["WrongArity 0..11 Function Left should have exactly 2 parameters"])]
+    #[test_case("1 + Unknown(x)" => vec!["UnknownFunction 4..14 Unable to find the function named 'Unknown'"])]
+    #[test_case("Iif(Unknown(), Round(1), 2)" => vec!["UnknownFunction 4..13 Unable to find the function named 'Unknown'", "WrongArity 15..23 Function Round should have exactly 2 parameters"])]
+    #[test_case("$\"{Now(1)} {In(x):N0}\"" => vec!["WrongArity 3..9 Function Now should have exactly 0 parameters", "WrongArity 12..17 Function In should have 2 parameters or more"])]
+    #[test_case("-Fixed(1, 2, true, 3) ?? DateEquals(d)" => vec!["WrongArity 1..21 Function Fixed should have between 1 and 3 parameters", "WrongArity 25..38 Function DateEquals should have between 2 and 8 parameters"])]
+    fn prepare_diagnostics(expression: &str) -> Vec<String> {
+        let expr = prepare_expr_and_identifiers(parse_expr(expression).unwrap(), &FunctionRegistry::builtins(), Arc::new(f_operators), Arc::new(f_unary_operators));
+        expr.diagnostics
+            .iter()
+            .map(|d| format!("{:?} {} {}", d.kind, d.span.map_or("?".to_string(), |s| format!("{}..{}", s.start, s.end)), d.message))
+            .collect()
+    }
+
+    #[test_case(FunctionRegistry::new().with_pack(crate::registry::FunctionPack::Math) ; "without the strings")]
+    #[test_case(FunctionRegistry::builtins().without_function("Format") ; "without format")]
+    #[test_case(FunctionRegistry::builtins().with_function("Concat", NonDeterministic, |_: &SliceSharedExpr, _: &IdentifierValues| Ok(ExprResult::Null)) ; "with another concat")]
+    fn interpolated_strings_without_the_registry(registry: FunctionRegistry) {
+        let expr = prepare_expr_and_identifiers(parse_expr("$\"a{1+1}b{x:N1}\"").unwrap(), &registry, Arc::new(f_operators), Arc::new(f_unary_operators));
+        assert_eq!(expr.diagnostics, vec![]);
+        assert_eq!(crate::types::infer_expr_type(&expr, &registry, &HashMap::new()).result_type, crate::types::ExprType::Text);
+        let values = IdentifierValues::from_slots(vec![Box::new(|| Ok(ExprResult::Int(3)))]);
+        assert_eq!(exec_expr(&expr.expr, &values).map(|r| r.to_string()).map_err(|e| e.message), Ok("a2b3.0".to_string()));
+        assert_eq!(exec_prepared_expr(&expr.compile(), &values).map(|r| r.to_string()).map_err(|e| e.message), Ok("a2b3.0".to_string()));
+    }
+
+    #[test_case(MissingIdentifier::Error => Err("Unable to find value for identifier named 'x'".to_string()))]
+    #[test_case(MissingIdentifier::Null => Ok("absent/".to_string()))]
+    #[test_case(MissingIdentifier::Default(ExprResult::Int(7)) => Ok("7/7".to_string()))]
+    fn missing_identifiers(missing: MissingIdentifier) -> Result<String, String> {
+        let expr = prepare_expr_and_identifiers(
+            parse_expr("$\"{Iif(IsNull(x), \"absent\", x)}/{x}\"").unwrap(),
+            &FunctionRegistry::builtins(),
+            Arc::new(f_operators),
+            Arc::new(f_unary_operators),
         );
+        let values = IdentifierValues::new().with_missing_identifier(missing);
+        exec_expr(&expr.expr, &values).map(|r| r.to_string()).map_err(|e| e.message)
+    }
+
+    #[test]
+    fn execute_one_expression() {
+        let funcs = FunctionRegistry::new()
+            .with_function("first", Deterministic, |v: &SliceSharedExpr, _: &IdentifierValues| {
+                v.first().map_or_else(|| Err("There was no first value.".into()), |x| Ok(ExprResult::NonExecuted(x.clone())))
+            })
+            .with_function("forty_two", Deterministic, |_v: &SliceSharedExpr, _: &IdentifierValues| Ok(exprresult_num!(42)))
+            .with_function("forty_two_str", Deterministic, |_v: &SliceSharedExpr, _: &IdentifierValues| Ok(exprresult_str!(Rc::new("42".to_string()))));
 
         let mut values = IdentifierValues::new();
-        values.insert("my".into(), Box::new(|| Rc::new("value".to_string())));
+        values.insert("my".into(), Box::new(|| Ok(exprresult_str!(Rc::new("value".to_string())))));
 
         let expression = "first(fiRst(FIRST(my,2,3),2,3),2,3)";
-        let result = parse_exec_expr(expression, &funcs, &values, Rc::new(null_op));
+        let result = parse_exec_expr(expression, &funcs, &values, Arc::new(null_op), Arc::new(null_unary_op));
         assert_eq!(result, "value");
 
         let expression = "fiRst(my,2,3) - 1";
-        let result = parse_exec_expr(expression, &funcs, &values, Rc::new(null_op));
+        let result = parse_exec_expr(expression, &funcs, &values, Arc::new(null_op), Arc::new(null_unary_op));
         assert_eq!(result, "");
     }
 
@@ -429,9 +1323,40 @@ mod tests {
     #[test_case("1-1" => "0")]
     #[test_case("1 == 1" => "true")]
     #[test_case("1 != 1" => "false")]
+    #[test_case("1 == 1.0" => "true")]
+    #[test_case("0.1 == 0.10" => "true")]
+    #[test_case("1.0 != 1" => "false")]
+    #[test_case("\"2.0\" == 2" => "false")]
+    #[test_case("AreEquals(2, 2.00)" => "true")]
     #[test_case("1/2" => "0.5")]
-    #[test_case("1-2/2" => "-0.5")]
+    #[test_case("0.1 + 0.2" => "0.3")]
+    #[test_case("Concat('a', '\\'', '\"', @\"C:\\path\"\"s\"\"\")" => "a'\"C:\\path\"s\"")]
+    #[test_case("$\"a{1 + 2}b\"" => "a3b")]
+    #[test_case("$\"{{{1}}}\"" => "{1}")]
+    #[test_case("$\"{null}|{true}\"" => "|true")]
+    #[test_case("$\"{1234.5:N1} {3.14159:F2} {42:D5} {-42:d3}\"" => "1,234.5 3.14 00042 -042")]
+    #[test_case("$\"{(1 > 2 ? \"a\" : \"b\")}{\"}\"}\"" => "b}")]
+    #[test_case("$@\"C:\\{Concat(\"dir\", 1)}\"\"\"" => "C:\\dir1\"")]
+    #[test_case("$\"{Date(\"2020-03-04\"):yyyy/MM/dd}\"" => "2020/03/04")]
+    #[test_case("9007199254740993 + 1" => "9007199254740994")]
+    #[test_case("9223372036854775807 + 1" => "9223372036854775808")]
+    #[test_case("Abs(-9223372036854775807 - 1)" => "9223372036854775808")]
+    #[test_case("3 * 0.5m" => "1.5")]
+    #[test_case("-7 % 3 == -1" => "true")]
+    #[test_case("0x10 - 1_000" => "-984")]
+    #[test_case("1-2/2" => "0")]
     #[test_case("1-(3/3)" => "0")]
+    #[test_case("1+2*3" => "7")]
+    #[test_case("(1+2)*3" => "9")]
+    #[test_case("10-4-3" => "3")]
+    #[test_case("1+2*3 == 7 && 2 > 1" => "true")]
+    #[test_case("false && true || true" => "true")]
+    #[test_case("!true" => "false")]
+    #[test_case("!IsNull(\"a\")" => "true")]
+    #[test_case("!(1 > 2) && true" => "true")]
+    #[test_case("-Abs(-3)" => "-3")]
+    #[test_case("-(1 + 2) * 2" => "-6")]
+    #[test_case("+\"4\" - -1" => "5")]
     #[test_case("1>42" => "false")]
     #[test_case("2 >= 2" => "true")]
     #[test_case("5>=2" => "true")]
@@ -459,6 +1384,9 @@ mod tests {
     #[test_case("In(true, 42, true, false)" => "true")]
     #[test_case("In(\"ok\", 42, true, \"ok\")" => "true")]
     #[test_case("In(42, 42, true, \"ok\")" => "true")]
+    #[test_case("In(2, [1, 2, 3])" => "true")]
+    #[test_case("In(\"b\", \"a\", Split(\"c,b\", \",\"))" => "true")]
+    #[test_case("In(4, new[] { 1, 2 }, 3)" => "false")]
     #[test_case("Like(42, 42)" => "true" )]
     #[test_case("ISLIKE(\"hello#world\", \"#\")" => "false" )]
     #[test_case("ISLIKE(\"hello#world\", \"%#%\")" => "true" )]
@@ -550,6 +1478,31 @@ mod tests {
     #[test_case("Split(\"a,b,c,d,e\", \",\", 2)" => "c")]
     #[test_case("Split(\"a,b,c,d,e\", \",\", 42)" => "")]
     #[test_case("Split(\"1,2,3,4,5,6,7\", \",\", 90)" => "")]
+    #[test_case("Split(\"a,b,c\", \",\")" => "[a, b, c]")]
+    #[test_case("Split(\"a,b,c\", \",\")[1]" => "b")]
+    #[test_case("Concat(\"x\", Split(\"a,b\", \",\"), [1, [2, null]])" => "xab12")]
+    #[test_case("[1, \"a\", [true]]" => "[1, a, [true]]")]
+    #[test_case("new[] { 1, 2 }[1.0] * 2" => "4")]
+    #[test_case("Where([1, 5, 12, 20], x => x > 10)" => "[12, 20]")]
+    #[test_case("[1, 5, 12, 20].Where(x => x > 10).Sum(x => x * 2)" => "64")]
+    #[test_case("Sum([1, 2], 3, \"4\")" => "10")]
+    #[test_case("-[1, -3].Sum()" => "2")]
+    #[test_case("Select(Split(\"a,bb\", \",\"), s => Len(s))" => "[1, 2]")]
+    #[test_case("\"a,b\".Split(\",\").Count()" => "2")]
+    #[test_case("[1, 2, 3, 4].Count(x => x % 2 == 0)" => "2")]
+    #[test_case("Count(null)" => "0")]
+    #[test_case("[1, 2, 3].Any(x => x > 2)" => "true")]
+    #[test_case("[].Any()" => "false")]
+    #[test_case("[1, 0].Any(x => 1 / x > 0)" => "true")]
+    #[test_case("[1, 2, 3].All(x => x > 1)" => "false")]
+    #[test_case("[3, 1, 2].First(x => x < 3)" => "1")]
+    #[test_case("[3, 1].FirstOrDefault(x => x > 3) ?? \"none\"" => "none")]
+    #[test_case("[3, 1.5, null, 2].OrderBy(x => x)" => "[, 1.5, 2, 3]")]
+    #[test_case("[\"bb\", \"c\", \"aa\"].OrderBy(s => Len(s))" => "[c, bb, aa]")]
+    #[test_case("[\"b\", \"a\", \"b\", 1, 1.0].Distinct()" => "[b, a, 1]")]
+    #[test_case("[[1, 2], [3]].Select(x => x.Count())" => "[2, 1]")]
+    #[test_case("[1, 2].Select(x => [10, 20].Select(y => x + y))" => "[[11, 21], [12, 22]]")]
+    #[test_case("[1, 2].Select(x => [10].Select(x => x))" => "[[10], [10]]")]
     #[test_case("Mid(\"abcdefghij\", 1, 2)" => "ab")]
     #[test_case("Mid(\"abcdefghij\", 2, 2)" => "bc")]
     #[test_case("Mid(\"abcdefghij\", 2, 3)" => "bcd")]
@@ -594,6 +1547,21 @@ mod tests {
     #[test_case("Xor(false, false)" => "false")]
     #[test_case("Iif(true, 42, NumberValue(\"BREAK\"))" => "42")]
     #[test_case("Iif(false, NumberValue(\"BREAK\"), 42)" => "42")]
+    #[test_case("null ?? 42" => "42")]
+    #[test_case("null ?? null ?? \"default\"" => "default")]
+    #[test_case("\"\" ?? \"default\"" => "")]
+    #[test_case("1 ?? NumberValue(\"BREAK\")" => "1")]
+    #[test_case("null ?? 1 + 2" => "3")]
+    #[test_case("(null ?? 2) * 3" => "6")]
+    #[test_case("null ?? false ? \"yes\" : \"no\"" => "no")]
+    #[test_case("true ? 42 : NumberValue(\"BREAK\")" => "42")]
+    #[test_case("false ? NumberValue(\"BREAK\") : 42" => "42")]
+    #[test_case("3 > 2 ? \"big\" : \"small\"" => "big")]
+    #[test_case("1 > 2 ? \"big\" : \"small\"" => "small")]
+    #[test_case("1 > 2 ? \"a\" : 2 > 1 ? \"b\" : \"c\"" => "b")]
+    #[test_case("true ? false ? 1 : 2 : 3" => "2")]
+    #[test_case("(false ? 1 : 2) * 10" => "20")]
+    #[test_case("Concat(\"x\", 1 == 1 ? \"y\" : \"z\")" => "xy")]
     // #[test_case("IIF(NUMBERVALUE(\"\") >= NUMBERVALUE(150), 0, 6.9)" => "6.9")]
     // #[test_case("IIF(NUMBERVALUE(\"\") < NUMBERVALUE(1), \"Hors Stock\", \"En stock\")" => "En stock")]
     #[test_case("Abs(2)" => "2")]
@@ -673,9 +1641,178 @@ mod tests {
     // #[test_case("Today()" => "---")]
     // #[test_case("Time()" => "---")]
     fn execute_some_real_world_expression(expression: &str) -> String {
-        let funcs = get_functions();
+        let funcs = FunctionRegistry::builtins();
         let op = f_operators;
-        parse_exec_expr(expression, &funcs, &IdentifierValues::new(), Rc::new(op))
+        parse_exec_expr(expression, &funcs, &IdentifierValues::new(), Arc::new(op), Arc::new(f_unary_operators))
+    }
+
+    #[test]
+    fn execute_interpolated_string() {
+        let funcs = FunctionRegistry::builtins();
+        let mut values = IdentifierValues::new();
+        values.insert("name".into(), Box::new(|| Ok(exprresult_str!(Rc::new("Ada".to_string())))));
+        values.insert("age".into(), Box::new(|| Ok(exprresult_str!(Rc::new("36".to_string())))));
+        let expression = "$\"Hello {name}, you are {age + 1}\\n\"";
+        let result = parse_exec_expr(expression, &funcs, &values, Arc::new(f_operators), Arc::new(f_unary_operators));
+        assert_eq!(result, "Hello Ada, you are 37\n");
+
+        let expr = prepare_expr_and_identifiers(parse_expr("$\"{1.5:X}\"").unwrap(), &funcs, Arc::new(f_operators), Arc::new(f_unary_operators));
+        assert_eq!(exec_expr(&expr.expr, &values).map_err(|e| e.message), Err("The format 'X' is not supported".to_string()));
+    }
+
+    #[test_case("price * qty" => "37.50")]
+    #[test_case("qty + 1" => "4")]
+    #[test_case("vip ? \"yes\" : \"no\"" => "yes")]
+    #[test_case("Year(birth) + qty" => "1993")]
+    #[test_case("nothing ?? \"none\"" => "none")]
+    #[test_case("price == 12.5" => "true")]
+    #[test_case("qty == 3.0 && price != 12" => "true")]
+    #[test_case("$\"{name} bought {qty} for {price:N1}\"" => "Ada bought 3 for 12.5")]
+    fn execute_with_typed_identifiers(expression: &str) -> String {
+        let mut values = IdentifierValues::new();
+        values.insert("name".into(), Box::new(|| Ok(exprresult_str!(Rc::new("Ada".to_string())))));
+        values.insert("price".into(), Box::new(|| Ok(ExprResult::Num(dec!(12.50)))));
+        values.insert("qty".into(), Box::new(|| Ok(ExprResult::Int(3))));
+        values.insert("vip".into(), Box::new(|| Ok(ExprResult::Boolean(true))));
+        values.insert("birth".into(), Box::new(|| Ok(ExprResult::Date(NaiveDate::from_ymd_opt(1990, 5, 6).unwrap().and_hms_opt(0, 0, 0).unwrap()))));
+        values.insert("nothing".into(), Box::new(|| Ok(ExprResult::Null)));
+        parse_exec_expr(expression, &FunctionRegistry::builtins(), &values, Arc::new(f_operators), Arc::new(f_unary_operators))
+    }
+
+    #[test_case("1 + Abs(x)" => ("Unable to find value for identifier named 'x'".to_string(), "x"))]
+    #[test_case("2 * NumberValue(\"abc\") - 1" => ("The value 'abc' is not a number.".to_string(), "NumberValue(\"abc\")"))]
+    #[test_case("true ? Left(\"s\") : 0" => ("Function Left should have exactly 2 parameters".to_string(), "Left(\"s\")"))]
+    #[test_case("$\"é {Unknown()}\"" => ("Unable to find the function named 'Unknown'".to_string(), "Unknown()"))]
+    #[test_case("1 + [3, 2][2]" => ("The index 2 is out of the 2 items".to_string(), "[3, 2][2]"))]
+    #[test_case("[3][-1]" => ("The index -1 is out of the 1 items".to_string(), "[3][-1]"))]
+    #[test_case("[3][0.5]" => ("The index 0.5 is not an integer".to_string(), "[3][0.5]"))]
+    #[test_case("Upper(\"x\")[0]" => ("The value 'X' is not an array.".to_string(), "Upper(\"x\")[0]"))]
+    #[test_case("1 + Where([1], 2)" => ("The parameter 2 of Where is not a lambda, like 'x => x > 10'".to_string(), "2"))]
+    #[test_case("[1, \"a\"].OrderBy(x => x)" => ("The values '1' and 'a' can't be compared".to_string(), "[1, \"a\"].OrderBy(x => x)"))]
+    #[test_case("[1].Select(x => x * \"a\")" => ("The value 'a' is not a number.".to_string(), "x * \"a\""))]
+    #[test_case("(x => x)" => ("A lambda is only a parameter of a function, like 'Where(items, x => x > 10)'".to_string(), "(x => x)"))]
+    fn execution_error_spans(expression: &str) -> (String, &str) {
+        let expr = prepare_expr_and_identifiers(parse_expr(expression).unwrap(), &FunctionRegistry::builtins(), Arc::new(f_operators), Arc::new(f_unary_operators));
+        let error = exec_expr(&expr.expr, &IdentifierValues::new()).unwrap_err();
+        let span = error.span.unwrap();
+        (error.message, &expression[span.start..span.end])
+    }
+
+    // past 20 items, the sort panics on keys without a total order
+    #[test]
+    fn order_by_many_mixed_keys() {
+        let items = (0..60).map(|i| if i % 3 == 0 { format!("\"t{}\"", i) } else { i.to_string() }).collect::<Vec<_>>();
+        let expression = format!("[{}].OrderBy(x => x)", items.join(", "));
+        let expr = prepare_expr_and_identifiers(parse_expr(&expression).unwrap(), &FunctionRegistry::builtins(), Arc::new(f_operators), Arc::new(f_unary_operators));
+        let error = exec_expr(&expr.expr, &IdentifierValues::new()).unwrap_err();
+        assert_eq!(error.message, "The values 't0' and '1' can't be compared");
+
+        let expression = format!("[{}].OrderBy(x => x)", (0..60).map(|i| ((i * 7) % 60).to_string()).collect::<Vec<_>>().join(", null, "));
+        let expr = prepare_expr_and_identifiers(parse_expr(&expression).unwrap(), &FunctionRegistry::builtins(), Arc::new(f_operators), Arc::new(f_unary_operators));
+        let sorted = match exec_expr(&expr.expr, &IdentifierValues::new()) {
+            Ok(ExprResult::Array(items)) => items.iter().filter(|i| **i != ExprResult::Null).map(|i| i.to_string()).collect::<Vec<_>>(),
+            result => panic!("{:?}", result.map_err(|e| e.message)),
+        };
+        assert_eq!(sorted, (0..60).map(|i| i.to_string()).collect::<Vec<_>>());
+    }
+
+    #[test_case("Concat(\"a\", \"b\")" => "Str(\"ab\")")]
+    #[test_case("Upper(\"x\")" => "Str(\"X\")")]
+    #[test_case("DateAddDays(\"2020-01-01\", 3)" => "Date(2020-01-04T00:00:00)")]
+    #[test_case("1+2*3" => "Int(7)")]
+    #[test_case("-(1.5) > 0 || !false" => "Boolean(true)")]
+    #[test_case("$\"{1.5:N2} {Upper(\"y\")}\"" => "Str(\"1.50 Y\")")]
+    #[test_case("x + Upper(\"a\")" => "PreparedIdentifier(\"x\",0)@0..1 + Str(\"A\")@4..14")]
+    #[test_case("Len(Now()) + (1 + 1)" => "PreparedFunctionCall(\"Len\",[PreparedFunctionCall(\"Now\",[])@4..9])@0..10 + Int(2)@13..20")]
+    #[test_case("Unknown(1 + 1)" => "FunctionCall(\"Unknown\",[Int(1)@8..9 + Int(1)@12..13@8..13])")]
+    #[test_case("[1 + 1, Split(\"a\", \",\")][1]" => "Array([Str(\"a\")])")]
+    #[test_case("[x, 1 + 1][0]" => "Array([PreparedIdentifier(\"x\",0)@1..2, Int(2)@4..9])@0..10[Int(0)@11..12]")]
+    #[test_case("[1, 2].Any(x => x > 1 + 1)" => "PreparedFunctionCall(\"Any\",[Array([Int(1)@1..2, Int(2)@4..5])@0..6, Lambda(\"x\",Identifier(\"x\")@16..17 > Int(2)@20..25@16..25)@11..25])")]
+    fn constant_folding(expression: &str) -> String {
+        let expr = prepare_expr_and_identifiers(parse_expr(expression).unwrap(), &FunctionRegistry::builtins(), Arc::new(f_operators), Arc::new(f_unary_operators));
+        format!("{:?}", expr.expr.without_span())
+    }
+
+    #[test_case("true ? 1 : NumberValue(\"abc\")" => Ok("1".to_string()))]
+    #[test_case("Iif(false, NumberValue(\"abc\"), 2)" => Ok("2".to_string()))]
+    #[test_case("false ? 1 : 2 * NumberValue(\"abc\")" => Err(("The value 'abc' is not a number.".to_string(), Some(Span { start: 16, end: 34 }))))]
+    #[test_case("[3, 1].First(x => x > 3)" => Err(("No item matches the predicate".to_string(), Some(Span { start: 0, end: 24 }))))]
+    #[test_case("First([])" => Err(("There is no item".to_string(), Some(Span { start: 0, end: 9 }))))]
+    #[test_case("[].FirstOrDefault()" => Ok("".to_string()))]
+    #[test_case("Round(1.5, 100)" => Err(("Couldn't round 1.5 to 100 digits".to_string(), Some(Span { start: 0, end: 15 }))))]
+    #[test_case("x ?? Left(\"abc\")" => Err(("Unable to find value for identifier named 'x'".to_string(), Some(Span { start: 0, end: 1 }))))]
+    fn constant_folding_keeps_failures(expression: &str) -> Result<String, (String, Option<Span>)> {
+        let expr = prepare_expr_and_identifiers(parse_expr(expression).unwrap(), &FunctionRegistry::builtins(), Arc::new(f_operators), Arc::new(f_unary_operators));
+        exec_expr(&expr.expr, &IdentifierValues::new()).map(|r| r.to_string()).map_err(|e| (e.message, e.span))
+    }
+
+    #[test]
+    fn constant_folding_keeps_panics() {
+        let registry = FunctionRegistry::builtins().with_function("Explode", Deterministic, |_: &SliceSharedExpr, _: &IdentifierValues| panic!("boom"));
+        let expr = prepare_expr_and_identifiers(parse_expr("true ? 1 : Explode()").unwrap(), &registry, Arc::new(f_operators), Arc::new(f_unary_operators));
+        assert_eq!(exec_expr(&expr.expr, &IdentifierValues::new()).map(|r| r.to_string()).map_err(|e| e.message), Ok("1".to_string()));
+        let expr = prepare_expr_and_identifiers(parse_expr("1 + Explode()").unwrap(), &registry, Arc::new(f_operators), Arc::new(f_unary_operators));
+        let error = exec_expr(&expr.expr, &IdentifierValues::new()).unwrap_err();
+        assert_eq!(error.message, "Unexpected failure : boom");
+        assert_eq!(error.span, Some(Span { start: 4, end: 13 }));
+    }
+
+    #[test]
+    fn lambda_parameters() {
+        let expression = "Concat(items.Where(x => X > min).Select(x => x * factor), \"/\", x)";
+        let expr = prepare_expr_and_identifiers(parse_expr(expression).unwrap(), &FunctionRegistry::builtins(), Arc::new(f_operators), Arc::new(f_unary_operators));
+        assert_eq!(expr.identifiers_names, vec!["items", "min", "factor", "x"]);
+
+        let items = ExprResult::Array(Rc::new(vec![ExprResult::Int(1), ExprResult::Int(5), ExprResult::Int(12)]));
+        let mut values = IdentifierValues::new();
+        values.insert("items".into(), Box::new(move || Ok(items.clone())));
+        values.insert("min".into(), Box::new(|| Ok(ExprResult::Int(4))));
+        values.insert("factor".into(), Box::new(|| Ok(ExprResult::Int(10))));
+        values.insert("x".into(), Box::new(|| Ok(ExprResult::Int(7))));
+        assert_eq!(exec_expr(&expr.expr, &values).map(|r| r.to_string()).map_err(|e| e.message), Ok("50120/7".to_string()));
+        assert_eq!(values.parameter("x"), None);
+
+        // a host function can take a lambda too
+        let registry = FunctionRegistry::builtins().with_function("ApplyTwice", Deterministic, |params: &SliceSharedExpr, values: &IdentifierValues| {
+            let (parameter, body) = match params[1].without_span() {
+                Expr::Lambda(parameter, body) => (parameter, body),
+                _ => return Err("not a lambda".into()),
+            };
+            let once = values.with_parameter(parameter, exec_expr(&params[0], values)?, || exec_expr(body, values))?;
+            values.with_parameter(parameter, once, || exec_expr(body, values))
+        });
+        assert_eq!(
+            parse_exec_expr("ApplyTwice(3, n => n * n)", &registry, &IdentifierValues::new(), Arc::new(f_operators), Arc::new(f_unary_operators)),
+            "81"
+        );
+    }
+
+    #[test]
+    fn execute_from_many_threads() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+        let registry = FunctionRegistry::builtins().with_function("Twice", Deterministic, |params: &SliceSharedExpr, values: &IdentifierValues| {
+            let value = exec_expr(&params[0], values)?;
+            Ok(exprresult_str!(Rc::new(format!("{}{}", value, value))))
+        });
+        assert_send_sync(&registry);
+        let expr = prepare_expr_and_identifiers(parse_expr("$\"{Twice(Upper(name))}-{row * 2}\"").unwrap(), &registry, Arc::new(f_operators), Arc::new(f_unary_operators));
+        assert_send_sync(&expr);
+
+        let expr = Arc::new(expr);
+        let threads = (0..8)
+            .map(|row| {
+                let expr = Arc::clone(&expr);
+                std::thread::spawn(move || {
+                    let mut values = IdentifierValues::new();
+                    values.insert("name".into(), Box::new(move || Ok(exprresult_str!(Rc::new(format!("t{}", row))))));
+                    values.insert("row".into(), Box::new(move || Ok(ExprResult::Int(row))));
+                    exec_expr(&expr.expr, &values).map(|r| r.to_string()).map_err(|e| e.message)
+                })
+            })
+            .collect::<Vec<_>>();
+        for (row, thread) in threads.into_iter().enumerate() {
+            assert_eq!(thread.join().unwrap(), Ok(format!("T{0}T{0}-{1}", row, row * 2)));
+        }
     }
 
     #[test]
@@ -691,12 +1828,12 @@ mod tests {
     }
 
     fn parse_exec_expr_with_defaults<'a>(expression: &'a str) -> String {
-        parse_exec_expr(expression, &get_functions(), &IdentifierValues::new(), Rc::new(f_operators))
+        parse_exec_expr(expression, &FunctionRegistry::builtins(), &IdentifierValues::new(), Arc::new(f_operators), Arc::new(f_unary_operators))
     }
 
-    fn parse_exec_expr<'a>(expression: &'a str, funcs: &FunctionImplList, values: &IdentifierValues, operators: BinaryOperatorsImplRc) -> String {
+    fn parse_exec_expr<'a>(expression: &'a str, funcs: &FunctionRegistry, values: &IdentifierValues, operators: SharedBinaryOperatorsImpl, unary_operators: SharedUnaryOperatorsImpl) -> String {
         let expr = parse_expr(expression).unwrap();
-        let expr = prepare_expr_and_identifiers(expr, funcs, operators);
+        let expr = prepare_expr_and_identifiers(expr, funcs, operators, unary_operators);
         let result = exec_expr(&expr.expr, values).unwrap();
         result.to_string()
     }
@@ -736,7 +1873,7 @@ mod tests {
     #[test_case("Upper(\"\") + 2" => true)]
     fn deterministic_or_not(expression: &str) -> bool {
         let expr = parse_expr(expression).unwrap();
-        let expr = prepare_expr_and_identifiers(expr, &get_functions(), Rc::new(null_op));
+        let expr = prepare_expr_and_identifiers(expr, &FunctionRegistry::builtins(), Arc::new(null_op), Arc::new(null_unary_op));
         expr.determinism == Deterministic
     }
 
@@ -745,16 +1882,20 @@ mod tests {
         assert_eq!(AssocOp::Multiply, AssocOp::Multiply);
         assert_eq!(Expr::Num(dec!(1)), Expr::Num(dec!(1)));
         assert_eq!(
-            Expr::BinaryOperator(RcExpr::new(Expr::Num(dec!(3))), RcExpr::new(Expr::Num(dec!(5))), AssocOp::Divide),
-            Expr::BinaryOperator(RcExpr::new(Expr::Num(dec!(3))), RcExpr::new(Expr::Num(dec!(5))), AssocOp::Divide)
+            Expr::BinaryOperator(SharedExpr::new(Expr::Num(dec!(3))), SharedExpr::new(Expr::Num(dec!(5))), AssocOp::Divide),
+            Expr::BinaryOperator(SharedExpr::new(Expr::Num(dec!(3))), SharedExpr::new(Expr::Num(dec!(5))), AssocOp::Divide)
         );
     }
 
-    fn null_op(l: RcExpr, r: RcExpr, op: AssocOp, _: &IdentifierValues) -> ExprFuncResult {
+    fn null_op(l: SharedExpr, r: SharedExpr, op: AssocOp, _: &IdentifierValues) -> ExprFuncResult {
         dbg!(l, op, r, "RETURNS Null (null_op)");
         Ok(ExprResult::Null)
     }
 
+    fn null_unary_op(_: SharedExpr, _: UnaryOp, _: &IdentifierValues) -> ExprFuncResult {
+        Ok(ExprResult::Null)
+    }
+
     // PLEASE TEST WITH:
     //  cargo test --release -- --nocapture fast_try_thousands
     // PERFORMANCES ARE ABOUT 7 times better than :
@@ -764,14 +1905,14 @@ mod tests {
     fn fast_try_thousands() {
         let s = "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
         let expr = parse_expr("test").unwrap();
-        let expr = prepare_expr_and_identifiers(expr, &get_functions(), Rc::new(f_operators));
+        let expr = prepare_expr_and_identifiers(expr, &FunctionRegistry::builtins(), Arc::new(f_operators), Arc::new(f_unary_operators));
         let now = Instant::now();
 
         for i in 0..1000000 {
             let mut values = IdentifierValues::new();
             let test_value = format!("{}{}", s, i);
             // let test_value2 = test_value.clone();
-            values.insert("test".into(), Box::new(move || Rc::new(format!("{}{}", s, i))));
+            values.insert("test".into(), Box::new(move || Ok(exprresult_str!(Rc::new(format!("{}{}", s, i))))));
             let result = exec_expr(&expr.expr, &values).unwrap();
             assert_eq!(result.to_string(), test_value);
         }
@@ -1,12 +1,20 @@
 use crate::expressions::*;
+use crate::registry::{FunctionMetadata, FunctionRegistry, RegisteredFunction};
+use crate::types::{infer_expr_type, ExprType, FunctionSignature, ReturnType};
 
+use chrono::{Duration, NaiveDate, NaiveDateTime};
 use once_cell::sync::Lazy;
-use std::cell::RefCell;
-use std::ffi::{CStr, CString};
+use rust_decimal::Decimal;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ffi::{c_void, CStr, CString};
 use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
 use std::ptr;
 use std::rc::Rc;
 use std::slice;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::vec::Vec;
 use unicase::UniCase;
 
@@ -18,22 +26,38 @@ fn str_from_c_char_ptr<'a>(s: *const c_char) -> Result<&'a str, std::str::Utf8Er
     .to_str()
 }
 
-fn string_from_c_char_ptr(s: *const c_char) -> Result<String, std::str::Utf8Error> {
-    Ok(str_from_c_char_ptr(s)?.to_string())
-}
-
 static UTF16: Lazy<&'static encoding_rs::Encoding> = Lazy::new(|| {
     let encoding = encoding_rs::Encoding::for_label("UTF-16".as_bytes()).unwrap();
     encoding
 });
 
-fn string_from_csharp(s: *const c_char) -> String {
-    let c_str = unsafe {
-        assert!(!s.is_null());
-        CStr::from_ptr(s)
-    };
-    let r_str = c_str.to_str().unwrap();
-    r_str.into()
+// The pointers given by the host are checked before being read, a null one is an error named after the exported function
+fn expr_from_ptr<'a>(function_name: &str, ptr: *mut ExprAndIdentifiers) -> Result<&'a ExprAndIdentifiers, String> {
+    unsafe { ptr.as_ref() }.ok_or_else(|| format!("{}: null expression handle", function_name))
+}
+
+fn str_from_ptr<'a>(function_name: &str, s: *const c_char, what: &str) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err(format!("{}: null {}", function_name, what));
+    }
+    unsafe { CStr::from_ptr(s) }.to_str().map_err(|e| format!("{}: the {} is not valid UTF-8 ({})", function_name, what, e))
+}
+
+// an empty slice may come without its pointer
+fn slice_from_ptr<'a, T>(function_name: &str, items: *const T, items_len: usize, what: &str) -> Result<&'a [T], String> {
+    if items_len == 0 {
+        return Ok(&[]);
+    }
+    if items.is_null() {
+        return Err(format!("{}: null {}", function_name, what));
+    }
+    Ok(unsafe { slice::from_raw_parts(items, items_len) })
+}
+
+// A panic must not unwind across the FFI, it's caught and turned into an error message for the host.
+// The state touched by the failing call may be left inconsistent, but it's only the one of this call.
+fn catch_panic<T, F: FnOnce() -> T>(function_name: &str, f: F) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| format!("Unexpected failure in {} : {}", function_name, panic_reason(payload.as_ref())))
 }
 
 #[repr(C)]
@@ -44,58 +68,88 @@ pub struct FFIParseResult {
     content: *mut ExprAndIdentifiers,
 }
 
+// The prepared expression can be executed from many threads at once, until it is freed with `ffi_free_expr`
 #[no_mangle]
 extern "C" fn ffi_parse_and_prepare_expr(expression: *const c_char) -> FFIParseResult {
-    let r_str = string_from_csharp(expression);
-    let result = parse_expr(&r_str);
-    match result {
+    parse_and_prepare_expr("ffi_parse_and_prepare_expr", expression, 0)
+}
+
+// the options of `ffi_parse_and_prepare_expr_with_options`, to combine
+pub const PREPARE_CASE_SENSITIVE_IDENTIFIERS: u32 = 1;
+// rejects the expressions calling unknown functions or with a wrong parameters count, instead of failing when executed
+pub const PREPARE_STRICT: u32 = 2;
+// compiles the expression to a bytecode, for the expressions executed many times, see `bytecode::compile_expr`
+pub const PREPARE_COMPILED: u32 = 4;
+
+#[no_mangle]
+extern "C" fn ffi_parse_and_prepare_expr_with_options(expression: *const c_char, options: u32) -> FFIParseResult {
+    parse_and_prepare_expr("ffi_parse_and_prepare_expr_with_options", expression, options)
+}
+
+// `function_name` is the entry point, for the errors
+fn parse_and_prepare_expr(function_name: &str, expression: *const c_char, options: u32) -> FFIParseResult {
+    let result = catch_panic(function_name, || {
+        let r_str = str_from_ptr(function_name, expression, "expression")?;
+        let expr = parse_expr(r_str).map_err(|err| err.to_string())?;
+        let funcs = registry_with_host_functions();
+        let identifier_case = if options & PREPARE_CASE_SENSITIVE_IDENTIFIERS != 0 {
+            IdentifierCase::Sensitive
+        } else {
+            IdentifierCase::Insensitive
+        };
+        let prepared = prepare_expr_and_identifiers_with_case(expr, &funcs, Arc::new(crate::functions::f_operators), Arc::new(crate::functions::f_unary_operators), identifier_case);
+        if options & PREPARE_STRICT != 0 && !prepared.diagnostics.is_empty() {
+            return Err(prepared.diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("; "));
+        }
+        Ok(if options & PREPARE_COMPILED != 0 { prepared.compile() } else { prepared })
+    });
+    match result.and_then(|r| r) {
         Err(err) => FFIParseResult {
             is_error: true,
-            error: CString::new(err).unwrap().into_raw(),
+            error: c_string_lossy(err).into_raw(),
             content: ptr::null_mut(),
         },
-        Ok(expr) => {
-            let funcs = crate::functions::get_functions();
-            let expr = prepare_expr_and_identifiers(expr, &funcs, Rc::new(crate::functions::f_operators));
-            FFIParseResult {
-                is_error: false,
-                error: ptr::null_mut(),
-                content: Box::into_raw(Box::new(expr)),
-            }
-        }
+        Ok(expr) => FFIParseResult {
+            is_error: false,
+            error: ptr::null_mut(),
+            content: Box::into_raw(Box::new(expr)),
+        },
     }
 }
 
+// The names separated by `|`, in the order of their slots for `ffi_exec_expr_slots`. Null on failure.
 #[no_mangle]
 extern "C" fn ffi_get_identifiers(ptr: *mut ExprAndIdentifiers) -> *mut c_char {
-    let expr = unsafe {
-        assert!(!ptr.is_null());
-        &mut *ptr
-    };
+    catch_panic("ffi_get_identifiers", || {
+        let expr = expr_from_ptr("ffi_get_identifiers", ptr)?;
 
-    let identifiers_separated = expr.identifiers_names.iter().cloned().collect::<Vec<String>>().join("|");
-    let c_str_result = CString::new(identifiers_separated).unwrap();
-    c_str_result.into_raw()
+        let identifiers_separated = expr.identifiers_names.join("|");
+        Ok(c_string_lossy(identifiers_separated).into_raw())
+    })
+    .and_then(|r: Result<_, String>| r)
+    .unwrap_or(ptr::null_mut())
 }
 
+// false on failure, as it's never wrong to execute the expression again
 #[no_mangle]
 extern "C" fn ffi_is_deterministic(ptr: *mut ExprAndIdentifiers) -> bool {
-    let expr = unsafe {
-        assert!(!ptr.is_null());
-        &mut *ptr
-    };
+    catch_panic("ffi_is_deterministic", || {
+        let expr = expr_from_ptr("ffi_is_deterministic", ptr)?;
 
-    match expr.determinism {
-        FunctionDeterminism::Deterministic => true,
-        FunctionDeterminism::NonDeterministic => false,
-    }
+        match expr.determinism {
+            FunctionDeterminism::Deterministic => Ok(true),
+            FunctionDeterminism::NonDeterministic => Ok(false),
+        }
+    })
+    .and_then(|r: Result<_, String>| r)
+    .unwrap_or(false)
 }
 
 #[repr(C)]
 #[derive(Debug)]
 pub struct IdentifierKeyValue {
     key: *const c_char,
-    value: *const c_char,
+    value: FFIValue,
 }
 
 #[repr(C)]
@@ -103,63 +157,72 @@ pub struct IdentifierKeyValue {
 pub struct FFIExecResult {
     is_error: bool,
     content: *mut c_char,
+    // the failing part of the expression, as UTF-8 bytes offsets, both are 0 when unknown
+    error_start: usize,
+    error_end: usize,
 }
 
-struct IdentifierStringValueLazyGetter {
-    value_ptr: *const c_char,
-    value: Option<Rc<String>>,
+struct IdentifierValueLazyGetter {
+    ffi_value: FFIValue,
+    value: Option<ExprResult>,
 }
 
-impl IdentifierStringValueLazyGetter {
-    fn new(value_ptr: *const c_char) -> IdentifierStringValueLazyGetter {
-        IdentifierStringValueLazyGetter { value_ptr, value: None }
+impl IdentifierValueLazyGetter {
+    fn new(ffi_value: FFIValue) -> IdentifierValueLazyGetter {
+        IdentifierValueLazyGetter { ffi_value, value: None }
     }
 
-    fn get_value(&mut self) -> Rc<String> {
+    fn get_value(&mut self) -> ExprFuncResult {
         match &self.value {
-            Some(rc) => rc.clone(),
+            Some(value) => Ok(value.clone()),
             None => {
-                let s = string_from_csharp(self.value_ptr);
-                let rc = Rc::new(s);
-                self.value = Some(rc.clone());
-                rc
+                let value = self.ffi_value.to_result()?;
+                self.value = Some(value.clone());
+                Ok(value)
             }
         }
     }
 }
 
-#[no_mangle]
-extern "C" fn ffi_exec_expr(ptr: *mut ExprAndIdentifiers, identifier_values: *const IdentifierKeyValue, identifier_values_len: usize) -> FFIExecResult {
-    let expr = unsafe {
-        assert!(!ptr.is_null());
-        &mut *ptr
-    };
-
-    let vals = unsafe {
-        assert!(!identifier_values.is_null());
-        slice::from_raw_parts(identifier_values, identifier_values_len)
-    };
+fn identifier_values_from_ffi(function_name: &str, identifier_values: *const IdentifierKeyValue, identifier_values_len: usize, case: IdentifierCase) -> Result<IdentifierValues, String> {
+    let vals = slice_from_ptr(function_name, identifier_values, identifier_values_len, "identifier values")?;
 
-    let mut values = IdentifierValues::new();
+    let mut values = IdentifierValues::with_case(case);
     for ikv in vals.iter() {
-        let k = string_from_c_char_ptr(ikv.key).unwrap();
-        let lazy_getter = IdentifierStringValueLazyGetter::new(ikv.value);
+        let k = str_from_ptr(function_name, ikv.key, "identifier name")?.to_string();
+        let lazy_getter = IdentifierValueLazyGetter::new(ikv.value);
         let lazy_refcell = RefCell::new(lazy_getter);
         let get_v = Box::new(move || lazy_refcell.borrow_mut().get_value());
         values.insert(k, get_v);
     }
+    Ok(values)
+}
+
+#[no_mangle]
+extern "C" fn ffi_exec_expr(ptr: *mut ExprAndIdentifiers, identifier_values: *const IdentifierKeyValue, identifier_values_len: usize) -> FFIExecResult {
+    let result = catch_panic("ffi_exec_expr", || {
+        let expr = expr_from_ptr("ffi_exec_expr", ptr)?;
 
-    let result = exec_expr(&expr.expr, &mut values);
+        let values = identifier_values_from_ffi("ffi_exec_expr", identifier_values, identifier_values_len, expr.identifier_case)?;
+        exec_prepared_expr(expr, &values)
+    });
 
-    match result {
+    match result.unwrap_or_else(|message| Err(message.into())) {
         Ok(r) => FFIExecResult {
             is_error: false,
-            content: CString::new(r.to_string()).unwrap().into_raw(),
-        },
-        Err(e) => FFIExecResult {
-            is_error: true,
-            content: CString::new(e).unwrap().into_raw(),
+            content: c_string_lossy(r.to_string()).into_raw(),
+            error_start: 0,
+            error_end: 0,
         },
+        Err(e) => {
+            let span = e.span.unwrap_or_default();
+            FFIExecResult {
+                is_error: true,
+                content: c_string_lossy(e.message).into_raw(),
+                error_start: span.start,
+                error_end: span.end,
+            }
+        }
     }
 }
 
@@ -168,9 +231,10 @@ extern "C" fn ffi_free_expr(ptr: *mut ExprAndIdentifiers) {
     if ptr.is_null() {
         return;
     }
-    unsafe {
-        Box::from_raw(ptr);
-    }
+    // nothing to report to the host, the expression is gone anyway
+    let _ = catch_panic("ffi_free_expr", || unsafe {
+        drop(Box::from_raw(ptr));
+    });
 }
 
 #[no_mangle]
@@ -178,62 +242,1559 @@ extern "C" fn ffi_free_cstring(ptr: *mut c_char) {
     if ptr.is_null() {
         return;
     }
-    unsafe { CString::from_raw(ptr) };
+    let _ = catch_panic("ffi_free_cstring", || unsafe {
+        drop(CString::from_raw(ptr));
+    });
 }
 
 // =========================================
+//              TYPED VALUES
 // =========================================
-//                  TESTING
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FFIValueKind {
+    Null = 0,
+    Str = 1,
+    Boolean = 2,
+    Int = 3,
+    Num = 4,
+    Date = 5,
+    TimeSpan = 6,
+    Array = 7,
+    Record = 8,
+}
+
+impl TryFrom<u32> for FFIValueKind {
+    type Error = String;
+
+    fn try_from(kind: u32) -> Result<Self, Self::Error> {
+        match kind {
+            0 => Ok(FFIValueKind::Null),
+            1 => Ok(FFIValueKind::Str),
+            2 => Ok(FFIValueKind::Boolean),
+            3 => Ok(FFIValueKind::Int),
+            4 => Ok(FFIValueKind::Num),
+            5 => Ok(FFIValueKind::Date),
+            6 => Ok(FFIValueKind::TimeSpan),
+            7 => Ok(FFIValueKind::Array),
+            8 => Ok(FFIValueKind::Record),
+            _ => Err(format!("Unknown value kind {}", kind)),
+        }
+    }
+}
+
+// A tagged value, only the fields of its kind are meaningful :
+// `boolean` for Boolean, `int` for Int and for the .net ticks of Date and TimeSpan, `text` for Str,
+// the `decimal_*` parts for Num, as given by the .net `decimal.GetBits`, and `items` for Array.
+// The `items` of a Record are its fields, each one as its name, a Str, followed by its value.
+// The kind is one of `FFIValueKind`, kept as a number as any value can come from the host.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct FFIValue {
+    kind: u32,
+    boolean: bool,
+    int: i64,
+    decimal_lo: u32,
+    decimal_mid: u32,
+    decimal_hi: u32,
+    decimal_flags: u32,
+    text: *const c_char,
+    items: *const FFIValue,
+    items_len: usize,
+}
+
+impl FFIValue {
+    fn new(kind: FFIValueKind) -> FFIValue {
+        FFIValue {
+            kind: kind as u32,
+            boolean: false,
+            int: 0,
+            decimal_lo: 0,
+            decimal_mid: 0,
+            decimal_hi: 0,
+            decimal_flags: 0,
+            text: ptr::null(),
+            items: ptr::null(),
+            items_len: 0,
+        }
+    }
+
+    // the text and the items, if any, are not set
+    fn from_result(result: &ExprResult) -> Result<FFIValue, String> {
+        let value = match result {
+            ExprResult::Null => FFIValue::new(FFIValueKind::Null),
+            ExprResult::Str(_) | ExprResult::NonExecuted(_) => FFIValue::new(FFIValueKind::Str),
+            ExprResult::Boolean(b) => FFIValue {
+                boolean: *b,
+                ..FFIValue::new(FFIValueKind::Boolean)
+            },
+            ExprResult::Int(i) => FFIValue {
+                int: *i,
+                ..FFIValue::new(FFIValueKind::Int)
+            },
+            ExprResult::Num(n) => {
+                let mantissa = n.mantissa().unsigned_abs();
+                FFIValue {
+                    decimal_lo: mantissa as u32,
+                    decimal_mid: (mantissa >> 32) as u32,
+                    decimal_hi: (mantissa >> 64) as u32,
+                    decimal_flags: (n.scale() << 16) | if n.is_sign_negative() { DECIMAL_SIGN_MASK } else { 0 },
+                    ..FFIValue::new(FFIValueKind::Num)
+                }
+            }
+            ExprResult::Date(d) => FFIValue {
+                int: duration_to_ticks(d.signed_duration_since(*DOTNET_EPOCH)).ok_or_else(|| format!("The date '{}' is out of the .net range", result))?,
+                ..FFIValue::new(FFIValueKind::Date)
+            },
+            ExprResult::TimeSpan(t) => FFIValue {
+                int: duration_to_ticks(*t).ok_or_else(|| format!("The time span '{}' is out of the .net range", result))?,
+                ..FFIValue::new(FFIValueKind::TimeSpan)
+            },
+            ExprResult::Array(_) => FFIValue::new(FFIValueKind::Array),
+            ExprResult::Record(_) => FFIValue::new(FFIValueKind::Record),
+        };
+        Ok(value)
+    }
+
+    // with its text and items, to free with `ffi_free_value`
+    fn into_raw(result: &ExprResult) -> Result<FFIValue, String> {
+        match result {
+            ExprResult::Array(_) | ExprResult::Record(_) => {
+                let items = result_items(result);
+                let mut raw_items = Vec::with_capacity(items.len());
+                for item in items.iter() {
+                    match FFIValue::into_raw(item) {
+                        Ok(raw_item) => raw_items.push(raw_item),
+                        Err(e) => {
+                            raw_items.into_iter().for_each(|raw_item| unsafe { raw_item.free() });
+                            return Err(e);
+                        }
+                    }
+                }
+                let value = FFIValue::from_result(result)?;
+                let items = raw_items.into_boxed_slice();
+                Ok(FFIValue {
+                    items_len: items.len(),
+                    items: Box::into_raw(items) as *const FFIValue,
+                    ..value
+                })
+            }
+            _ => Ok(FFIValue {
+                text: result_text(result).map_or(ptr::null(), |t| t.into_raw()),
+                ..FFIValue::from_result(result)?
+            }),
+        }
+    }
+
+    // only for the values made by `into_raw`
+    unsafe fn free(self) {
+        if !self.text.is_null() {
+            drop(CString::from_raw(self.text as *mut c_char));
+        }
+        if !self.items.is_null() {
+            let items = Box::from_raw(ptr::slice_from_raw_parts_mut(self.items as *mut FFIValue, self.items_len));
+            for item in items.iter() {
+                item.free();
+            }
+        }
+    }
+
+    fn to_result(self) -> Result<ExprResult, String> {
+        match FFIValueKind::try_from(self.kind)? {
+            FFIValueKind::Null => Ok(ExprResult::Null),
+            FFIValueKind::Str if self.text.is_null() => Err("The string value has no text".to_string()),
+            FFIValueKind::Str => Ok(ExprResult::Str(Rc::new(str_from_c_char_ptr(self.text).map_err(|e| e.to_string())?.to_string()))),
+            FFIValueKind::Boolean => Ok(ExprResult::Boolean(self.boolean)),
+            FFIValueKind::Int => Ok(ExprResult::Int(self.int)),
+            FFIValueKind::Num => {
+                let scale = (self.decimal_flags >> 16) & 0xFF;
+                if scale > 28 {
+                    return Err(format!("The decimal scale {} is greater than 28", scale));
+                }
+                let negative = self.decimal_flags & DECIMAL_SIGN_MASK != 0;
+                Ok(ExprResult::Num(Decimal::from_parts(self.decimal_lo, self.decimal_mid, self.decimal_hi, negative, scale)))
+            }
+            FFIValueKind::Date => Ok(ExprResult::Date(*DOTNET_EPOCH + ticks_to_duration(self.int))),
+            FFIValueKind::TimeSpan => Ok(ExprResult::TimeSpan(ticks_to_duration(self.int))),
+            FFIValueKind::Array if self.items.is_null() && self.items_len > 0 => Err("The array value has no items".to_string()),
+            FFIValueKind::Array if self.items_len == 0 => Ok(ExprResult::Array(Rc::new(Vec::new()))),
+            FFIValueKind::Array => {
+                let items = unsafe { slice::from_raw_parts(self.items, self.items_len) };
+                Ok(ExprResult::Array(Rc::new(items.iter().map(|item| item.to_result()).collect::<Result<_, _>>()?)))
+            }
+            FFIValueKind::Record if self.items.is_null() && self.items_len > 0 => Err("The record value has no fields".to_string()),
+            FFIValueKind::Record if !self.items_len.is_multiple_of(2) => Err("The record value should have a name and a value for each field".to_string()),
+            FFIValueKind::Record if self.items_len == 0 => Ok(ExprResult::Record(Rc::new(Vec::new()))),
+            FFIValueKind::Record => {
+                let items = unsafe { slice::from_raw_parts(self.items, self.items_len) };
+                let fields = items
+                    .chunks(2)
+                    .map(|field| match field[0].to_result()? {
+                        ExprResult::Str(name) => Ok((name.to_string(), field[1].to_result()?)),
+                        name => Err(format!("The name of a record field should be a text, not '{}'", name)),
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(ExprResult::Record(Rc::new(fields)))
+            }
+        }
+    }
+}
+
+const DECIMAL_SIGN_MASK: u32 = 0x8000_0000;
+const TICKS_PER_SECOND: i64 = 10_000_000;
+const NANOSECONDS_PER_TICK: i64 = 100;
+
+// the .net ticks of the dates start at 0001-01-01
+static DOTNET_EPOCH: Lazy<NaiveDateTime> = Lazy::new(|| NaiveDate::from_ymd_opt(1, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap());
+
+// None beyond the .net range, about 29,000 years
+fn duration_to_ticks(duration: Duration) -> Option<i64> {
+    let seconds = duration.num_seconds();
+    let nanoseconds = (duration - Duration::seconds(seconds)).num_nanoseconds().unwrap_or(0);
+    seconds.checked_mul(TICKS_PER_SECOND)?.checked_add(nanoseconds / NANOSECONDS_PER_TICK)
+}
+
+fn ticks_to_duration(ticks: i64) -> Duration {
+    Duration::seconds(ticks / TICKS_PER_SECOND) + Duration::nanoseconds((ticks % TICKS_PER_SECOND) * NANOSECONDS_PER_TICK)
+}
+
+// an inner nul byte cannot cross the FFI, the text is cut there
+fn c_string_lossy(text: String) -> CString {
+    CString::new(text).unwrap_or_else(|e| {
+        let nul_position = e.nul_position();
+        let mut bytes = e.into_vec();
+        bytes.truncate(nul_position);
+        CString::new(bytes).unwrap()
+    })
+}
+
+// the items of an array, or the fields of a record as their names followed by their values
+fn result_items(result: &ExprResult) -> Vec<ExprResult> {
+    match result {
+        ExprResult::Array(items) => items.to_vec(),
+        ExprResult::Record(fields) => fields.iter().flat_map(|(name, value)| vec![ExprResult::Str(Rc::new(name.clone())), value.clone()]).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn result_text(result: &ExprResult) -> Option<CString> {
+    match result {
+        ExprResult::Str(s) => Some(c_string_lossy(s.to_string())),
+        ExprResult::NonExecuted(_) => Some(c_string_lossy(result.to_string())),
+        _ => None,
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct FFIExecValueResult {
+    is_error: bool,
+    // the message of the error, to free with `ffi_free_cstring`
+    error: *mut c_char,
+    // the text and the items of the value, if any, are to free with `ffi_free_value`
+    value: FFIValue,
+    // the failing part of the expression, as UTF-8 bytes offsets, both are 0 when unknown
+    error_start: usize,
+    error_end: usize,
+}
+
+// Same as `ffi_exec_expr`, but the result keeps its type
+#[no_mangle]
+extern "C" fn ffi_exec_expr_value(ptr: *mut ExprAndIdentifiers, identifier_values: *const IdentifierKeyValue, identifier_values_len: usize) -> FFIExecValueResult {
+    exec_value("ffi_exec_expr_value", ptr, identifier_values, identifier_values_len, FFIMissingIdentifier::error())
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FFIMissingIdentifierPolicy {
+    Error = 0,
+    Null = 1,
+    Default = 2,
+}
+
+impl TryFrom<u32> for FFIMissingIdentifierPolicy {
+    type Error = String;
+
+    // `Self::Error` would be the Error policy
+    fn try_from(policy: u32) -> Result<Self, String> {
+        match policy {
+            0 => Ok(FFIMissingIdentifierPolicy::Error),
+            1 => Ok(FFIMissingIdentifierPolicy::Null),
+            2 => Ok(FFIMissingIdentifierPolicy::Default),
+            _ => Err(format!("Unknown missing identifier policy {}", policy)),
+        }
+    }
+}
+
+// What an identifier without value gives, `default_value` is only read by the Default policy.
+// The policy is one of `FFIMissingIdentifierPolicy`, kept as a number as any value can come from the host.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct FFIMissingIdentifier {
+    policy: u32,
+    default_value: FFIValue,
+}
+
+impl FFIMissingIdentifier {
+    // the policy of the functions without `missing`
+    fn error() -> FFIMissingIdentifier {
+        FFIMissingIdentifier {
+            policy: FFIMissingIdentifierPolicy::Error as u32,
+            default_value: FFIValue::new(FFIValueKind::Null),
+        }
+    }
+
+    fn to_missing_identifier(self) -> Result<MissingIdentifier, String> {
+        match FFIMissingIdentifierPolicy::try_from(self.policy)? {
+            FFIMissingIdentifierPolicy::Error => Ok(MissingIdentifier::Error),
+            FFIMissingIdentifierPolicy::Null => Ok(MissingIdentifier::Null),
+            FFIMissingIdentifierPolicy::Default => Ok(MissingIdentifier::Default(self.default_value.to_result()?)),
+        }
+    }
+}
+
+// Same as `ffi_exec_expr_value`, an identifier without value gives what `missing` says
+#[no_mangle]
+extern "C" fn ffi_exec_expr_value_with_missing(ptr: *mut ExprAndIdentifiers, identifier_values: *const IdentifierKeyValue, identifier_values_len: usize, missing: FFIMissingIdentifier) -> FFIExecValueResult {
+    exec_value("ffi_exec_expr_value_with_missing", ptr, identifier_values, identifier_values_len, missing)
+}
+
+fn exec_value(function_name: &str, ptr: *mut ExprAndIdentifiers, identifier_values: *const IdentifierKeyValue, identifier_values_len: usize, missing: FFIMissingIdentifier) -> FFIExecValueResult {
+    let result = catch_panic(function_name, || {
+        let expr = expr_from_ptr(function_name, ptr)?;
+
+        let missing = missing.to_missing_identifier()?;
+        let values = identifier_values_from_ffi(function_name, identifier_values, identifier_values_len, expr.identifier_case)?.with_missing_identifier(missing);
+        exec_prepared_expr(expr, &values)
+    });
+    FFIExecValueResult::new(result.unwrap_or_else(|message| Err(message.into())))
+}
+
+// Same as `ffi_exec_expr_value`, but the identifier values come without their names, one per slot,
+// in the order given by `ffi_get_identifiers`
+#[no_mangle]
+extern "C" fn ffi_exec_expr_slots(ptr: *mut ExprAndIdentifiers, slot_values: *const FFIValue, slot_values_len: usize) -> FFIExecValueResult {
+    exec_slots("ffi_exec_expr_slots", ptr, slot_values, slot_values_len, FFIMissingIdentifier::error())
+}
+
+// Same as `ffi_exec_expr_slots`, an identifier without value gives what `missing` says
+#[no_mangle]
+extern "C" fn ffi_exec_expr_slots_with_missing(ptr: *mut ExprAndIdentifiers, slot_values: *const FFIValue, slot_values_len: usize, missing: FFIMissingIdentifier) -> FFIExecValueResult {
+    exec_slots("ffi_exec_expr_slots_with_missing", ptr, slot_values, slot_values_len, missing)
+}
+
+fn exec_slots(function_name: &str, ptr: *mut ExprAndIdentifiers, slot_values: *const FFIValue, slot_values_len: usize, missing: FFIMissingIdentifier) -> FFIExecValueResult {
+    let result = catch_panic(function_name, || {
+        let expr = expr_from_ptr(function_name, ptr)?;
+        let slot_values = slice_from_ptr(function_name, slot_values, slot_values_len, "slot values")?;
+        let missing = missing.to_missing_identifier()?;
+
+        let values = IdentifierValues::from_slots_with_case(
+            slot_values
+                .iter()
+                .map(|value| {
+                    let lazy_refcell = RefCell::new(IdentifierValueLazyGetter::new(*value));
+                    Box::new(move || lazy_refcell.borrow_mut().get_value()) as Box<IdentifierValueGetter>
+                })
+                .collect(),
+            expr.identifier_case,
+        )
+        .with_missing_identifier(missing);
+        exec_prepared_expr(expr, &values)
+    });
+    FFIExecValueResult::new(result.unwrap_or_else(|message| Err(message.into())))
+}
+
+impl FFIExecValueResult {
+    fn new(result: ExprFuncResult) -> FFIExecValueResult {
+        match result.and_then(|r| Ok(FFIValue::into_raw(&r)?)) {
+            Ok(value) => FFIExecValueResult {
+                is_error: false,
+                error: ptr::null_mut(),
+                value,
+                error_start: 0,
+                error_end: 0,
+            },
+            Err(e) => {
+                let span = e.span.unwrap_or_default();
+                FFIExecValueResult {
+                    is_error: true,
+                    error: c_string_lossy(e.message).into_raw(),
+                    value: FFIValue::new(FFIValueKind::Null),
+                    error_start: span.start,
+                    error_end: span.end,
+                }
+            }
+        }
+    }
+}
+
+// The values of an identifier, one per row, `values_len` must be at least the number of rows
+#[repr(C)]
+#[derive(Debug)]
+pub struct FFIIdentifierColumn {
+    key: *const c_char,
+    values: *const FFIValue,
+    values_len: usize,
+}
+
+// Reads the value of the current row, once per row
+struct ColumnValueGetter {
+    values: *const FFIValue,
+    row: Rc<Cell<usize>>,
+    cached: RefCell<Option<(usize, ExprResult)>>,
+}
+
+impl ColumnValueGetter {
+    fn get_value(&self) -> ExprFuncResult {
+        let row = self.row.get();
+        if let Some((cached_row, value)) = &*self.cached.borrow() {
+            if *cached_row == row {
+                return Ok(value.clone());
+            }
+        }
+        let value = unsafe { *self.values.add(row) }.to_result()?;
+        *self.cached.borrow_mut() = Some((row, value.clone()));
+        Ok(value)
+    }
+}
+
+// Executes the expression for `rows_len` rows, the identifier values come by columns of at least `rows_len` values.
+// `results` must have room for `rows_len` results, as the ones of `ffi_exec_expr_value`.
+// A failing row does not stop the others, the number of failed rows is returned.
+#[no_mangle]
+extern "C" fn ffi_exec_expr_batch(ptr: *mut ExprAndIdentifiers, columns: *const FFIIdentifierColumn, columns_len: usize, rows_len: usize, results: *mut FFIExecValueResult) -> usize {
+    exec_batch("ffi_exec_expr_batch", ptr, columns, columns_len, rows_len, results, FFIMissingIdentifier::error())
+}
+
+// Same as `ffi_exec_expr_batch`, an identifier without value gives what `missing` says
+#[no_mangle]
+extern "C" fn ffi_exec_expr_batch_with_missing(
+    ptr: *mut ExprAndIdentifiers,
+    columns: *const FFIIdentifierColumn,
+    columns_len: usize,
+    rows_len: usize,
+    results: *mut FFIExecValueResult,
+    missing: FFIMissingIdentifier,
+) -> usize {
+    exec_batch("ffi_exec_expr_batch_with_missing", ptr, columns, columns_len, rows_len, results, missing)
+}
+
+fn exec_batch(function_name: &str, ptr: *mut ExprAndIdentifiers, columns: *const FFIIdentifierColumn, columns_len: usize, rows_len: usize, results: *mut FFIExecValueResult, missing: FFIMissingIdentifier) -> usize {
+    // without a results buffer, no row can succeed
+    if results.is_null() {
+        return rows_len;
+    }
+    let results = unsafe { slice::from_raw_parts_mut(results, rows_len) };
+    // the rows are guarded one by one in `exec_rows`, so a failure here comes before any row is filled
+    catch_panic(function_name, || exec_rows(function_name, ptr, columns, columns_len, rows_len, results, missing))
+        .and_then(|r| r)
+        .unwrap_or_else(|message| {
+            for result in results.iter_mut() {
+                *result = FFIExecValueResult::new(Err(message.clone().into()));
+            }
+            rows_len
+        })
+}
+
+fn exec_rows(
+    function_name: &str,
+    ptr: *mut ExprAndIdentifiers,
+    columns: *const FFIIdentifierColumn,
+    columns_len: usize,
+    rows_len: usize,
+    results: &mut [FFIExecValueResult],
+    missing: FFIMissingIdentifier,
+) -> Result<usize, String> {
+    let expr = expr_from_ptr(function_name, ptr)?;
+    let columns = slice_from_ptr(function_name, columns, columns_len, "columns")?;
+    let missing = missing.to_missing_identifier()?;
+
+    // the values are built once, their getters follow the current row
+    let row = Rc::new(Cell::new(0));
+    let mut values = IdentifierValues::with_case(expr.identifier_case);
+    for column in columns.iter() {
+        let k = str_from_ptr(function_name, column.key, "column name")?.to_string();
+        // a shorter column would be read past its end
+        if column.values_len < rows_len {
+            return Err(format!("{}: The column '{}' has {} values for {} rows", function_name, k, column.values_len, rows_len));
+        }
+        if column.values.is_null() && rows_len > 0 {
+            return Err(format!("{}: null values for the column '{}'", function_name, k));
+        }
+        let getter = ColumnValueGetter {
+            values: column.values,
+            row: Rc::clone(&row),
+            cached: RefCell::new(None),
+        };
+        values.insert(k, Box::new(move || getter.get_value()));
+    }
+    let values = values.with_missing_identifier(missing);
+
+    let mut errors = 0;
+    for (i, result) in results.iter_mut().enumerate() {
+        row.set(i);
+        *result = catch_panic(function_name, || FFIExecValueResult::new(exec_prepared_expr(expr, &values))).unwrap_or_else(|message| FFIExecValueResult::new(Err(message.into())));
+        if result.is_error {
+            errors += 1;
+        }
+    }
+    Ok(errors)
+}
+
+// The values given by `ffi_exec_expr_value` and the others, with their text and items
+#[no_mangle]
+extern "C" fn ffi_free_value(value: FFIValue) {
+    let _ = catch_panic("ffi_free_value", || unsafe { value.free() });
+}
+
+// An FFIValue and the text and items it points to, for the arguments of the host functions
+struct OwnedFFIValue {
+    value: FFIValue,
+    _text: Option<CString>,
+    _items: Vec<OwnedFFIValue>,
+    _item_values: Vec<FFIValue>,
+}
+
+impl OwnedFFIValue {
+    fn from_result(result: &ExprResult) -> Result<OwnedFFIValue, String> {
+        let text = result_text(result);
+        let items = result_items(result).iter().map(OwnedFFIValue::from_result).collect::<Result<Vec<_>, _>>()?;
+        let item_values = items.iter().map(|i: &OwnedFFIValue| i.value).collect::<Vec<_>>();
+        let value = FFIValue {
+            text: text.as_ref().map_or(ptr::null(), |t| t.as_ptr()),
+            items: if item_values.is_empty() { ptr::null() } else { item_values.as_ptr() },
+            items_len: item_values.len(),
+            ..FFIValue::from_result(result)?
+        };
+        Ok(OwnedFFIValue {
+            value,
+            _text: text,
+            _items: items,
+            _item_values: item_values,
+        })
+    }
+}
+
+// =========================================
+//              HOST FUNCTIONS
+// =========================================
+
+// Filled by the host during the callback, with `ffi_host_result_set_value` or `ffi_host_result_set_error`
+#[derive(Debug, Default)]
+pub struct FFIHostResult {
+    result: Option<Result<ExprResult, String>>,
+}
+
+// The arguments are only valid during the call
+pub type FFIHostFunction = extern "C" fn(user_data: *mut c_void, args: *const FFIValue, args_len: usize, result: *mut FFIHostResult);
+
+#[derive(Clone, Copy)]
+struct HostFunction {
+    determinism: FunctionDeterminism,
+    arity: FunctionArity,
+    callback: FFIHostFunction,
+    user_data: *mut c_void,
+}
+
+// the user data is never read on the Rust side, it's only given back to the host,
+// which must accept calls from any thread executing an expression
+unsafe impl Send for HostFunction {}
+unsafe impl Sync for HostFunction {}
+
+static HOST_FUNCTIONS: Lazy<Mutex<HashMap<UniCase<String>, HostFunction>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// a panic caught while the lock was held leaves the map usable, so the poisoning is ignored
+fn lock_host_functions() -> MutexGuard<'static, HashMap<UniCase<String>, HostFunction>> {
+    HOST_FUNCTIONS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl HostFunction {
+    fn call(&self, name: &str, params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+        crate::functions::assert_params_count(params, self.arity.min, self.arity.max, name)?;
+        let args = params.iter().map(|p| Ok(OwnedFFIValue::from_result(&exec_expr(p, values)?)?)).collect::<Result<Vec<_>, ExecError>>()?;
+        let ffi_args = args.iter().map(|a| a.value).collect::<Vec<_>>();
+        let mut result = FFIHostResult::default();
+        (self.callback)(self.user_data, ffi_args.as_ptr(), ffi_args.len(), &mut result);
+        match result.result {
+            Some(Ok(r)) => Ok(r),
+            Some(Err(e)) => Err(e.into()),
+            None => Ok(ExprResult::Null),
+        }
+    }
+}
+
+// Looked up at each call and not kept by the prepared expressions : once unregistered, the host may release the callback
+fn call_host_function(name: &UniCase<String>, params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    // copied out so the lock isn't held while the host runs, it may register functions itself
+    let function = lock_host_functions().get(name).copied();
+    match function {
+        Some(function) => function.call(name, params, values),
+        None => Err(format!("The host function '{}' is no longer registered", name).into()),
+    }
+}
+
+// The built-in functions, overridden by the host ones registered so far
+fn registry_with_host_functions() -> FunctionRegistry {
+    let host_functions = lock_host_functions();
+    host_functions.iter().fold(FunctionRegistry::builtins(), |registry, (name, function)| {
+        let function_name = name.clone();
+        let implementation = move |params: &SliceSharedExpr, values: &IdentifierValues| call_host_function(&function_name, params, values);
+        let metadata = FunctionMetadata {
+            category: "Host".to_string(),
+            ..FunctionMetadata::default()
+        };
+        registry.with_definition(
+            name,
+            FunctionDefinition::new(function.determinism, function.arity, FunctionSignature::default(), Arc::new(implementation)).with_metadata(metadata),
+        )
+    })
+}
+
+// Only the expressions prepared afterwards know the function, but they call the one registered under its name
+// at the time of the call : registering it again replaces it for them too, the replaced callback may still be running
+// as for `ffi_unregister_function`. A negative `max_params` means no maximum.
+// Returns false when the function couldn't be registered, e.g. with a null or non UTF-8 name.
+#[no_mangle]
+extern "C" fn ffi_register_function(name: *const c_char, deterministic: bool, min_params: i32, max_params: i32, callback: FFIHostFunction, user_data: *mut c_void) -> bool {
+    catch_panic("ffi_register_function", || {
+        let name = str_from_ptr("ffi_register_function", name, "function name")?.to_string();
+        let function = HostFunction {
+            determinism: if deterministic { FunctionDeterminism::Deterministic } else { FunctionDeterminism::NonDeterministic },
+            arity: FunctionArity {
+                min: min_params.max(0) as usize,
+                max: if max_params < 0 { None } else { Some(max_params as usize) },
+            },
+            callback,
+            user_data,
+        };
+        let mut host_functions = lock_host_functions();
+        let name = UniCase::new(name);
+        host_functions.remove(&name);
+        host_functions.insert(name, function);
+        Ok(())
+    })
+    .and_then(|r: Result<_, String>| r)
+    .is_ok()
+}
+
+// The expressions prepared before then fail to call it. The host can release the callback once the calls
+// already running are over.
+#[no_mangle]
+extern "C" fn ffi_unregister_function(name: *const c_char) -> bool {
+    catch_panic("ffi_unregister_function", || {
+        let name = str_from_ptr("ffi_unregister_function", name, "function name")?.to_string();
+        Ok(lock_host_functions().remove(&UniCase::new(name)).is_some())
+    })
+    .and_then(|r: Result<_, String>| r)
+    .unwrap_or(false)
+}
+
+// Without a result to write to, there's nowhere to report a failure : it's ignored
+#[no_mangle]
+extern "C" fn ffi_host_result_set_value(result: *mut FFIHostResult, value: FFIValue) {
+    if result.is_null() {
+        return;
+    }
+    let result = unsafe { &mut *result };
+    let value = catch_panic("ffi_host_result_set_value", || value.to_result()).and_then(|r| r);
+    result.result = Some(value);
+}
+
+#[no_mangle]
+extern "C" fn ffi_host_result_set_error(result: *mut FFIHostResult, message: *const c_char) {
+    if result.is_null() {
+        return;
+    }
+    let result = unsafe { &mut *result };
+    let message = catch_panic("ffi_host_result_set_error", || str_from_ptr("ffi_host_result_set_error", message, "error message").map(str::to_string))
+        .and_then(|r| r)
+        .unwrap_or_else(|message| message);
+    result.result = Some(Err(message));
+}
+
 // =========================================
+//              TYPES
 // =========================================
 
+// The type is one of `ExprType`, kept as a number as any value can come from the host
+#[repr(C)]
+#[derive(Debug)]
+pub struct FFIIdentifierType {
+    key: *const c_char,
+    identifier_type: u32,
+}
+
+// UTF-8 bytes offsets of the diagnostic in the expression, both are 0 when unknown
+#[repr(C)]
+#[derive(Debug)]
+pub struct FFIDiagnostic {
+    kind: DiagnosticKind,
+    message: *mut c_char,
+    start: usize,
+    end: usize,
+}
+
+// To free with `ffi_free_type_result`, whether it's an error or not
+#[repr(C)]
+#[derive(Debug)]
+pub struct FFITypeResult {
+    is_error: bool,
+    error: *mut c_char,
+    result_type: ExprType,
+    diagnostics: *mut FFIDiagnostic,
+    diagnostics_len: usize,
+}
+
+// The type of the expression result and its definite type errors, see `types::infer_expr_type`.
+// The functions signatures are the ones of the functions registered now.
 #[no_mangle]
-extern "C" fn PassLPStr(s: *const c_char) {
-    let c_str = unsafe {
-        assert!(!s.is_null());
-        CStr::from_ptr(s)
+extern "C" fn ffi_infer_type(ptr: *mut ExprAndIdentifiers, identifier_types: *const FFIIdentifierType, identifier_types_len: usize) -> FFITypeResult {
+    let result = catch_panic("ffi_infer_type", || {
+        let expr = expr_from_ptr("ffi_infer_type", ptr)?;
+        let types = slice_from_ptr("ffi_infer_type", identifier_types, identifier_types_len, "identifier types")?;
+        let identifier_types = types
+            .iter()
+            .map(|t| {
+                let name = str_from_ptr("ffi_infer_type", t.key, "identifier name")?.to_string();
+                let identifier_type = ExprType::try_from(t.identifier_type).map_err(|e| format!("ffi_infer_type: {} for the identifier '{}'", e, name))?;
+                Ok((name, identifier_type))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(infer_expr_type(expr, &registry_with_host_functions(), &identifier_types))
+    });
+    match result.and_then(|r| r) {
+        Err(message) => FFITypeResult {
+            is_error: true,
+            error: c_string_lossy(message).into_raw(),
+            result_type: ExprType::Any,
+            diagnostics: ptr::null_mut(),
+            diagnostics_len: 0,
+        },
+        Ok(inference) => {
+            let diagnostics = inference
+                .diagnostics
+                .into_iter()
+                .map(|d| {
+                    let span = d.span.unwrap_or_default();
+                    FFIDiagnostic {
+                        kind: d.kind,
+                        message: c_string_lossy(d.message).into_raw(),
+                        start: span.start,
+                        end: span.end,
+                    }
+                })
+                .collect::<Vec<_>>()
+                .into_boxed_slice();
+            FFITypeResult {
+                is_error: false,
+                error: ptr::null_mut(),
+                result_type: inference.result_type,
+                diagnostics_len: diagnostics.len(),
+                diagnostics: Box::into_raw(diagnostics) as *mut FFIDiagnostic,
+            }
+        }
+    }
+}
+
+#[no_mangle]
+extern "C" fn ffi_free_type_result(result: FFITypeResult) {
+    ffi_free_cstring(result.error);
+    if result.diagnostics.is_null() {
+        return;
+    }
+    let _ = catch_panic("ffi_free_type_result", || unsafe {
+        let diagnostics = Box::from_raw(ptr::slice_from_raw_parts_mut(result.diagnostics, result.diagnostics_len));
+        for d in diagnostics.iter() {
+            ffi_free_cstring(d.message);
+        }
+    });
+}
+
+// =========================================
+//              FUNCTIONS
+// =========================================
+
+fn json_string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+fn json_array<T, F: Fn(&T) -> String>(items: &[T], f: F) -> String {
+    format!("[{}]", items.iter().map(f).collect::<Vec<_>>().join(","))
+}
+
+fn function_json(function: &RegisteredFunction) -> String {
+    let definition = function.definition;
+    let parameters = json_array(&definition.signature.parameters, |parameter| {
+        format!(
+            "{{\"name\":{},\"type\":{},\"default\":{}}}",
+            json_string(&parameter.name),
+            json_string(&parameter.parameter_type.to_string()),
+            parameter.default_value.as_deref().map_or("null".to_string(), json_string)
+        )
+    });
+    let returns = match definition.signature.returns {
+        ReturnType::Fixed(returns) => returns,
+        ReturnType::CommonOfParameters(_) => ExprType::Any,
     };
+    format!(
+        "{{\"name\":{},\"aliases\":{},\"category\":{},\"description\":{},\"deterministic\":{},\"parameters\":{},\"minParameters\":{},\"maxParameters\":{},\"returns\":{}}}",
+        json_string(function.name),
+        json_array(&function.aliases, |alias| json_string(alias)),
+        json_string(&definition.metadata.category),
+        json_string(&definition.metadata.description),
+        definition.determinism == FunctionDeterminism::Deterministic,
+        parameters,
+        definition.arity.min,
+        definition.arity.max.map_or("null".to_string(), |max| max.to_string()),
+        json_string(&returns.to_string())
+    )
+}
+
+// The functions registered now, built-in and host ones, as a JSON array sorted by name. Null on failure.
+// The parameters from `minParameters` are optional, the last one repeats when `maxParameters` is null :
+// `{"name":"Fixed","aliases":[],"category":"Strings","description":"...","deterministic":true,
+//   "parameters":[{"name":"number","type":"number","default":null},...],"minParameters":1,"maxParameters":3,"returns":"text"}`
+#[no_mangle]
+extern "C" fn ffi_list_functions() -> *mut c_char {
+    catch_panic("ffi_list_functions", || {
+        let registry = registry_with_host_functions();
+        c_string_lossy(json_array(&registry.list(), function_json)).into_raw()
+    })
+    .unwrap_or(ptr::null_mut())
+}
+
+// =========================================
+// =========================================
+//                  TESTING
+// =========================================
+// =========================================
 
-    let r_str = c_str.to_str().unwrap();
-    dbg!("PassLPStr", r_str);
+#[no_mangle]
+extern "C" fn PassLPStr(s: *const c_char) {
+    // nothing to report to the host, a wrong string is only not printed
+    let _ = catch_panic("PassLPStr", || {
+        let r_str = str_from_ptr("PassLPStr", s, "string")?;
+        dbg!("PassLPStr", r_str);
+        Ok::<_, String>(())
+    });
 }
 #[no_mangle]
 extern "C" fn PassLPWStr(s: *const c_char) {
-    let c_str = unsafe {
-        assert!(!s.is_null());
-        CStr::from_ptr(s)
-    };
-
-    let r_str = c_str.to_str().unwrap();
-    dbg!("PassLPWStr", r_str);
+    let _ = catch_panic("PassLPWStr", || {
+        let r_str = str_from_ptr("PassLPWStr", s, "string")?;
+        dbg!("PassLPWStr", r_str);
+        Ok::<_, String>(())
+    });
 }
 #[no_mangle]
 extern "C" fn PassLPTStr(s: *const c_char) {
-    let c_str = unsafe {
-        assert!(!s.is_null());
-        CStr::from_ptr(s)
-    };
-
-    let r_str = c_str.to_str().unwrap();
-    dbg!("PassLPTStr", r_str);
+    let _ = catch_panic("PassLPTStr", || {
+        let r_str = str_from_ptr("PassLPTStr", s, "string")?;
+        dbg!("PassLPTStr", r_str);
+        Ok::<_, String>(())
+    });
 }
 #[no_mangle]
 extern "C" fn PassLPUTF8Str(s: *const c_char) {
-    let c_str = unsafe {
-        assert!(!s.is_null());
-        CStr::from_ptr(s)
-    };
-
-    let r_str = c_str.to_str().unwrap();
-    dbg!("PassLPUTF8Str", r_str);
+    let _ = catch_panic("PassLPUTF8Str", || {
+        let r_str = str_from_ptr("PassLPUTF8Str", s, "string")?;
+        dbg!("PassLPUTF8Str", r_str);
+        Ok::<_, String>(())
+    });
 }
 #[no_mangle]
 extern "C" fn PassBStr(s: *const c_char) {
-    let c_str = unsafe {
-        assert!(!s.is_null());
-        CStr::from_ptr(s)
-    };
+    let _ = catch_panic("PassBStr", || {
+        let r_str = str_from_ptr("PassBStr", s, "string")?;
+        dbg!("PassBStr", r_str);
+        Ok::<_, String>(())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::functions::{f_operators, f_unary_operators};
+    use test_case::test_case;
+
+    // describes its arguments, or fails when there is none
+    extern "C" fn describe(user_data: *mut c_void, args: *const FFIValue, args_len: usize, result: *mut FFIHostResult) {
+        let prefix = str_from_c_char_ptr(user_data as *const c_char).unwrap();
+        let args = unsafe { slice::from_raw_parts(args, args_len) };
+        if args.is_empty() {
+            let message = CString::new("no arguments").unwrap();
+            ffi_host_result_set_error(result, message.as_ptr());
+            return;
+        }
+        let description = args
+            .iter()
+            .map(|a| {
+                let kind = FFIValueKind::try_from(a.kind).unwrap();
+                match kind {
+                    FFIValueKind::Boolean => format!("{:?}:{}", kind, a.boolean),
+                    FFIValueKind::Int | FFIValueKind::Date | FFIValueKind::TimeSpan => format!("{:?}:{}", kind, a.int),
+                    FFIValueKind::Num => format!("{:?}:{},{:x}", kind, a.decimal_lo, a.decimal_flags),
+                    FFIValueKind::Null => "Null".to_string(),
+                    FFIValueKind::Str => format!("{:?}:{}", kind, str_from_c_char_ptr(a.text).unwrap()),
+                    FFIValueKind::Array | FFIValueKind::Record => format!("{:?}:{}", kind, a.items_len),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let text = CString::new(format!("{}{}", prefix, description)).unwrap();
+        ffi_host_result_set_value(
+            result,
+            FFIValue {
+                text: text.as_ptr(),
+                ..FFIValue::new(FFIValueKind::Str)
+            },
+        );
+    }
+
+    extern "C" fn tier(_: *mut c_void, args: *const FFIValue, _: usize, result: *mut FFIHostResult) {
+        let id = unsafe { (*args).int };
+        // (id * 100 + 5) / 10
+        let tier = FFIValue {
+            decimal_lo: (id * 100 + 5) as u32,
+            decimal_flags: 1 << 16,
+            ..FFIValue::new(FFIValueKind::Num)
+        };
+        ffi_host_result_set_value(result, tier);
+    }
+
+    fn exec(expression: &str) -> Result<String, String> {
+        let expr = prepare_expr_and_identifiers(parse_expr(expression).unwrap(), &registry_with_host_functions(), Arc::new(f_operators), Arc::new(f_unary_operators));
+        exec_expr(&expr.expr, &IdentifierValues::new()).map(|r| r.to_string()).map_err(|e| e.message)
+    }
+
+    #[test]
+    fn host_functions() {
+        static PREFIX: &[u8] = b"host:\0";
+        let describe_name = CString::new("Describe").unwrap();
+        ffi_register_function(describe_name.as_ptr(), true, 0, -1, describe, PREFIX.as_ptr() as *mut c_void);
+        let tier_name = CString::new("GetCustomerTier").unwrap();
+        ffi_register_function(tier_name.as_ptr(), false, 1, 1, tier, ptr::null_mut());
+
+        assert_eq!(
+            exec("describe(1, -2.50, \"a\", true, null, Date(\"2020-02-03\"))"),
+            Ok("host:Int:1,Num:250,80020000,Str:a,Boolean:true,Null,Date:637162848000000000".to_string())
+        );
+        assert_eq!(exec("Describe()"), Err("no arguments".to_string()));
+        assert_eq!(exec("Describe(Split(\"a,b\", \",\"), [])"), Ok("host:Array:2,Array:0".to_string()));
+        assert_eq!(exec("GetCustomerTier(4) * 2"), Ok("81.0".to_string()));
+        assert_eq!(exec("GetCustomerTier(4, 2)"), Err("Function GetCustomerTier should have exactly 1 parameters".to_string()));
+
+        let expr = prepare_expr_and_identifiers(parse_expr("GetCustomerTier(1)").unwrap(), &registry_with_host_functions(), Arc::new(f_operators), Arc::new(f_unary_operators));
+        assert_eq!(expr.determinism, FunctionDeterminism::NonDeterministic);
+
+        let prepared_before = prepare_expr_and_identifiers(parse_expr("GetCustomerTier(4)").unwrap(), &registry_with_host_functions(), Arc::new(f_operators), Arc::new(f_unary_operators));
+        assert!(ffi_unregister_function(tier_name.as_ptr()));
+        assert!(!ffi_unregister_function(tier_name.as_ptr()));
+        assert_eq!(exec("GetCustomerTier(4)"), Err("Unable to find the function named 'GetCustomerTier'".to_string()));
+        assert_eq!(
+            exec_expr(&prepared_before.expr, &IdentifierValues::new()).map(|r| r.to_string()).map_err(|e| e.message),
+            Err("The host function 'GetCustomerTier' is no longer registered".to_string())
+        );
+    }
+
+    fn exec_value(expression: &str) -> FFIExecValueResult {
+        let expression = CString::new(expression).unwrap();
+        let parsed = ffi_parse_and_prepare_expr(expression.as_ptr());
+        let no_values: [IdentifierKeyValue; 0] = [];
+        let result = ffi_exec_expr_value(parsed.content, no_values.as_ptr(), 0);
+        ffi_free_expr(parsed.content);
+        result
+    }
+
+    #[test]
+    fn exec_typed_values() {
+        let result = exec_value("1.5 * 2 > 2 ? Date(\"2020-02-03\") : null");
+        assert!(!result.is_error);
+        assert_eq!((result.value.kind, result.value.int), (FFIValueKind::Date as u32, 637_162_848_000_000_000));
+
+        let result = exec_value("Concat(\"a\", 1)");
+        assert_eq!(result.value.kind, FFIValueKind::Str as u32);
+        assert_eq!(str_from_c_char_ptr(result.value.text), Ok("a1"));
+        ffi_free_cstring(result.value.text as *mut c_char);
+
+        let result = exec_value("Split(\"a,b\", \",\")");
+        assert_eq!((result.value.kind, result.value.items_len), (FFIValueKind::Array as u32, 2));
+        assert_eq!(
+            result.value.to_result(),
+            Ok(ExprResult::Array(Rc::new(vec![ExprResult::Str(Rc::new("a".to_string())), ExprResult::Str(Rc::new("b".to_string()))])))
+        );
+        ffi_free_value(result.value);
+
+        let result = exec_value("1 + Abs(\"x\")");
+        assert!(result.is_error);
+        assert_eq!((result.error_start, result.error_end), (4, 12));
+        ffi_free_cstring(result.error);
+    }
+
+    #[test]
+    fn exec_with_typed_identifier_values() {
+        let expression = CString::new("DateGreater(DateAddDays(start, days), limit) && label == \"é\"").unwrap();
+        let parsed = ffi_parse_and_prepare_expr(expression.as_ptr());
+        let (start, days, limit, label) = (CString::new("start").unwrap(), CString::new("days").unwrap(), CString::new("limit").unwrap(), CString::new("label").unwrap());
+        let label_text = CString::new("é").unwrap();
+        let date = NaiveDate::from_ymd_opt(2020, 2, 3).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        let values = [
+            IdentifierKeyValue {
+                key: start.as_ptr(),
+                value: FFIValue::from_result(&ExprResult::Date(date)).unwrap(),
+            },
+            IdentifierKeyValue {
+                key: days.as_ptr(),
+                value: FFIValue::from_result(&ExprResult::Num(Decimal::new(20, 1))).unwrap(),
+            },
+            IdentifierKeyValue {
+                key: limit.as_ptr(),
+                value: FFIValue::from_result(&ExprResult::Date(date + Duration::days(1))).unwrap(),
+            },
+            IdentifierKeyValue {
+                key: label.as_ptr(),
+                value: FFIValue {
+                    text: label_text.as_ptr(),
+                    ..FFIValue::new(FFIValueKind::Str)
+                },
+            },
+        ];
+        let result = ffi_exec_expr_value(parsed.content, values.as_ptr(), values.len());
+        ffi_free_expr(parsed.content);
+        assert!(!result.is_error, "{:?}", str_from_c_char_ptr(result.error));
+        assert_eq!((result.value.kind, result.value.boolean), (FFIValueKind::Boolean as u32, true));
+    }
+
+    #[test]
+    fn exec_with_slot_values() {
+        let expression = CString::new("Concat(label, qty * price, label)").unwrap();
+        let parsed = ffi_parse_and_prepare_expr(expression.as_ptr());
+        let identifiers = ffi_get_identifiers(parsed.content);
+        assert_eq!(str_from_c_char_ptr(identifiers), Ok("label|qty|price"));
+        ffi_free_cstring(identifiers);
+
+        let label_text = CString::new("x").unwrap();
+        let values = [
+            FFIValue {
+                text: label_text.as_ptr(),
+                ..FFIValue::new(FFIValueKind::Str)
+            },
+            FFIValue::from_result(&ExprResult::Int(3)).unwrap(),
+            FFIValue::from_result(&ExprResult::Num(Decimal::new(15, 1))).unwrap(),
+        ];
+        let result = ffi_exec_expr_slots(parsed.content, values.as_ptr(), values.len());
+        assert_eq!(str_from_c_char_ptr(result.value.text), Ok("x4.5x"));
+        ffi_free_cstring(result.value.text as *mut c_char);
+
+        let result = ffi_exec_expr_slots(parsed.content, values.as_ptr(), 2);
+        assert_eq!(take_error(result.is_error, result.error), "Unable to find value for identifier named 'price'");
+        ffi_free_expr(parsed.content);
+    }
+
+    #[test]
+    fn exec_with_array_slot_values() {
+        let expression = CString::new("In(3, quantities) ? Concat(quantities[1] * 10, quantities) : null").unwrap();
+        let parsed = ffi_parse_and_prepare_expr(expression.as_ptr());
+        let items = [FFIValue::from_result(&ExprResult::Int(2)).unwrap(), FFIValue::from_result(&ExprResult::Int(3)).unwrap()];
+        let mut values = [FFIValue {
+            items: items.as_ptr(),
+            items_len: items.len(),
+            ..FFIValue::new(FFIValueKind::Array)
+        }];
+        let result = ffi_exec_expr_slots(parsed.content, values.as_ptr(), values.len());
+        assert_eq!(str_from_c_char_ptr(result.value.text), Ok("3023"));
+        ffi_free_value(result.value);
+
+        values[0].items = ptr::null();
+        let result = ffi_exec_expr_slots(parsed.content, values.as_ptr(), values.len());
+        assert_eq!(take_error(result.is_error, result.error), "The array value has no items");
+
+        values[0].kind = 42;
+        let result = ffi_exec_expr_slots(parsed.content, values.as_ptr(), values.len());
+        assert_eq!(take_error(result.is_error, result.error), "Unknown value kind 42");
+        ffi_free_expr(parsed.content);
+    }
+
+    #[test]
+    fn exec_with_record_slot_values() {
+        let expression = CString::new("lines.Where(x => x.Price > 10).Sum(x => x.Price)").unwrap();
+        let parsed = ffi_parse_and_prepare_expr(expression.as_ptr());
+        let price = CString::new("Price").unwrap();
+        let name = FFIValue {
+            text: price.as_ptr(),
+            ..FFIValue::new(FFIValueKind::Str)
+        };
+        let fields = [
+            [name, FFIValue::from_result(&ExprResult::Int(5)).unwrap()],
+            [name, FFIValue::from_result(&ExprResult::Int(12)).unwrap()],
+            [name, FFIValue::from_result(&ExprResult::Int(20)).unwrap()],
+        ];
+        let mut lines = fields
+            .iter()
+            .map(|f| FFIValue {
+                items: f.as_ptr(),
+                items_len: f.len(),
+                ..FFIValue::new(FFIValueKind::Record)
+            })
+            .collect::<Vec<_>>();
+        let values = [FFIValue {
+            items: lines.as_ptr(),
+            items_len: lines.len(),
+            ..FFIValue::new(FFIValueKind::Array)
+        }];
+        let result = ffi_exec_expr_slots(parsed.content, values.as_ptr(), values.len());
+        assert!(!result.is_error);
+        assert_eq!(result.value.to_result().map(|r| r.to_string()), Ok("32".to_string()));
+        ffi_free_value(result.value);
+
+        lines[1].items_len = 1;
+        let values = [FFIValue { items: lines.as_ptr(), ..values[0] }];
+        let result = ffi_exec_expr_slots(parsed.content, values.as_ptr(), values.len());
+        assert_eq!(take_error(result.is_error, result.error), "The record value should have a name and a value for each field");
+
+        lines[1].items_len = 2;
+        lines[1].items = fields[1][1..].as_ptr();
+        let values = [FFIValue { items: lines.as_ptr(), ..values[0] }];
+        let result = ffi_exec_expr_slots(parsed.content, values.as_ptr(), values.len());
+        assert_eq!(take_error(result.is_error, result.error), "The name of a record field should be a text, not '12'");
+        ffi_free_expr(parsed.content);
+    }
+
+    #[test_case(0 => (Some("qty".to_string()), Some("6".to_string())))]
+    #[test_case(PREPARE_CASE_SENSITIVE_IDENTIFIERS => (Some("qty|QTY".to_string()), None))]
+    fn exec_with_identifier_case(options: u32) -> (Option<String>, Option<String>) {
+        let expression = CString::new("qty + QTY").unwrap();
+        let parsed = ffi_parse_and_prepare_expr_with_options(expression.as_ptr(), options);
+        let identifiers = ffi_get_identifiers(parsed.content);
+        let names = str_from_c_char_ptr(identifiers).ok().map(str::to_string);
+        ffi_free_cstring(identifiers);
+
+        let qty = CString::new("Qty").unwrap();
+        let values = [IdentifierKeyValue {
+            key: qty.as_ptr(),
+            value: FFIValue::from_result(&ExprResult::Int(3)).unwrap(),
+        }];
+        let result = ffi_exec_expr_value(parsed.content, values.as_ptr(), values.len());
+        ffi_free_expr(parsed.content);
+        if result.is_error {
+            ffi_free_cstring(result.error);
+        }
+        (names, result.value.to_result().ok().filter(|_| !result.is_error).map(|r| r.to_string()))
+    }
+
+    #[test_case(0 => "0")]
+    #[test_case(PREPARE_CASE_SENSITIVE_IDENTIFIERS => "2")]
+    fn exec_slots_with_lambda_parameters_case(options: u32) -> String {
+        let expression = CString::new("items.Count(x => items.Any(X => x > X))").unwrap();
+        let parsed = ffi_parse_and_prepare_expr_with_options(expression.as_ptr(), options);
+        let items = [1, 2, 3].map(|i| FFIValue::from_result(&ExprResult::Int(i)).unwrap());
+        let values = [FFIValue {
+            items: items.as_ptr(),
+            items_len: items.len(),
+            ..FFIValue::new(FFIValueKind::Array)
+        }];
+        let result = ffi_exec_expr_slots(parsed.content, values.as_ptr(), values.len());
+        ffi_free_expr(parsed.content);
+        result.value.to_result().unwrap().to_string()
+    }
+
+    #[test]
+    fn list_functions() {
+        let json = ffi_list_functions();
+        let text = str_from_c_char_ptr(json).unwrap().to_string();
+        ffi_free_cstring(json);
+        assert!(text.starts_with("[{\"name\":\"Abs\","));
+        assert!(text.contains(
+            "{\"name\":\"Iif\",\"aliases\":[\"If\"],\"category\":\"Logic\",\"description\":\"whenTrue when the condition is true, whenFalse otherwise\",\"deterministic\":true,\
+             \"parameters\":[{\"name\":\"condition\",\"type\":\"boolean\",\"default\":null},{\"name\":\"whenTrue\",\"type\":\"any\",\"default\":null},\
+             {\"name\":\"whenFalse\",\"type\":\"any\",\"default\":null}],\"minParameters\":3,\"maxParameters\":3,\"returns\":\"any\"}"
+        ));
+        assert!(text.contains("{\"name\":\"decimals\",\"type\":\"number\",\"default\":\"2\"}"));
+        assert!(text.contains("\"name\":\"Now\",\"aliases\":[],\"category\":\"Dates\",\"description\":\"The current date and time, in UTC\",\"deterministic\":false,"));
+        assert!(!text.contains("\"name\":\"If\""));
+    }
 
-    let r_str = c_str.to_str().unwrap();
-    dbg!("PassBStr", r_str);
+    #[test_case("plain" => "\"plain\"")]
+    #[test_case("say \"hi\"\\" => "\"say \\\"hi\\\"\\\\\"")]
+    #[test_case("a\nb\u{1}" => "\"a\\nb\\u0001\"")]
+    fn json_strings(text: &str) -> String {
+        json_string(text)
+    }
+
+    #[test]
+    fn infer_type() {
+        let expression = CString::new("Year(Date(d)) + \"a\" * Len(name)").unwrap();
+        let parsed = ffi_parse_and_prepare_expr(expression.as_ptr());
+        let (d, name) = (CString::new("D").unwrap(), CString::new("name").unwrap());
+        let mut types = [
+            FFIIdentifierType { key: d.as_ptr(), identifier_type: 42 },
+            FFIIdentifierType {
+                key: name.as_ptr(),
+                identifier_type: ExprType::Text as u32,
+            },
+        ];
+        let result = ffi_infer_type(parsed.content, types.as_ptr(), types.len());
+        assert_eq!(take_error(result.is_error, result.error), "ffi_infer_type: Unknown type 42 for the identifier 'D'");
+
+        types[0].identifier_type = ExprType::Number as u32;
+        let result = ffi_infer_type(parsed.content, types.as_ptr(), types.len());
+        ffi_free_expr(parsed.content);
+        assert!(!result.is_error);
+        assert_eq!(result.result_type, ExprType::Number);
+        let diagnostics = unsafe { slice::from_raw_parts(result.diagnostics, result.diagnostics_len) }
+            .iter()
+            .map(|d| (d.kind, str_from_c_char_ptr(d.message).unwrap().to_string(), d.start, d.end))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (DiagnosticKind::TypeMismatch, "The parameter 1 of Date should be a date, not a number".to_string(), 5, 12),
+                (DiagnosticKind::TypeMismatch, "The value 'a' is not a number.".to_string(), 16, 19),
+            ]
+        );
+        ffi_free_type_result(result);
+    }
+
+    #[test_case("Left(\"abc\", 1)", PREPARE_STRICT => Ok(()))]
+    #[test_case("Left(\"abc\") + Unknown()", 0 => Ok(()))]
+    #[test_case("Left(\"abc\") + Unknown()", PREPARE_STRICT | PREPARE_CASE_SENSITIVE_IDENTIFIERS => Err("Function Left should have exactly 2 parameters; Unable to find the function named 'Unknown'".to_string()))]
+    fn prepare_strict(expression: &str, options: u32) -> Result<(), String> {
+        let expression = CString::new(expression).unwrap();
+        let parsed = ffi_parse_and_prepare_expr_with_options(expression.as_ptr(), options);
+        if parsed.is_error {
+            Err(take_error(parsed.is_error, parsed.error))
+        } else {
+            ffi_free_expr(parsed.content);
+            Ok(())
+        }
+    }
+
+    #[test_case(FFIMissingIdentifierPolicy::Error as u32, ExprResult::Null => Err("Unable to find value for identifier named 'missing'".to_string()))]
+    #[test_case(FFIMissingIdentifierPolicy::Null as u32, ExprResult::Int(5) => Ok("true/".to_string()))]
+    #[test_case(FFIMissingIdentifierPolicy::Default as u32, ExprResult::Int(5) => Ok("false/5".to_string()))]
+    #[test_case(42, ExprResult::Int(5) => Err("Unknown missing identifier policy 42".to_string()))]
+    fn exec_with_missing_identifiers(policy: u32, default_value: ExprResult) -> Result<String, String> {
+        let expression = CString::new("$\"{IsNull(missing)}/{missing}\"").unwrap();
+        let parsed = ffi_parse_and_prepare_expr(expression.as_ptr());
+        let no_values: [IdentifierKeyValue; 0] = [];
+        let missing = FFIMissingIdentifier {
+            policy,
+            default_value: FFIValue::from_result(&default_value).unwrap(),
+        };
+        let take_text = |result: FFIExecValueResult| {
+            if result.is_error {
+                Err(take_error(result.is_error, result.error))
+            } else {
+                let text = str_from_c_char_ptr(result.value.text).unwrap().to_string();
+                ffi_free_cstring(result.value.text as *mut c_char);
+                Ok(text)
+            }
+        };
+        let value = take_text(ffi_exec_expr_value_with_missing(parsed.content, no_values.as_ptr(), 0, missing));
+        // the same for the slots and for the rows
+        assert_eq!(take_text(ffi_exec_expr_slots_with_missing(parsed.content, ptr::null(), 0, missing)), value);
+        let mut results = vec![FFIExecValueResult::new(Ok(ExprResult::Null)); 2];
+        ffi_exec_expr_batch_with_missing(parsed.content, ptr::null(), 0, 2, results.as_mut_ptr(), missing);
+        assert!(results.into_iter().map(take_text).all(|row| row == value));
+        ffi_free_expr(parsed.content);
+        value
+    }
+
+    #[test]
+    fn exec_batch() {
+        let expression = CString::new("NumberValue(label) * qty").unwrap();
+        let parsed = ffi_parse_and_prepare_expr(expression.as_ptr());
+        let (label, qty) = (CString::new("label").unwrap(), CString::new("qty").unwrap());
+        let texts = [CString::new("1.5").unwrap(), CString::new("oops").unwrap(), CString::new("-2").unwrap()];
+        let labels = texts
+            .iter()
+            .map(|t| FFIValue {
+                text: t.as_ptr(),
+                ..FFIValue::new(FFIValueKind::Str)
+            })
+            .collect::<Vec<_>>();
+        let quantities = (1..=3).map(|i| FFIValue::from_result(&ExprResult::Int(i)).unwrap()).collect::<Vec<_>>();
+        let columns = [
+            FFIIdentifierColumn {
+                key: label.as_ptr(),
+                values: labels.as_ptr(),
+                values_len: labels.len(),
+            },
+            FFIIdentifierColumn {
+                key: qty.as_ptr(),
+                values: quantities.as_ptr(),
+                values_len: quantities.len(),
+            },
+        ];
+        let mut results = vec![FFIExecValueResult::new(Ok(ExprResult::Null)); 3];
+
+        let errors = ffi_exec_expr_batch(parsed.content, columns.as_ptr(), columns.len(), 3, results.as_mut_ptr());
+        ffi_free_expr(parsed.content);
+
+        assert_eq!(errors, 1);
+        let results = results
+            .into_iter()
+            .map(|r| if r.is_error { Err(take_error(r.is_error, r.error)) } else { r.value.to_result().map(|v| v.to_string()) })
+            .collect::<Vec<_>>();
+        assert_eq!(results, vec![Ok("1.5".to_string()), Err("The value 'oops' is not a number.".to_string()), Ok("-6".to_string())]);
+    }
+
+    #[test]
+    fn exec_one_handle_from_many_threads() {
+        let expression = CString::new("Len(name) * factor").unwrap();
+        // the handle is shared as is, without any lock on the Rust side
+        let handle = ffi_parse_and_prepare_expr(expression.as_ptr()).content as usize;
+        let threads = (0..8_i64)
+            .map(|factor| {
+                std::thread::spawn(move || {
+                    let (name, name_value, factor_name) = (CString::new("name").unwrap(), CString::new("abc").unwrap(), CString::new("factor").unwrap());
+                    let values = [
+                        IdentifierKeyValue {
+                            key: name.as_ptr(),
+                            value: FFIValue {
+                                text: name_value.as_ptr(),
+                                ..FFIValue::new(FFIValueKind::Str)
+                            },
+                        },
+                        IdentifierKeyValue {
+                            key: factor_name.as_ptr(),
+                            value: FFIValue::from_result(&ExprResult::Int(factor)).unwrap(),
+                        },
+                    ];
+                    (0..100)
+                        .map(|_| ffi_exec_expr_value(handle as *mut ExprAndIdentifiers, values.as_ptr(), values.len()).value.to_result().map(|r| r.to_string()))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        for (factor, thread) in threads.into_iter().enumerate() {
+            assert!(thread.join().unwrap().iter().all(|r| *r == Ok((3 * factor).to_string())));
+        }
+        ffi_free_expr(handle as *mut ExprAndIdentifiers);
+    }
+
+    fn exec_with_options(expression: &str, options: u32) -> (bool, String, usize, usize) {
+        let expression = CString::new(expression).unwrap();
+        let parsed = ffi_parse_and_prepare_expr_with_options(expression.as_ptr(), options);
+        assert_eq!(unsafe { (*parsed.content).compiled.is_some() }, options & PREPARE_COMPILED != 0);
+        let qty = CString::new("qty").unwrap();
+        let values = [IdentifierKeyValue {
+            key: qty.as_ptr(),
+            value: FFIValue::from_result(&ExprResult::Int(3)).unwrap(),
+        }];
+        let result = ffi_exec_expr(parsed.content, values.as_ptr(), values.len());
+        ffi_free_expr(parsed.content);
+        let content = str_from_c_char_ptr(result.content).unwrap().to_string();
+        ffi_free_cstring(result.content);
+        (result.is_error, content, result.error_start, result.error_end)
+    }
+
+    #[test_case("qty * 2 > 5 ? $\"{qty:N1} items\" : \"few\"" => (false, "3.0 items".to_string(), 0, 0))]
+    #[test_case("Iif(qty > 2, Left(\"abcdef\", qty), 1/0)" => (false, "abc".to_string(), 0, 0))]
+    #[test_case("1 + Abs(\"x\") * qty" => (true, "The value 'x' is not a number.".to_string(), 4, 12))]
+    #[test_case("qty > 1 ? Left(\"abc\") : null" => (true, "Function Left should have exactly 2 parameters".to_string(), 10, 21))]
+    fn exec_compiled(expression: &str) -> (bool, String, usize, usize) {
+        let compiled = exec_with_options(expression, PREPARE_COMPILED);
+        assert_eq!(compiled, exec_with_options(expression, 0));
+        compiled
+    }
+
+    fn take_error(is_error: bool, error: *mut c_char) -> String {
+        assert!(is_error);
+        let message = str_from_c_char_ptr(error).unwrap().to_string();
+        ffi_free_cstring(error);
+        message
+    }
+
+    extern "C" fn do_nothing(_: *mut c_void, _: *const FFIValue, _: usize, _: *mut FFIHostResult) {}
+
+    #[test]
+    fn hostile_inputs_are_errors() {
+        let invalid_utf8 = [0xC3_u8, 0x28, 0];
+        let invalid_utf8 = invalid_utf8.as_ptr() as *const c_char;
+
+        let parsed = ffi_parse_and_prepare_expr(ptr::null());
+        assert_eq!(take_error(parsed.is_error, parsed.error), "ffi_parse_and_prepare_expr: null expression");
+        let parsed = ffi_parse_and_prepare_expr(invalid_utf8);
+        assert_eq!(
+            take_error(parsed.is_error, parsed.error),
+            "ffi_parse_and_prepare_expr: the expression is not valid UTF-8 (invalid utf-8 sequence of 1 bytes from index 0)"
+        );
+        let parsed = ffi_parse_and_prepare_expr_with_options(ptr::null(), PREPARE_STRICT);
+        assert_eq!(take_error(parsed.is_error, parsed.error), "ffi_parse_and_prepare_expr_with_options: null expression");
+
+        let result = ffi_exec_expr(ptr::null_mut(), ptr::null(), 0);
+        assert_eq!(take_error(result.is_error, result.content), "ffi_exec_expr: null expression handle");
+        let result = ffi_exec_expr_value(ptr::null_mut(), ptr::null(), 0);
+        assert_eq!(take_error(result.is_error, result.error), "ffi_exec_expr_value: null expression handle");
+        let result = ffi_exec_expr_slots(ptr::null_mut(), ptr::null(), 0);
+        assert_eq!(take_error(result.is_error, result.error), "ffi_exec_expr_slots: null expression handle");
+        assert!(ffi_get_identifiers(ptr::null_mut()).is_null());
+        assert!(!ffi_is_deterministic(ptr::null_mut()));
+        let result = ffi_infer_type(ptr::null_mut(), ptr::null(), 0);
+        assert_eq!(take_error(result.is_error, result.error), "ffi_infer_type: null expression handle");
+
+        let expression = CString::new("a").unwrap();
+        let parsed = ffi_parse_and_prepare_expr(expression.as_ptr());
+        let result = ffi_exec_expr_value(parsed.content, ptr::null(), 1);
+        assert_eq!(take_error(result.is_error, result.error), "ffi_exec_expr_value: null identifier values");
+        let result = ffi_exec_expr_slots(parsed.content, ptr::null(), 1);
+        assert_eq!(take_error(result.is_error, result.error), "ffi_exec_expr_slots: null slot values");
+        let values = [IdentifierKeyValue {
+            key: ptr::null(),
+            value: FFIValue::new(FFIValueKind::Null),
+        }];
+        let result = ffi_exec_expr(parsed.content, values.as_ptr(), values.len());
+        assert_eq!(take_error(result.is_error, result.content), "ffi_exec_expr: null identifier name");
+        let values = [IdentifierKeyValue {
+            key: invalid_utf8,
+            value: FFIValue::new(FFIValueKind::Null),
+        }];
+        let result = ffi_exec_expr(parsed.content, values.as_ptr(), values.len());
+        assert_eq!(
+            take_error(result.is_error, result.content),
+            "ffi_exec_expr: the identifier name is not valid UTF-8 (invalid utf-8 sequence of 1 bytes from index 0)"
+        );
+        let types = [FFIIdentifierType {
+            key: ptr::null(),
+            identifier_type: ExprType::Number as u32,
+        }];
+        let result = ffi_infer_type(parsed.content, types.as_ptr(), types.len());
+        assert_eq!(take_error(result.is_error, result.error), "ffi_infer_type: null identifier name");
+        // the expression is still usable after a failure
+        let a = CString::new("a").unwrap();
+        let values = [IdentifierKeyValue {
+            key: a.as_ptr(),
+            value: FFIValue::from_result(&ExprResult::Int(3)).unwrap(),
+        }];
+        let result = ffi_exec_expr_value(parsed.content, values.as_ptr(), values.len());
+        assert!(!result.is_error);
+        assert_eq!(result.value.int, 3);
+        ffi_free_expr(parsed.content);
+
+        assert!(!ffi_register_function(ptr::null(), true, 0, 0, do_nothing, ptr::null_mut()));
+        assert!(!ffi_unregister_function(invalid_utf8));
+        ffi_host_result_set_value(ptr::null_mut(), FFIValue::new(FFIValueKind::Null));
+        ffi_host_result_set_error(ptr::null_mut(), invalid_utf8);
+        let mut host_result = FFIHostResult::default();
+        ffi_host_result_set_error(&mut host_result, invalid_utf8);
+        assert_eq!(
+            host_result.result,
+            Some(Err("ffi_host_result_set_error: the error message is not valid UTF-8 (invalid utf-8 sequence of 1 bytes from index 0)".to_string()))
+        );
+        ffi_host_result_set_error(&mut host_result, ptr::null());
+        assert_eq!(host_result.result, Some(Err("ffi_host_result_set_error: null error message".to_string())));
+    }
+
+    #[test]
+    fn hostile_batch_inputs_fail_every_row() {
+        assert_eq!(ffi_exec_expr_batch(ptr::null_mut(), ptr::null(), 0, 2, ptr::null_mut()), 2);
+
+        let mut results = vec![FFIExecValueResult::new(Ok(ExprResult::Null)); 2];
+        assert_eq!(ffi_exec_expr_batch(ptr::null_mut(), ptr::null(), 0, 2, results.as_mut_ptr()), 2);
+        for result in results {
+            assert_eq!(take_error(result.is_error, result.error), "ffi_exec_expr_batch: null expression handle");
+        }
+
+        let expression = CString::new("qty").unwrap();
+        let parsed = ffi_parse_and_prepare_expr(expression.as_ptr());
+        let qty = CString::new("qty").unwrap();
+        let columns = [FFIIdentifierColumn {
+            key: qty.as_ptr(),
+            values: ptr::null(),
+            values_len: 2,
+        }];
+        let mut results = vec![FFIExecValueResult::new(Ok(ExprResult::Null)); 2];
+        assert_eq!(ffi_exec_expr_batch(parsed.content, columns.as_ptr(), columns.len(), 2, results.as_mut_ptr()), 2);
+        for result in results {
+            assert_eq!(take_error(result.is_error, result.error), "ffi_exec_expr_batch: null values for the column 'qty'");
+        }
+
+        // a column shorter than the batch is rejected before any row is read
+        let quantities = [FFIValue::from_result(&ExprResult::Int(1)).unwrap()];
+        let columns = [FFIIdentifierColumn {
+            key: qty.as_ptr(),
+            values: quantities.as_ptr(),
+            values_len: quantities.len(),
+        }];
+        let mut results = vec![FFIExecValueResult::new(Ok(ExprResult::Null)); 2];
+        assert_eq!(ffi_exec_expr_batch(parsed.content, columns.as_ptr(), columns.len(), 2, results.as_mut_ptr()), 2);
+        ffi_free_expr(parsed.content);
+        for result in results {
+            assert_eq!(take_error(result.is_error, result.error), "ffi_exec_expr_batch: The column 'qty' has 1 values for 2 rows");
+        }
+    }
+
+    #[test]
+    fn c_strings_are_cut_at_nul() {
+        assert_eq!(c_string_lossy("ab\0cd".to_string()).to_str(), Ok("ab"));
+    }
+
+    #[test_case(ExprResult::Num(Decimal::new(-125, 2)) => (0x7d, 0, 0, 0x8002_0000))]
+    #[test_case(ExprResult::Num(Decimal::MAX) => (u32::MAX, u32::MAX, u32::MAX, 0))]
+    #[test_case(ExprResult::Num(Decimal::new(1, 28)) => (1, 0, 0, 0x001c_0000))]
+    fn ffi_decimal_parts(result: ExprResult) -> (u32, u32, u32, u32) {
+        let value = FFIValue::from_result(&result).unwrap();
+        (value.decimal_lo, value.decimal_mid, value.decimal_hi, value.decimal_flags)
+    }
+
+    #[test]
+    fn ffi_ticks() {
+        let date = NaiveDate::from_ymd_opt(2020, 2, 3).unwrap().and_hms_nano_opt(4, 5, 6, 789_123_400).unwrap();
+        assert_eq!(FFIValue::from_result(&ExprResult::Date(date)).unwrap().int, 637_162_995_067_891_234);
+        assert_eq!(FFIValue::from_result(&ExprResult::TimeSpan(Duration::milliseconds(-1_500))).unwrap().int, -15_000_000);
+
+        let far_date = NaiveDate::from_ymd_opt(30_000, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        assert_eq!(
+            FFIValue::from_result(&ExprResult::Date(far_date)).map(|v| v.int),
+            Err("The date '01/01/30000 00:00:00' is out of the .net range".to_string())
+        );
+        assert_eq!(
+            FFIValue::from_result(&ExprResult::TimeSpan(Duration::days(-11_000_000))).map(|v| v.int),
+            Err("The time span '-11000000.00:00:00' is out of the .net range".to_string())
+        );
+        let items = ExprResult::Array(Rc::new(vec![ExprResult::Str(Rc::new("a".to_string())), ExprResult::Date(far_date)]));
+        assert_eq!(FFIValue::into_raw(&items).map(|v| v.items_len), Err("The date '01/01/30000 00:00:00' is out of the .net range".to_string()));
+    }
+
+    #[test]
+    fn ffi_values_round_trip() {
+        let results = vec![
+            ExprResult::Null,
+            ExprResult::Str(Rc::new("é".to_string())),
+            ExprResult::Boolean(true),
+            ExprResult::Int(-42),
+            ExprResult::Num(Decimal::new(-125, 2)),
+            ExprResult::Num(Decimal::MIN),
+            ExprResult::Date(NaiveDate::from_ymd_opt(2020, 2, 3).unwrap().and_hms_nano_opt(4, 5, 6, 789_123_400).unwrap()),
+            ExprResult::TimeSpan(Duration::milliseconds(-90_061_001)),
+            ExprResult::Array(Rc::new(vec![])),
+            ExprResult::Array(Rc::new(vec![ExprResult::Int(1), ExprResult::Array(Rc::new(vec![ExprResult::Str(Rc::new("a".to_string())), ExprResult::Null]))])),
+            ExprResult::Record(Rc::new(vec![])),
+            ExprResult::Record(Rc::new(vec![("Price".to_string(), ExprResult::Int(3)), ("Tags".to_string(), ExprResult::Array(Rc::new(vec![ExprResult::Null])))])),
+        ];
+        for result in results {
+            let owned = OwnedFFIValue::from_result(&result).unwrap();
+            assert_eq!(owned.value.to_result().map(|r| format!("{:?}", r)), Ok(format!("{:?}", result)));
+            let raw = FFIValue::into_raw(&result).unwrap();
+            assert_eq!(raw.to_result().map(|r| format!("{:?}", r)), Ok(format!("{:?}", result)));
+            ffi_free_value(raw);
+        }
+    }
 }
@@ -1,27 +1,32 @@
 use crate::expressions::*;
+use crate::registry::{FunctionMetadata, FunctionPack};
+use crate::types::{ExprType, FunctionParameter, FunctionSignature};
 use chrono::{prelude::*, Duration, TimeZone};
 use chrono_tz::Tz;
 use num_format::{Locale, ToFormattedString};
 use regex::{Regex, RegexBuilder};
 use rust_decimal::prelude::*;
 use rust_decimal_macros::*;
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::rc::Rc;
+use std::sync::Arc;
 use unicase::UniCase;
 
 fn get_rc_empty_string() -> Rc<String> {
     Rc::new(String::with_capacity(0))
 }
 
-fn exec_vec_is_null(params: &SliceRcExpr, values: &IdentifierValues) -> Result<bool, String> {
+fn exec_vec_is_null(params: &SliceSharedExpr, values: &IdentifierValues) -> Result<bool, ExecError> {
     match params.len() {
         0 => Ok(true),
         1 => exec_expr_is_null(params.get(0).unwrap(), values),
-        _ => Err("is_null only takes 0 or 1 parameter".to_string()),
+        _ => Err("is_null only takes 0 or 1 parameter".into()),
     }
 }
 
-fn exec_expr_is_null(expr: &RcExpr, values: &IdentifierValues) -> Result<bool, String> {
+fn exec_expr_is_null(expr: &SharedExpr, values: &IdentifierValues) -> Result<bool, ExecError> {
     let res = exec_expr(expr, values)?;
     Ok(expr_result_is_null(&res))
 }
@@ -42,25 +47,108 @@ fn results_are_equals(left: &ExprResult, right: &ExprResult) -> bool {
     }
 }
 
-fn result_to_string(expr: &ExprResult) -> Result<Rc<String>, String> {
+fn result_to_string(expr: &ExprResult) -> Result<Rc<String>, ExecError> {
     if expr.is_final() {
         Ok(expr.to_rc_string())
     } else {
-        Err("Can't change this expression to string".to_string())
+        Err("Can't change this expression to string".into())
     }
 }
 
-fn exec_expr_to_string(expr: &RcExpr, values: &IdentifierValues) -> Result<Rc<String>, String> {
+fn exec_expr_to_string(expr: &SharedExpr, values: &IdentifierValues) -> Result<Rc<String>, ExecError> {
     let res = exec_expr(expr, values)?;
     result_to_string(&res)
 }
 
-fn exec_expr_to_num(expr: &RcExpr, values: &IdentifierValues, decimal_separator: Option<char>) -> Result<ExprDecimal, String> {
-    let res = exec_expr(expr, values)?;
+fn result_to_array(res: &ExprResult) -> Result<Rc<Vec<ExprResult>>, ExecError> {
+    match res {
+        ExprResult::Array(items) => Ok(items.clone()),
+        res => Err(format!("The value '{}' is not an array.", result_to_string(res)?).into()),
+    }
+}
+
+// The items of an array, or the value itself, as the functions taking many values also take them in arrays
+fn flatten_result(res: ExprResult) -> Vec<ExprResult> {
+    match res {
+        ExprResult::Array(items) => items.iter().cloned().flat_map(flatten_result).collect(),
+        res => vec![res],
+    }
+}
+
+// The items of an array, none for a null
+fn exec_expr_to_items(expr: &SharedExpr, values: &IdentifierValues) -> Result<Rc<Vec<ExprResult>>, ExecError> {
+    match exec_expr(expr, values)? {
+        ExprResult::Null => Ok(Rc::new(vec![])),
+        res => result_to_array(&res),
+    }
+}
+
+// The parameter name and the body of `x => x > 10`
+type LambdaRef<'a> = (&'a str, &'a SharedExpr);
+
+// the lambda given at `position` (from 1) to the function
+fn expr_to_lambda<'a>(expr: &'a SharedExpr, position: usize, f_name: &str) -> Result<LambdaRef<'a>, ExecError> {
+    match expr.without_span() {
+        Expr::Lambda(parameter, body) => Ok((parameter, body)),
+        _ => Err(ExecError {
+            message: format!("The parameter {} of {} is not a lambda, like 'x => x > 10'", position, f_name),
+            span: expr.span(),
+        }),
+    }
+}
+
+// The body is executed for each item, only when the function needs it
+fn call_lambda(lambda: LambdaRef, item: &ExprResult, values: &IdentifierValues) -> ExprFuncResult {
+    let (parameter, body) = lambda;
+    values.with_parameter(parameter, item.clone(), || exec_expr(body, values))
+}
+
+// Whether the item matches the optional predicate, every item does without one
+fn matches_lambda(predicate: Option<LambdaRef>, item: &ExprResult, values: &IdentifierValues) -> Result<bool, ExecError> {
+    match predicate {
+        Some(predicate) => Ok(result_to_bool(&call_lambda(predicate, item, values)?)),
+        None => Ok(true),
+    }
+}
+
+// items[index], from 0 like in C#
+pub fn index_value(target: ExprResult, index: ExprResult) -> ExprFuncResult {
+    let items = result_to_array(&target)?;
+    let index = match result_to_number(index)? {
+        Number::Int(i) => i,
+        Number::Decimal(d) if d.fract().is_zero() => d.to_i64().ok_or_else(|| format!("The index {} is out of the {} items", d, items.len()))?,
+        Number::Decimal(d) => return Err(format!("The index {} is not an integer", d).into()),
+    };
+    usize::try_from(index)
+        .ok()
+        .and_then(|i| items.get(i))
+        .cloned()
+        .ok_or_else(|| format!("The index {} is out of the {} items", index, items.len()).into())
+}
+
+// `x.Price`, the field of a record, its name matched as the identifiers are
+pub fn member_value(target: ExprResult, name: &str, case: IdentifierCase) -> ExprFuncResult {
+    match &target {
+        ExprResult::Record(fields) => fields
+            .iter()
+            .find(|(n, _)| case.same_names(n, name))
+            .map(|(_, value)| value.clone())
+            .ok_or_else(|| format!("The record '{}' has no field '{}'", target, name).into()),
+        _ => Err(format!("The value '{}' is not a record, it has no field '{}'", target, name).into()),
+    }
+}
+
+fn exec_expr_to_num(expr: &SharedExpr, values: &IdentifierValues, decimal_separator: Option<char>) -> Result<ExprDecimal, ExecError> {
+    result_to_num(exec_expr(expr, values)?, decimal_separator)
+}
+
+fn result_to_num(res: ExprResult, decimal_separator: Option<char>) -> Result<ExprDecimal, ExecError> {
     if let ExprResult::Num(n) = res {
         Ok(n)
+    } else if let ExprResult::Int(i) = res {
+        Ok(ExprDecimal::from(i))
     } else {
-        let mut s = exec_expr_to_string(expr, values)?.to_string();
+        let mut s = result_to_string(&res)?.to_string();
         // if s.is_empty() {
         //     return Ok(dec!(0));
         // }
@@ -72,49 +160,133 @@ fn exec_expr_to_num(expr: &RcExpr, values: &IdentifierValues, decimal_separator:
                 s = s.replace(",", ".")
             }
         }
-        let n: ExprDecimal = s.parse().or_else(|_| Err(format!("The value '{}' is not a number.", exec_expr_to_string(expr, values)?)))?;
+        let n: ExprDecimal = match s.parse() {
+            Ok(n) => n,
+            Err(_) => return Err(format!("The value '{}' is not a number.", result_to_string(&res)?).into()),
+        };
         Ok(n)
     }
 }
 
-fn exec_expr_to_float(expr: &RcExpr, values: &IdentifierValues, decimal_separator: Option<char>) -> Result<f64, String> {
+// An integer stays an integer through the arithmetic as long as all the operands are integers and the result fits,
+// otherwise it is promoted to a decimal
+#[derive(Clone, Copy)]
+enum Number {
+    Int(i64),
+    Decimal(ExprDecimal),
+}
+
+impl Number {
+    fn to_decimal(self) -> ExprDecimal {
+        match self {
+            Number::Int(i) => ExprDecimal::from(i),
+            Number::Decimal(d) => d,
+        }
+    }
+
+    fn combine(self, other: Number, int_op: fn(i64, i64) -> Option<i64>, decimal_op: fn(ExprDecimal, ExprDecimal) -> Option<ExprDecimal>) -> Option<Number> {
+        if let (Number::Int(a), Number::Int(b)) = (self, other) {
+            if let Some(result) = int_op(a, b) {
+                return Some(Number::Int(result));
+            }
+        }
+        decimal_op(self.to_decimal(), other.to_decimal()).map(Number::Decimal)
+    }
+}
+
+impl std::fmt::Display for Number {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Number::Int(i) => write!(f, "{}", i),
+            Number::Decimal(d) => write!(f, "{}", d),
+        }
+    }
+}
+
+impl From<Number> for ExprResult {
+    fn from(number: Number) -> Self {
+        match number {
+            Number::Int(i) => ExprResult::Int(i),
+            Number::Decimal(d) => ExprResult::Num(d),
+        }
+    }
+}
+
+fn exec_expr_to_number(expr: &SharedExpr, values: &IdentifierValues) -> Result<Number, ExecError> {
+    result_to_number(exec_expr(expr, values)?)
+}
+
+fn result_to_number(res: ExprResult) -> Result<Number, ExecError> {
+    match res {
+        ExprResult::Int(i) => Ok(Number::Int(i)),
+        ExprResult::Num(n) => Ok(Number::Decimal(n)),
+        res => Ok(Number::Decimal(result_to_num(res, None)?)),
+    }
+}
+
+fn add_numbers(a: Number, b: Number) -> Result<Number, ExecError> {
+    Ok(a.combine(b, i64::checked_add, ExprDecimal::checked_add).ok_or_else(|| format!("Couldn't add {} to {} : overflow", b, a))?)
+}
+
+fn multiply_numbers(a: Number, b: Number) -> Result<Number, ExecError> {
+    Ok(a.combine(b, i64::checked_mul, ExprDecimal::checked_mul).ok_or_else(|| format!("Couldn't multiply {} by {} : overflow", a, b))?)
+}
+
+fn subtract_numbers(num: Number, sub: Number) -> Result<Number, ExecError> {
+    Ok(num.combine(sub, i64::checked_sub, ExprDecimal::checked_sub).ok_or_else(|| format!("Couldn't remove {} from {}", sub, num))?)
+}
+
+fn modulo_numbers(num: Number, divisor: Number) -> Result<Number, ExecError> {
+    Ok(num.combine(divisor, i64::checked_rem, ExprDecimal::checked_rem).ok_or_else(|| format!("Couldn't module {} by {}", num, divisor))?)
+}
+
+fn divide_decimals(num: ExprDecimal, divisor: ExprDecimal) -> Result<ExprDecimal, ExecError> {
+    Ok(std::panic::catch_unwind(|| num / divisor).map_err(|_| format!("Couldn't divide {} by {}", num, divisor))?)
+}
+
+fn exec_expr_to_float(expr: &SharedExpr, values: &IdentifierValues, decimal_separator: Option<char>) -> Result<f64, ExecError> {
     let num = exec_expr_to_num(expr, values, decimal_separator)?;
-    num.to_f64().ok_or_else(|| "Error casting value to float.".to_string())
+    num.to_f64().ok_or_else(|| "Error casting value to float.".into())
 }
 
-// fn exec_expr_to_int(expr: &RcExpr, values: &IdentifierValues, decimal_separator: Option<char>) -> Result<i64, String> {
+// fn exec_expr_to_int(expr: &SharedExpr, values: &IdentifierValues, decimal_separator: Option<char>) -> Result<i64, String> {
 //     let num = exec_expr_to_num(expr, values, decimal_separator)?;
 //     num.to_i64().ok_or_else(|| "Error casting value to integer".to_string())
 // }
 
-fn exec_expr_to_int(expr: &RcExpr, values: &IdentifierValues) -> Result<isize, String> {
+fn exec_expr_to_int(expr: &SharedExpr, values: &IdentifierValues) -> Result<isize, ExecError> {
     let res = exec_expr(expr, values)?;
     match &res {
         ExprResult::Num(n) => Ok(n.to_isize().ok_or_else(|| "Error casting value to integer".to_string())?),
+        ExprResult::Int(i) => Ok(i.to_isize().ok_or_else(|| "Error casting value to integer".to_string())?),
         ExprResult::Str(s) => Ok(s.parse::<isize>().or_else(|_| Err(format!("The value '{}' is not a integer.", s)))?),
-        expr => Err(format!("The value '{}' is not a number, nor a string.", expr)),
+        expr => Err(format!("The value '{}' is not a number, nor a string.", expr).into()),
     }
 }
 
-fn exec_expr_to_bool(expr: &RcExpr, values: &IdentifierValues) -> Result<bool, String> {
+pub fn exec_expr_to_bool(expr: &SharedExpr, values: &IdentifierValues) -> Result<bool, ExecError> {
+    Ok(result_to_bool(&exec_expr(expr, values)?))
+}
+
+pub fn result_to_bool(res: &ExprResult) -> bool {
     lazy_static! {
         static ref TRUE_STRING: Regex = RegexBuilder::new("^\\s*(true|1)\\s*$").case_insensitive(true).build().unwrap();
     }
-    let res = exec_expr(expr, values)?;
-    match &res {
-        ExprResult::Boolean(b) => Ok(*b),
-        ExprResult::Num(n) => Ok(*n == dec!(1)),
-        ExprResult::Str(s) => Ok(TRUE_STRING.is_match(&*s)),
-        _ => Ok(false),
+    match res {
+        ExprResult::Boolean(b) => *b,
+        ExprResult::Num(n) => *n == dec!(1),
+        ExprResult::Int(i) => *i == 1,
+        ExprResult::Str(s) => TRUE_STRING.is_match(&*s),
+        _ => false,
     }
 }
 
-fn exec_expr_to_date_no_defaults(expr: &RcExpr, values: &IdentifierValues) -> Result<NaiveDateTime, String> {
+fn exec_expr_to_date_no_defaults(expr: &SharedExpr, values: &IdentifierValues) -> Result<NaiveDateTime, ExecError> {
     exec_expr_to_date(expr, values, false, false, false, false, false, false)
 }
 
 fn exec_expr_to_date(
-    expr: &RcExpr,
+    expr: &SharedExpr,
     values: &IdentifierValues,
     default_year: bool,
     default_month: bool,
@@ -122,8 +294,11 @@ fn exec_expr_to_date(
     default_hour: bool,
     default_minute: bool,
     default_second: bool,
-) -> Result<NaiveDateTime, String> {
-    let res = exec_expr(expr, values)?;
+) -> Result<NaiveDateTime, ExecError> {
+    result_to_date(exec_expr(expr, values)?, default_year, default_month, default_day, default_hour, default_minute, default_second)
+}
+
+fn result_to_date(res: ExprResult, default_year: bool, default_month: bool, default_day: bool, default_hour: bool, default_minute: bool, default_second: bool) -> Result<NaiveDateTime, ExecError> {
     let mut date_time = match &res {
         ExprResult::Date(d) => *d,
         e => {
@@ -214,7 +389,26 @@ fn exec_expr_to_date(
     Ok(date_time)
 }
 
-fn assert_exact_params_count(params: &SliceRcExpr, count: usize, f_name: &str) -> Result<(), String> {
+// Fails as the functions do when the value is given for a parameter of this type, see `types::infer_expr_type`
+pub fn check_result_type(res: &ExprResult, expected: ExprType) -> Result<(), ExecError> {
+    match expected {
+        ExprType::Number => result_to_num(res.clone(), None).map(|_| ()),
+        ExprType::Date => result_to_date(res.clone(), false, false, false, false, false, false).map(|_| ()),
+        ExprType::Text => result_to_string(res).map(|_| ()),
+        ExprType::Array => match res {
+            ExprResult::Null => Ok(()),
+            res => result_to_array(res).map(|_| ()),
+        },
+        ExprType::Record => match res {
+            ExprResult::Null | ExprResult::Record(_) => Ok(()),
+            res => Err(format!("The value '{}' is not a record.", result_to_string(res)?).into()),
+        },
+        ExprType::Lambda => Err(format!("The value '{}' is not a lambda.", result_to_string(res)?).into()),
+        ExprType::Any | ExprType::Null | ExprType::Boolean | ExprType::TimeSpan => Ok(()),
+    }
+}
+
+fn assert_exact_params_count(params: &SliceSharedExpr, count: usize, f_name: &str) -> Result<(), String> {
     if params.len() == count {
         Ok(())
     } else {
@@ -222,7 +416,7 @@ fn assert_exact_params_count(params: &SliceRcExpr, count: usize, f_name: &str) -
     }
 }
 
-fn assert_max_params_count(params: &SliceRcExpr, count: usize, f_name: &str) -> Result<(), String> {
+fn assert_max_params_count(params: &SliceSharedExpr, count: usize, f_name: &str) -> Result<(), String> {
     if params.len() <= count {
         Ok(())
     } else {
@@ -230,7 +424,7 @@ fn assert_max_params_count(params: &SliceRcExpr, count: usize, f_name: &str) ->
     }
 }
 
-fn assert_min_params_count(params: &SliceRcExpr, count: usize, f_name: &str) -> Result<(), String> {
+fn assert_min_params_count(params: &SliceSharedExpr, count: usize, f_name: &str) -> Result<(), String> {
     if params.len() >= count {
         Ok(())
     } else {
@@ -238,7 +432,7 @@ fn assert_min_params_count(params: &SliceRcExpr, count: usize, f_name: &str) ->
     }
 }
 
-fn assert_between_params_count(params: &SliceRcExpr, count_min: usize, count_max: usize, f_name: &str) -> Result<(), String> {
+fn assert_between_params_count(params: &SliceSharedExpr, count_min: usize, count_max: usize, f_name: &str) -> Result<(), String> {
     let len = params.len();
     if len < count_min || len > count_max {
         Err(format!("Function {} should have between {} and {} parameters", f_name, count_min, count_max).to_string())
@@ -247,6 +441,15 @@ fn assert_between_params_count(params: &SliceRcExpr, count_min: usize, count_max
     }
 }
 
+// `None` for no maximum
+pub fn assert_params_count(params: &SliceSharedExpr, count_min: usize, count_max: Option<usize>, f_name: &str) -> Result<(), String> {
+    match count_max {
+        Some(count_max) if count_max == count_min => assert_exact_params_count(params, count_min, f_name),
+        Some(count_max) => assert_between_params_count(params, count_min, count_max, f_name),
+        None => assert_min_params_count(params, count_min, f_name),
+    }
+}
+
 /**********************************/
 /*          Regex helpers         */
 /**********************************/
@@ -340,102 +543,783 @@ fn make_case_insensitive_like_regex(search_pattern: &str) -> Result<Regex, Strin
 
 pub fn get_functions() -> FunctionImplList {
     let mut funcs = FunctionImplList::new();
-    funcs.insert(UniCase::new("IsNull".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_is_null)));
-    funcs.insert(UniCase::new("IsBlank".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_is_null)));
-    funcs.insert(UniCase::new("AreEquals".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_are_equals)));
-    funcs.insert(UniCase::new("In".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_in)));
-    funcs.insert(UniCase::new("InLike".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_in_like)));
-    funcs.insert(UniCase::new("IsLike".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_is_like)));
-    funcs.insert(UniCase::new("Like".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_is_like)));
-    funcs.insert(UniCase::new("FirstNotNull".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_first_not_null)));
-    funcs.insert(UniCase::new("FirstNotEmpty".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_first_not_null)));
-    funcs.insert(UniCase::new("Concatenate".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_concat)));
-    funcs.insert(UniCase::new("Concat".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_concat)));
-    funcs.insert(UniCase::new("Exact".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_exact)));
-    funcs.insert(UniCase::new("Find".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_find)));
-    funcs.insert(UniCase::new("Substitute".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_substitute)));
-    funcs.insert(UniCase::new("Fixed".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_fixed)));
-    funcs.insert(UniCase::new("Left".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_left)));
-    funcs.insert(UniCase::new("Right".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_right)));
-    funcs.insert(UniCase::new("Mid".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_mid)));
-    funcs.insert(UniCase::new("Len".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_len)));
-    funcs.insert(UniCase::new("Lower".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_lower)));
-    funcs.insert(UniCase::new("Upper".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_upper)));
-    funcs.insert(UniCase::new("Trim".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_trim)));
-    funcs.insert(UniCase::new("FirstWord".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_first_word)));
-    funcs.insert(UniCase::new("FirstSentence".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_first_sentence)));
-    funcs.insert(UniCase::new("Capitalize".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_capitalize)));
-    funcs.insert(UniCase::new("Split".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_split)));
-    funcs.insert(UniCase::new("NumberValue".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_number_value)));
-    funcs.insert(UniCase::new("Text".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_text)));
-    funcs.insert(UniCase::new("StartsWith".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_starts_with)));
-    funcs.insert(UniCase::new("EndsWith".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_ends_with)));
-    funcs.insert(UniCase::new("ReplaceEquals".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_replace_equals)));
-    funcs.insert(UniCase::new("ReplaceLike".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_replace_like)));
-    funcs.insert(UniCase::new("And".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_and)));
-    funcs.insert(UniCase::new("Or".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_or)));
-    funcs.insert(UniCase::new("Not".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_not)));
-    funcs.insert(UniCase::new("Xor".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_xor)));
-    funcs.insert(UniCase::new("Iif".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_iif)));
-    funcs.insert(UniCase::new("If".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_iif)));
-    funcs.insert(UniCase::new("Abs".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_abs)));
-    funcs.insert(UniCase::new("Product".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_product)));
-    funcs.insert(UniCase::new("Sum".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_sum)));
-    funcs.insert(UniCase::new("Divide".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_divide)));
-    funcs.insert(UniCase::new("Subtract".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_subtract)));
-    funcs.insert(UniCase::new("Mod".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_mod)));
-    funcs.insert(UniCase::new("Modulo".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_mod)));
-    funcs.insert(UniCase::new("Round".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_round)));
-    funcs.insert(UniCase::new("GreaterThan".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_greater_than)));
-    funcs.insert(UniCase::new("Gt".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_greater_than)));
-    funcs.insert(UniCase::new("LowerThan".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_lower_than)));
-    funcs.insert(UniCase::new("Lt".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_lower_than)));
-    funcs.insert(UniCase::new("GreaterThanOrEqual".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_greater_than_or_equal)));
-    funcs.insert(UniCase::new("Gtoe".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_greater_than_or_equal)));
-    funcs.insert(UniCase::new("LowerThanOrEqual".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_lower_than_or_equal)));
-    funcs.insert(UniCase::new("Ltoe".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_lower_than_or_equal)));
-    funcs.insert(UniCase::new("Date".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_date)));
-    funcs.insert(UniCase::new("Now".to_string()), (FunctionDeterminism::NonDeterministic, Rc::new(f_now)));
-    funcs.insert(UniCase::new("Year".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_year)));
-    funcs.insert(UniCase::new("Month".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_month)));
-    funcs.insert(UniCase::new("Day".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_day)));
-    funcs.insert(UniCase::new("DateDiff".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_date_diff)));
-    funcs.insert(UniCase::new("DateDiffHours".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_date_diff_hours)));
-    funcs.insert(UniCase::new("DateDiffDays".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_date_diff_days)));
-    funcs.insert(UniCase::new("DateDiffMonths".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_date_diff_months)));
-    funcs.insert(UniCase::new("DateEquals".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_date_equals)));
-    funcs.insert(UniCase::new("DateNotEquals".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_date_not_equals)));
-    funcs.insert(UniCase::new("DateLower".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_date_lower)));
-    funcs.insert(UniCase::new("DateLowerOrEquals".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_date_lower_or_equals)));
-    funcs.insert(UniCase::new("DateGreater".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_date_greater)));
-    funcs.insert(UniCase::new("DateGreaterOrEquals".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_date_greater_or_equals)));
-    funcs.insert(UniCase::new("DateAddHours".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_date_add_hours)));
-    funcs.insert(UniCase::new("DateAddDays".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_date_add_days)));
-    funcs.insert(UniCase::new("DateAddMonths".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_date_add_months)));
-    funcs.insert(UniCase::new("DateAddYears".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_date_add_years)));
-    funcs.insert(UniCase::new("LocalDate".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_local_date)));
-    funcs.insert(UniCase::new("DateFormat".to_string()), (FunctionDeterminism::Deterministic, Rc::new(f_date_format)));
-    funcs.insert(UniCase::new("NowSpecificTimeZone".to_string()), (FunctionDeterminism::NonDeterministic, Rc::new(f_now_specific_timezone)));
-    funcs.insert(UniCase::new("Today".to_string()), (FunctionDeterminism::NonDeterministic, Rc::new(f_today)));
-    funcs.insert(UniCase::new("Time".to_string()), (FunctionDeterminism::NonDeterministic, Rc::new(f_time)));
+    for pack in FunctionPack::ALL.iter() {
+        funcs.extend(get_pack_functions(*pack));
+    }
+    funcs
+}
+
+lazy_static! {
+    // `Concat` and `Format`, as the interpolated strings call them whatever the registry has
+    static ref INTERPOLATION_FUNCTIONS: FunctionImplList = {
+        let mut strings = get_pack_functions(FunctionPack::Strings);
+        ["Concat", "Format"].iter().map(|name| UniCase::new(name.to_string())).map(|name| (name.clone(), strings.remove(&name).unwrap())).collect()
+    };
+}
+
+// The implementation bound by the parser to the calls of an interpolated string, see `parsing::interpolated_string_expr`
+pub fn interpolation_function(name: &str) -> Arc<FunctionImpl> {
+    Arc::clone(&INTERPOLATION_FUNCTIONS[&UniCase::new(name.to_string())].implementation)
+}
+
+// The definition of a call bound by `interpolation_function`, if it's one
+pub fn interpolation_definition(implementation: &Arc<FunctionImpl>) -> Option<&'static FunctionDefinition> {
+    INTERPOLATION_FUNCTIONS.values().find(|definition| Arc::ptr_eq(&definition.implementation, implementation))
+}
+
+pub fn get_pack_functions(pack: FunctionPack) -> FunctionImplList {
+    use ExprType::*;
+    use FunctionDeterminism::*;
+    let p = FunctionParameter::new;
+    let opt = FunctionParameter::with_default;
+    let mut funcs = FunctionImplList::new();
+    // the first name is the canonical one, the others are aliases sharing the same implementation
+    let mut add = |names: &[&str], determinism, arity, signature, description: &str, implementation: Arc<FunctionImpl>| {
+        let metadata = FunctionMetadata {
+            name: names[0].to_string(),
+            aliases: names[1..].iter().map(|name| name.to_string()).collect(),
+            category: pack.to_string(),
+            description: description.to_string(),
+        };
+        let definition = FunctionDefinition::new(determinism, arity, signature, implementation).with_metadata(metadata);
+        for name in names {
+            funcs.insert(UniCase::new(name.to_string()), definition.clone());
+        }
+    };
+    match pack {
+        FunctionPack::Strings => {
+            add(
+                &["InLike"],
+                Deterministic,
+                FunctionArity::at_least(2),
+                FunctionSignature::new(vec![p("pattern", Text), p("texts", Text)], Boolean),
+                "Whether one of the texts matches the pattern, where % is any text and _ any character, ignoring the case",
+                Arc::new(f_in_like),
+            );
+            add(
+                &["IsLike", "Like"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("text", Text), p("pattern", Text)], Boolean),
+                "Whether the text matches the pattern, where % is any text and _ any character, ignoring the case",
+                Arc::new(f_is_like),
+            );
+            add(
+                &["Concat", "Concatenate"],
+                Deterministic,
+                FunctionArity::at_least(0),
+                FunctionSignature::new(vec![p("texts", Any)], Text),
+                "Joins the texts, and the ones of the arrays",
+                Arc::new(f_concat),
+            );
+            add(
+                &["Format"],
+                Deterministic,
+                FunctionArity::between(1, 2),
+                FunctionSignature::new(vec![p("value", Any), p("format", Text)], Text),
+                "The text of the value, with a .NET format as N2 or yyyy-MM-dd, like a hole of an interpolated string",
+                Arc::new(f_format),
+            );
+            add(
+                &["Exact"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("left", Text), p("right", Text)], Boolean),
+                "Whether the two texts are the same, with the same case",
+                Arc::new(f_exact),
+            );
+            add(
+                &["Find"],
+                Deterministic,
+                FunctionArity::between(2, 3),
+                FunctionSignature::new(vec![p("findText", Text), p("withinText", Text), opt("startNum", Number, "1")], Number),
+                "The position of findText in withinText from startNum, ignoring the case, starting at 1 and 0 when not found",
+                Arc::new(f_find),
+            );
+            add(
+                &["Substitute"],
+                Deterministic,
+                FunctionArity::exactly(3),
+                FunctionSignature::new(vec![p("text", Text), p("findText", Text), p("replaceText", Text)], Text),
+                "Replaces every findText of the text by replaceText",
+                Arc::new(f_substitute),
+            );
+            add(
+                &["Fixed"],
+                Deterministic,
+                FunctionArity::between(1, 3),
+                FunctionSignature::new(vec![p("number", Number), opt("decimals", Number, "2"), opt("noCommas", Boolean, "true")], Text),
+                "The number rounded to the decimals, with commas between the thousands unless noCommas",
+                Arc::new(f_fixed),
+            );
+            add(
+                &["Left"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("text", Text), p("count", Number)], Text),
+                "The first count characters of the text",
+                Arc::new(f_left),
+            );
+            add(
+                &["Right"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("text", Text), p("count", Number)], Text),
+                "The last count characters of the text",
+                Arc::new(f_right),
+            );
+            add(
+                &["Mid"],
+                Deterministic,
+                FunctionArity::exactly(3),
+                FunctionSignature::new(vec![p("text", Text), p("start", Number), p("count", Number)], Text),
+                "The count characters of the text from start, starting at 1",
+                Arc::new(f_mid),
+            );
+            add(
+                &["Len"],
+                Deterministic,
+                FunctionArity::exactly(1),
+                FunctionSignature::new(vec![p("text", Text)], Number),
+                "The characters count of the text",
+                Arc::new(f_len),
+            );
+            add(
+                &["Lower"],
+                Deterministic,
+                FunctionArity::exactly(1),
+                FunctionSignature::new(vec![p("text", Text)], Text),
+                "The text in lower case",
+                Arc::new(f_lower),
+            );
+            add(
+                &["Upper"],
+                Deterministic,
+                FunctionArity::exactly(1),
+                FunctionSignature::new(vec![p("text", Text)], Text),
+                "The text in upper case",
+                Arc::new(f_upper),
+            );
+            add(
+                &["Trim"],
+                Deterministic,
+                FunctionArity::exactly(1),
+                FunctionSignature::new(vec![p("text", Text)], Text),
+                "The text without its leading and trailing spaces",
+                Arc::new(f_trim),
+            );
+            add(
+                &["FirstWord"],
+                Deterministic,
+                FunctionArity::exactly(1),
+                FunctionSignature::new(vec![p("text", Text)], Text),
+                "The first word of the text",
+                Arc::new(f_first_word),
+            );
+            add(
+                &["FirstSentence"],
+                Deterministic,
+                FunctionArity::exactly(1),
+                FunctionSignature::new(vec![p("text", Text)], Text),
+                "The first sentence of the text",
+                Arc::new(f_first_sentence),
+            );
+            add(
+                &["Capitalize"],
+                Deterministic,
+                FunctionArity::exactly(1),
+                FunctionSignature::new(vec![p("text", Text)], Text),
+                "The text with its first character in upper case",
+                Arc::new(f_capitalize),
+            );
+            add(
+                &["Split"],
+                Deterministic,
+                FunctionArity::between(2, 3),
+                FunctionSignature::new(vec![p("text", Text), p("separator", Text), p("index", Number)], Any),
+                "The parts of the text split by the separator, or only the one at the index, starting at 0 and null when missing",
+                Arc::new(f_split),
+            );
+            add(
+                &["Text"],
+                Deterministic,
+                FunctionArity::exactly(1),
+                FunctionSignature::new(vec![p("value", Text)], Text),
+                "The value as a text",
+                Arc::new(f_text),
+            );
+            add(
+                &["StartsWith"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("text", Text), p("start", Text)], Boolean),
+                "Whether the text starts with start, ignoring the case",
+                Arc::new(f_starts_with),
+            );
+            add(
+                &["EndsWith"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("text", Text), p("end", Text)], Boolean),
+                "Whether the text ends with end, ignoring the case",
+                Arc::new(f_ends_with),
+            );
+            add(
+                &["ReplaceEquals"],
+                Deterministic,
+                FunctionArity::at_least(4),
+                FunctionSignature::new(vec![p("text", Text), p("default", Any), p("pattern", Text), p("replacement", Any)], Any),
+                "The replacement following the first pattern equal to the text, where * is any text, or the default. The patterns and replacements come 2 by 2",
+                Arc::new(f_replace_equals),
+            );
+            add(
+                &["ReplaceLike"],
+                Deterministic,
+                FunctionArity::at_least(4),
+                FunctionSignature::new(vec![p("text", Text), p("default", Any), p("pattern", Text), p("replacement", Any)], Any),
+                "The replacement following the first pattern the text is like, where % is any text and _ any character, or the default. The patterns and replacements come 2 by 2",
+                Arc::new(f_replace_like),
+            );
+        }
+        FunctionPack::Dates => {
+            // the ignored parts of both dates are set to 1 before comparing them
+            let date_comparison = || {
+                let ignore = |part: &str| opt(part, Boolean, "false");
+                FunctionSignature::new(
+                    vec![
+                        p("left", Date),
+                        p("right", Date),
+                        ignore("ignoreYear"),
+                        ignore("ignoreMonth"),
+                        ignore("ignoreDay"),
+                        ignore("ignoreHour"),
+                        ignore("ignoreMinute"),
+                        ignore("ignoreSecond"),
+                    ],
+                    Boolean,
+                )
+            };
+            add(
+                &["Date"],
+                Deterministic,
+                FunctionArity::exactly(1),
+                FunctionSignature::new(vec![p("value", Date)], Date),
+                "The value as a date",
+                Arc::new(f_date),
+            );
+            add(
+                &["Now"],
+                NonDeterministic,
+                FunctionArity::exactly(0),
+                FunctionSignature::new(vec![], Date),
+                "The current date and time, in UTC",
+                Arc::new(f_now),
+            );
+            add(
+                &["Year"],
+                Deterministic,
+                FunctionArity::exactly(1),
+                FunctionSignature::new(vec![p("date", Date)], Number),
+                "The year of the date",
+                Arc::new(f_year),
+            );
+            add(
+                &["Month"],
+                Deterministic,
+                FunctionArity::exactly(1),
+                FunctionSignature::new(vec![p("date", Date)], Number),
+                "The month of the date, from 1 to 12",
+                Arc::new(f_month),
+            );
+            add(
+                &["Day"],
+                Deterministic,
+                FunctionArity::exactly(1),
+                FunctionSignature::new(vec![p("date", Date)], Number),
+                "The day of the month of the date",
+                Arc::new(f_day),
+            );
+            add(
+                &["DateDiff"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("left", Date), p("right", Date)], TimeSpan),
+                "The time span from right to left",
+                Arc::new(f_date_diff),
+            );
+            add(
+                &["DateDiffHours"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("left", Date), p("right", Date)], Number),
+                "The hours from right to left",
+                Arc::new(f_date_diff_hours),
+            );
+            add(
+                &["DateDiffDays"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("left", Date), p("right", Date)], Number),
+                "The days from right to left",
+                Arc::new(f_date_diff_days),
+            );
+            add(
+                &["DateDiffMonths"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("left", Date), p("right", Date)], Number),
+                "The months from right to left",
+                Arc::new(f_date_diff_months),
+            );
+            add(
+                &["DateEquals"],
+                Deterministic,
+                FunctionArity::between(2, 8),
+                date_comparison(),
+                "Whether the two dates are the same",
+                Arc::new(f_date_equals),
+            );
+            add(
+                &["DateNotEquals"],
+                Deterministic,
+                FunctionArity::between(2, 8),
+                date_comparison(),
+                "Whether the two dates are different",
+                Arc::new(f_date_not_equals),
+            );
+            add(
+                &["DateLower"],
+                Deterministic,
+                FunctionArity::between(2, 8),
+                date_comparison(),
+                "Whether the left date is before the right one",
+                Arc::new(f_date_lower),
+            );
+            add(
+                &["DateLowerOrEquals"],
+                Deterministic,
+                FunctionArity::between(2, 8),
+                date_comparison(),
+                "Whether the left date is before or the same as the right one",
+                Arc::new(f_date_lower_or_equals),
+            );
+            add(
+                &["DateGreater"],
+                Deterministic,
+                FunctionArity::between(2, 8),
+                date_comparison(),
+                "Whether the left date is after the right one",
+                Arc::new(f_date_greater),
+            );
+            add(
+                &["DateGreaterOrEquals"],
+                Deterministic,
+                FunctionArity::between(2, 8),
+                date_comparison(),
+                "Whether the left date is after or the same as the right one",
+                Arc::new(f_date_greater_or_equals),
+            );
+            add(
+                &["DateAddHours"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("date", Date), p("count", Number)], Date),
+                "The date moved by count hours",
+                Arc::new(f_date_add_hours),
+            );
+            add(
+                &["DateAddDays"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("date", Date), p("count", Number)], Date),
+                "The date moved by count days",
+                Arc::new(f_date_add_days),
+            );
+            add(
+                &["DateAddMonths"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("date", Date), p("count", Number)], Date),
+                "The date moved by count months",
+                Arc::new(f_date_add_months),
+            );
+            add(
+                &["DateAddYears"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("date", Date), p("count", Number)], Date),
+                "The date moved by count years",
+                Arc::new(f_date_add_years),
+            );
+            add(
+                &["LocalDate"],
+                Deterministic,
+                FunctionArity::between(1, 2),
+                FunctionSignature::new(vec![p("date", Date), opt("timeZone", Text, "Romance Standard Time")], Date),
+                "The UTC date in the time zone",
+                Arc::new(f_local_date),
+            );
+            add(
+                &["DateFormat"],
+                Deterministic,
+                FunctionArity::between(1, 2),
+                FunctionSignature::new(vec![p("date", Date), opt("format", Text, "yyyy-MM-dd HH:mm:ss.fff")], Text),
+                "The date as a text, with a .NET format",
+                Arc::new(f_date_format),
+            );
+            add(
+                &["NowSpecificTimeZone"],
+                NonDeterministic,
+                FunctionArity::between(0, 1),
+                FunctionSignature::new(vec![p("timeZone", Text)], Date),
+                "The current date and time in the time zone, in UTC without it",
+                Arc::new(f_now_specific_timezone),
+            );
+            add(
+                &["Today"],
+                NonDeterministic,
+                FunctionArity::exactly(0),
+                FunctionSignature::new(vec![], Date),
+                "The current date at midnight, in UTC",
+                Arc::new(f_today),
+            );
+            add(
+                &["Time"],
+                NonDeterministic,
+                FunctionArity::exactly(0),
+                FunctionSignature::new(vec![], TimeSpan),
+                "The time span since midnight, in UTC",
+                Arc::new(f_time),
+            );
+        }
+        FunctionPack::Math => {
+            add(
+                &["NumberValue"],
+                Deterministic,
+                FunctionArity::between(1, 2),
+                FunctionSignature::new(vec![p("text", Text), p("decimalSeparator", Text)], Number),
+                "The text as a number, with the decimal separator when given",
+                Arc::new(f_number_value),
+            );
+            add(
+                &["Abs"],
+                Deterministic,
+                FunctionArity::exactly(1),
+                FunctionSignature::new(vec![p("number", Number)], Number),
+                "The absolute value of the number",
+                Arc::new(f_abs),
+            );
+            add(
+                &["Product"],
+                Deterministic,
+                FunctionArity::at_least(0),
+                FunctionSignature::new(vec![p("numbers", Number)], Number),
+                "Multiplies the numbers",
+                Arc::new(f_product),
+            );
+            add(
+                &["Sum"],
+                Deterministic,
+                FunctionArity::at_least(0),
+                FunctionSignature::new(vec![p("numbers", Any)], Number),
+                "Adds the numbers, and the ones of the arrays, or the ones selected from the items of the array by the lambda",
+                Arc::new(f_sum),
+            );
+            add(
+                &["Divide"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("dividend", Number), p("divisor", Number)], Number),
+                "Divides the dividend by the divisor",
+                Arc::new(f_divide),
+            );
+            add(
+                &["Subtract"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("left", Number), p("right", Number)], Number),
+                "Subtracts right from left",
+                Arc::new(f_subtract),
+            );
+            add(
+                &["Mod", "Modulo"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("dividend", Number), p("divisor", Number)], Number),
+                "The remainder of the dividend divided by the divisor",
+                Arc::new(f_mod),
+            );
+            add(
+                &["Round"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("number", Number), p("digits", Number)], Number),
+                "The number rounded to the digits",
+                Arc::new(f_round),
+            );
+        }
+        FunctionPack::Logic => {
+            add(
+                &["IsNull", "IsBlank"],
+                Deterministic,
+                FunctionArity::between(0, 1),
+                FunctionSignature::new(vec![p("value", Any)], Boolean),
+                "Whether the value is null or an empty text, true without value",
+                Arc::new(f_is_null),
+            );
+            add(
+                &["AreEquals"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("left", Text), p("right", Text)], Boolean),
+                "Whether the two values are the same once as texts",
+                Arc::new(f_are_equals),
+            );
+            add(
+                &["In"],
+                Deterministic,
+                FunctionArity::at_least(2),
+                FunctionSignature::new(vec![p("value", Any), p("candidates", Any)], Boolean),
+                "Whether the value equals one of the candidates, or one of the items of the arrays",
+                Arc::new(f_in),
+            );
+            add(
+                &["FirstNotNull", "FirstNotEmpty"],
+                Deterministic,
+                FunctionArity::at_least(0),
+                FunctionSignature::common_of(vec![p("values", Any)], 0),
+                "The first value that is not null nor an empty text",
+                Arc::new(f_first_not_null),
+            );
+            add(
+                &["And"],
+                Deterministic,
+                FunctionArity::at_least(0),
+                FunctionSignature::new(vec![p("conditions", Boolean)], Boolean),
+                "Whether all the conditions are true",
+                Arc::new(f_and),
+            );
+            add(
+                &["Or"],
+                Deterministic,
+                FunctionArity::at_least(0),
+                FunctionSignature::new(vec![p("conditions", Boolean)], Boolean),
+                "Whether one of the conditions is true",
+                Arc::new(f_or),
+            );
+            add(
+                &["Not"],
+                Deterministic,
+                FunctionArity::exactly(1),
+                FunctionSignature::new(vec![p("condition", Boolean)], Boolean),
+                "The opposite of the condition",
+                Arc::new(f_not),
+            );
+            add(
+                &["Xor"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("left", Boolean), p("right", Boolean)], Boolean),
+                "Whether only one of the two conditions is true",
+                Arc::new(f_xor),
+            );
+            add(
+                &["Iif", "If"],
+                Deterministic,
+                FunctionArity::exactly(3),
+                FunctionSignature::common_of(vec![p("condition", Boolean), p("whenTrue", Any), p("whenFalse", Any)], 1),
+                "whenTrue when the condition is true, whenFalse otherwise",
+                Arc::new(f_iif),
+            );
+            add(
+                &["GreaterThan", "Gt"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("left", Number), p("right", Number)], Boolean),
+                "Whether left is greater than right",
+                Arc::new(f_greater_than),
+            );
+            add(
+                &["LowerThan", "Lt"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("left", Number), p("right", Number)], Boolean),
+                "Whether left is lower than right",
+                Arc::new(f_lower_than),
+            );
+            add(
+                &["GreaterThanOrEqual", "Gtoe"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("left", Number), p("right", Number)], Boolean),
+                "Whether left is greater than or equal to right",
+                Arc::new(f_greater_than_or_equal),
+            );
+            add(
+                &["LowerThanOrEqual", "Ltoe"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("left", Number), p("right", Number)], Boolean),
+                "Whether left is lower than or equal to right",
+                Arc::new(f_lower_than_or_equal),
+            );
+        }
+        FunctionPack::Collections => {
+            add(
+                &["Where"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("items", Array), p("predicate", Lambda)], Array),
+                "The items for which the predicate is true",
+                Arc::new(f_where),
+            );
+            add(
+                &["Select"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("items", Array), p("selector", Lambda)], Array),
+                "The results of the selector for each item",
+                Arc::new(f_select),
+            );
+            add(
+                &["Any"],
+                Deterministic,
+                FunctionArity::between(1, 2),
+                FunctionSignature::new(vec![p("items", Array), p("predicate", Lambda)], Boolean),
+                "Whether the predicate is true for one of the items, or whether there are items without predicate",
+                Arc::new(f_any),
+            );
+            add(
+                &["All"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("items", Array), p("predicate", Lambda)], Boolean),
+                "Whether the predicate is true for all the items, true without items",
+                Arc::new(f_all),
+            );
+            add(
+                &["Count"],
+                Deterministic,
+                FunctionArity::between(1, 2),
+                FunctionSignature::new(vec![p("items", Array), p("predicate", Lambda)], Number),
+                "The count of the items for which the predicate is true, of all the items without predicate",
+                Arc::new(f_count),
+            );
+            add(
+                &["First"],
+                Deterministic,
+                FunctionArity::between(1, 2),
+                FunctionSignature::new(vec![p("items", Array), p("predicate", Lambda)], Any),
+                "The first item for which the predicate is true, or the first item without predicate, an error when there is none",
+                Arc::new(f_first),
+            );
+            add(
+                &["FirstOrDefault"],
+                Deterministic,
+                FunctionArity::between(1, 2),
+                FunctionSignature::new(vec![p("items", Array), p("predicate", Lambda)], Any),
+                "The first item for which the predicate is true, or the first item without predicate, null when there is none",
+                Arc::new(f_first_or_default),
+            );
+            add(
+                &["OrderBy"],
+                Deterministic,
+                FunctionArity::exactly(2),
+                FunctionSignature::new(vec![p("items", Array), p("key", Lambda)], Array),
+                "The items sorted by the key of each, the items with the same key staying in the same order",
+                Arc::new(f_order_by),
+            );
+            add(
+                &["Distinct"],
+                Deterministic,
+                FunctionArity::exactly(1),
+                FunctionSignature::new(vec![p("items", Array)], Array),
+                "The items without the repeated ones, in the order they first appear",
+                Arc::new(f_distinct),
+            );
+        }
+    }
     funcs
 }
 
-pub fn f_operators(left: RcExpr, right: RcExpr, op: AssocOp, values: &IdentifierValues) -> ExprFuncResult {
-    match (op, left, right) {
-        (AssocOp::Add, l, r) => f_sum(&vec![l, r], values),
-        (AssocOp::Divide, l, r) => f_divide(&vec![l, r], values),
-        (AssocOp::Equal, l, r) => f_are_equals(&vec![l, r], values),
-        (AssocOp::Greater, l, r) => f_greater_than(&vec![l, r], values),
-        (AssocOp::GreaterEqual, l, r) => f_greater_than_or_equal(&vec![l, r], values),
-        (AssocOp::LAnd, l, r) => f_and(&vec![l, r], values),
-        (AssocOp::Less, l, r) => f_lower_than(&vec![l, r], values),
-        (AssocOp::LessEqual, l, r) => f_lower_than_or_equal(&vec![l, r], values),
-        (AssocOp::LOr, l, r) => f_or(&vec![l, r], values),
-        (AssocOp::Modulus, l, r) => f_mod(&vec![l, r], values),
-        (AssocOp::Multiply, l, r) => f_product(&vec![l, r], values),
-        (AssocOp::NotEqual, l, r) => f_are_not_equals(&vec![l, r], values),
-        (AssocOp::Subtract, l, r) => f_subtract(&vec![l, r], values),
+pub fn f_operators(left: SharedExpr, right: SharedExpr, op: AssocOp, values: &IdentifierValues) -> ExprFuncResult {
+    match op {
+        AssocOp::LAnd => f_and(&[left, right], values),
+        AssocOp::LOr => f_or(&[left, right], values),
+        AssocOp::NullCoalescing => f_null_coalescing(&left, &right, values),
+        _ => {
+            let left = convert_operand(exec_expr(&left, values)?, operand_conversion(op))?;
+            let right = convert_operand(exec_expr(&right, values)?, operand_conversion(op))?;
+            binary_operator_on_values(op, left, right)
+        }
+    }
+}
+
+pub fn f_unary_operators(operand: SharedExpr, op: UnaryOp, values: &IdentifierValues) -> ExprFuncResult {
+    unary_operator_on_value(op, exec_expr(&operand, values)?)
+}
+
+// What the built-in binary operators change each operand to, before working on them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OperandConversion {
+    Number,
+    Decimal,
+    TextOrNumber,
+    Bool,
+    Unchanged,
+}
+
+pub fn operand_conversion(op: AssocOp) -> OperandConversion {
+    match op {
+        AssocOp::Add | AssocOp::Subtract | AssocOp::Multiply | AssocOp::Modulus => OperandConversion::Number,
+        AssocOp::Divide | AssocOp::Greater | AssocOp::GreaterEqual | AssocOp::Less | AssocOp::LessEqual => OperandConversion::Decimal,
+        AssocOp::Equal | AssocOp::NotEqual => OperandConversion::TextOrNumber,
+        AssocOp::LAnd | AssocOp::LOr => OperandConversion::Bool,
+        AssocOp::NullCoalescing => OperandConversion::Unchanged,
+    }
+}
+
+pub fn convert_operand(res: ExprResult, conversion: OperandConversion) -> ExprFuncResult {
+    match conversion {
+        OperandConversion::Number => Ok(result_to_number(res)?.into()),
+        OperandConversion::Decimal => Ok(ExprResult::Num(result_to_num(res, None)?)),
+        // the numbers are compared by value, so `1 == 1.0` as in C#
+        OperandConversion::TextOrNumber => match res {
+            ExprResult::Int(_) | ExprResult::Num(_) => Ok(res),
+            res => Ok(ExprResult::Str(result_to_string(&res)?)),
+        },
+        OperandConversion::Bool => Ok(ExprResult::Boolean(result_to_bool(&res))),
+        OperandConversion::Unchanged => Ok(res),
+    }
+}
+
+// The eager built-in binary operators, on operands already changed by `convert_operand`.
+// `&&`, `||` and `??` only execute their right side when needed, so they have no value form.
+pub fn binary_operator_on_values(op: AssocOp, left: ExprResult, right: ExprResult) -> ExprFuncResult {
+    match op {
+        AssocOp::Add => Ok(add_numbers(result_to_number(left)?, result_to_number(right)?)?.into()),
+        AssocOp::Subtract => Ok(subtract_numbers(result_to_number(left)?, result_to_number(right)?)?.into()),
+        AssocOp::Multiply => Ok(multiply_numbers(result_to_number(left)?, result_to_number(right)?)?.into()),
+        AssocOp::Modulus => Ok(modulo_numbers(result_to_number(left)?, result_to_number(right)?)?.into()),
+        AssocOp::Divide => Ok(ExprResult::Num(divide_decimals(result_to_num(left, None)?, result_to_num(right, None)?)?)),
+        AssocOp::Greater => Ok(ExprResult::Boolean(result_to_num(left, None)? > result_to_num(right, None)?)),
+        AssocOp::GreaterEqual => Ok(ExprResult::Boolean(result_to_num(left, None)? >= result_to_num(right, None)?)),
+        AssocOp::Less => Ok(ExprResult::Boolean(result_to_num(left, None)? < result_to_num(right, None)?)),
+        AssocOp::LessEqual => Ok(ExprResult::Boolean(result_to_num(left, None)? <= result_to_num(right, None)?)),
+        AssocOp::Equal => Ok(ExprResult::Boolean(values_are_equals(&left, &right)?)),
+        AssocOp::NotEqual => Ok(ExprResult::Boolean(!values_are_equals(&left, &right)?)),
+        AssocOp::LAnd | AssocOp::LOr | AssocOp::NullCoalescing => Err(format!("The operator '{}' can't work on values", op).into()),
+    }
+}
+
+pub fn unary_operator_on_value(op: UnaryOp, operand: ExprResult) -> ExprFuncResult {
+    match op {
+        UnaryOp::Not => Ok(ExprResult::Boolean(!result_to_bool(&operand))),
+        UnaryOp::Negate => match result_to_number(operand)? {
+            Number::Int(i) => Ok(i.checked_neg().map_or_else(|| ExprResult::Num(-ExprDecimal::from(i)), ExprResult::Int)),
+            Number::Decimal(d) => Ok(ExprResult::Num(-d)),
+        },
+        UnaryOp::Plus => Ok(result_to_number(operand)?.into()),
     }
 }
 
@@ -454,38 +1338,37 @@ pub fn f_operators(left: RcExpr, right: RcExpr, op: AssocOp, values: &Identifier
 /**********************************/
 
 // IsNull, IsBlank
-fn f_is_null(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
+fn f_is_null(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let res = exec_vec_is_null(params, values)?;
     Ok(ExprResult::Boolean(res))
 }
 
 // AreEquals
-fn f_are_equals(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 2, "AreEquals")?;
+fn f_are_equals(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let equals = are_equals_internal(params, values)?;
     Ok(ExprResult::Boolean(equals))
 }
 
-// AreNotEquals
-fn f_are_not_equals(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 2, "AreNotEquals")?;
-    let equals = are_equals_internal(params, values)?;
-    Ok(ExprResult::Boolean(!equals))
+fn are_equals_internal(params: &SliceSharedExpr, values: &IdentifierValues) -> Result<bool, ExecError> {
+    let left = exec_expr(params.get(0).unwrap(), values)?;
+    let right = exec_expr(params.get(1).unwrap(), values)?;
+    values_are_equals(&left, &right)
 }
 
-fn are_equals_internal(params: &SliceRcExpr, values: &IdentifierValues) -> Result<bool, String> {
-    let left = exec_expr_to_string(params.get(0).unwrap(), values)?;
-    let right = exec_expr_to_string(params.get(1).unwrap(), values)?;
-    Ok(left == right)
+// Two numbers are equal by value, whatever their type and scale, anything else is compared as text
+fn values_are_equals(left: &ExprResult, right: &ExprResult) -> Result<bool, ExecError> {
+    match (left, right) {
+        (ExprResult::Int(_), ExprResult::Int(_) | ExprResult::Num(_)) | (ExprResult::Num(_), ExprResult::Int(_) | ExprResult::Num(_)) => Ok(left == right),
+        _ => Ok(result_to_string(left)? == result_to_string(right)?),
+    }
 }
 
 // In
-fn f_in(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_min_params_count(params, 2, "In")?;
+fn f_in(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let search = exec_expr(params.get(0).unwrap(), values)?;
     for p in params.iter().skip(1) {
         let p_result = exec_expr(p, values)?;
-        if results_are_equals(&search, &p_result) {
+        if flatten_result(p_result).iter().any(|candidate| results_are_equals(&search, candidate)) {
             return Ok(ExprResult::Boolean(true));
         }
     }
@@ -493,8 +1376,7 @@ fn f_in(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
 }
 
 // InLike
-fn f_in_like(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_min_params_count(params, 2, "InLike")?;
+fn f_in_like(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let search = exec_expr_to_string(params.get(0).unwrap(), values)?;
     let regex = make_case_insensitive_like_regex(&search)?;
     for p in params.iter().skip(1) {
@@ -507,15 +1389,14 @@ fn f_in_like(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult
 }
 
 // IsLike, Like
-fn f_is_like(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 2, "IsLike")?;
+fn f_is_like(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let text = exec_expr_to_string(params.get(0).unwrap(), values)?;
     let search = exec_expr_to_string(params.get(1).unwrap(), values)?;
     let regex = make_case_insensitive_like_regex(&search)?;
     Ok(ExprResult::Boolean(regex.is_match(&text)))
 }
 
-fn f_first_not_null(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
+fn f_first_not_null(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     for p in params.iter() {
         let p_result = exec_expr(p, values)?;
         if !expr_result_is_null(&p_result) {
@@ -525,31 +1406,39 @@ fn f_first_not_null(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFunc
     Ok(ExprResult::Null)
 }
 
+// `??` follows the C# semantics : unlike `FirstNotNull`, only a real null (not an empty string) falls back to the right side,
+// which is only executed in that case
+fn f_null_coalescing(left: &SharedExpr, right: &SharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    match exec_expr(left, values)? {
+        ExprResult::Null => exec_expr(right, values),
+        result => Ok(result),
+    }
+}
+
 /**********************************/
 /*          Strings               */
 /**********************************/
 
 // Concatenate, Concat
-fn f_concat(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
+fn f_concat(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let mut result = String::new();
     for p in params.iter() {
-        let s = exec_expr_to_string(p, values)?;
-        result.push_str(&s);
+        for part in flatten_result(exec_expr(p, values)?) {
+            result.push_str(&result_to_string(&part)?);
+        }
     }
     Ok(ExprResult::Str(Rc::new(result)))
 }
 
 // Exact
-fn f_exact(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 2, "Exact")?;
+fn f_exact(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let left = exec_expr_to_string(params.get(0).unwrap(), values)?;
     let right = exec_expr_to_string(params.get(1).unwrap(), values)?;
     Ok(ExprResult::Boolean(left == right))
 }
 
 // Find
-fn f_find(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_between_params_count(params, 2, 3, "Find")?;
+fn f_find(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let start_num: usize = match params.get(2) {
         None => 0,
         Some(epxr) => (exec_expr_to_int(epxr, values)? - 1).max(0) as usize,
@@ -567,9 +1456,7 @@ fn f_find(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
 }
 
 // Substitute
-fn f_substitute(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 3, "Substitute")?;
-
+fn f_substitute(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let within_text = exec_expr_to_string(params.get(0).unwrap(), values)?;
     let find_text = exec_expr_to_string(params.get(1).unwrap(), values)?;
     let replace_text = exec_expr_to_string(params.get(2).unwrap(), values)?;
@@ -589,9 +1476,7 @@ fn f_substitute(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResu
 }
 
 // Fixed
-fn f_fixed(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_between_params_count(params, 1, 3, "Fixed")?;
-
+fn f_fixed(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let number = exec_expr_to_num(params.get(0).unwrap(), values, None)?;
 
     let decimals = match params.get(1) {
@@ -599,13 +1484,16 @@ fn f_fixed(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
         Some(epxr) => exec_expr_to_int(epxr, values)?.max(0) as u32,
     };
 
-    let number = number.round_dp_with_strategy(decimals, RoundingStrategy::RoundHalfDown);
-
     let no_commas = match params.get(2) {
         None => true,
         Some(epxr) => exec_expr_to_bool(epxr, values)?,
     };
 
+    Ok(ExprResult::Str(Rc::new(format_fixed(number, decimals, no_commas)?)))
+}
+
+fn format_fixed(number: ExprDecimal, decimals: u32, no_commas: bool) -> Result<String, String> {
+    let number = number.round_dp_with_strategy(decimals, RoundingStrategy::RoundHalfDown);
     let result = if no_commas {
         format!("{num:.prec$}", num = number, prec = decimals as usize)
     } else {
@@ -618,12 +1506,52 @@ fn f_fixed(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
         };
         result
     };
-    Ok(ExprResult::Str(Rc::new(result)))
+    Ok(result)
+}
+
+// Format, a hole of the interpolated strings : `$"{price:N2}"` is `Concat(Format(price, "N2"))`, see `interpolation_function`
+fn f_format(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    let value = exec_expr(&params[0], values)?;
+    let text = match params.get(1) {
+        Some(format) => Rc::new(format_expr_result(&value, &exec_expr_to_string(format, values)?)?),
+        None => value.to_rc_string(),
+    };
+    Ok(ExprResult::Str(text))
+}
+
+// Formats a value with a .NET format string, as in the holes of the interpolated strings : `{price:N2}`, `{date:yyyy-MM-dd}`
+fn format_expr_result(result: &ExprResult, format: &str) -> Result<String, String> {
+    match result {
+        ExprResult::Date(d) => Ok(d.format(&dotnet_format_to_strptime_format(format)).to_string()),
+        ExprResult::Int(i) => format_number(Number::Int(*i), format),
+        ExprResult::Num(n) => format_number(Number::Decimal(*n), format),
+        // like in .NET, the format is ignored by the values that are not formattable
+        _ => Ok(result.to_string()),
+    }
+}
+
+// Standard numeric formats : N (thousands separators), F (fixed-point), D (integer digits), with an optional precision
+fn format_number(number: Number, format: &str) -> Result<String, String> {
+    let unsupported = || format!("The format '{}' is not supported", format);
+    let mut chars = format.chars();
+    let specifier = chars.next().map(|c| c.to_ascii_uppercase());
+    let precision = match chars.as_str() {
+        "" => None,
+        p => Some(p.parse::<u32>().map_err(|_| unsupported())?),
+    };
+    match (specifier, number) {
+        (Some('N'), n) => format_fixed(n.to_decimal(), precision.unwrap_or(2), false),
+        (Some('F'), n) => format_fixed(n.to_decimal(), precision.unwrap_or(2), true),
+        (Some('D'), Number::Int(i)) => {
+            let digits = format!("{:0width$}", i.unsigned_abs(), width = precision.unwrap_or(0) as usize);
+            Ok(if i < 0 { format!("-{}", digits) } else { digits })
+        }
+        _ => Err(unsupported()),
+    }
 }
 
 // Left
-fn f_left(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 2, "Left")?;
+fn f_left(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let size = exec_expr_to_int(params.get(1).unwrap(), values)?.max(0) as usize;
     if size == 0 {
         return Ok(ExprResult::Str(get_rc_empty_string()));
@@ -638,8 +1566,7 @@ fn f_left(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
 }
 
 // Right
-fn f_right(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 2, "Right")?;
+fn f_right(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let size = exec_expr_to_int(params.get(1).unwrap(), values)?.max(0) as usize;
     if size == 0 {
         return Ok(ExprResult::Str(get_rc_empty_string()));
@@ -654,8 +1581,7 @@ fn f_right(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
 }
 
 // Mid
-fn f_mid(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 3, "Mid")?;
+fn f_mid(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let size = exec_expr_to_int(params.get(2).unwrap(), values)?.max(0) as usize;
     if size == 0 {
         return Ok(ExprResult::Str(get_rc_empty_string()));
@@ -674,8 +1600,7 @@ fn f_mid(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
     }
 }
 
-fn single_string_func<F: FnOnce(Rc<String>) -> ExprFuncResult>(params: &SliceRcExpr, values: &IdentifierValues, f_name: &str, func: F) -> ExprFuncResult {
-    assert_exact_params_count(params, 1, f_name)?;
+fn single_string_func<F: FnOnce(Rc<String>) -> ExprFuncResult>(params: &SliceSharedExpr, values: &IdentifierValues, func: F) -> ExprFuncResult {
     let s = exec_expr_to_string(params.get(0).unwrap(), values)?;
     func(s)
 }
@@ -685,23 +1610,23 @@ fn get_human_string_length(s: &str) -> usize {
 }
 
 // Len
-fn f_len(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    single_string_func(params, values, "Len", |s| Ok(ExprResult::Num(ExprDecimal::from(get_human_string_length(&s)))))
+fn f_len(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    single_string_func(params, values, |s| Ok(ExprResult::Num(ExprDecimal::from(get_human_string_length(&s)))))
 }
 
 // Lower
-fn f_lower(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    single_string_func(params, values, "Lower", |s| Ok(ExprResult::Str(Rc::new(s.to_lowercase()))))
+fn f_lower(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    single_string_func(params, values, |s| Ok(ExprResult::Str(Rc::new(s.to_lowercase()))))
 }
 
 // Upper
-fn f_upper(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    single_string_func(params, values, "Upper", |s| Ok(ExprResult::Str(Rc::new(s.to_uppercase()))))
+fn f_upper(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    single_string_func(params, values, |s| Ok(ExprResult::Str(Rc::new(s.to_uppercase()))))
 }
 
 // Trim
-fn f_trim(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    single_string_func(params, values, "Trim", |s| Ok(ExprResult::Str(Rc::new(s.trim().to_string()))))
+fn f_trim(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    single_string_func(params, values, |s| Ok(ExprResult::Str(Rc::new(s.trim().to_string()))))
 }
 
 fn is_punctuation(c: char) -> bool {
@@ -715,8 +1640,8 @@ fn is_sentence_punctuation(c: char) -> bool {
 }
 
 // FirstWord
-fn f_first_word(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    single_string_func(params, values, "FirstWord", |s| {
+fn f_first_word(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    single_string_func(params, values, |s| {
         let position = s.chars().position(|c| is_space(c) || is_punctuation(c));
         match position {
             None => Ok(ExprResult::Str(s)),
@@ -726,13 +1651,13 @@ fn f_first_word(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResu
 }
 
 // Text
-fn f_text(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    single_string_func(params, values, "Text", |s| Ok(ExprResult::Str(s)))
+fn f_text(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    single_string_func(params, values, |s| Ok(ExprResult::Str(s)))
 }
 
 // FirstSentence
-fn f_first_sentence(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    single_string_func(params, values, "FirstSentence", |s| {
+fn f_first_sentence(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    single_string_func(params, values, |s| {
         let position = s.chars().position(|c| is_sentence_punctuation(c));
         match position {
             None => Ok(ExprResult::Str(s)),
@@ -742,8 +1667,8 @@ fn f_first_sentence(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFunc
 }
 
 // Capitalize
-fn f_capitalize(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    single_string_func(params, values, "Capitalize", |s| {
+fn f_capitalize(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    single_string_func(params, values, |s| {
         let (_, result) = s.chars().into_iter().fold((true, String::with_capacity(s.capacity())), |state, c| {
             let (should_capitalize, mut s) = state;
             match (should_capitalize, is_sentence_punctuation(c), is_space(c)) {
@@ -769,12 +1694,14 @@ fn f_capitalize(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResu
 }
 
 // Split
-fn f_split(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 3, "Split")?;
+fn f_split(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let s = exec_expr_to_string(params.get(0).unwrap(), values)?.to_string();
     let separator = exec_expr_to_string(params.get(1).unwrap(), values)?.to_string();
-    let index = exec_expr_to_int(params.get(2).unwrap(), values)?.max(0) as usize;
     let parts: Vec<&str> = s.split(&separator).collect();
+    let index = match params.get(2) {
+        None => return Ok(ExprResult::Array(Rc::new(parts.iter().map(|p| ExprResult::Str(Rc::new(p.to_string()))).collect()))),
+        Some(index) => exec_expr_to_int(index, values)?.max(0) as usize,
+    };
     let result = match parts.get(index) {
         None => ExprResult::Null,
         Some(p) => ExprResult::Str(Rc::new(p.to_string())),
@@ -783,8 +1710,7 @@ fn f_split(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
 }
 
 // NumberValue
-fn f_number_value(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_between_params_count(params, 1, 2, "NumberValue")?;
+fn f_number_value(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let separator = match params.get(1) {
         None => None,
         Some(expr) => exec_expr_to_string(expr, values)?.chars().next(),
@@ -795,8 +1721,7 @@ fn f_number_value(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncRe
 }
 
 // StartsWith
-fn f_starts_with(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 2, "StartsWith")?;
+fn f_starts_with(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let text = exec_expr_to_string(params.get(0).unwrap(), values)?;
     let search = exec_expr_to_string(params.get(1).unwrap(), values)?;
 
@@ -821,8 +1746,7 @@ fn f_starts_with(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncRes
 }
 
 // EndsWith
-fn f_ends_with(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 2, "EndsWith")?;
+fn f_ends_with(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let text = exec_expr_to_string(params.get(0).unwrap(), values)?;
     let search = exec_expr_to_string(params.get(1).unwrap(), values)?;
 
@@ -847,10 +1771,9 @@ fn f_ends_with(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResul
 }
 
 // ReplaceEquals
-fn f_replace_equals(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_min_params_count(params, 4, "ReplaceEquals")?;
+fn f_replace_equals(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     if params.len() % 2 == 1 {
-        return Err("Remplacement key/value parameters must come 2 by 2".to_string());
+        return Err("Remplacement key/value parameters must come 2 by 2".into());
     }
 
     let text = exec_expr_to_string(params.get(0).unwrap(), values)?;
@@ -875,10 +1798,9 @@ fn f_replace_equals(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFunc
 }
 
 // ReplaceLike
-fn f_replace_like(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_min_params_count(params, 4, "ReplaceLike")?;
+fn f_replace_like(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     if params.len() % 2 == 1 {
-        return Err("Remplacement key/value parameters must come 2 by 2".to_string());
+        return Err("Remplacement key/value parameters must come 2 by 2".into());
     }
 
     let text = exec_expr_to_string(params.get(0).unwrap(), values)?;
@@ -907,7 +1829,7 @@ fn f_replace_like(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncRe
 /**********************************/
 
 // And
-fn f_and(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
+fn f_and(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     for expr in params {
         let b = exec_expr_to_bool(expr, values)?;
         if !b {
@@ -918,7 +1840,7 @@ fn f_and(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
 }
 
 // Or
-fn f_or(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
+fn f_or(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     for expr in params {
         let b = exec_expr_to_bool(expr, values)?;
         if b {
@@ -929,22 +1851,19 @@ fn f_or(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
 }
 
 // Not
-fn f_not(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 1, "Not")?;
+fn f_not(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     Ok(ExprResult::Boolean(!exec_expr_to_bool(params.get(0).unwrap(), values)?))
 }
 
 // Xor
-fn f_xor(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 2, "Xor")?;
+fn f_xor(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let p0 = exec_expr_to_bool(params.get(0).unwrap(), values)?;
     let p1 = exec_expr_to_bool(params.get(1).unwrap(), values)?;
     Ok(ExprResult::Boolean(p0 ^ p1))
 }
 
 // Iif, If
-fn f_iif(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 3, "Iif")?;
+fn f_iif(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let test = exec_expr_to_bool(params.get(0).unwrap(), values)?;
     exec_expr(params.get(if test { 1 } else { 2 }).unwrap(), values)
 }
@@ -954,96 +1873,227 @@ fn f_iif(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
 /**********************************/
 
 // Abs
-fn f_abs(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 1, "Abs")?;
-    let num = exec_expr_to_num(params.get(0).unwrap(), values, None)?;
-    Ok(ExprResult::Num(num.abs()))
+fn f_abs(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    match exec_expr_to_number(params.get(0).unwrap(), values)? {
+        Number::Int(i) => Ok(i.checked_abs().map_or_else(|| ExprResult::Num(ExprDecimal::from(i).abs()), ExprResult::Int)),
+        Number::Decimal(d) => Ok(ExprResult::Num(d.abs())),
+    }
 }
 
 // Product
-fn f_product(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    let mut result = ExprDecimal::from(1);
+fn f_product(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    let mut result = Number::Int(1);
     for expr in params.iter() {
-        let i = exec_expr_to_num(expr, values, None)?;
-        let intermediate_result = result;
-        result = std::panic::catch_unwind(|| intermediate_result * i).map_err(|_| format!("Couldn't multiply {} by {} : overflow", result, i).to_string())?;
+        result = multiply_numbers(result, exec_expr_to_number(expr, values)?)?;
     }
-    Ok(ExprResult::Num(result))
+    Ok(result.into())
 }
 
-// Sum
-fn f_sum(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    let mut result = ExprDecimal::from(0);
-    for expr in params.iter() {
-        let i = exec_expr_to_num(expr, values, None)?;
-        let intermediate_result = result;
-        result = std::panic::catch_unwind(|| intermediate_result + i).map_err(|_| format!("Couldn't add {} to {} : overflow", i, result).to_string())?;
+// Sum, of the numbers and the items of the arrays, or of what the lambda selects from each item : `Sum(items, x => x * 2)`
+fn f_sum(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    let numbers = match params {
+        [items, selector] if matches!(selector.without_span(), Expr::Lambda(_, _)) => {
+            let selector = expr_to_lambda(selector, 2, "Sum")?;
+            exec_expr_to_items(items, values)?.iter().map(|item| call_lambda(selector, item, values)).collect::<Result<Vec<_>, _>>()?
+        }
+        _ => params.iter().map(|expr| exec_expr(expr, values).map(flatten_result)).collect::<Result<Vec<_>, _>>()?.concat(),
+    };
+    let mut result = Number::Int(0);
+    for number in numbers {
+        result = add_numbers(result, result_to_number(number)?)?;
     }
-    Ok(ExprResult::Num(result))
+    Ok(result.into())
 }
 
-// Divide
-fn f_divide(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 2, "Divide")?;
+// Divide, always a decimal division : `1 / 2` is 0.5, unlike the C# integer division
+fn f_divide(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let num = exec_expr_to_num(params.get(0).unwrap(), values, None)?;
     let divisor = exec_expr_to_num(params.get(1).unwrap(), values, None)?;
-    let result = std::panic::catch_unwind(|| num / divisor).map_err(|_| format!("Couldn't divide {} by {}", num, divisor).to_string())?;
-    Ok(ExprResult::Num(result))
+    Ok(ExprResult::Num(divide_decimals(num, divisor)?))
 }
 
 // Subtract
-fn f_subtract(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 2, "Subtract")?;
-    let num = exec_expr_to_num(params.get(0).unwrap(), values, None)?;
-    let sub = exec_expr_to_num(params.get(1).unwrap(), values, None)?;
-    let result = std::panic::catch_unwind(|| num - sub).map_err(|_| format!("Couldn't remove {} from {}", sub, num).to_string())?;
-    Ok(ExprResult::Num(result))
+fn f_subtract(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    let num = exec_expr_to_number(params.get(0).unwrap(), values)?;
+    let sub = exec_expr_to_number(params.get(1).unwrap(), values)?;
+    Ok(subtract_numbers(num, sub)?.into())
 }
 
 // Mod, Modulo
-fn f_mod(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 2, "Mod")?;
-    let num = exec_expr_to_num(params.get(0).unwrap(), values, None)?;
-    let divisor = exec_expr_to_num(params.get(1).unwrap(), values, None)?;
-    let result = std::panic::catch_unwind(|| num % divisor).map_err(|_| format!("Couldn't module {} by {}", num, divisor).to_string())?;
-    Ok(ExprResult::Num(result))
+fn f_mod(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    let num = exec_expr_to_number(params.get(0).unwrap(), values)?;
+    let divisor = exec_expr_to_number(params.get(1).unwrap(), values)?;
+    Ok(modulo_numbers(num, divisor)?.into())
 }
 
 // Round
-fn f_round(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 2, "Round")?;
+fn f_round(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let num = exec_expr_to_num(params.get(0).unwrap(), values, None)?;
     let digits = exec_expr_to_int(params.get(1).unwrap(), values)?.max(0) as u32;
-    let mult_div = ExprDecimal::from((10 as u32).pow(digits));
+    let mult_div = ExprDecimal::from((10 as u32).checked_pow(digits).ok_or_else(|| format!("Couldn't round {} to {} digits", num, digits))?);
     let result = std::panic::catch_unwind(|| (num * mult_div).round() / mult_div).map_err(|_| format!("Couldn't round {} to {} digits", num, digits).to_string())?;
     Ok(ExprResult::Num(result))
 }
 
-fn simple_operator<F: FnOnce(ExprDecimal, ExprDecimal) -> ExprFuncResult>(params: &SliceRcExpr, values: &IdentifierValues, f_name: &str, func: F) -> ExprFuncResult {
-    assert_exact_params_count(params, 2, f_name)?;
+fn simple_operator<F: FnOnce(ExprDecimal, ExprDecimal) -> ExprFuncResult>(params: &SliceSharedExpr, values: &IdentifierValues, func: F) -> ExprFuncResult {
     let num_a = exec_expr_to_num(params.get(0).unwrap(), values, None)?;
     let num_b = exec_expr_to_num(params.get(1).unwrap(), values, None)?;
     func(num_a, num_b)
 }
 
 // GreaterThan, Gt
-fn f_greater_than(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    simple_operator(params, values, "GreaterThan", |a, b| Ok(ExprResult::Boolean(a > b)))
+fn f_greater_than(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    simple_operator(params, values, |a, b| Ok(ExprResult::Boolean(a > b)))
 }
 
 // LowerThan, Lt
-fn f_lower_than(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    simple_operator(params, values, "LowerThan", |a, b| Ok(ExprResult::Boolean(a < b)))
+fn f_lower_than(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    simple_operator(params, values, |a, b| Ok(ExprResult::Boolean(a < b)))
 }
 
 // GreaterThanOrEqual, Gtoe
-fn f_greater_than_or_equal(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    simple_operator(params, values, "GreaterThanOrEqual", |a, b| Ok(ExprResult::Boolean(a >= b)))
+fn f_greater_than_or_equal(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    simple_operator(params, values, |a, b| Ok(ExprResult::Boolean(a >= b)))
 }
 
 // LowerThanOrEqual, Ltoe
-fn f_lower_than_or_equal(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    simple_operator(params, values, "LowerThanOrEqual", |a, b| Ok(ExprResult::Boolean(a <= b)))
+fn f_lower_than_or_equal(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    simple_operator(params, values, |a, b| Ok(ExprResult::Boolean(a <= b)))
+}
+
+/**********************************/
+/*          Collections           */
+/**********************************/
+
+// Where
+fn f_where(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    let items = exec_expr_to_items(&params[0], values)?;
+    let predicate = expr_to_lambda(&params[1], 2, "Where")?;
+    let mut result = Vec::new();
+    for item in items.iter() {
+        if matches_lambda(Some(predicate), item, values)? {
+            result.push(item.clone());
+        }
+    }
+    Ok(ExprResult::Array(Rc::new(result)))
+}
+
+// Select
+fn f_select(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    let items = exec_expr_to_items(&params[0], values)?;
+    let selector = expr_to_lambda(&params[1], 2, "Select")?;
+    let result = items.iter().map(|item| call_lambda(selector, item, values)).collect::<Result<Vec<_>, _>>()?;
+    Ok(ExprResult::Array(Rc::new(result)))
+}
+
+// The items of the first parameter and the optional predicate of the second one
+fn items_and_predicate<'a>(params: &'a SliceSharedExpr, values: &IdentifierValues, f_name: &str) -> Result<(Rc<Vec<ExprResult>>, Option<LambdaRef<'a>>), ExecError> {
+    let items = exec_expr_to_items(&params[0], values)?;
+    let predicate = params.get(1).map(|p| expr_to_lambda(p, 2, f_name)).transpose()?;
+    Ok((items, predicate))
+}
+
+// Any
+fn f_any(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    let (items, predicate) = items_and_predicate(params, values, "Any")?;
+    for item in items.iter() {
+        if matches_lambda(predicate, item, values)? {
+            return Ok(ExprResult::Boolean(true));
+        }
+    }
+    Ok(ExprResult::Boolean(false))
+}
+
+// All
+fn f_all(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    let items = exec_expr_to_items(&params[0], values)?;
+    let predicate = expr_to_lambda(&params[1], 2, "All")?;
+    for item in items.iter() {
+        if !matches_lambda(Some(predicate), item, values)? {
+            return Ok(ExprResult::Boolean(false));
+        }
+    }
+    Ok(ExprResult::Boolean(true))
+}
+
+// Count
+fn f_count(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    let (items, predicate) = items_and_predicate(params, values, "Count")?;
+    let mut count = 0;
+    for item in items.iter() {
+        if matches_lambda(predicate, item, values)? {
+            count += 1;
+        }
+    }
+    Ok(ExprResult::Int(count))
+}
+
+// The first item matching the optional predicate of `First` and `FirstOrDefault`
+fn find_first(params: &SliceSharedExpr, values: &IdentifierValues, f_name: &str) -> Result<Option<ExprResult>, ExecError> {
+    let (items, predicate) = items_and_predicate(params, values, f_name)?;
+    for item in items.iter() {
+        if matches_lambda(predicate, item, values)? {
+            return Ok(Some(item.clone()));
+        }
+    }
+    Ok(None)
+}
+
+// First, an error when there is no such item, as in C#
+fn f_first(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    match find_first(params, values, "First")? {
+        Some(item) => Ok(item),
+        None if params.len() > 1 => Err("No item matches the predicate".into()),
+        None => Err("There is no item".into()),
+    }
+}
+
+// FirstOrDefault, null when there is no such item
+fn f_first_or_default(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    Ok(find_first(params, values, "FirstOrDefault")?.unwrap_or(ExprResult::Null))
+}
+
+// The order of two keys of `OrderBy` : the nulls first, then the values of the same type
+fn compare_results(left: &ExprResult, right: &ExprResult) -> Result<Ordering, ExecError> {
+    match (left, right) {
+        (ExprResult::Null, ExprResult::Null) => Ok(Ordering::Equal),
+        (ExprResult::Null, _) => Ok(Ordering::Less),
+        (_, ExprResult::Null) => Ok(Ordering::Greater),
+        (ExprResult::Int(a), ExprResult::Int(b)) => Ok(a.cmp(b)),
+        (ExprResult::Int(_), ExprResult::Num(_)) | (ExprResult::Num(_), ExprResult::Int(_)) | (ExprResult::Num(_), ExprResult::Num(_)) => Ok(result_to_num(left.clone(), None)?.cmp(&result_to_num(right.clone(), None)?)),
+        (ExprResult::Str(a), ExprResult::Str(b)) => Ok(a.cmp(b)),
+        (ExprResult::Boolean(a), ExprResult::Boolean(b)) => Ok(a.cmp(b)),
+        (ExprResult::Date(a), ExprResult::Date(b)) => Ok(a.cmp(b)),
+        (ExprResult::TimeSpan(a), ExprResult::TimeSpan(b)) => Ok(a.cmp(b)),
+        _ => Err(format!("The values '{}' and '{}' can't be compared", left, right).into()),
+    }
+}
+
+// OrderBy, a stable sort like in C#
+fn f_order_by(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    let items = exec_expr_to_items(&params[0], values)?;
+    let key = expr_to_lambda(&params[1], 2, "OrderBy")?;
+    let mut keyed = items.iter().map(|item| Ok((call_lambda(key, item, values)?, item.clone()))).collect::<Result<Vec<_>, ExecError>>()?;
+    // the keys are checked before sorting, the sort needs a total order : a key compared to the first one not null compares to all the others
+    if let Some((first, _)) = keyed.iter().find(|(key, _)| *key != ExprResult::Null) {
+        for (key, _) in keyed.iter() {
+            compare_results(first, key)?;
+        }
+    }
+    keyed.sort_by(|(a, _), (b, _)| compare_results(a, b).unwrap_or(Ordering::Equal));
+    Ok(ExprResult::Array(Rc::new(keyed.into_iter().map(|(_, item)| item).collect())))
+}
+
+// Distinct, the items equal as by `==` in C#, so 1 and 1.0 are the same item
+fn f_distinct(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    let items = exec_expr_to_items(&params[0], values)?;
+    let mut result: Vec<ExprResult> = Vec::with_capacity(items.len());
+    for item in items.iter() {
+        if !result.contains(item) {
+            result.push(item.clone());
+        }
+    }
+    Ok(ExprResult::Array(Rc::new(result)))
 }
 
 /**********************************/
@@ -1051,29 +2101,24 @@ fn f_lower_than_or_equal(params: &SliceRcExpr, values: &IdentifierValues) -> Exp
 /**********************************/
 
 // Now
-fn f_now(params: &SliceRcExpr, _values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 0, "Now")?;
+fn f_now(_params: &SliceSharedExpr, _values: &IdentifierValues) -> ExprFuncResult {
     Ok(ExprResult::Date(Utc::now().naive_utc()))
 }
 
 // Today
-fn f_today(params: &SliceRcExpr, _values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 0, "Today")?;
+fn f_today(_params: &SliceSharedExpr, _values: &IdentifierValues) -> ExprFuncResult {
     let date = NaiveDateTime::new(Utc::now().date().naive_utc(), NaiveTime::from_hms(0, 0, 0));
     Ok(ExprResult::Date(date))
 }
 
 // Time
-fn f_time(params: &SliceRcExpr, _values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 0, "Time")?;
+fn f_time(_params: &SliceSharedExpr, _values: &IdentifierValues) -> ExprFuncResult {
     let duration = Utc::now().time().signed_duration_since(NaiveTime::from_hms(0, 0, 0));
     Ok(ExprResult::TimeSpan(duration))
 }
 
 // NowSpecificTimeZone
-fn f_now_specific_timezone(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_between_params_count(params, 0, 1, "NowSpecificTimeZone")?;
-
+fn f_now_specific_timezone(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let now = Utc::now().naive_utc();
 
     match params.get(0) {
@@ -1085,42 +2130,38 @@ fn f_now_specific_timezone(params: &SliceRcExpr, values: &IdentifierValues) -> E
     }
 }
 
-fn single_date_func<F: FnOnce(NaiveDateTime) -> ExprFuncResult>(params: &SliceRcExpr, values: &IdentifierValues, f_name: &str, func: F) -> ExprFuncResult {
-    assert_exact_params_count(params, 1, f_name)?;
+fn single_date_func<F: FnOnce(NaiveDateTime) -> ExprFuncResult>(params: &SliceSharedExpr, values: &IdentifierValues, func: F) -> ExprFuncResult {
     let date = exec_expr_to_date_no_defaults(params.get(0).unwrap(), values)?;
     func(date)
 }
 
 // Date
-fn f_date(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    single_date_func(params, values, "Date", |d| Ok(ExprResult::Date(d)))
+fn f_date(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    single_date_func(params, values, |d| Ok(ExprResult::Date(d)))
 }
 
 // Year
-fn f_year(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    single_date_func(params, values, "Year", |d| Ok(ExprResult::Num(ExprDecimal::from(d.year()))))
+fn f_year(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    single_date_func(params, values, |d| Ok(ExprResult::Num(ExprDecimal::from(d.year()))))
 }
 
 // Month
-fn f_month(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    single_date_func(params, values, "Month", |d| Ok(ExprResult::Num(ExprDecimal::from(d.month()))))
+fn f_month(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    single_date_func(params, values, |d| Ok(ExprResult::Num(ExprDecimal::from(d.month()))))
 }
 
 // Day
-fn f_day(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    single_date_func(params, values, "Day", |d| Ok(ExprResult::Num(ExprDecimal::from(d.day()))))
+fn f_day(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    single_date_func(params, values, |d| Ok(ExprResult::Num(ExprDecimal::from(d.day()))))
 }
 
-fn two_dates_func_no_defaults<F: FnOnce(NaiveDateTime, NaiveDateTime) -> ExprFuncResult>(params: &SliceRcExpr, values: &IdentifierValues, f_name: &str, func: F) -> ExprFuncResult {
-    assert_exact_params_count(params, 2, f_name)?;
+fn two_dates_func_no_defaults<F: FnOnce(NaiveDateTime, NaiveDateTime) -> ExprFuncResult>(params: &SliceSharedExpr, values: &IdentifierValues, func: F) -> ExprFuncResult {
     let date_left = exec_expr_to_date_no_defaults(params.get(0).unwrap(), values)?;
     let date_right = exec_expr_to_date_no_defaults(params.get(1).unwrap(), values)?;
     func(date_left, date_right)
 }
 
-fn two_dates_func<F: FnOnce(NaiveDateTime, NaiveDateTime) -> ExprFuncResult>(params: &SliceRcExpr, values: &IdentifierValues, f_name: &str, func: F) -> ExprFuncResult {
-    assert_between_params_count(params, 2, 8, f_name)?;
-
+fn two_dates_func<F: FnOnce(NaiveDateTime, NaiveDateTime) -> ExprFuncResult>(params: &SliceSharedExpr, values: &IdentifierValues, func: F) -> ExprFuncResult {
     let default_year = params.get(2).map_or(Ok(false), |expr| exec_expr_to_bool(expr, values))?;
     let default_month = params.get(3).map_or(Ok(false), |expr| exec_expr_to_bool(expr, values))?;
     let default_day = params.get(4).map_or(Ok(false), |expr| exec_expr_to_bool(expr, values))?;
@@ -1134,8 +2175,8 @@ fn two_dates_func<F: FnOnce(NaiveDateTime, NaiveDateTime) -> ExprFuncResult>(par
 }
 
 // DateDiff
-fn f_date_diff(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    two_dates_func_no_defaults(params, values, "DateDiff", |d1, d2| Ok(ExprResult::TimeSpan(d1 - d2)))
+fn f_date_diff(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    two_dates_func_no_defaults(params, values, |d1, d2| Ok(ExprResult::TimeSpan(d1 - d2)))
 }
 
 pub const SECONDS_IN_MIN: i64 = 60;
@@ -1144,62 +2185,61 @@ pub const SECONDS_IN_DAYS: i64 = SECONDS_IN_HOURS * 24;
 // pub const SECONDS_IN_MONTHS_size: f64 = SECONDS_IN_DAYS as f64 * 30.5_f64;
 
 //DateDiffHours
-fn f_date_diff_hours(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    two_dates_func_no_defaults(params, values, "DateDiffHours", |d1, d2| {
+fn f_date_diff_hours(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    two_dates_func_no_defaults(params, values, |d1, d2| {
         let hours = ((d1 - d2).num_seconds() / SECONDS_IN_HOURS).abs();
         Ok(ExprResult::Num(ExprDecimal::from(hours)))
     })
 }
 
 // DateDiffDays
-fn f_date_diff_days(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    two_dates_func_no_defaults(params, values, "DateDiffDays", |d1, d2| {
+fn f_date_diff_days(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    two_dates_func_no_defaults(params, values, |d1, d2| {
         let days = ((d1 - d2).num_seconds() / SECONDS_IN_DAYS).abs();
         Ok(ExprResult::Num(ExprDecimal::from(days)))
     })
 }
 
 // DateDiffMonths
-fn f_date_diff_months(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    two_dates_func_no_defaults(params, values, "DateDiffMonths", |d1, d2| {
+fn f_date_diff_months(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    two_dates_func_no_defaults(params, values, |d1, d2| {
         let months = ((d1.month() as i32 - d2.month() as i32) + 12 * (d1.year() - d2.year())).abs();
         Ok(ExprResult::Num(ExprDecimal::from(months)))
     })
 }
 
 // DateEquals
-fn f_date_equals(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    two_dates_func(params, values, "DateEquals", |d1, d2| Ok(ExprResult::Boolean(d1 == d2)))
+fn f_date_equals(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    two_dates_func(params, values, |d1, d2| Ok(ExprResult::Boolean(d1 == d2)))
 }
 
 // DateNotEquals
-fn f_date_not_equals(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    two_dates_func(params, values, "DateNotEquals", |d1, d2| Ok(ExprResult::Boolean(d1 != d2)))
+fn f_date_not_equals(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    two_dates_func(params, values, |d1, d2| Ok(ExprResult::Boolean(d1 != d2)))
 }
 
 // DateLower
-fn f_date_lower(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    two_dates_func(params, values, "DateLower", |d1, d2| Ok(ExprResult::Boolean(d1 < d2)))
+fn f_date_lower(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    two_dates_func(params, values, |d1, d2| Ok(ExprResult::Boolean(d1 < d2)))
 }
 
 // DateLowerOrEquals
-fn f_date_lower_or_equals(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    two_dates_func(params, values, "DateLowerOrEquals", |d1, d2| Ok(ExprResult::Boolean(d1 <= d2)))
+fn f_date_lower_or_equals(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    two_dates_func(params, values, |d1, d2| Ok(ExprResult::Boolean(d1 <= d2)))
 }
 
 // DateGreater
-fn f_date_greater(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    two_dates_func(params, values, "DateGreater", |d1, d2| Ok(ExprResult::Boolean(d1 > d2)))
+fn f_date_greater(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    two_dates_func(params, values, |d1, d2| Ok(ExprResult::Boolean(d1 > d2)))
 }
 
 // DateGreaterOrEquals
-fn f_date_greater_or_equals(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    two_dates_func(params, values, "DateGreaterOrEquals", |d1, d2| Ok(ExprResult::Boolean(d1 >= d2)))
+fn f_date_greater_or_equals(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+    two_dates_func(params, values, |d1, d2| Ok(ExprResult::Boolean(d1 >= d2)))
 }
 
 // DateAddHours
-fn f_date_add_hours(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 2, "DateAddHours")?;
+fn f_date_add_hours(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let date_time = exec_expr_to_date_no_defaults(params.get(0).unwrap(), values)?;
     let hours = exec_expr_to_float(params.get(1).unwrap(), values, None)?;
     let date_time = date_time + Duration::seconds((hours * SECONDS_IN_HOURS as f64) as i64);
@@ -1207,8 +2247,7 @@ fn f_date_add_hours(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFunc
 }
 
 // DateAddDays
-fn f_date_add_days(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 2, "DateAddDays")?;
+fn f_date_add_days(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let date_time = exec_expr_to_date_no_defaults(params.get(0).unwrap(), values)?;
     let days = exec_expr_to_float(params.get(1).unwrap(), values, None)?;
     let date_time = date_time + Duration::seconds((days * SECONDS_IN_DAYS as f64) as i64);
@@ -1216,8 +2255,7 @@ fn f_date_add_days(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncR
 }
 
 // DateAddMonths
-fn f_date_add_months(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 2, "DateAddMonths")?;
+fn f_date_add_months(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let date_time = exec_expr_to_date_no_defaults(params.get(0).unwrap(), values)?;
 
     let months = exec_expr_to_int(params.get(1).unwrap(), values)?;
@@ -1241,8 +2279,7 @@ fn f_date_add_months(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFun
 }
 
 // DateAddYears
-fn f_date_add_years(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_exact_params_count(params, 2, "DateAddYears")?;
+fn f_date_add_years(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let date_time = exec_expr_to_date_no_defaults(params.get(0).unwrap(), values)?;
     let years = exec_expr_to_int(params.get(1).unwrap(), values)? as i32;
 
@@ -1255,8 +2292,7 @@ fn get_rc_default_timezone_name() -> Rc<String> {
     Rc::new("Romance Standard Time".into())
 }
 // LocalDate
-fn f_local_date(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_between_params_count(params, 1, 2, "LocalDate")?;
+fn f_local_date(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let date_time = exec_expr_to_date_no_defaults(params.get(0).unwrap(), values)?;
     let time_zone_name = params.get(1).map_or(Ok(get_rc_default_timezone_name()), |expr| exec_expr_to_string(expr, values))?;
     naive_datetime_to_timezone(&date_time, &time_zone_name)
@@ -1267,8 +2303,7 @@ fn get_rc_default_date_format() -> Rc<String> {
 }
 
 // DateFormat
-fn f_date_format(params: &SliceRcExpr, values: &IdentifierValues) -> ExprFuncResult {
-    assert_between_params_count(params, 1, 2, "DateFormat")?;
+fn f_date_format(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
     let date_time = exec_expr_to_date_no_defaults(params.get(0).unwrap(), values)?;
     let format = params.get(1).map_or(Ok(get_rc_default_date_format()), |expr| exec_expr_to_string(expr, values))?;
 
@@ -1283,6 +2318,18 @@ mod tests {
     use super::*;
     use test_case::test_case;
 
+    // the functions don't check their parameters count, the arity does it when preparing : each count it accepts has to be enough
+    #[test]
+    fn arity_matches_the_execution() {
+        for (name, definition) in get_functions() {
+            for count in (0..10).filter(|count| definition.arity.accepts(*count)) {
+                let params = vec![SharedExpr::new(Expr::Null); count];
+                let call = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| (definition.implementation)(&params, &IdentifierValues::new()).is_ok()));
+                assert!(call.is_ok(), "{} with {} parameters", name, count);
+            }
+        }
+    }
+
     #[test_case("yyyy-MM-dd HH:mm:ss.fff" => "%Y-%m-%d %H:%M:%S.%3f")]
     fn test_dotnet_format_to_strptime_format(dotnet_format: &str) -> String {
         dotnet_format_to_strptime_format(dotnet_format)
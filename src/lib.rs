@@ -8,7 +8,13 @@
 #[macro_use]
 extern crate lazy_static;
 
+pub mod bytecode;
 pub mod expressions;
 pub mod ffi;
 mod functions;
 mod parsing;
+pub mod registry;
+pub mod types;
+
+// the built-in operators, to prepare an expression from outside of the crate
+pub use functions::{f_operators, f_unary_operators};
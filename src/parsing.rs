@@ -1,17 +1,21 @@
 use crate::expressions::*;
+use crate::functions::interpolation_function;
 
 use nom::{
     branch::alt,
     bytes::complete::{escaped, tag, take_while1}, // escaped_transform
-    character::complete::{alphanumeric1, char, multispace0, one_of},
-    combinator::{map, opt, recognize},
-    error::{context, ParseError},
-    number::complete::double,
-    sequence::{delimited, preceded, tuple},
+    character::complete::{alphanumeric1, char, digit1, hex_digit1, multispace0, none_of, one_of},
+    combinator::{map, map_opt, not, opt, recognize},
+    error::{context, ErrorKind, ParseError as NomParseError},
+    multi::many0,
+    sequence::{delimited, pair, preceded, terminated, tuple},
     IResult,
 };
-use rust_decimal::prelude::FromPrimitive;
 use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+use std::iter::Peekable;
+use std::str::FromStr;
 use unescape::unescape;
 use unicase::UniCase;
 
@@ -20,9 +24,28 @@ enum Lex {
     ParenthesisOpen,
     ParenthesisClose,
     Comma,
+    QuestionMark,
+    NullConditional,
+    Colon,
+    ArrayOpen,
+    BracketOpen,
+    BracketClose,
+    BraceClose,
     FunctionOpen(String),
+    MethodOpen(String),
+    Member(String),
+    LambdaOpen(String),
     Expr(crate::expressions::Expr),
+    InterpolatedString(Vec<InterpolationPart>),
     Op(crate::expressions::AssocOp),
+    UnaryOp(crate::expressions::UnaryOp),
+}
+
+#[derive(Debug)]
+enum InterpolationPart {
+    Text(String),
+    // offset of the expression from the start of the interpolated string token
+    Hole { offset: usize, expression: String, format: Option<String> },
 }
 
 /// A nom parser has the following signature:
@@ -30,7 +53,7 @@ enum Lex {
 /// `type IResult<I, O, E = (I, ErrorKind)> = Result<(I, O), Err<E>>;`
 
 // string parser from here : https://github.com/Geal/nom/issues/1075
-fn string<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, &'a str, E> {
+fn string<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, &'a str, E> {
     delimited(
         char('\"'),
         map(
@@ -45,20 +68,169 @@ fn string<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, &'a st
     )(input)
 }
 
-fn boolean<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, bool, E> {
+// 'a', '\n', '\u0041'
+fn char_literal<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, String, E> {
+    map_opt(delimited(char('\''), escaped(none_of("\\'"), '\\', one_of("'\"\\0bfnrtux")), char('\'')), |s| {
+        unescape(s).filter(|s| s.chars().count() == 1)
+    })(input)
+}
+
+// @"C:\path", where "" is the only escape sequence
+fn verbatim_string<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, String, E> {
+    preceded(tag("@\""), terminated(map(many0(alt((map(tag("\"\""), |_| '"'), none_of("\"")))), |chars| chars.into_iter().collect()), char('"')))(input)
+}
+
+// $"Hello {name}, you are {age + 1:N0}", and its verbatim forms $@"..." or @$"..."
+// The holes are only delimited here, their expressions are parsed by the parser machine to report errors at the right position.
+fn interpolated_string<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Vec<InterpolationPart>, E> {
+    let (body, verbatim) = alt((map(tag("$\""), |_| false), map(alt((tag("$@\""), tag("@$\""))), |_| true)))(input)?;
+    let error = || nom::Err::Error(E::from_error_kind(input, ErrorKind::Escaped));
+    let mut parts = vec![];
+    let mut text = String::new();
+    let mut i = 0;
+    loop {
+        let rest = &body[i..];
+        let c = rest.chars().next().ok_or_else(error)?;
+        if (verbatim && rest.starts_with("\"\"")) || rest.starts_with("{{") || rest.starts_with("}}") {
+            text.push(c);
+            i += 2;
+        } else if c == '"' {
+            if !text.is_empty() {
+                parts.push(InterpolationPart::Text(text));
+            }
+            return Ok((&rest[1..], parts));
+        } else if c == '{' {
+            let (expression, format, length) = interpolation_hole(&rest[1..]).ok_or_else(error)?;
+            if !text.is_empty() {
+                parts.push(InterpolationPart::Text(std::mem::take(&mut text)));
+            }
+            parts.push(InterpolationPart::Hole {
+                offset: input.len() - rest.len() + 1,
+                expression: expression.to_string(),
+                format: format.map(|f| f.to_string()),
+            });
+            i += length + 1;
+        } else if c == '}' {
+            return Err(error());
+        } else if c == '\\' && !verbatim {
+            let length = if rest[1..].starts_with('u') { 6 } else { 1 + rest[1..].chars().next().map_or(0, char::len_utf8) };
+            text.push_str(&rest.get(..length).and_then(unescape).ok_or_else(error)?);
+            i += length;
+        } else {
+            text.push(c);
+            i += c.len_utf8();
+        }
+    }
+}
+
+// Finds the end of a hole : the first '}' or ':' (starting the format) that is not nested in parenthesis or in a literal.
+// Returns the expression, the format and the length of the hole, closing brace included.
+fn interpolation_hole(hole: &str) -> Option<(&str, Option<&str>, usize)> {
+    let mut depth = 0;
+    let mut chars = hole.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' => depth -= 1,
+            '}' if depth > 0 => depth -= 1,
+            '}' => return Some((&hole[..i], None, i + 1)),
+            ':' if depth == 0 => {
+                let format_length = hole[i + 1..].find('}')?;
+                return Some((&hole[..i], Some(&hole[i + 1..i + 1 + format_length]), i + format_length + 2));
+            }
+            '"' | '\'' => loop {
+                match chars.next()? {
+                    (_, '\\') => {
+                        chars.next()?;
+                    }
+                    (_, q) if q == c => break,
+                    _ => {}
+                }
+            },
+            _ => {}
+        }
+    }
+    None
+}
+
+fn boolean<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, bool, E> {
     context("boolean", alt((map(tag("false"), |_| false), map(tag("true"), |_| true))))(input)
 }
 
-fn null<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Expr, E> {
+fn null<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Expr, E> {
     let (input, _) = tag("null")(input)?;
     Ok((input, Expr::Null))
 }
 
-fn identifier<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, &'a str, E> {
+// C# numeric literals, read from their text so that `0.1` is exactly 0.1 and big integers keep all their digits
+// see : https://docs.microsoft.com/en-us/dotnet/csharp/language-reference/builtin-types/integral-numeric-types#integer-literals
+fn digits<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, &'a str, E> {
+    recognize(pair(digit1, many0(preceded(many0(char('_')), digit1))))(input)
+}
+
+fn hex_digits<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, &'a str, E> {
+    recognize(pair(hex_digit1, many0(preceded(many0(char('_')), hex_digit1))))(input)
+}
+
+fn integer_suffix<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, &'a str, E> {
+    recognize(alt((pair(one_of("uU"), opt(one_of("lL"))), pair(one_of("lL"), opt(one_of("uU"))))))(input)
+}
+
+fn real_suffix<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, &'a str, E> {
+    recognize(one_of("mMdDfF"))(input)
+}
+
+fn hex_number<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Expr, E> {
+    map_opt(preceded(alt((tag("0x"), tag("0X"))), tuple((hex_digits, opt(integer_suffix)))), |(digits, _)| {
+        u64::from_str_radix(&digits.replace('_', ""), 16).ok().map(integer_to_expr)
+    })(input)
+}
+
+fn decimal_number<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Expr, E> {
+    let mantissa = recognize(alt((recognize(pair(digits, opt(pair(char('.'), digits)))), recognize(pair(char('.'), digits)))));
+    let exponent = recognize(tuple((one_of("eE"), opt(one_of("+-")), digits)));
+    let suffix = alt((map(integer_suffix, |_| true), map(real_suffix, |_| false)));
+    map_opt(tuple((mantissa, opt(exponent), opt(suffix))), |(mantissa, exponent, is_integer_suffix)| {
+        let is_integer = exponent.is_none() && !mantissa.contains('.');
+        let text = format!("{}{}", mantissa, exponent.unwrap_or_default()).replace('_', "");
+        match is_integer_suffix {
+            Some(true) if !is_integer => None,
+            Some(true) => text.parse::<u64>().ok().map(integer_to_expr),
+            Some(false) => text_to_decimal(&text).map(Expr::Num),
+            None if is_integer => match text.parse::<i64>() {
+                Ok(i) => Some(Expr::Int(i)),
+                Err(_) => text_to_decimal(&text).map(Expr::Num),
+            },
+            None => text_to_decimal(&text).map(Expr::Num),
+        }
+    })(input)
+}
+
+// an integer literal too big for an `Int` is kept as a decimal, like C# would choose `ulong` over `long`
+fn integer_to_expr(value: u64) -> Expr {
+    match i64::try_from(value) {
+        Ok(i) => Expr::Int(i),
+        Err(_) => Expr::Num(ExprDecimal::from(value)),
+    }
+}
+
+fn text_to_decimal(text: &str) -> Option<ExprDecimal> {
+    if text.contains(['e', 'E']) {
+        ExprDecimal::from_scientific(text).ok()
+    } else {
+        ExprDecimal::from_str(text).ok()
+    }
+}
+
+fn number<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Expr, E> {
+    alt((hex_number, decimal_number))(input)
+}
+
+fn identifier<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, &'a str, E> {
     preceded(opt(tag("@")), recognize(tuple((opt(tag("_")), alphanumeric1))))(input)
 }
 
-fn binary_operator<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, AssocOp, E> {
+fn binary_operator<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, AssocOp, E> {
     alt((
         map(tag("+"), |_| AssocOp::Add),
         map(tag("-"), |_| AssocOp::Subtract),
@@ -73,50 +245,113 @@ fn binary_operator<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a st
         map(tag("%"), |_| AssocOp::Modulus),
         map(tag("&&"), |_| AssocOp::LAnd),
         map(tag("||"), |_| AssocOp::LOr),
+        map(tag("??"), |_| AssocOp::NullCoalescing),
     ))(input)
 }
 
-fn open_parenthesis<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Lex, E> {
+fn unary_operator<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, UnaryOp, E> {
+    map(tag("!"), |_| UnaryOp::Not)(input)
+}
+
+fn open_parenthesis<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Lex, E> {
     let (input, _) = char('(')(input)?;
     Ok((input, Lex::ParenthesisOpen))
 }
-fn close_parenthesis<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Lex, E> {
+fn close_parenthesis<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Lex, E> {
     let (input, _) = char(')')(input)?;
     Ok((input, Lex::ParenthesisClose))
 }
-fn comma<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Lex, E> {
+fn comma<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Lex, E> {
     let (input, _) = char(',')(input)?;
     Ok((input, Lex::Comma))
 }
 
-fn open_function<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, &'a str, E> {
+// `a?.5:1` is still a ternary, like in C#
+fn null_conditional<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Lex, E> {
+    let (input, _) = tag("?.")(input)?;
+    let (input, _) = not(digit1)(input)?;
+    Ok((input, Lex::NullConditional))
+}
+fn question_mark<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Lex, E> {
+    let (input, _) = char('?')(input)?;
+    Ok((input, Lex::QuestionMark))
+}
+fn colon<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Lex, E> {
+    let (input, _) = char(':')(input)?;
+    Ok((input, Lex::Colon))
+}
+
+// `new[] { 1, 2 }`, the other array literal `[1, 2]` starts with a bracket
+fn open_array<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Lex, E> {
+    let (input, _) = tuple((tag("new"), multispace0, char('['), multispace0, char(']'), multispace0, char('{')))(input)?;
+    Ok((input, Lex::ArrayOpen))
+}
+fn bracket<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Lex, E> {
+    alt((map(char('['), |_| Lex::BracketOpen), map(char(']'), |_| Lex::BracketClose), map(char('}'), |_| Lex::BraceClose)))(input)
+}
+
+// `items.Count(` is the call `Count(items`, like a C# extension method, and `x.Price` reads the field of a record.
+fn member<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Lex, E> {
+    let (input, name) = preceded(tuple((char('.'), multispace0, not(digit1))), identifier)(input)?;
+    let (input, call) = opt(pair(multispace0, open_parenthesis))(input)?;
+    match call {
+        Some(_) => Ok((input, Lex::MethodOpen(name.into()))),
+        None => Ok((input, Lex::Member(name.into()))),
+    }
+}
+
+// `x => x > 10`, the body follows
+fn open_lambda<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, &'a str, E> {
+    terminated(identifier, pair(multispace0, tag("=>")))(input)
+}
+
+fn open_function<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, &'a str, E> {
     let (input, name) = identifier(input)?;
     let (input, _) = multispace0(input)?;
     let (input, _) = open_parenthesis(input)?;
     Ok((input, name))
 }
 
-fn full_lexer<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Lex, E> {
+fn full_lexer<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Lex, E> {
     alt((
         open_parenthesis,
         close_parenthesis,
         comma,
         map(binary_operator, |op| Lex::Op(op)),
-        map(string, |s| Lex::Expr(Expr::Str(unescape(s).unwrap()))),
+        null_conditional,
+        question_mark,
+        colon,
+        bracket,
+        map(unary_operator, |op| Lex::UnaryOp(op)),
+        map_opt(string, |s| unescape(s).map(|s| Lex::Expr(Expr::Str(s)))),
+        map(char_literal, |s| Lex::Expr(Expr::Str(s))),
+        map(verbatim_string, |s| Lex::Expr(Expr::Str(s))),
+        map(interpolated_string, Lex::InterpolatedString),
         map(null, |_| Lex::Expr(Expr::Null)),
         map(boolean, |b| Lex::Expr(Expr::Boolean(b))),
-        map(double, |d| Lex::Expr(Expr::Num(FromPrimitive::from_f64(d).unwrap()))),
+        map(number, Lex::Expr),
+        member,
+        open_array,
+        map(open_lambda, |p| Lex::LambdaOpen(p.into())),
         map(open_function, |id| Lex::FunctionOpen(id.into())),
         map(identifier, |id| Lex::Expr(Expr::Identifier(id.into()))),
     ))(input)
 }
 
-fn second_chance_lexer<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Expr, E> {
-    map(double, |d| Expr::Num(FromPrimitive::from_f64(d).unwrap()))(input)
+fn second_chance_lexer<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Expr, E> {
+    map_opt(pair(opt(one_of("+-")), number), |(sign, n)| match (sign, n) {
+        (Some('-'), Expr::Int(i)) => i.checked_neg().map(Expr::Int),
+        (Some('-'), Expr::Num(n)) => Some(Expr::Num(-n)),
+        (_, n) => Some(n),
+    })(input)
 }
 
 #[derive(Debug)]
-struct ParserMachine {
+struct ParserMachine<'a> {
+    source: &'a str,
+    // where `source` starts in the whole expression, for the holes of an interpolated string
+    base: usize,
+    position: usize,
     parsers: Vec<Parser>,
 }
 
@@ -125,16 +360,115 @@ enum OperatorParseTryResult {
     ShouldBeANumber,
 }
 
-impl ParserMachine {
-    fn new() -> ParserMachine {
-        // let mut machine = ParserMachine { parsers: vec![] };
-        // machine.push_parser(ParsingState::Started);
-        // machine
-        ParserMachine { parsers: vec![] }
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParseErrorKind {
+    InvalidToken,
+    UnexpectedComma,
+    UnexpectedExpression,
+    UnexpectedOperator,
+    UnexpectedOpenParenthesis,
+    UnexpectedCloseParenthesis,
+    UnexpectedOpenBracket,
+    UnexpectedCloseBracket,
+    UnexpectedCloseBrace,
+    UnexpectedEndOfInput,
+    // `a?.b`, only the member access `a.b` is supported
+    UnsupportedMemberAccess,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExpectedToken {
+    Expression,
+    Operator,
+    Comma,
+    CloseParenthesis,
+    CloseBracket,
+    CloseBrace,
+    Colon,
+    EndOfInput,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub offset: usize, // in bytes, from the start of the expression
+    pub line: usize,   // 1 based
+    pub column: usize, // 1 based, in chars
+    pub expected: Vec<ExpectedToken>,
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, source: &str, offset: usize, expected: Vec<ExpectedToken>) -> ParseError {
+        let before = &source[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+        ParseError { kind, offset, line, column, expected }
+    }
+}
+
+impl Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::InvalidToken => write!(f, "Invalid token"),
+            ParseErrorKind::UnexpectedComma => write!(f, "Unexpected comma ','"),
+            ParseErrorKind::UnexpectedExpression => write!(f, "Unexpected expression"),
+            ParseErrorKind::UnexpectedOperator => write!(f, "Unexpected operator"),
+            ParseErrorKind::UnexpectedOpenParenthesis => write!(f, "Unexpected parenthesis '('"),
+            ParseErrorKind::UnexpectedCloseParenthesis => write!(f, "Unexpected parenthesis ')'"),
+            ParseErrorKind::UnexpectedOpenBracket => write!(f, "Unexpected bracket '['"),
+            ParseErrorKind::UnexpectedCloseBracket => write!(f, "Unexpected bracket ']'"),
+            ParseErrorKind::UnexpectedCloseBrace => write!(f, "Unexpected brace '}}'"),
+            ParseErrorKind::UnexpectedEndOfInput => write!(f, "Unexpected end of expression"),
+            ParseErrorKind::UnsupportedMemberAccess => write!(f, "The null-conditional member access '?.' is not supported"),
+        }
+    }
+}
+
+impl Display for ExpectedToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpectedToken::Expression => write!(f, "an expression"),
+            ExpectedToken::Operator => write!(f, "an operator"),
+            ExpectedToken::Comma => write!(f, "','"),
+            ExpectedToken::CloseParenthesis => write!(f, "')'"),
+            ExpectedToken::CloseBracket => write!(f, "']'"),
+            ExpectedToken::CloseBrace => write!(f, "'}}'"),
+            ExpectedToken::Colon => write!(f, "':'"),
+            ExpectedToken::EndOfInput => write!(f, "the end of the expression"),
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at line {}, column {}", self.kind, self.line, self.column)?;
+        if !self.expected.is_empty() {
+            let expected = self.expected.iter().map(|e| e.to_string()).collect::<Vec<String>>().join(" or ");
+            write!(f, ", expecting {}", expected)?;
+        }
+        Ok(())
+    }
+}
+type ParseStepResult = Result<(), ParseError>;
+
+impl<'a> ParserMachine<'a> {
+    fn new(source: &'a str, base: usize) -> ParserMachine<'a> {
+        ParserMachine {
+            source,
+            base,
+            position: 0,
+            parsers: vec![],
+        }
     }
 
     fn push_parser(&mut self, state: ParsingState) {
-        self.parsers.push(Parser { state: state });
+        let start = self.offset();
+        self.parsers.push(Parser { state, start });
+    }
+
+    // the current position in the whole expression
+    fn offset(&self) -> usize {
+        self.base + self.position
     }
 
     fn ensure_one_parser(&mut self) {
@@ -143,65 +477,202 @@ impl ParserMachine {
         }
     }
 
-    fn current_parser_state<'a>(&'a mut self) -> &'a ParsingState {
+    fn current_parser_state<'b>(&'b mut self) -> &'b ParsingState {
         self.ensure_one_parser();
         &(self.parsers.last_mut().unwrap().state)
     }
 
-    fn current_parser_mut<'a>(&'a mut self) -> &'a mut Parser {
+    fn current_parser_mut<'b>(&'b mut self) -> &'b mut Parser {
         self.ensure_one_parser();
         self.parsers.last_mut().unwrap()
     }
 
-    fn current_parser_read<'a>(&'a mut self) -> &'a Parser {
+    fn current_parser_read<'b>(&'b mut self) -> &'b Parser {
         self.ensure_one_parser();
         self.parsers.last().unwrap()
     }
 
-    fn open_parenthesis(&mut self) {
+    fn error<T>(&mut self, kind: ParseErrorKind) -> Result<T, ParseError> {
+        let expected = self.expected_tokens();
+        Err(ParseError::new(kind, self.source, self.position, expected))
+    }
+
+    // What the current parser could accept as next token, to help the user fixing its expression
+    fn expected_tokens(&mut self) -> Vec<ExpectedToken> {
+        self.ensure_one_parser();
+        // the closest enclosing context decides what can end the current expression
+        let closing_tokens = self
+            .parsers
+            .iter()
+            .rev()
+            .skip(1)
+            .find_map(|p| match &p.state {
+                ParsingState::Function(_, _, _) => Some(vec![ExpectedToken::Comma, ExpectedToken::CloseParenthesis]),
+                ParsingState::Array(_, _, closing) => Some(vec![ExpectedToken::Comma, *closing]),
+                ParsingState::JustParenthesis(_) => Some(vec![ExpectedToken::CloseParenthesis]),
+                ParsingState::Index(_, _) => Some(vec![ExpectedToken::CloseBracket]),
+                ParsingState::TernaryWhenTrue(_, _) => Some(vec![ExpectedToken::Colon]),
+                // like the last branch of a ternary, a lambda body ends with its enclosing context
+                _ => None,
+            })
+            .unwrap_or_else(|| vec![ExpectedToken::EndOfInput]);
+        match &self.parsers.last().unwrap().state {
+            ParsingState::Started
+            | ParsingState::JustParenthesis(None)
+            | ParsingState::AwaitingNextOperand(_, _)
+            | ParsingState::Unary(_)
+            | ParsingState::Function(_, _, true)
+            | ParsingState::Array(_, true, _)
+            | ParsingState::Index(_, None)
+            | ParsingState::TernaryWhenTrue(_, None)
+            | ParsingState::TernaryWhenFalse(_, _, None)
+            | ParsingState::Lambda(_, None) => vec![ExpectedToken::Expression],
+            ParsingState::Function(_, p, false) if p.borrow().is_empty() => vec![ExpectedToken::Expression, ExpectedToken::CloseParenthesis],
+            ParsingState::Function(_, _, false) => vec![ExpectedToken::Operator, ExpectedToken::Comma, ExpectedToken::CloseParenthesis],
+            ParsingState::Array(p, false, closing) if p.borrow().is_empty() => vec![ExpectedToken::Expression, *closing],
+            ParsingState::Array(_, false, closing) => vec![ExpectedToken::Operator, ExpectedToken::Comma, *closing],
+            ParsingState::JustParenthesis(Some(_)) => vec![ExpectedToken::Operator, ExpectedToken::CloseParenthesis],
+            ParsingState::Index(_, Some(_)) => vec![ExpectedToken::Operator, ExpectedToken::CloseBracket],
+            ParsingState::TernaryWhenTrue(_, Some(_)) => vec![ExpectedToken::Operator, ExpectedToken::Colon],
+            ParsingState::Expr(_) | ParsingState::Operation(_, _) | ParsingState::TernaryWhenFalse(_, _, Some(_)) | ParsingState::Lambda(_, Some(_)) | ParsingState::Method(_) => {
+                let mut expected = vec![ExpectedToken::Operator];
+                expected.extend(closing_tokens);
+                expected
+            }
+        }
+    }
+
+    fn open_parenthesis(&mut self) -> ParseStepResult {
+        let start = self.offset();
         let current = self.current_parser_mut();
+        if !current.state.accepts_expression() {
+            return self.error(ParseErrorKind::UnexpectedOpenParenthesis);
+        }
         let new_state = ParsingState::JustParenthesis(None);
         match &current.state {
             ParsingState::Started => {
                 current.state = new_state;
+                current.start = start;
             }
             _ => self.push_parser(new_state),
         };
+        Ok(())
     }
 
-    fn open_function(&mut self, name: String) {
-        let next_state = ParsingState::Function(UniCase::new(name.clone()), RefCell::new(vec![]), false);
+    fn open_function(&mut self, name: String) -> ParseStepResult {
+        if !self.current_parser_read().state.accepts_expression() {
+            return self.error(ParseErrorKind::UnexpectedExpression);
+        }
+        let next_state = ParsingState::Function(UniCase::new(name), RefCell::new(vec![]), false);
         self.push_parser(next_state);
+        Ok(())
+    }
+
+    // The previous operand becomes the first parameter, see `ParsingState::Method`
+    fn open_method(&mut self, name: String) -> ParseStepResult {
+        match self.take_operand(false) {
+            Some(target) => {
+                self.push_parser(ParsingState::Method(target));
+                self.push_parser(ParsingState::Function(UniCase::new(name), RefCell::new(vec![]), false));
+                Ok(())
+            }
+            None => self.error(ParseErrorKind::UnexpectedOperator),
+        }
     }
 
-    fn comma(&mut self) {
+    // The previous operand becomes the record of the field
+    fn member(&mut self, name: String, end: usize) -> ParseStepResult {
+        match self.take_operand(false) {
+            Some(target) => self.expression(postfixed(&target, end, &|target| Expr::Member(target, name.clone()))),
+            None => self.error(ParseErrorKind::UnexpectedOperator),
+        }
+    }
+
+    fn open_lambda(&mut self, parameter: String) -> ParseStepResult {
+        if !self.current_parser_read().state.accepts_expression() {
+            return self.error(ParseErrorKind::UnexpectedExpression);
+        }
+        self.push_parser(ParsingState::Lambda(parameter, None));
+        Ok(())
+    }
+
+    fn open_array(&mut self, closing: ExpectedToken) -> ParseStepResult {
+        if !self.current_parser_read().state.accepts_expression() {
+            return self.error(ParseErrorKind::UnexpectedExpression);
+        }
+        self.push_parser(ParsingState::Array(RefCell::new(vec![]), false, closing));
+        Ok(())
+    }
+
+    // A bracket starts an array where an expression is expected, and indexes the previous operand otherwise
+    fn open_bracket(&mut self) -> ParseStepResult {
+        if self.current_parser_read().state.accepts_expression() {
+            return self.open_array(ExpectedToken::CloseBracket);
+        }
+        match self.take_operand(false) {
+            Some(target) => {
+                self.push_parser(ParsingState::Index(target, None));
+                Ok(())
+            }
+            None => self.error(ParseErrorKind::UnexpectedOpenBracket),
+        }
+    }
+
+    fn comma(&mut self) -> ParseStepResult {
+        self.close_operation()?;
         let current = self.current_parser_mut();
         match &current.state {
-            ParsingState::Function(s, p, false) => {
+            ParsingState::Function(s, p, false) if !p.borrow().is_empty() => {
                 current.state = ParsingState::Function(s.clone(), p.clone(), true);
-                return;
+                Ok(())
             }
-            _ => {
-                dbg!(current);
-                todo!("Unable to handle a comma ',' here");
+            ParsingState::Array(p, false, closing) if !p.borrow().is_empty() => {
+                current.state = ParsingState::Array(p.clone(), true, *closing);
+                Ok(())
             }
-        };
+            _ => self.error(ParseErrorKind::UnexpectedComma),
+        }
     }
 
-    fn expression(&mut self, expr: RcExpr) {
+    fn expression(&mut self, expr: SharedExpr) -> ParseStepResult {
         let current = self.current_parser_mut();
-        match &current.state {
+        match &mut current.state {
             ParsingState::Function(s, p, has_comma) => {
-                let parameters = p.clone().into_inner();
-                if !has_comma && parameters.len() != 0 {
-                    dbg!(&self);
-                    todo!("There should be a comma to separate arguments")
+                if !*has_comma && !p.borrow().is_empty() {
+                    return self.error(ParseErrorKind::UnexpectedExpression);
                 }
                 p.borrow_mut().push(expr);
                 current.state = ParsingState::Function(s.clone(), p.clone(), false);
             }
-            ParsingState::AwaitingNextOperand(e, op) => {
-                current.state = ParsingState::Expr(RcExpr::new(Expr::BinaryOperator(e.clone(), expr, op.clone())));
+            ParsingState::Array(p, has_comma, closing) => {
+                if !*has_comma && !p.borrow().is_empty() {
+                    return self.error(ParseErrorKind::UnexpectedExpression);
+                }
+                p.borrow_mut().push(expr);
+                current.state = ParsingState::Array(p.clone(), false, *closing);
+            }
+            ParsingState::Index(target, None) => {
+                current.state = ParsingState::Index(target.clone(), Some(expr));
+            }
+            // the call of the method, only given once closed
+            ParsingState::Method(target) => {
+                let end = span_of(&expr).end;
+                let call = match expr.without_span() {
+                    Expr::FunctionCall(name, parameters) => (name.clone(), parameters.clone()),
+                    _ => return self.error(ParseErrorKind::UnexpectedExpression),
+                };
+                current.state = ParsingState::Expr(postfixed(target, end, &|target| {
+                    let (name, parameters) = call.clone();
+                    Expr::FunctionCall(name, std::iter::once(target).chain(parameters).collect())
+                }));
+            }
+            ParsingState::Lambda(parameter, None) => {
+                current.state = ParsingState::Lambda(parameter.clone(), Some(expr));
+            }
+            ParsingState::AwaitingNextOperand(operands, operators) => {
+                let mut operands = std::mem::take(operands);
+                operands.push(expr);
+                current.state = ParsingState::Operation(operands, std::mem::take(operators));
             }
             ParsingState::Started => {
                 current.state = ParsingState::Expr(expr);
@@ -209,17 +680,31 @@ impl ParserMachine {
             ParsingState::JustParenthesis(None) => {
                 current.state = ParsingState::JustParenthesis(Some(expr));
             }
-            _ => {
-                dbg!(current);
-                todo!("Unable to handle an expression here")
+            ParsingState::TernaryWhenTrue(condition, None) => {
+                current.state = ParsingState::TernaryWhenTrue(condition.clone(), Some(expr));
             }
+            ParsingState::TernaryWhenFalse(condition, when_true, None) => {
+                current.state = ParsingState::TernaryWhenFalse(condition.clone(), when_true.clone(), Some(expr));
+            }
+            ParsingState::Unary(op) => {
+                let end = span_of(&expr).end;
+                current.state = ParsingState::Expr(spanned(Expr::UnaryOperator(expr, *op), current.start, end));
+            }
+            _ => return self.error(ParseErrorKind::UnexpectedExpression),
         };
+        Ok(())
     }
 
     fn operator(&mut self, op: AssocOp) -> OperatorParseTryResult {
         let current = self.current_parser_mut();
-        let (current_state_to_change, next_state_to_push, result) = match &current.state {
-            ParsingState::Expr(e) => (Some(ParsingState::AwaitingNextOperand(e.clone(), op)), None, OperatorParseTryResult::Ok),
+        let (current_state_to_change, next_state_to_push, result) = match &mut current.state {
+            ParsingState::Expr(e) => (Some(ParsingState::AwaitingNextOperand(vec![e.clone()], vec![op])), None, OperatorParseTryResult::Ok),
+            ParsingState::Operation(operands, operators) => {
+                let operands = std::mem::take(operands);
+                let mut operators = std::mem::take(operators);
+                operators.push(op);
+                (Some(ParsingState::AwaitingNextOperand(operands, operators)), None, OperatorParseTryResult::Ok)
+            }
             ParsingState::Function(n, p, false) => {
                 let mut parameters = p.borrow_mut();
                 if parameters.len() == 0 {
@@ -227,15 +712,46 @@ impl ParserMachine {
                 } else {
                     let expr = parameters.pop().unwrap();
                     drop(parameters);
-                    let expr = expr.clone();
                     (
                         Some(ParsingState::Function(n.clone(), p.clone(), true)),
-                        Some(ParsingState::AwaitingNextOperand(expr, op)),
+                        Some(ParsingState::AwaitingNextOperand(vec![expr], vec![op])),
                         OperatorParseTryResult::Ok,
                     )
                 }
             }
-            ParsingState::JustParenthesis(Some(expr)) => (Some(ParsingState::JustParenthesis(None)), Some(ParsingState::AwaitingNextOperand(expr.clone(), op)), OperatorParseTryResult::Ok),
+            ParsingState::Array(p, false, closing) if !p.borrow().is_empty() => {
+                let expr = p.borrow_mut().pop().unwrap();
+                (
+                    Some(ParsingState::Array(p.clone(), true, *closing)),
+                    Some(ParsingState::AwaitingNextOperand(vec![expr], vec![op])),
+                    OperatorParseTryResult::Ok,
+                )
+            }
+            ParsingState::JustParenthesis(Some(expr)) => (
+                Some(ParsingState::JustParenthesis(None)),
+                Some(ParsingState::AwaitingNextOperand(vec![expr.clone()], vec![op])),
+                OperatorParseTryResult::Ok,
+            ),
+            ParsingState::Index(target, Some(expr)) => (
+                Some(ParsingState::Index(target.clone(), None)),
+                Some(ParsingState::AwaitingNextOperand(vec![expr.clone()], vec![op])),
+                OperatorParseTryResult::Ok,
+            ),
+            ParsingState::TernaryWhenTrue(condition, Some(expr)) => (
+                Some(ParsingState::TernaryWhenTrue(condition.clone(), None)),
+                Some(ParsingState::AwaitingNextOperand(vec![expr.clone()], vec![op])),
+                OperatorParseTryResult::Ok,
+            ),
+            ParsingState::TernaryWhenFalse(condition, when_true, Some(expr)) => (
+                Some(ParsingState::TernaryWhenFalse(condition.clone(), when_true.clone(), None)),
+                Some(ParsingState::AwaitingNextOperand(vec![expr.clone()], vec![op])),
+                OperatorParseTryResult::Ok,
+            ),
+            ParsingState::Lambda(parameter, Some(expr)) => (
+                Some(ParsingState::Lambda(parameter.clone(), None)),
+                Some(ParsingState::AwaitingNextOperand(vec![expr.clone()], vec![op])),
+                OperatorParseTryResult::Ok,
+            ),
             _ => (None, None, OperatorParseTryResult::ShouldBeANumber),
         };
 
@@ -249,68 +765,206 @@ impl ParserMachine {
         result
     }
 
-    fn close_parenthesis(&mut self) {
+    // A prefix operator applies to the very next operand, since it binds tighter than any binary operator
+    fn unary_operator(&mut self, op: UnaryOp) -> ParseStepResult {
+        if !self.current_parser_read().state.accepts_expression() {
+            return self.error(ParseErrorKind::UnexpectedOperator);
+        }
+        self.push_parser(ParsingState::Unary(op));
+        Ok(())
+    }
+
+    // An operation (operands separated by binary operators) is only complete when its parent context says so :
+    // a comma, a closing parenthesis, a colon or the end of the input. Then the operators precedence can be applied.
+    // The same goes for the last branch of a ternary and for a lambda body, which extend as far as possible to the right.
+    fn close_operation(&mut self) -> ParseStepResult {
+        loop {
+            let current = self.current_parser_mut();
+            let expr = match &mut current.state {
+                ParsingState::Operation(operands, operators) => build_operation(std::mem::take(operands), std::mem::take(operators)),
+                ParsingState::TernaryWhenFalse(condition, when_true, Some(when_false)) => {
+                    let (start, end) = (span_of(condition).start, span_of(when_false).end);
+                    spanned(Expr::Ternary(condition.clone(), when_true.clone(), when_false.clone()), start, end)
+                }
+                ParsingState::Lambda(parameter, Some(body)) => spanned(Expr::Lambda(parameter.clone(), body.clone()), current.start, span_of(body).end),
+                _ => return Ok(()),
+            };
+            current.state = ParsingState::Expr(expr);
+            self.reduce()?;
+        }
+    }
+
+    // Takes back the last complete operand of the current context, to become the condition of a ternary or an indexed value.
+    // The ternary having the lowest precedence, a whole pending operation is its condition,
+    // while an indexer binds tighter than any operator and only takes the last operand.
+    fn take_operand(&mut self, whole_operation: bool) -> Option<SharedExpr> {
+        let is_root = self.parsers.len() == 1;
+        let current = self.current_parser_mut();
+        let (expr, next_state) = match &mut current.state {
+            ParsingState::Operation(operands, operators) if whole_operation => (build_operation(std::mem::take(operands), std::mem::take(operators)), None),
+            ParsingState::Operation(operands, operators) => {
+                let expr = operands.pop().unwrap();
+                (expr, Some(ParsingState::AwaitingNextOperand(std::mem::take(operands), std::mem::take(operators))))
+            }
+            ParsingState::Expr(e) => (e.clone(), None),
+            ParsingState::Function(n, p, false) if !p.borrow().is_empty() => {
+                let expr = p.borrow_mut().pop().unwrap();
+                (expr, Some(ParsingState::Function(n.clone(), p.clone(), true)))
+            }
+            ParsingState::Array(p, false, closing) if !p.borrow().is_empty() => {
+                let expr = p.borrow_mut().pop().unwrap();
+                (expr, Some(ParsingState::Array(p.clone(), true, *closing)))
+            }
+            ParsingState::JustParenthesis(Some(e)) => (e.clone(), Some(ParsingState::JustParenthesis(None))),
+            ParsingState::Index(target, Some(e)) => (e.clone(), Some(ParsingState::Index(target.clone(), None))),
+            ParsingState::TernaryWhenTrue(condition, Some(e)) => (e.clone(), Some(ParsingState::TernaryWhenTrue(condition.clone(), None))),
+            ParsingState::TernaryWhenFalse(condition, when_true, Some(e)) => (e.clone(), Some(ParsingState::TernaryWhenFalse(condition.clone(), when_true.clone(), None))),
+            ParsingState::Lambda(parameter, Some(e)) => (e.clone(), Some(ParsingState::Lambda(parameter.clone(), None))),
+            _ => return None,
+        };
+        match next_state {
+            Some(s) => current.state = s,
+            None if is_root => current.state = ParsingState::Started,
+            // a pushed operation : its parent is already waiting for an operand
+            None => {
+                self.parsers.pop();
+            }
+        }
+        Some(expr)
+    }
+
+    fn question_mark(&mut self) -> ParseStepResult {
+        match self.take_operand(true) {
+            Some(condition) => {
+                self.push_parser(ParsingState::TernaryWhenTrue(condition, None));
+                Ok(())
+            }
+            None => self.error(ParseErrorKind::UnexpectedOperator),
+        }
+    }
+
+    fn colon(&mut self) -> ParseStepResult {
+        self.close_operation()?;
+        let current = self.current_parser_mut();
+        match &current.state {
+            ParsingState::TernaryWhenTrue(condition, Some(when_true)) => {
+                current.state = ParsingState::TernaryWhenFalse(condition.clone(), when_true.clone(), None);
+                Ok(())
+            }
+            _ => self.error(ParseErrorKind::UnexpectedOperator),
+        }
+    }
+
+    fn close_parenthesis(&mut self) -> ParseStepResult {
+        self.close_operation()?;
+        // the closing parenthesis is part of the span
+        let end = self.offset() + 1;
         let current = self.current_parser_mut();
         match &current.state {
             ParsingState::Function(s, p, false) => {
                 let parameters = p.clone().into_inner();
-                let expr = RcExpr::new(Expr::FunctionCall(s.clone(), parameters));
+                let expr = spanned(Expr::FunctionCall(s.clone(), parameters), current.start, end);
                 current.state = ParsingState::Expr(expr);
             }
             ParsingState::JustParenthesis(Some(expr)) => {
-                current.state = ParsingState::Expr(expr.clone());
+                let expr = respanned(expr, current.start, end);
+                current.state = ParsingState::Expr(expr);
             }
-            _ => {
-                dbg!(current);
-                todo!("Unable to close any parenthesis here")
+            _ => return self.error(ParseErrorKind::UnexpectedCloseParenthesis),
+        }
+        Ok(())
+    }
+
+    // `closing` is the bracket or the brace just read
+    fn close_bracket(&mut self, closing: ExpectedToken) -> ParseStepResult {
+        self.close_operation()?;
+        let end = self.offset() + 1;
+        let current = self.current_parser_mut();
+        match &current.state {
+            ParsingState::Array(p, false, c) if *c == closing => {
+                let items = p.clone().into_inner();
+                current.state = ParsingState::Expr(spanned(Expr::Array(items), current.start, end));
             }
+            ParsingState::Index(target, Some(index)) if closing == ExpectedToken::CloseBracket => {
+                current.state = ParsingState::Expr(postfixed(target, end, &|target| Expr::Index(target, index.clone())));
+            }
+            _ if closing == ExpectedToken::CloseBracket => return self.error(ParseErrorKind::UnexpectedCloseBracket),
+            _ => return self.error(ParseErrorKind::UnexpectedCloseBrace),
         }
+        Ok(())
     }
 
-    fn finalize(mut self) -> Expr {
-        self.reduce();
+    fn finalize(mut self) -> Result<Expr, ParseError> {
+        self.position = self.source.len();
+        self.close_operation()?;
+        self.reduce()?;
 
-        if self.parsers.len() != 1 {
-            dbg!(&self);
-            todo!("There should be nothing else than one expression.");
+        if self.parsers.len() != 1 || !self.current_parser_read().is_final() {
+            return self.error(ParseErrorKind::UnexpectedEndOfInput);
         }
 
-        RcExpr::try_unwrap(self.parsers.pop().unwrap().finalize()).unwrap()
+        let expr = self.parsers.pop().unwrap().finalize();
+        Ok(SharedExpr::try_unwrap(expr).unwrap_or_else(|rc| rc.as_ref().clone()))
     }
 
-    fn reduce(&mut self) {
+    fn reduce(&mut self) -> ParseStepResult {
         while (self.parsers.len() > 1) && self.parsers.last().unwrap().is_final() {
             let expr = self.parsers.pop().unwrap().finalize();
-            self.expression(expr);
+            self.expression(expr)?;
         }
+        Ok(())
     }
 }
 
 #[derive(Debug)]
 struct Parser {
     state: ParsingState,
+    // where the tokens of this parser begin, for the span of a function call, a unary operation or a parenthesis
+    start: usize,
 }
 
 #[derive(Debug)]
 enum ParsingState {
     Started,
-    JustParenthesis(Option<RcExpr>),
-    Expr(RcExpr),
-    AwaitingNextOperand(RcExpr, AssocOp),
-    Function(UniCase<String>, RefCell<VecRcExpr>, bool),
+    JustParenthesis(Option<SharedExpr>),
+    Expr(SharedExpr),
+    AwaitingNextOperand(VecSharedExpr, Vec<AssocOp>),
+    Operation(VecSharedExpr, Vec<AssocOp>),
+    Unary(UnaryOp),
+    Function(UniCase<String>, RefCell<VecSharedExpr>, bool),
+    // the items, whether a comma is waiting for the next one, and the closing token
+    Array(RefCell<VecSharedExpr>, bool, ExpectedToken),
+    Index(SharedExpr, Option<SharedExpr>),
+    TernaryWhenTrue(SharedExpr, Option<SharedExpr>),
+    TernaryWhenFalse(SharedExpr, SharedExpr, Option<SharedExpr>),
+    // the target of `target.Name(...)`, under the function parser of the call
+    Method(SharedExpr),
+    Lambda(String, Option<SharedExpr>),
 }
 
-impl Parser {
-    fn finalize(self) -> RcExpr {
-        match self.try_finalize() {
-            Some(e) => e,
-            None => todo!("Cannot finalize parser in this state"),
+impl ParsingState {
+    fn accepts_expression(&self) -> bool {
+        match self {
+            ParsingState::Started
+            | ParsingState::JustParenthesis(None)
+            | ParsingState::AwaitingNextOperand(_, _)
+            | ParsingState::Unary(_)
+            | ParsingState::Index(_, None)
+            | ParsingState::TernaryWhenTrue(_, None)
+            | ParsingState::TernaryWhenFalse(_, _, None)
+            | ParsingState::Lambda(_, None) => true,
+            ParsingState::Function(_, p, has_comma) | ParsingState::Array(p, has_comma, _) => *has_comma || p.borrow().is_empty(),
+            _ => false,
         }
     }
-    fn try_finalize(self) -> Option<RcExpr> {
+}
+
+impl Parser {
+    // only called on final parsers, see `is_final`
+    fn finalize(self) -> SharedExpr {
         match self.state {
-            ParsingState::Expr(e) => Some(e),
-            _ => None,
+            ParsingState::Expr(e) => e,
+            _ => unreachable!("Cannot finalize parser in this state"),
         }
     }
     fn is_final(&self) -> bool {
@@ -321,38 +975,144 @@ impl Parser {
     }
 }
 
-fn parser<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Expr, E> {
-    let mut machine = ParserMachine::new();
+// Precedence climbing over a flat `operand (operator operand)*` list,
+// see : https://eli.thegreenplace.net/2012/08/02/parsing-expressions-by-precedence-climbing
+fn build_operation(operands: VecSharedExpr, operators: Vec<AssocOp>) -> SharedExpr {
+    let mut operands = operands.into_iter();
+    let mut operators = operators.into_iter().peekable();
+    let first = operands.next().unwrap();
+    climb_precedence(first, 0, &mut operands, &mut operators)
+}
 
-    let (input, _) = multispace0(input)?;
-    let mut input = input;
-    while input.len() != 0 {
-        let (i, lex) = full_lexer(input)?;
-        // dbg!(&machine, &input, &lex);
+fn climb_precedence<I: Iterator<Item = SharedExpr>, O: Iterator<Item = AssocOp>>(mut left: SharedExpr, min_precedence: u8, operands: &mut I, operators: &mut Peekable<O>) -> SharedExpr {
+    while let Some(op) = operators.next_if(|op| op.precedence() >= min_precedence) {
+        let mut right = operands.next().unwrap();
+        while let Some(next) = operators.peek() {
+            if next.precedence() < op.precedence() || (next.precedence() == op.precedence() && !next.is_right_associative()) {
+                break;
+            }
+            let next_precedence = next.precedence();
+            right = climb_precedence(right, next_precedence, operands, operators);
+        }
+        let (start, end) = (span_of(&left).start, span_of(&right).end);
+        left = spanned(Expr::BinaryOperator(left, right, op), start, end);
+    }
+    left
+}
+
+fn spanned(expr: Expr, start: usize, end: usize) -> SharedExpr {
+    SharedExpr::new(Expr::Spanned(SharedExpr::new(expr), Span { start, end }))
+}
+
+// A parenthesized expression keeps its inner node, with a span covering the parenthesis
+fn respanned(expr: &SharedExpr, start: usize, end: usize) -> SharedExpr {
+    match expr.as_ref() {
+        Expr::Spanned(inner, _) => SharedExpr::new(Expr::Spanned(inner.clone(), Span { start, end })),
+        _ => spanned(expr.as_ref().clone(), start, end),
+    }
+}
+
+// The prefix operators are already applied to their operand when an indexer or a method call comes, but `-a[0]` is `-(a[0])`.
+// A parenthesized operation ends with its parenthesis, not with its operand.
+fn postfixed(target: &SharedExpr, end: usize, apply: &dyn Fn(SharedExpr) -> Expr) -> SharedExpr {
+    let span = span_of(target);
+    match target.without_span() {
+        Expr::UnaryOperator(operand, op) if span_of(operand).end == span.end => spanned(Expr::UnaryOperator(postfixed(operand, end, apply), *op), span.start, end),
+        _ => spanned(apply(target.clone()), span.start, end),
+    }
+}
+
+// Every node built by the parser is spanned
+fn span_of(expr: &SharedExpr) -> Span {
+    match expr.as_ref() {
+        Expr::Spanned(_, span) => *span,
+        _ => Span::default(),
+    }
+}
+
+fn parser(source: &str) -> Result<Expr, ParseError> {
+    parse_at(source, 0)
+}
+
+// `base` is the offset of `source` in the whole expression, the spans and the errors are relative to the latter
+fn parse_at(source: &str, base: usize) -> Result<Expr, ParseError> {
+    let mut machine = ParserMachine::new(source, base);
+
+    let mut input = skip_spaces(source);
+    while !input.is_empty() {
+        machine.position = source.len() - input.len();
+        let (i, lex) = match full_lexer::<(&str, ErrorKind)>(input) {
+            Ok(r) => r,
+            Err(_) => return machine.error(ParseErrorKind::InvalidToken),
+        };
         let mut i = i;
+        let start = machine.offset();
         match lex {
-            Lex::ParenthesisOpen => machine.open_parenthesis(),
-            Lex::ParenthesisClose => machine.close_parenthesis(),
-            Lex::Expr(e) => machine.expression(RcExpr::new(e)),
+            Lex::ParenthesisOpen => machine.open_parenthesis()?,
+            Lex::ParenthesisClose => machine.close_parenthesis()?,
+            Lex::Expr(e) => machine.expression(spanned(e, start, base + source.len() - i.len()))?,
+            Lex::InterpolatedString(parts) => {
+                let expr = interpolated_string_expr(source, base, machine.position, parts)?;
+                machine.expression(spanned(expr, start, base + source.len() - i.len()))?
+            }
             Lex::Op(op) => {
                 if let OperatorParseTryResult::ShouldBeANumber = machine.operator(op) {
-                    let (i2, expr) = second_chance_lexer(input)?;
-                    machine.expression(RcExpr::new(expr));
-                    i = i2;
+                    match (second_chance_lexer::<(&str, ErrorKind)>(input), op) {
+                        (Ok((i2, expr)), _) => {
+                            machine.expression(spanned(expr, start, base + source.len() - i2.len()))?;
+                            i = i2;
+                        }
+                        (Err(_), AssocOp::Subtract) => machine.unary_operator(UnaryOp::Negate)?,
+                        (Err(_), AssocOp::Add) => machine.unary_operator(UnaryOp::Plus)?,
+                        (Err(_), _) => return machine.error(ParseErrorKind::UnexpectedOperator),
+                    }
                 }
             }
-            Lex::Comma => machine.comma(),
-            Lex::FunctionOpen(s) => machine.open_function(s),
+            Lex::UnaryOp(op) => machine.unary_operator(op)?,
+            Lex::Comma => machine.comma()?,
+            Lex::QuestionMark => machine.question_mark()?,
+            Lex::Colon => machine.colon()?,
+            Lex::NullConditional => return machine.error(ParseErrorKind::UnsupportedMemberAccess),
+            Lex::Member(name) => machine.member(name, base + source.len() - i.len())?,
+            Lex::FunctionOpen(s) => machine.open_function(s)?,
+            Lex::MethodOpen(s) => machine.open_method(s)?,
+            Lex::LambdaOpen(p) => machine.open_lambda(p)?,
+            Lex::ArrayOpen => machine.open_array(ExpectedToken::CloseBrace)?,
+            Lex::BracketOpen => machine.open_bracket()?,
+            Lex::BracketClose => machine.close_bracket(ExpectedToken::CloseBracket)?,
+            Lex::BraceClose => machine.close_bracket(ExpectedToken::CloseBrace)?,
         }
-        machine.reduce();
-        let (i, _) = multispace0(i)?;
-        input = i;
+        machine.reduce()?;
+        input = skip_spaces(i);
     }
 
-    Ok((input, machine.finalize()))
+    machine.finalize()
 }
 
-pub fn expr<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Expr, E> {
+// `$"Hello {name}, you are {age + 1:N0}"` is `Concat("Hello ", Format(name), ", you are ", Format(age + 1, "N0"))`,
+// both calls already bound to the built-in functions, so they don't depend on the registry.
+// Each hole is parsed as a whole expression, its spans and errors are moved to their position in the source
+fn interpolated_string_expr(source: &str, base: usize, position: usize, parts: Vec<InterpolationPart>) -> Result<Expr, ParseError> {
+    let parts = parts
+        .into_iter()
+        .map(|part| match part {
+            InterpolationPart::Text(text) => Ok(SharedExpr::new(Expr::Str(text))),
+            InterpolationPart::Hole { offset, expression, format } => parse_at(&expression, base + position + offset)
+                .map(|e| {
+                    let parameters = std::iter::once(SharedExpr::new(e)).chain(format.map(|f| SharedExpr::new(Expr::Str(f)))).collect();
+                    SharedExpr::new(Expr::PreparedFunctionCall(UniCase::new("Format".to_string()), parameters, interpolation_function("Format")))
+                })
+                .map_err(|e| ParseError::new(e.kind, source, position + offset + e.offset, e.expected)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Expr::PreparedFunctionCall(UniCase::new("Concat".to_string()), parts, interpolation_function("Concat")))
+}
+
+fn skip_spaces(input: &str) -> &str {
+    multispace0::<&str, (&str, ErrorKind)>(input).map_or(input, |(i, _)| i)
+}
+
+pub fn expr(input: &str) -> Result<Expr, ParseError> {
     parser(input)
 }
 
@@ -361,7 +1121,6 @@ mod tests {
     use super::*;
     use nom::error::ErrorKind;
     use rust_decimal_macros::*;
-    use std::rc::Rc;
     use std::time::Instant;
     use test_case::test_case;
 
@@ -372,17 +1131,22 @@ mod tests {
     }
     macro_rules! rc_expr_str {
         ( $x:expr ) => {
-            Rc::new(Expr::Str($x.into()))
+            SharedExpr::new(Expr::Str($x.into()))
         };
     }
     macro_rules! rc_expr_id {
         ( $x:expr ) => {
-            Rc::new(Expr::Identifier($x.to_string()))
+            SharedExpr::new(Expr::Identifier($x.to_string()))
+        };
+    }
+    macro_rules! rc_expr_int {
+        ( $x:expr ) => {
+            SharedExpr::new(Expr::Int($x))
         };
     }
     macro_rules! rc_expr_num {
         ( $x:expr ) => {
-            Rc::new(Expr::Num(dec!($x)))
+            SharedExpr::new(Expr::Num(dec!($x)))
         };
     }
 
@@ -440,7 +1204,7 @@ mod tests {
         // machine.finalize();
     }
 
-    fn get_lexed<'a, E: ParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Vec<Lex>, E> {
+    fn get_lexed<'a, E: NomParseError<&'a str>>(input: &'a str) -> IResult<&'a str, Vec<Lex>, E> {
         let mut lexed = vec![];
         let (input, _) = multispace0(input)?;
         let mut input = input;
@@ -454,33 +1218,33 @@ mod tests {
         Ok((input, lexed))
     }
 
-    #[test_case("1+2", (Expr::Num(dec!(1)), Expr::Num(dec!(2)), AssocOp::Add))]
-    #[test_case("(3)+(4)", (Expr::Num(dec!(3)), Expr::Num(dec!(4)), AssocOp::Add))]
-    #[test_case(" 3- 2 ", (Expr::Num(dec!(3)), Expr::Num(dec!(2)), AssocOp::Subtract))]
-    #[test_case(" 3 /2", (Expr::Num(dec!(3)), Expr::Num(dec!(2)), AssocOp::Divide))]
-    #[test_case("3|| 5 ", (Expr::Num(dec!(3)), Expr::Num(dec!(5)), AssocOp::LOr))]
-    #[test_case("5 * 5", (Expr::Num(dec!(5)), Expr::Num(dec!(5)), AssocOp::Multiply))]
-    #[test_case(" 42 % \"2\"", (Expr::Num(dec!(42)), Expr::Str("2".to_string()), AssocOp::Modulus))]
-    #[test_case("2 == 2", (Expr::Num(dec!(2)), Expr::Num(dec!(2)), AssocOp::Equal))]
-    #[test_case("2 > 2", (Expr::Num(dec!(2)), Expr::Num(dec!(2)), AssocOp::Greater))]
-    #[test_case("2 < 2", (Expr::Num(dec!(2)), Expr::Num(dec!(2)), AssocOp::Less))]
-    #[test_case("2 >= 2", (Expr::Num(dec!(2)), Expr::Num(dec!(2)), AssocOp::GreaterEqual))]
-    #[test_case("2 <= 2", (Expr::Num(dec!(2)), Expr::Num(dec!(2)), AssocOp::LessEqual))]
+    #[test_case("1+2", (Expr::Int(1), Expr::Int(2), AssocOp::Add))]
+    #[test_case("(3)+(4)", (Expr::Int(3), Expr::Int(4), AssocOp::Add))]
+    #[test_case(" 3- 2 ", (Expr::Int(3), Expr::Int(2), AssocOp::Subtract))]
+    #[test_case(" 3 /2", (Expr::Int(3), Expr::Int(2), AssocOp::Divide))]
+    #[test_case("3|| 5 ", (Expr::Int(3), Expr::Int(5), AssocOp::LOr))]
+    #[test_case("5 * 5", (Expr::Int(5), Expr::Int(5), AssocOp::Multiply))]
+    #[test_case(" 42 % \"2\"", (Expr::Int(42), Expr::Str("2".to_string()), AssocOp::Modulus))]
+    #[test_case("2 == 2", (Expr::Int(2), Expr::Int(2), AssocOp::Equal))]
+    #[test_case("2 > 2", (Expr::Int(2), Expr::Int(2), AssocOp::Greater))]
+    #[test_case("2 < 2", (Expr::Int(2), Expr::Int(2), AssocOp::Less))]
+    #[test_case("2 >= 2", (Expr::Int(2), Expr::Int(2), AssocOp::GreaterEqual))]
+    #[test_case("2 <= 2", (Expr::Int(2), Expr::Int(2), AssocOp::LessEqual))]
     #[test_case("true && false", (Expr::Boolean(true), Expr::Boolean(false), AssocOp::LAnd))]
     #[test_case("false || true", (Expr::Boolean(false), Expr::Boolean(true), AssocOp::LOr))]
     fn binary_operations_test(text: &str, expected: (Expr, Expr, AssocOp)) {
-        let result = parser::<(&str, ErrorKind)>(text);
+        let result = parser(text);
         let (left, right, op) = expected;
-        assert_eq!(result, Ok(("", Expr::BinaryOperator(RcExpr::new(left), RcExpr::new(right), op))));
+        assert_eq!(result, Ok(Expr::BinaryOperator(SharedExpr::new(left), SharedExpr::new(right), op)));
     }
 
-    #[test_case("test()", Expr::FunctionCall(unicase!("test"), VecRcExpr::new()))]
-    #[test_case(" toto () ", Expr::FunctionCall(unicase!("toto"), VecRcExpr::new()))]
-    #[test_case(" toto (toto()) ", Expr::FunctionCall(unicase!("toto"), vec![RcExpr::new(Expr::FunctionCall(unicase!("toto"), VecRcExpr::new()))]))]
-    #[test_case("toto((null - null)) ", Expr::FunctionCall(unicase!("toto"), vec![RcExpr::new(Expr::BinaryOperator( RcExpr::new(Expr::Null),RcExpr::new(Expr::Null), AssocOp::Subtract))]))]
-    #[test_case("(null - null) ", Expr::BinaryOperator(RcExpr::new(Expr::Null), RcExpr::new(Expr::Null), AssocOp::Subtract))]
-    #[test_case("2 - null ", Expr::BinaryOperator(RcExpr::new(Expr::Num(dec!(2))), RcExpr::new(Expr::Null), AssocOp::Subtract))]
-    #[test_case("tata(null - null) ", Expr::FunctionCall(unicase!("tata"), vec![RcExpr::new(Expr::BinaryOperator( RcExpr::new(Expr::Null),RcExpr::new(Expr::Null), AssocOp::Subtract))]))]
+    #[test_case("test()", Expr::FunctionCall(unicase!("test"), VecSharedExpr::new()))]
+    #[test_case(" toto () ", Expr::FunctionCall(unicase!("toto"), VecSharedExpr::new()))]
+    #[test_case(" toto (toto()) ", Expr::FunctionCall(unicase!("toto"), vec![SharedExpr::new(Expr::FunctionCall(unicase!("toto"), VecSharedExpr::new()))]))]
+    #[test_case("toto((null - null)) ", Expr::FunctionCall(unicase!("toto"), vec![SharedExpr::new(Expr::BinaryOperator( SharedExpr::new(Expr::Null),SharedExpr::new(Expr::Null), AssocOp::Subtract))]))]
+    #[test_case("(null - null) ", Expr::BinaryOperator(SharedExpr::new(Expr::Null), SharedExpr::new(Expr::Null), AssocOp::Subtract))]
+    #[test_case("2 - null ", Expr::BinaryOperator(SharedExpr::new(Expr::Int(2)), SharedExpr::new(Expr::Null), AssocOp::Subtract))]
+    #[test_case("tata(null - null) ", Expr::FunctionCall(unicase!("tata"), vec![SharedExpr::new(Expr::BinaryOperator( SharedExpr::new(Expr::Null),SharedExpr::new(Expr::Null), AssocOp::Subtract))]))]
     #[test_case("Find(\"\\t\", \"bo\\tbo\")", Expr::FunctionCall(unicase!("Find"), vec![rc_expr_str!("\t"), rc_expr_str!("bo\tbo")]))]
     fn parse_some_expr(text: &str, expected: Expr) {
         let result = parse_expr(text).unwrap();
@@ -489,17 +1253,167 @@ mod tests {
 
     #[test]
     fn binary_operation_parenthesis_test() {
-        let expected = Ok((
-            "",
-            Expr::BinaryOperator(
-                RcExpr::new(Expr::BinaryOperator(RcExpr::new(Expr::Num(dec!(3))), RcExpr::new(Expr::Num(dec!(5))), AssocOp::Divide)),
-                RcExpr::new(Expr::Str("2".to_string())),
-                AssocOp::Subtract,
-            ),
+        let expected = Ok(Expr::BinaryOperator(
+            SharedExpr::new(Expr::BinaryOperator(SharedExpr::new(Expr::Int(3)), SharedExpr::new(Expr::Int(5)), AssocOp::Divide)),
+            SharedExpr::new(Expr::Str("2".to_string())),
+            AssocOp::Subtract,
         ));
-        assert_eq!(parser::<(&str, ErrorKind)>("3 / 5-\"2\""), expected);
-        assert_eq!(parser::<(&str, ErrorKind)>("(3) / (5) -(\"2\")"), expected);
-        assert_eq!(parser::<(&str, ErrorKind)>("(3 / 5-\"2\")"), expected);
+        assert_eq!(parser("3 / 5-\"2\""), expected);
+        assert_eq!(parser("(3) / (5) -(\"2\")"), expected);
+        assert_eq!(parser("(3 / 5-\"2\")"), expected);
+    }
+
+    macro_rules! rc_expr_op {
+        ( $l:expr, $op:ident, $r:expr ) => {
+            SharedExpr::new(Expr::BinaryOperator($l, $r, AssocOp::$op))
+        };
+    }
+
+    #[test_case("1 + 2 * 3" => rc_expr_op!(rc_expr_int!(1), Add, rc_expr_op!(rc_expr_int!(2), Multiply, rc_expr_int!(3))))]
+    #[test_case("1 * 2 + 3" => rc_expr_op!(rc_expr_op!(rc_expr_int!(1), Multiply, rc_expr_int!(2)), Add, rc_expr_int!(3)))]
+    #[test_case("(1 + 2) * 3" => rc_expr_op!(rc_expr_op!(rc_expr_int!(1), Add, rc_expr_int!(2)), Multiply, rc_expr_int!(3)))]
+    #[test_case("1 - 2 - 3" => rc_expr_op!(rc_expr_op!(rc_expr_int!(1), Subtract, rc_expr_int!(2)), Subtract, rc_expr_int!(3)))]
+    #[test_case("1 - (2 - 3)" => rc_expr_op!(rc_expr_int!(1), Subtract, rc_expr_op!(rc_expr_int!(2), Subtract, rc_expr_int!(3))))]
+    #[test_case("8 / 4 / 2" => rc_expr_op!(rc_expr_op!(rc_expr_int!(8), Divide, rc_expr_int!(4)), Divide, rc_expr_int!(2)))]
+    #[test_case("1 - 2 / 2" => rc_expr_op!(rc_expr_int!(1), Subtract, rc_expr_op!(rc_expr_int!(2), Divide, rc_expr_int!(2))))]
+    #[test_case("1 + 6 % 4 * 2" => rc_expr_op!(rc_expr_int!(1), Add, rc_expr_op!(rc_expr_op!(rc_expr_int!(6), Modulus, rc_expr_int!(4)), Multiply, rc_expr_int!(2))))]
+    #[test_case("1 + 2 < 4" => rc_expr_op!(rc_expr_op!(rc_expr_int!(1), Add, rc_expr_int!(2)), Less, rc_expr_int!(4)))]
+    #[test_case("1 < 2 == 3 >= 4" => rc_expr_op!(rc_expr_op!(rc_expr_int!(1), Less, rc_expr_int!(2)), Equal, rc_expr_op!(rc_expr_int!(3), GreaterEqual, rc_expr_int!(4))))]
+    #[test_case("1 + 2 * 3 == 7 && x" => rc_expr_op!(rc_expr_op!(rc_expr_op!(rc_expr_int!(1), Add, rc_expr_op!(rc_expr_int!(2), Multiply, rc_expr_int!(3))), Equal, rc_expr_int!(7)), LAnd, rc_expr_id!("x")))]
+    #[test_case("a || b && c" => rc_expr_op!(rc_expr_id!("a"), LOr, rc_expr_op!(rc_expr_id!("b"), LAnd, rc_expr_id!("c"))))]
+    #[test_case("a && b || c && d" => rc_expr_op!(rc_expr_op!(rc_expr_id!("a"), LAnd, rc_expr_id!("b")), LOr, rc_expr_op!(rc_expr_id!("c"), LAnd, rc_expr_id!("d"))))]
+    #[test_case("a != b || c" => rc_expr_op!(rc_expr_op!(rc_expr_id!("a"), NotEqual, rc_expr_id!("b")), LOr, rc_expr_id!("c")))]
+    #[test_case("1 * 2 + 3 * 4 - 5 / 6" => rc_expr_op!(rc_expr_op!(rc_expr_op!(rc_expr_int!(1), Multiply, rc_expr_int!(2)), Add, rc_expr_op!(rc_expr_int!(3), Multiply, rc_expr_int!(4))), Subtract, rc_expr_op!(rc_expr_int!(5), Divide, rc_expr_int!(6))))]
+    #[test_case("(4 + 2 * 3)" => rc_expr_op!(rc_expr_int!(4), Add, rc_expr_op!(rc_expr_int!(2), Multiply, rc_expr_int!(3))))]
+    #[test_case("(1 + 2) * (3 - 4) / 5" => rc_expr_op!(rc_expr_op!(rc_expr_op!(rc_expr_int!(1), Add, rc_expr_int!(2)), Multiply, rc_expr_op!(rc_expr_int!(3), Subtract, rc_expr_int!(4))), Divide, rc_expr_int!(5)))]
+    #[test_case("f(1 + 2 * 3, 4 - 5 * 6)" => SharedExpr::new(Expr::FunctionCall(unicase!("f"), vec![rc_expr_op!(rc_expr_int!(1), Add, rc_expr_op!(rc_expr_int!(2), Multiply, rc_expr_int!(3))), rc_expr_op!(rc_expr_int!(4), Subtract, rc_expr_op!(rc_expr_int!(5), Multiply, rc_expr_int!(6)))])))]
+    #[test_case("f((1 + 2) * 3)" => SharedExpr::new(Expr::FunctionCall(unicase!("f"), vec![rc_expr_op!(rc_expr_op!(rc_expr_int!(1), Add, rc_expr_int!(2)), Multiply, rc_expr_int!(3))])))]
+    #[test_case("1 + f(2) * 3" => rc_expr_op!(rc_expr_int!(1), Add, rc_expr_op!(SharedExpr::new(Expr::FunctionCall(unicase!("f"), vec![rc_expr_int!(2)])), Multiply, rc_expr_int!(3))))]
+    #[test_case("a ?? b ?? c" => rc_expr_op!(rc_expr_id!("a"), NullCoalescing, rc_expr_op!(rc_expr_id!("b"), NullCoalescing, rc_expr_id!("c"))))]
+    #[test_case("a || b ?? c" => rc_expr_op!(rc_expr_op!(rc_expr_id!("a"), LOr, rc_expr_id!("b")), NullCoalescing, rc_expr_id!("c")))]
+    #[test_case("a ?? b + c" => rc_expr_op!(rc_expr_id!("a"), NullCoalescing, rc_expr_op!(rc_expr_id!("b"), Add, rc_expr_id!("c"))))]
+    #[test_case("a ?? b == c ?? d" => rc_expr_op!(rc_expr_id!("a"), NullCoalescing, rc_expr_op!(rc_expr_op!(rc_expr_id!("b"), Equal, rc_expr_id!("c")), NullCoalescing, rc_expr_id!("d"))))]
+    fn parse_operators_precedence(expression: &str) -> SharedExpr {
+        SharedExpr::new(parse_expr(expression).unwrap())
+    }
+
+    macro_rules! rc_expr_unary {
+        ( $op:ident, $e:expr ) => {
+            SharedExpr::new(Expr::UnaryOperator($e, UnaryOp::$op))
+        };
+    }
+
+    #[test_case("!a" => rc_expr_unary!(Not, rc_expr_id!("a")))]
+    #[test_case("!!a" => rc_expr_unary!(Not, rc_expr_unary!(Not, rc_expr_id!("a"))))]
+    #[test_case("-a" => rc_expr_unary!(Negate, rc_expr_id!("a")))]
+    #[test_case("+a" => rc_expr_unary!(Plus, rc_expr_id!("a")))]
+    #[test_case("- 2" => rc_expr_unary!(Negate, rc_expr_int!(2)))]
+    #[test_case("-2" => rc_expr_int!(-2))]
+    #[test_case("!IsNull(x)" => rc_expr_unary!(Not, SharedExpr::new(Expr::FunctionCall(unicase!("IsNull"), vec![rc_expr_id!("x")]))))]
+    #[test_case("-Abs(y)" => rc_expr_unary!(Negate, SharedExpr::new(Expr::FunctionCall(unicase!("Abs"), vec![rc_expr_id!("y")]))))]
+    #[test_case("-(a + b)" => rc_expr_unary!(Negate, rc_expr_op!(rc_expr_id!("a"), Add, rc_expr_id!("b"))))]
+    #[test_case("-a * b" => rc_expr_op!(rc_expr_unary!(Negate, rc_expr_id!("a")), Multiply, rc_expr_id!("b")))]
+    #[test_case("a - -b" => rc_expr_op!(rc_expr_id!("a"), Subtract, rc_expr_unary!(Negate, rc_expr_id!("b"))))]
+    #[test_case("a * -b + c" => rc_expr_op!(rc_expr_op!(rc_expr_id!("a"), Multiply, rc_expr_unary!(Negate, rc_expr_id!("b"))), Add, rc_expr_id!("c")))]
+    #[test_case("!a && b" => rc_expr_op!(rc_expr_unary!(Not, rc_expr_id!("a")), LAnd, rc_expr_id!("b")))]
+    #[test_case("!(a && b)" => rc_expr_unary!(Not, rc_expr_op!(rc_expr_id!("a"), LAnd, rc_expr_id!("b"))))]
+    #[test_case("a != !b" => rc_expr_op!(rc_expr_id!("a"), NotEqual, rc_expr_unary!(Not, rc_expr_id!("b"))))]
+    #[test_case("f(-a, !b)" => SharedExpr::new(Expr::FunctionCall(unicase!("f"), vec![rc_expr_unary!(Negate, rc_expr_id!("a")), rc_expr_unary!(Not, rc_expr_id!("b"))])))]
+    #[test_case("f(-a * 2)" => SharedExpr::new(Expr::FunctionCall(unicase!("f"), vec![rc_expr_op!(rc_expr_unary!(Negate, rc_expr_id!("a")), Multiply, rc_expr_int!(2))])))]
+    fn parse_unary_operators(expression: &str) -> SharedExpr {
+        SharedExpr::new(parse_expr(expression).unwrap())
+    }
+
+    macro_rules! rc_expr_ternary {
+        ( $c:expr, $t:expr, $f:expr ) => {
+            SharedExpr::new(Expr::Ternary($c, $t, $f))
+        };
+    }
+
+    #[test_case("a ? b : c" => rc_expr_ternary!(rc_expr_id!("a"), rc_expr_id!("b"), rc_expr_id!("c")))]
+    #[test_case("x > 3 ? \"big\" : \"small\"" => rc_expr_ternary!(rc_expr_op!(rc_expr_id!("x"), Greater, rc_expr_int!(3)), rc_expr_str!("big"), rc_expr_str!("small")))]
+    #[test_case("a || b ? 1 + 2 : 3 * 4" => rc_expr_ternary!(rc_expr_op!(rc_expr_id!("a"), LOr, rc_expr_id!("b")), rc_expr_op!(rc_expr_int!(1), Add, rc_expr_int!(2)), rc_expr_op!(rc_expr_int!(3), Multiply, rc_expr_int!(4))))]
+    #[test_case("a ? b : c ? d : e" => rc_expr_ternary!(rc_expr_id!("a"), rc_expr_id!("b"), rc_expr_ternary!(rc_expr_id!("c"), rc_expr_id!("d"), rc_expr_id!("e"))))]
+    #[test_case("a ? b ? c : d : e" => rc_expr_ternary!(rc_expr_id!("a"), rc_expr_ternary!(rc_expr_id!("b"), rc_expr_id!("c"), rc_expr_id!("d")), rc_expr_id!("e")))]
+    #[test_case("a ? b : c + d ? e : f" => rc_expr_ternary!(rc_expr_id!("a"), rc_expr_id!("b"), rc_expr_ternary!(rc_expr_op!(rc_expr_id!("c"), Add, rc_expr_id!("d")), rc_expr_id!("e"), rc_expr_id!("f"))))]
+    #[test_case("(a ? b : c) + d" => rc_expr_op!(rc_expr_ternary!(rc_expr_id!("a"), rc_expr_id!("b"), rc_expr_id!("c")), Add, rc_expr_id!("d")))]
+    #[test_case("!a ? -b : (c)" => rc_expr_ternary!(rc_expr_unary!(Not, rc_expr_id!("a")), rc_expr_unary!(Negate, rc_expr_id!("b")), rc_expr_id!("c")))]
+    #[test_case("f(a ? b : c, d)" => SharedExpr::new(Expr::FunctionCall(unicase!("f"), vec![rc_expr_ternary!(rc_expr_id!("a"), rc_expr_id!("b"), rc_expr_id!("c")), rc_expr_id!("d")])))]
+    #[test_case("f(d, a > 1 ? b : c)" => SharedExpr::new(Expr::FunctionCall(unicase!("f"), vec![rc_expr_id!("d"), rc_expr_ternary!(rc_expr_op!(rc_expr_id!("a"), Greater, rc_expr_int!(1)), rc_expr_id!("b"), rc_expr_id!("c"))])))]
+    #[test_case("g(a) ? g(b) : g(c)" => rc_expr_ternary!(SharedExpr::new(Expr::FunctionCall(unicase!("g"), vec![rc_expr_id!("a")])), SharedExpr::new(Expr::FunctionCall(unicase!("g"), vec![rc_expr_id!("b")])), SharedExpr::new(Expr::FunctionCall(unicase!("g"), vec![rc_expr_id!("c")]))))]
+    #[test_case("a ?? b ? c : d" => rc_expr_ternary!(rc_expr_op!(rc_expr_id!("a"), NullCoalescing, rc_expr_id!("b")), rc_expr_id!("c"), rc_expr_id!("d")))]
+    #[test_case("a?.5:1" => rc_expr_ternary!(rc_expr_id!("a"), rc_expr_num!(0.5), rc_expr_int!(1)))]
+    fn parse_ternary(expression: &str) -> SharedExpr {
+        SharedExpr::new(parse_expr(expression).unwrap())
+    }
+
+    macro_rules! rc_expr_array {
+        ( $( $e:expr ),* ) => {
+            SharedExpr::new(Expr::Array(vec![ $( $e ),* ]))
+        };
+    }
+    macro_rules! rc_expr_index {
+        ( $e:expr, $i:expr ) => {
+            SharedExpr::new(Expr::Index($e, $i))
+        };
+    }
+
+    #[test_case("[1, \"a\", b]" => rc_expr_array!(rc_expr_int!(1), rc_expr_str!("a"), rc_expr_id!("b")))]
+    #[test_case("[]" => rc_expr_array!())]
+    #[test_case("new[] { 1, 2 }" => rc_expr_array!(rc_expr_int!(1), rc_expr_int!(2)))]
+    #[test_case("new []{}" => rc_expr_array!())]
+    #[test_case("[[1], new[] { -2 + x }]" => rc_expr_array!(rc_expr_array!(rc_expr_int!(1)), rc_expr_array!(rc_expr_op!(rc_expr_int!(-2), Add, rc_expr_id!("x")))))]
+    #[test_case("a[0]" => rc_expr_index!(rc_expr_id!("a"), rc_expr_int!(0)))]
+    #[test_case("a[b[0]][1 + i]" => rc_expr_index!(rc_expr_index!(rc_expr_id!("a"), rc_expr_index!(rc_expr_id!("b"), rc_expr_int!(0))), rc_expr_op!(rc_expr_int!(1), Add, rc_expr_id!("i"))))]
+    #[test_case("[1, 2][x]" => rc_expr_index!(rc_expr_array!(rc_expr_int!(1), rc_expr_int!(2)), rc_expr_id!("x")))]
+    #[test_case("Split(s, \",\")[1]" => rc_expr_index!(SharedExpr::new(Expr::FunctionCall(unicase!("Split"), vec![rc_expr_id!("s"), rc_expr_str!(",")])), rc_expr_int!(1)))]
+    #[test_case("1 + a[0] * 2" => rc_expr_op!(rc_expr_int!(1), Add, rc_expr_op!(rc_expr_index!(rc_expr_id!("a"), rc_expr_int!(0)), Multiply, rc_expr_int!(2))))]
+    #[test_case("!a[0]" => rc_expr_unary!(Not, rc_expr_index!(rc_expr_id!("a"), rc_expr_int!(0))))]
+    #[test_case("(-a)[0]" => rc_expr_index!(rc_expr_unary!(Negate, rc_expr_id!("a")), rc_expr_int!(0)))]
+    #[test_case("f(a, b[0])" => SharedExpr::new(Expr::FunctionCall(unicase!("f"), vec![rc_expr_id!("a"), rc_expr_index!(rc_expr_id!("b"), rc_expr_int!(0))])))]
+    #[test_case("c ? a[0] : b[1]" => rc_expr_ternary!(rc_expr_id!("c"), rc_expr_index!(rc_expr_id!("a"), rc_expr_int!(0)), rc_expr_index!(rc_expr_id!("b"), rc_expr_int!(1))))]
+    #[test_case("a[c ? 0 : 1]" => rc_expr_index!(rc_expr_id!("a"), rc_expr_ternary!(rc_expr_id!("c"), rc_expr_int!(0), rc_expr_int!(1))))]
+    #[test_case("$\"{a[0]}{new[] { 1 }}\"" => SharedExpr::new(Expr::PreparedFunctionCall(unicase!("Concat"), vec![SharedExpr::new(Expr::PreparedFunctionCall(unicase!("Format"), vec![rc_expr_index!(rc_expr_id!("a"), rc_expr_int!(0))], interpolation_function("Format"))), SharedExpr::new(Expr::PreparedFunctionCall(unicase!("Format"), vec![rc_expr_array!(rc_expr_int!(1))], interpolation_function("Format")))], interpolation_function("Concat"))))]
+    fn parse_arrays(expression: &str) -> SharedExpr {
+        SharedExpr::new(parse_expr(expression).unwrap())
+    }
+
+    macro_rules! rc_expr_lambda {
+        ( $p:expr, $b:expr ) => {
+            SharedExpr::new(Expr::Lambda($p.to_string(), $b))
+        };
+    }
+    macro_rules! rc_expr_member {
+        ( $e:expr, $m:expr ) => {
+            SharedExpr::new(Expr::Member($e, $m.to_string()))
+        };
+    }
+    macro_rules! rc_expr_call {
+        ( $n:expr, $( $p:expr ),* ) => {
+            SharedExpr::new(Expr::FunctionCall(unicase!($n), vec![ $( $p ),* ]))
+        };
+    }
+
+    #[test_case("x => x > 1" => rc_expr_lambda!("x", rc_expr_op!(rc_expr_id!("x"), Greater, rc_expr_int!(1))))]
+    #[test_case("Where(items, x=>x)" => rc_expr_call!("Where", rc_expr_id!("items"), rc_expr_lambda!("x", rc_expr_id!("x"))))]
+    #[test_case("x => y => x ?? y" => rc_expr_lambda!("x", rc_expr_lambda!("y", rc_expr_op!(rc_expr_id!("x"), NullCoalescing, rc_expr_id!("y")))))]
+    #[test_case("Any(a, x => x ? 1 : 2)" => rc_expr_call!("Any", rc_expr_id!("a"), rc_expr_lambda!("x", rc_expr_ternary!(rc_expr_id!("x"), rc_expr_int!(1), rc_expr_int!(2)))))]
+    #[test_case("items.Where(x => x > 1).Count()" => rc_expr_call!("Count", rc_expr_call!("Where", rc_expr_id!("items"), rc_expr_lambda!("x", rc_expr_op!(rc_expr_id!("x"), Greater, rc_expr_int!(1))))))]
+    #[test_case("items . Count ( )" => rc_expr_call!("Count", rc_expr_id!("items")))]
+    #[test_case("1 + s.Split(\",\")[0] * 2" => rc_expr_op!(rc_expr_int!(1), Add, rc_expr_op!(rc_expr_index!(rc_expr_call!("Split", rc_expr_id!("s"), rc_expr_str!(",")), rc_expr_int!(0)), Multiply, rc_expr_int!(2))))]
+    #[test_case("-a.Abs()" => rc_expr_unary!(Negate, rc_expr_call!("Abs", rc_expr_id!("a"))))]
+    #[test_case("(-a).Abs()" => rc_expr_call!("Abs", rc_expr_unary!(Negate, rc_expr_id!("a"))))]
+    #[test_case("1.5.Round(1)" => rc_expr_call!("Round", rc_expr_num!(1.5), rc_expr_int!(1)))]
+    #[test_case("[1, 2][0].Abs()" => rc_expr_call!("Abs", rc_expr_index!(rc_expr_array!(rc_expr_int!(1), rc_expr_int!(2)), rc_expr_int!(0))))]
+    #[test_case("x.Price" => rc_expr_member!(rc_expr_id!("x"), "Price"))]
+    #[test_case("order . Customer.Name" => rc_expr_member!(rc_expr_member!(rc_expr_id!("order"), "Customer"), "Name"))]
+    #[test_case("items.Where(x => x.Price > 10).Sum(x => x.Price)" => rc_expr_call!("Sum", rc_expr_call!("Where", rc_expr_id!("items"), rc_expr_lambda!("x", rc_expr_op!(rc_expr_member!(rc_expr_id!("x"), "Price"), Greater, rc_expr_int!(10)))), rc_expr_lambda!("x", rc_expr_member!(rc_expr_id!("x"), "Price"))))]
+    #[test_case("1 + -x.Price * 2" => rc_expr_op!(rc_expr_int!(1), Add, rc_expr_op!(rc_expr_unary!(Negate, rc_expr_member!(rc_expr_id!("x"), "Price")), Multiply, rc_expr_int!(2))))]
+    #[test_case("lines[0].Price" => rc_expr_member!(rc_expr_index!(rc_expr_id!("lines"), rc_expr_int!(0)), "Price"))]
+    #[test_case("x.Tags[0]" => rc_expr_index!(rc_expr_member!(rc_expr_id!("x"), "Tags"), rc_expr_int!(0)))]
+    #[test_case("x.Name.ToUpper()" => rc_expr_call!("ToUpper", rc_expr_member!(rc_expr_id!("x"), "Name")))]
+    fn parse_lambdas_and_methods(expression: &str) -> SharedExpr {
+        SharedExpr::new(parse_expr(expression).unwrap())
     }
 
     #[test_case("true" => Expr::Boolean(true))]
@@ -508,9 +1422,9 @@ mod tests {
         parse_expr(expression).unwrap()
     }
 
-    #[test_case("1 + 2" => Expr::BinaryOperator(rc_expr_num!(1), rc_expr_num!(2), AssocOp::Add))]
-    #[test_case("(4) + (2)" => Expr::BinaryOperator(rc_expr_num!(4), rc_expr_num!(2), AssocOp::Add))]
-    #[test_case("(1 * 3)" => Expr::BinaryOperator(rc_expr_num!(1), rc_expr_num!(3), AssocOp::Multiply))]
+    #[test_case("1 + 2" => Expr::BinaryOperator(rc_expr_int!(1), rc_expr_int!(2), AssocOp::Add))]
+    #[test_case("(4) + (2)" => Expr::BinaryOperator(rc_expr_int!(4), rc_expr_int!(2), AssocOp::Add))]
+    #[test_case("(1 * 3)" => Expr::BinaryOperator(rc_expr_int!(1), rc_expr_int!(3), AssocOp::Multiply))]
     fn parse_binary_operator(expression: &str) -> Expr {
         parse_expr(expression).unwrap()
     }
@@ -523,28 +1437,95 @@ mod tests {
 
     #[test]
     fn parse_simple() {
-        assert_eq!(parser::<(&str, ErrorKind)>("true"), Ok(("", Expr::Boolean(true))));
-        assert_eq!(parser::<(&str, ErrorKind)>("2"), Ok(("", Expr::Num(dec!(2)))));
-        assert_eq!(parser::<(&str, ErrorKind)>("(true)"), Ok(("", Expr::Boolean(true))));
-        assert_eq!(parser::<(&str, ErrorKind)>("(-2)"), Ok(("", Expr::Num(dec!(-2)))));
-        // assert_eq!(parser::<(&str, ErrorKind)>("true)"), Ok((")", Expr::Boolean(true))));
-        // assert_eq!(parser::<(&str, ErrorKind)>("(true"), Err(nom::Err::Error(("", ErrorKind::Char))));
-        // assert_eq!(parser::<(&str, ErrorKind)>("2 ,"), Ok((",", Expr::Num(dec!(2)))));
-        // assert_eq!(parser::<(&str, ErrorKind)>("(2) ,"), Ok((" ,", Expr::Num(dec!(2)))));
+        assert_eq!(parser("true"), Ok(Expr::Boolean(true)));
+        assert_eq!(parser("2"), Ok(Expr::Int(2)));
+        assert_eq!(parser("(true)"), Ok(Expr::Boolean(true)));
+        assert_eq!(parser("(-2)"), Ok(Expr::Int(-2)));
     }
 
     #[test]
     fn parse_identifier_or_function_call() {
-        assert_eq!(parser::<(&str, ErrorKind)>("true"), Ok(("", Expr::Boolean(true))));
-        assert_eq!(parser::<(&str, ErrorKind)>("(true)"), Ok(("", Expr::Boolean(true))));
-        assert_eq!(parser::<(&str, ErrorKind)>("( true )"), Ok(("", Expr::Boolean(true))));
-        assert_eq!(parser::<(&str, ErrorKind)>("( _id )"), Ok(("", Expr::Identifier("_id".into()))));
-        assert_eq!(parser::<(&str, ErrorKind)>("( id ( ) )"), Ok(("", Expr::FunctionCall(unicase!("id"), vec!()))));
-        assert_eq!(parser::<(&str, ErrorKind)>("_id()"), Ok(("", Expr::FunctionCall(unicase!("_id"), vec!()))));
-        // assert_eq!(parser::<(&str, ErrorKind)>("_id()toto"), Ok(("toto", Expr::FunctionCall(unicase!("_id"), vec!()))));
-        assert_eq!(parser::<(&str, ErrorKind)>("_id(1,2)"), Ok(("", Expr::FunctionCall(unicase!("_id"), vec![rc_expr_num!(1), rc_expr_num!(2)]))));
-        // assert_eq!(parser::<(&str, ErrorKind)>("_id( a , b"), Err(nom::Err::Error(("", ErrorKind::Char))));
-        // assert_eq!(parser::<(&str, ErrorKind)>("id("), Err(nom::Err::Error(("", ErrorKind::Char))));
+        assert_eq!(parser("true"), Ok(Expr::Boolean(true)));
+        assert_eq!(parser("(true)"), Ok(Expr::Boolean(true)));
+        assert_eq!(parser("( true )"), Ok(Expr::Boolean(true)));
+        assert_eq!(parser("( _id )"), Ok(Expr::Identifier("_id".into())));
+        assert_eq!(parser("( id ( ) )"), Ok(Expr::FunctionCall(unicase!("id"), vec!())));
+        assert_eq!(parser("_id()"), Ok(Expr::FunctionCall(unicase!("_id"), vec!())));
+        assert_eq!(parser("_id(1,2)"), Ok(Expr::FunctionCall(unicase!("_id"), vec![rc_expr_int!(1), rc_expr_int!(2)])));
+    }
+
+    #[test_case("true)" => (ParseErrorKind::UnexpectedCloseParenthesis, 4, vec![ExpectedToken::Operator, ExpectedToken::EndOfInput]))]
+    #[test_case("(true" => (ParseErrorKind::UnexpectedEndOfInput, 5, vec![ExpectedToken::Operator, ExpectedToken::CloseParenthesis]))]
+    #[test_case("2 ," => (ParseErrorKind::UnexpectedComma, 2, vec![ExpectedToken::Operator, ExpectedToken::EndOfInput]))]
+    #[test_case("1,2" => (ParseErrorKind::UnexpectedComma, 1, vec![ExpectedToken::Operator, ExpectedToken::EndOfInput]))]
+    #[test_case("(2) ," => (ParseErrorKind::UnexpectedComma, 4, vec![ExpectedToken::Operator, ExpectedToken::EndOfInput]))]
+    #[test_case("_id()toto" => (ParseErrorKind::UnexpectedExpression, 5, vec![ExpectedToken::Operator, ExpectedToken::EndOfInput]))]
+    #[test_case("_id( a , b" => (ParseErrorKind::UnexpectedEndOfInput, 10, vec![ExpectedToken::Operator, ExpectedToken::Comma, ExpectedToken::CloseParenthesis]))]
+    #[test_case("id(" => (ParseErrorKind::UnexpectedEndOfInput, 3, vec![ExpectedToken::Expression, ExpectedToken::CloseParenthesis]))]
+    #[test_case("f(1 2)" => (ParseErrorKind::UnexpectedExpression, 4, vec![ExpectedToken::Operator, ExpectedToken::Comma, ExpectedToken::CloseParenthesis]))]
+    #[test_case("f(1, )" => (ParseErrorKind::UnexpectedCloseParenthesis, 5, vec![ExpectedToken::Expression]))]
+    #[test_case("f(,1)" => (ParseErrorKind::UnexpectedComma, 2, vec![ExpectedToken::Expression, ExpectedToken::CloseParenthesis]))]
+    #[test_case(")" => (ParseErrorKind::UnexpectedCloseParenthesis, 0, vec![ExpectedToken::Expression]))]
+    #[test_case("(" => (ParseErrorKind::UnexpectedEndOfInput, 1, vec![ExpectedToken::Expression]))]
+    #[test_case("()" => (ParseErrorKind::UnexpectedCloseParenthesis, 1, vec![ExpectedToken::Expression]))]
+    #[test_case("" => (ParseErrorKind::UnexpectedEndOfInput, 0, vec![ExpectedToken::Expression]))]
+    #[test_case("1 +" => (ParseErrorKind::UnexpectedEndOfInput, 3, vec![ExpectedToken::Expression]))]
+    #[test_case("* 2" => (ParseErrorKind::UnexpectedOperator, 0, vec![ExpectedToken::Expression]))]
+    #[test_case("a !b" => (ParseErrorKind::UnexpectedOperator, 2, vec![ExpectedToken::Operator, ExpectedToken::EndOfInput]))]
+    #[test_case("1 + -" => (ParseErrorKind::UnexpectedEndOfInput, 5, vec![ExpectedToken::Expression]))]
+    #[test_case("1 (2)" => (ParseErrorKind::UnexpectedOpenParenthesis, 2, vec![ExpectedToken::Operator, ExpectedToken::EndOfInput]))]
+    #[test_case("func() .. / \" wtf" => (ParseErrorKind::InvalidToken, 7, vec![ExpectedToken::Operator, ExpectedToken::EndOfInput]))]
+    #[test_case("? a : b" => (ParseErrorKind::UnexpectedOperator, 0, vec![ExpectedToken::Expression]))]
+    #[test_case("a ? b" => (ParseErrorKind::UnexpectedEndOfInput, 5, vec![ExpectedToken::Operator, ExpectedToken::Colon]))]
+    #[test_case("a ? : b" => (ParseErrorKind::UnexpectedOperator, 4, vec![ExpectedToken::Expression]))]
+    #[test_case("x : y" => (ParseErrorKind::UnexpectedOperator, 2, vec![ExpectedToken::Operator, ExpectedToken::EndOfInput]))]
+    #[test_case("a ? b : c : d" => (ParseErrorKind::UnexpectedOperator, 10, vec![ExpectedToken::Operator, ExpectedToken::EndOfInput]))]
+    #[test_case("f(a ? b, c)" => (ParseErrorKind::UnexpectedComma, 7, vec![ExpectedToken::Operator, ExpectedToken::Colon]))]
+    #[test_case("(a ? b + 1)" => (ParseErrorKind::UnexpectedCloseParenthesis, 10, vec![ExpectedToken::Operator, ExpectedToken::Colon]))]
+    #[test_case("a ? (b + 1 : c)" => (ParseErrorKind::UnexpectedOperator, 11, vec![ExpectedToken::Operator, ExpectedToken::CloseParenthesis]))]
+    #[test_case("''" => (ParseErrorKind::InvalidToken, 0, vec![ExpectedToken::Expression]))]
+    #[test_case("'ab'" => (ParseErrorKind::InvalidToken, 0, vec![ExpectedToken::Expression]))]
+    #[test_case("@\"unterminated" => (ParseErrorKind::InvalidToken, 0, vec![ExpectedToken::Expression]))]
+    #[test_case("$\"{a\"" => (ParseErrorKind::InvalidToken, 0, vec![ExpectedToken::Expression]))]
+    #[test_case("$\"b}\"" => (ParseErrorKind::InvalidToken, 0, vec![ExpectedToken::Expression]))]
+    #[test_case("1 + $\"{}\"" => (ParseErrorKind::UnexpectedEndOfInput, 7, vec![ExpectedToken::Expression]))]
+    #[test_case("1 + $\"x{a b}\"" => (ParseErrorKind::UnexpectedExpression, 10, vec![ExpectedToken::Operator, ExpectedToken::EndOfInput]))]
+    #[test_case("4.2L" => (ParseErrorKind::InvalidToken, 1, vec![ExpectedToken::Operator, ExpectedToken::EndOfInput]))]
+    #[test_case("1 + 0x" => (ParseErrorKind::UnexpectedExpression, 5, vec![ExpectedToken::Operator, ExpectedToken::EndOfInput]))]
+    #[test_case("a?.b" => (ParseErrorKind::UnsupportedMemberAccess, 1, vec![ExpectedToken::Operator, ExpectedToken::EndOfInput]))]
+    #[test_case("Any(a, x => x?.b)" => (ParseErrorKind::UnsupportedMemberAccess, 13, vec![ExpectedToken::Operator, ExpectedToken::Comma, ExpectedToken::CloseParenthesis]))]
+    #[test_case(".Price" => (ParseErrorKind::UnexpectedOperator, 0, vec![ExpectedToken::Expression]))]
+    #[test_case("1 + .Price" => (ParseErrorKind::UnexpectedOperator, 4, vec![ExpectedToken::Expression]))]
+    #[test_case("Any(a, x => )" => (ParseErrorKind::UnexpectedCloseParenthesis, 12, vec![ExpectedToken::Expression]))]
+    #[test_case(".Count()" => (ParseErrorKind::UnexpectedOperator, 0, vec![ExpectedToken::Expression]))]
+    #[test_case("1 x => x" => (ParseErrorKind::UnexpectedExpression, 2, vec![ExpectedToken::Operator, ExpectedToken::EndOfInput]))]
+    #[test_case("a.Count(" => (ParseErrorKind::UnexpectedEndOfInput, 8, vec![ExpectedToken::Expression, ExpectedToken::CloseParenthesis]))]
+    #[test_case("a ?? ?? b" => (ParseErrorKind::UnexpectedOperator, 5, vec![ExpectedToken::Expression]))]
+    #[test_case("\"\\u\"" => (ParseErrorKind::InvalidToken, 0, vec![ExpectedToken::Expression]))]
+    #[test_case("[1, 2" => (ParseErrorKind::UnexpectedEndOfInput, 5, vec![ExpectedToken::Operator, ExpectedToken::Comma, ExpectedToken::CloseBracket]))]
+    #[test_case("[1, ]" => (ParseErrorKind::UnexpectedCloseBracket, 4, vec![ExpectedToken::Expression]))]
+    #[test_case("new[] { 1 ]" => (ParseErrorKind::UnexpectedCloseBracket, 10, vec![ExpectedToken::Operator, ExpectedToken::Comma, ExpectedToken::CloseBrace]))]
+    #[test_case("[1 }" => (ParseErrorKind::UnexpectedCloseBrace, 3, vec![ExpectedToken::Operator, ExpectedToken::Comma, ExpectedToken::CloseBracket]))]
+    #[test_case("a[]" => (ParseErrorKind::UnexpectedCloseBracket, 2, vec![ExpectedToken::Expression]))]
+    #[test_case("a[1, 2]" => (ParseErrorKind::UnexpectedComma, 3, vec![ExpectedToken::Operator, ExpectedToken::CloseBracket]))]
+    #[test_case("a[1" => (ParseErrorKind::UnexpectedEndOfInput, 3, vec![ExpectedToken::Operator, ExpectedToken::CloseBracket]))]
+    #[test_case("x + ]" => (ParseErrorKind::UnexpectedCloseBracket, 4, vec![ExpectedToken::Expression]))]
+    #[test_case("f(a, [)" => (ParseErrorKind::UnexpectedCloseParenthesis, 6, vec![ExpectedToken::Expression, ExpectedToken::CloseBracket]))]
+    #[test_case("a ? [ : b" => (ParseErrorKind::UnexpectedOperator, 6, vec![ExpectedToken::Expression, ExpectedToken::CloseBracket]))]
+    fn parse_errors(expression: &str) -> (ParseErrorKind, usize, Vec<ExpectedToken>) {
+        let err = parse_expr(expression).unwrap_err();
+        (err.kind, err.offset, err.expected)
+    }
+
+    #[test]
+    fn parse_error_location() {
+        let err = parse_expr("Concat(\r\n  \"a\",\r\n  \"b\" \"c\")").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::UnexpectedExpression);
+        assert_eq!((err.offset, err.line, err.column), (23, 3, 7));
+        assert_eq!(err.to_string(), "Unexpected expression at line 3, column 7, expecting an operator or ',' or ')'");
+
+        let err = parse_expr("Concat(\"é\" ,, 2)").unwrap_err();
+        assert_eq!((err.offset, err.line, err.column), (13, 1, 13));
     }
 
     #[test_case(stringify!("null") => "null")]
@@ -563,8 +1544,8 @@ mod tests {
         let result = parse_expr(expression);
         println!("{:?}", result);
         let expr = result.unwrap();
-        if let Expr::Str(result) = expr {
-            result
+        if let Expr::Str(result) = expr.without_span() {
+            result.clone()
         } else {
             panic!("{:?}", expr)
         }
@@ -576,15 +1557,77 @@ mod tests {
         assert_eq!(result, Ok(Expr::Str(" te sΓé¼t\tt ab ./".to_string())));
     }
 
-    #[test_case("1" => Expr::Num(dec!(1)))]
+    #[test_case("1" => Expr::Int(1))]
     #[test_case("1.2" => Expr::Num(dec!(1.2)))]
     #[test_case("-0.42" => Expr::Num(dec!(-0.42)))]
     #[test_case("(3.14)" => Expr::Num(dec!(3.14)))]
-    #[test_case("(((42)))" => Expr::Num(dec!(42)))]
+    #[test_case("(((42)))" => Expr::Int(42))]
+    #[test_case("0.1" => Expr::Num(dec!(0.1)))]
+    #[test_case(".5" => Expr::Num(dec!(0.5)))]
+    #[test_case("9007199254740993" => Expr::Int(9007199254740993))]
+    #[test_case("-9223372036854775807" => Expr::Int(-9223372036854775807))]
+    #[test_case("79228162514264337593543950335" => Expr::Num(ExprDecimal::MAX))]
+    #[test_case("12345678901234567890.123" => Expr::Num(dec!(12345678901234567890.123)))]
+    #[test_case("1_000_000" => Expr::Int(1_000_000))]
+    #[test_case("1__0.2_5" => Expr::Num(dec!(10.25)))]
+    #[test_case("0x1F" => Expr::Int(31))]
+    #[test_case("0XFF_FF" => Expr::Int(65535))]
+    #[test_case("0xFFFFFFFFFFFFFFFF" => Expr::Num(dec!(18446744073709551615)))]
+    #[test_case("42L" => Expr::Int(42))]
+    #[test_case("42u" => Expr::Int(42))]
+    #[test_case("42UL" => Expr::Int(42))]
+    #[test_case("42m" => Expr::Num(dec!(42)))]
+    #[test_case("4.2M" => Expr::Num(dec!(4.2)))]
+    #[test_case("4.2d" => Expr::Num(dec!(4.2)))]
+    #[test_case("4.2f" => Expr::Num(dec!(4.2)))]
+    #[test_case("1.5e3" => Expr::Num(dec!(1500)))]
+    #[test_case("25E-2" => Expr::Num(dec!(0.25)))]
     fn parse_num(expression: &str) -> Expr {
         parse_expr(expression).unwrap()
     }
 
+    #[test_case("'a'" => Expr::Str("a".to_string()))]
+    #[test_case("'\\''" => Expr::Str("'".to_string()))]
+    #[test_case("'\\n'" => Expr::Str("\n".to_string()))]
+    #[test_case("'\\u00e8'" => Expr::Str("è".to_string()))]
+    #[test_case("'è'" => Expr::Str("è".to_string()))]
+    #[test_case("@\"C:\\path\\\"" => Expr::Str("C:\\path\\".to_string()))]
+    #[test_case("@\"say \"\"hi\"\"\"" => Expr::Str("say \"hi\"".to_string()))]
+    #[test_case("@\"\"" => Expr::Str(String::new()))]
+    #[test_case("@\"multi\nline\"" => Expr::Str("multi\nline".to_string()))]
+    fn parse_char_and_verbatim_strings(expression: &str) -> Expr {
+        parse_expr(expression).unwrap()
+    }
+
+    macro_rules! interpolated {
+        ( $( $e:expr ),* ) => {
+            Expr::PreparedFunctionCall(unicase!("Concat"), vec![ $( $e ),* ], interpolation_function("Concat"))
+        };
+    }
+
+    macro_rules! hole {
+        ( $( $e:expr ),* ) => {
+            SharedExpr::new(Expr::PreparedFunctionCall(unicase!("Format"), vec![ $( $e ),* ], interpolation_function("Format")))
+        };
+    }
+
+    #[test_case("$\"\"" => interpolated!())]
+    #[test_case("$\"text\"" => interpolated!(rc_expr_str!("text")))]
+    #[test_case("$\"Hello {name}!\"" => interpolated!(rc_expr_str!("Hello "), hole!(rc_expr_id!("name")), rc_expr_str!("!")))]
+    #[test_case("$\"{a}{b}\"" => interpolated!(hole!(rc_expr_id!("a")), hole!(rc_expr_id!("b"))))]
+    #[test_case("$\"{ age + 1 :N0}\"" => interpolated!(hole!(rc_expr_op!(rc_expr_id!("age"), Add, rc_expr_int!(1)), rc_expr_str!("N0"))))]
+    #[test_case("$\"{d:yyyy-MM-dd HH:mm}\"" => interpolated!(hole!(rc_expr_id!("d"), rc_expr_str!("yyyy-MM-dd HH:mm"))))]
+    #[test_case("$\"{{{a}}}\"" => interpolated!(rc_expr_str!("{"), hole!(rc_expr_id!("a")), rc_expr_str!("}")))]
+    #[test_case("$\"\\t{a}\\\"\"" => interpolated!(rc_expr_str!("\t"), hole!(rc_expr_id!("a")), rc_expr_str!("\"")))]
+    #[test_case("$@\"\\{b}\"\"\"" => interpolated!(rc_expr_str!("\\"), hole!(rc_expr_id!("b")), rc_expr_str!("\"")))]
+    #[test_case("@$\"{a}\"" => interpolated!(hole!(rc_expr_id!("a"))))]
+    #[test_case("$\"{(a ? b : c)}\"" => interpolated!(hole!(rc_expr_ternary!(rc_expr_id!("a"), rc_expr_id!("b"), rc_expr_id!("c")))))]
+    #[test_case("$\"{f(\"}\", ':')}\"" => interpolated!(hole!(SharedExpr::new(Expr::FunctionCall(unicase!("f"), vec![rc_expr_str!("}"), rc_expr_str!(":")])))))]
+    #[test_case("$\"{$\"{a}\"}\"" => interpolated!(hole!(SharedExpr::new(interpolated!(hole!(rc_expr_id!("a")))))))]
+    fn parse_interpolated_strings(expression: &str) -> Expr {
+        parse_expr(expression).unwrap()
+    }
+
     #[test_case("null" => Expr::Null)]
     fn parse_null(expression: &str) -> Expr {
         parse_expr(expression).unwrap()
@@ -603,43 +1646,77 @@ mod tests {
         parse_expr(expression).unwrap()
     }
 
-    #[test_case("test(1,2)" => Expr::FunctionCall(unicase!("test"), vec![rc_expr_num!(1), rc_expr_num!(2)]))]
-    #[test_case("(test( ( 3 ), (4)))" => Expr::FunctionCall(unicase!("test"), vec![rc_expr_num!(3), rc_expr_num!(4)]))]
-    #[test_case("test ( 1 , 42 )" => Expr::FunctionCall(unicase!("test"), vec![rc_expr_num!(1), rc_expr_num!(42)]))]
+    #[test_case("test(1,2)" => Expr::FunctionCall(unicase!("test"), vec![rc_expr_int!(1), rc_expr_int!(2)]))]
+    #[test_case("(test( ( 3 ), (4)))" => Expr::FunctionCall(unicase!("test"), vec![rc_expr_int!(3), rc_expr_int!(4)]))]
+    #[test_case("test ( 1 , 42 )" => Expr::FunctionCall(unicase!("test"), vec![rc_expr_int!(1), rc_expr_int!(42)]))]
     #[test_case("test()" => Expr::FunctionCall(unicase!("test"), vec!()))]
-    #[test_case("test((test()))" => Expr::FunctionCall(unicase!("test"), vec![Rc::new(Expr::FunctionCall(unicase!("test"), vec![]))]))]
-    #[test_case("test(aa)" => Expr::FunctionCall(unicase!("test"), vec![Rc::new(Expr::Identifier("aa".to_string()))]))]
-    #[test_case("Test(42)" => Expr::FunctionCall(unicase!("Test"), vec![rc_expr_num!(42)]))]
-    #[test_case("Test(1 / 2)" => Expr::FunctionCall(unicase!("Test"), vec![RcExpr::new(Expr::BinaryOperator(rc_expr_num!(1), rc_expr_num!(2), AssocOp::Divide))]))]
+    #[test_case("test((test()))" => Expr::FunctionCall(unicase!("test"), vec![SharedExpr::new(Expr::FunctionCall(unicase!("test"), vec![]))]))]
+    #[test_case("test(aa)" => Expr::FunctionCall(unicase!("test"), vec![SharedExpr::new(Expr::Identifier("aa".to_string()))]))]
+    #[test_case("Test(42)" => Expr::FunctionCall(unicase!("Test"), vec![rc_expr_int!(42)]))]
+    #[test_case("Test(1 / 2)" => Expr::FunctionCall(unicase!("Test"), vec![SharedExpr::new(Expr::BinaryOperator(rc_expr_int!(1), rc_expr_int!(2), AssocOp::Divide))]))]
     fn parse_function_call(expression: &str) -> Expr {
         parse_expr(expression).unwrap()
     }
 
-    #[test_case("test(\"value\" , 2 , \"null\")" => Expr::FunctionCall(unicase!("test"), vec![rc_expr_str!("value"), rc_expr_num!(2), rc_expr_str!("null")]))]
+    #[test_case("test(\"value\" , 2 , \"null\")" => Expr::FunctionCall(unicase!("test"), vec![rc_expr_str!("value"), rc_expr_int!(2), rc_expr_str!("null")]))]
     #[test_case("hello" => Expr::Identifier("hello".into()))]
     #[test_case("\"€\"" => Expr::Str("€".into()))]
     #[test_case(" _hella " => Expr::Identifier("_hella".into()))]
     #[test_case(" helloworld " => Expr::Identifier("helloworld".into()))]
     #[test_case("test(\"value\")" => Expr::FunctionCall(unicase!("test"), vec![rc_expr_str!("value")]))]
     #[test_case("test(\"va lue\")" => Expr::FunctionCall(unicase!("test"), vec![rc_expr_str!("va lue")]))]
-    #[test_case("test(\"va lue\") - 3" => Expr::BinaryOperator(RcExpr::new( Expr::FunctionCall(unicase!("test"), vec![rc_expr_str!("va lue")])), rc_expr_num!(3), AssocOp::Subtract))]
-    #[test_case("42 / test(\"va lue\")" => Expr::BinaryOperator(rc_expr_num!(42), RcExpr::new(Expr::FunctionCall(unicase!("test"), vec![rc_expr_str!("va lue")])), AssocOp::Divide))]
-    #[test_case("42 \r\n \t / func()" => Expr::BinaryOperator(rc_expr_num!(42), RcExpr::new(Expr::FunctionCall(unicase!("func"), vec![])), AssocOp::Divide))]
-    #[test_case("(43 \r\n \t / ( func() ) )" => Expr::BinaryOperator(rc_expr_num!(43), RcExpr::new(Expr::FunctionCall(unicase!("func"), vec![])), AssocOp::Divide))]
-    #[test_case("Func(2 + 1, 42)" => Expr::FunctionCall(unicase!("Func"), vec![RcExpr::new(Expr::BinaryOperator(rc_expr_num!(2), rc_expr_num!(1), AssocOp::Add)), rc_expr_num!(42)]))]
-    #[test_case(" IIF (  ISLIKE(@var0, \"hello\" ), NUMBERVALUE( @var1 ) * NUMBERVALUE( 1.5), @var2 )" => Expr::FunctionCall(unicase!("IIF"), vec![RcExpr::new(Expr::FunctionCall(unicase!("ISLIKE"), vec![rc_expr_id!("var0"), rc_expr_str!("hello")])), RcExpr::new(Expr::BinaryOperator(RcExpr::new(Expr::FunctionCall(unicase!("NUMBERVALUE"), vec![rc_expr_id!("var1")])), RcExpr::new(Expr::FunctionCall(unicase!("NUMBERVALUE"), vec![rc_expr_num!(1.5)])), AssocOp::Multiply)), rc_expr_id!("var2") ]))]
+    #[test_case("test(\"va lue\") - 3" => Expr::BinaryOperator(SharedExpr::new( Expr::FunctionCall(unicase!("test"), vec![rc_expr_str!("va lue")])), rc_expr_int!(3), AssocOp::Subtract))]
+    #[test_case("42 / test(\"va lue\")" => Expr::BinaryOperator(rc_expr_int!(42), SharedExpr::new(Expr::FunctionCall(unicase!("test"), vec![rc_expr_str!("va lue")])), AssocOp::Divide))]
+    #[test_case("42 \r\n \t / func()" => Expr::BinaryOperator(rc_expr_int!(42), SharedExpr::new(Expr::FunctionCall(unicase!("func"), vec![])), AssocOp::Divide))]
+    #[test_case("(43 \r\n \t / ( func() ) )" => Expr::BinaryOperator(rc_expr_int!(43), SharedExpr::new(Expr::FunctionCall(unicase!("func"), vec![])), AssocOp::Divide))]
+    #[test_case("Func(2 + 1, 42)" => Expr::FunctionCall(unicase!("Func"), vec![SharedExpr::new(Expr::BinaryOperator(rc_expr_int!(2), rc_expr_int!(1), AssocOp::Add)), rc_expr_int!(42)]))]
+    #[test_case(" IIF (  ISLIKE(@var0, \"hello\" ), NUMBERVALUE( @var1 ) * NUMBERVALUE( 1.5), @var2 )" => Expr::FunctionCall(unicase!("IIF"), vec![SharedExpr::new(Expr::FunctionCall(unicase!("ISLIKE"), vec![rc_expr_id!("var0"), rc_expr_str!("hello")])), SharedExpr::new(Expr::BinaryOperator(SharedExpr::new(Expr::FunctionCall(unicase!("NUMBERVALUE"), vec![rc_expr_id!("var1")])), SharedExpr::new(Expr::FunctionCall(unicase!("NUMBERVALUE"), vec![rc_expr_num!(1.5)])), AssocOp::Multiply)), rc_expr_id!("var2") ]))]
     fn parse_complexe_expressions(expression: &'static str) -> Expr {
-        let expr = expr::<(&str, ErrorKind)>(expression);
+        let expr = expr(expression);
         match expr {
-            Ok((rest, expr)) => match rest.len() {
-                0 => expr,
-                _ => {
-                    dbg!(expr);
-                    panic!(rest)
+            Ok(expr) => expr,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    #[test_case("4.2" => vec!["4.2"])]
+    #[test_case(" ( 43 ) " => vec!["( 43 )"])]
+    #[test_case("1 + f(x, 2) * -y" => vec!["1 + f(x, 2) * -y", "1", "f(x, 2) * -y", "f(x, 2)", "x", "2", "-y", "y"])]
+    #[test_case("-3 - (a)" => vec!["-3 - (a)", "-3", "(a)"])]
+    #[test_case("!b ? 'c' : @\"d\"" => vec!["!b ? 'c' : @\"d\"", "!b", "b", "'c'", "@\"d\""])]
+    #[test_case("$\"é{e + 1:N2}\"" => vec!["$\"é{e + 1:N2}\"", "e + 1", "e", "1"])]
+    #[test_case("x + -a[i + 1]" => vec!["x + -a[i + 1]", "x", "-a[i + 1]", "a[i + 1]", "a", "i + 1", "i", "1"])]
+    #[test_case("(-a)[0][1]" => vec!["(-a)[0][1]", "(-a)[0]", "(-a)", "a", "0", "1"])]
+    #[test_case("new [] { 1, [b] }" => vec!["new [] { 1, [b] }", "1", "[b]", "b"])]
+    #[test_case("items.Where(x => x > 1).Count()" => vec!["items.Where(x => x > 1).Count()", "items.Where(x => x > 1)", "items", "x => x > 1", "x > 1", "x", "1"])]
+    #[test_case("-x.Customer .Age" => vec!["-x.Customer .Age", "x.Customer .Age", "x.Customer", "x"])]
+    fn parse_spans(expression: &str) -> Vec<&str> {
+        fn walk<'a>(expr: &Expr, source: &'a str, spans: &mut Vec<&'a str>) {
+            match expr {
+                Expr::Spanned(e, span) => {
+                    spans.push(&source[span.start..span.end]);
+                    walk(e, source, spans);
                 }
-            },
-            Err(err_kind) => panic!(format!("{:?}", err_kind)),
+                Expr::FunctionCall(_, parameters) | Expr::PreparedFunctionCall(_, parameters, _) => parameters.iter().for_each(|p| walk(p, source, spans)),
+                Expr::BinaryOperator(left, right, _) => {
+                    walk(left, source, spans);
+                    walk(right, source, spans);
+                }
+                Expr::UnaryOperator(e, _) => walk(e, source, spans),
+                Expr::Ternary(condition, when_true, when_false) => [condition, when_true, when_false].iter().for_each(|e| walk(e, source, spans)),
+                Expr::Array(items) => items.iter().for_each(|e| walk(e, source, spans)),
+                Expr::Index(e, index) => {
+                    walk(e, source, spans);
+                    walk(index, source, spans);
+                }
+                Expr::Lambda(_, body) => walk(body, source, spans),
+                Expr::Member(e, _) => walk(e, source, spans),
+                _ => {}
+            }
         }
+        let mut spans = vec![];
+        walk(&expr(expression).unwrap(), expression, &mut spans);
+        spans
     }
 
     #[test]
@@ -657,9 +1734,8 @@ mod tests {
             }
             // dbg!(complexity, &expression);
             let now = Instant::now();
-            let expr = expr::<(&str, ErrorKind)>(&expression);
-            let (rest, _) = expr.unwrap();
-            assert_eq!(rest.len(), 0);
+            let expr = expr(&expression);
+            assert!(expr.is_ok());
             dbg!(now.elapsed());
         }
     }
@@ -676,9 +1752,8 @@ mod tests {
             }
             // dbg!(complexity, &expression);
             let _now = Instant::now();
-            let expr = expr::<(&str, ErrorKind)>(&expression);
-            let (rest, _) = expr.unwrap();
-            assert_eq!(rest.len(), 0);
+            let expr = expr(&expression);
+            assert!(expr.is_ok());
             dbg!(_now.elapsed());
         }
     }
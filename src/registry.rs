@@ -0,0 +1,309 @@
+use crate::expressions::*;
+use crate::functions::{get_functions, get_pack_functions};
+use crate::types::FunctionSignature;
+use std::sync::Arc;
+use unicase::UniCase;
+
+// The built-in functions, grouped by what they work on
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FunctionPack {
+    Strings,
+    Dates,
+    Math,
+    Logic,
+    // the LINQ-like functions on arrays, most of them taking a lambda
+    Collections,
+}
+
+impl FunctionPack {
+    pub const ALL: [FunctionPack; 5] = [FunctionPack::Strings, FunctionPack::Dates, FunctionPack::Math, FunctionPack::Logic, FunctionPack::Collections];
+}
+
+impl std::fmt::Display for FunctionPack {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+// What an editor shows about a function, the built-in ones are all described.
+// `name` is the canonical name when the function is also registered under `aliases`, empty for the registered name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FunctionMetadata {
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub category: String,
+    pub description: String,
+}
+
+// A function listed once for all its names, see `FunctionRegistry::list()`
+#[derive(Clone)]
+pub struct RegisteredFunction<'a> {
+    pub name: &'a str,
+    pub aliases: Vec<&'a str>,
+    pub definition: &'a FunctionDefinition,
+}
+
+// The functions an expression can call, by case insensitive name.
+// Start from `FunctionRegistry::builtins()` or an empty `FunctionRegistry::new()`, then chain the `with_*` / `without_*` calls :
+// `FunctionRegistry::new().with_pack(FunctionPack::Strings).with_function("Twice", Deterministic, f_twice).without_function("Split")`
+#[derive(Clone, Default)]
+pub struct FunctionRegistry {
+    functions: FunctionImplList,
+}
+
+impl FunctionRegistry {
+    pub fn new() -> FunctionRegistry {
+        FunctionRegistry::default()
+    }
+
+    pub fn builtins() -> FunctionRegistry {
+        FunctionRegistry::from(get_functions())
+    }
+
+    // the pack functions override the ones with the same name
+    pub fn with_pack(mut self, pack: FunctionPack) -> FunctionRegistry {
+        for (name, function) in get_pack_functions(pack) {
+            self.functions.remove(&name);
+            self.functions.insert(name, function);
+        }
+        self
+    }
+
+    // removes the functions of the pack by their names, even if they were overriden
+    pub fn without_pack(mut self, pack: FunctionPack) -> FunctionRegistry {
+        for name in get_pack_functions(pack).keys() {
+            self.functions.remove(name);
+        }
+        self
+    }
+
+    // adds the function, or overrides the one with the same name, its parameters count is only checked by the function itself
+    pub fn with_function<F>(self, name: &str, determinism: FunctionDeterminism, function: F) -> FunctionRegistry
+    where
+        F: Fn(&SliceSharedExpr, &IdentifierValues) -> ExprFuncResult + Send + Sync + 'static,
+    {
+        self.with_function_arity(name, determinism, FunctionArity::default(), function)
+    }
+
+    // same as `with_function`, the calls with a parameters count out of `arity` are reported when preparing an expression,
+    // and fail without calling the function when executed
+    pub fn with_function_arity<F>(self, name: &str, determinism: FunctionDeterminism, arity: FunctionArity, function: F) -> FunctionRegistry
+    where
+        F: Fn(&SliceSharedExpr, &IdentifierValues) -> ExprFuncResult + Send + Sync + 'static,
+    {
+        self.with_definition(name, FunctionDefinition::new(determinism, arity, FunctionSignature::default(), Arc::new(function)))
+    }
+
+    // the most complete form, with the signature used by `types::infer_expr_type`
+    pub fn with_definition(mut self, name: &str, definition: FunctionDefinition) -> FunctionRegistry {
+        let name = UniCase::new(name.to_string());
+        // removed first, so the new name casing is the one kept
+        self.functions.remove(&name);
+        self.functions.insert(name, definition);
+        self
+    }
+
+    pub fn without_function(mut self, name: &str) -> FunctionRegistry {
+        self.functions.remove(&UniCase::new(name.to_string()));
+        self
+    }
+
+    pub fn definition(&self, name: &str) -> Option<&FunctionDefinition> {
+        self.functions.get(&UniCase::new(name.to_string()))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.functions.contains_key(&UniCase::new(name.to_string()))
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        let mut names = self.functions.keys().map(|name| name.as_str()).collect::<Vec<_>>();
+        names.sort_unstable_by_key(|name| name.to_lowercase());
+        names
+    }
+
+    pub fn len(&self) -> usize {
+        self.functions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.functions.is_empty()
+    }
+
+    pub fn functions(&self) -> &FunctionImplList {
+        &self.functions
+    }
+
+    // the functions by canonical name, the aliases are only listed with it when they are still the same function
+    pub fn list(&self) -> Vec<RegisteredFunction<'_>> {
+        let mut functions = self
+            .functions
+            .iter()
+            .filter(|(name, definition)| !self.is_alias(name, definition))
+            .map(|(name, definition)| RegisteredFunction {
+                name: name.as_str(),
+                aliases: self.aliases(name, definition),
+                definition,
+            })
+            .collect::<Vec<_>>();
+        functions.sort_unstable_by_key(|function| function.name.to_lowercase());
+        functions
+    }
+
+    fn is_alias(&self, name: &UniCase<String>, definition: &FunctionDefinition) -> bool {
+        let canonical = &definition.metadata.name;
+        !canonical.is_empty() && UniCase::new(canonical.as_str()) != UniCase::new(name.as_str()) && self.is_registered_as(canonical, definition)
+    }
+
+    // the aliases removed or overridden since are left out, and only the canonical name has aliases
+    fn aliases<'a>(&self, name: &UniCase<String>, definition: &'a FunctionDefinition) -> Vec<&'a str> {
+        if UniCase::new(definition.metadata.name.as_str()) != UniCase::new(name.as_str()) {
+            return Vec::new();
+        }
+        definition.metadata.aliases.iter().filter(|alias| self.is_registered_as(alias, definition)).map(String::as_str).collect()
+    }
+
+    fn is_registered_as(&self, name: &str, definition: &FunctionDefinition) -> bool {
+        self.definition(name).is_some_and(|d| Arc::ptr_eq(&d.implementation, &definition.implementation))
+    }
+}
+
+impl From<FunctionImplList> for FunctionRegistry {
+    fn from(functions: FunctionImplList) -> Self {
+        FunctionRegistry { functions }
+    }
+}
+
+impl std::fmt::Debug for FunctionRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::functions::{f_operators, f_unary_operators};
+    use std::rc::Rc;
+    use test_case::test_case;
+
+    fn exec_with(registry: &FunctionRegistry, expression: &str) -> Result<String, String> {
+        let expr = prepare_expr_and_identifiers(parse_expr(expression).unwrap(), registry, Arc::new(f_operators), Arc::new(f_unary_operators));
+        exec_expr(&expr.expr, &IdentifierValues::new()).map(|r| r.to_string()).map_err(|e| e.message)
+    }
+
+    fn f_twice(params: &SliceSharedExpr, values: &IdentifierValues) -> ExprFuncResult {
+        let value = exec_expr(&params[0], values)?;
+        Ok(ExprResult::Str(Rc::new(format!("{}{}", value, value))))
+    }
+
+    #[test]
+    fn builtins_are_all_the_packs() {
+        let registry = FunctionRegistry::builtins();
+        assert!(registry.contains("LEFT"));
+        assert!(registry.contains("dateadddays"));
+        assert!(!registry.contains("Twice"));
+        assert_eq!(FunctionPack::ALL.iter().map(|p| get_pack_functions(*p).len()).sum::<usize>(), registry.len());
+    }
+
+    #[test_case(FunctionPack::Strings => (true, false, false, false, false))]
+    #[test_case(FunctionPack::Dates => (false, true, false, false, false))]
+    #[test_case(FunctionPack::Math => (false, false, true, false, false))]
+    #[test_case(FunctionPack::Logic => (false, false, false, true, false))]
+    #[test_case(FunctionPack::Collections => (false, false, false, false, true))]
+    fn one_pack(pack: FunctionPack) -> (bool, bool, bool, bool, bool) {
+        let registry = FunctionRegistry::new().with_pack(pack);
+        (
+            registry.contains("Left"),
+            registry.contains("Year"),
+            registry.contains("Round"),
+            registry.contains("Iif"),
+            registry.contains("Where"),
+        )
+    }
+
+    #[test]
+    fn add_override_and_remove() {
+        let registry = FunctionRegistry::builtins()
+            .with_function("Twice", FunctionDeterminism::Deterministic, f_twice)
+            .with_function("UPPER", FunctionDeterminism::Deterministic, |_: &SliceSharedExpr, _: &IdentifierValues| {
+                Ok(ExprResult::Str(Rc::new("up".to_string())))
+            })
+            .without_function("lower");
+        assert_eq!(exec_with(&registry, "twice(\"ab\")"), Ok("abab".to_string()));
+        assert_eq!(exec_with(&registry, "Upper(\"ab\")"), Ok("up".to_string()));
+        assert_eq!(exec_with(&registry, "Lower(\"AB\")"), Err("Unable to find the function named 'Lower'".to_string()));
+        assert!(registry.names().contains(&"UPPER"));
+        assert!(!registry.names().contains(&"Upper"));
+    }
+
+    #[test]
+    fn function_arity() {
+        let registry = FunctionRegistry::new()
+            .with_function_arity("Twice", FunctionDeterminism::Deterministic, FunctionArity::exactly(1), f_twice)
+            .with_function("Unchecked", FunctionDeterminism::Deterministic, f_twice);
+        let diagnostics = |expression: &str| {
+            let expr = prepare_expr_and_identifiers(parse_expr(expression).unwrap(), &registry, Arc::new(f_operators), Arc::new(f_unary_operators));
+            expr.diagnostics.into_iter().map(|d| d.kind).collect::<Vec<_>>()
+        };
+        assert_eq!(diagnostics("Twice(1) + Unchecked(1, 2, 3)"), vec![]);
+        assert_eq!(diagnostics("Twice(1, 2) + Unknown()"), vec![DiagnosticKind::WrongArity, DiagnosticKind::UnknownFunction]);
+        assert_eq!(exec_with(&registry, "Twice(\"a\", \"b\")"), Err("Function Twice should have exactly 1 parameters".to_string()));
+        assert_eq!(exec_with(&registry, "Unchecked(\"a\", \"b\")"), Ok("aa".to_string()));
+    }
+
+    #[test]
+    fn list_by_canonical_name() {
+        let registry = FunctionRegistry::builtins();
+        let names = registry.list().iter().map(|f| f.name).collect::<Vec<_>>();
+        assert!(names.contains(&"Iif"));
+        assert!(!names.contains(&"If"));
+        let iif = registry.list().into_iter().find(|f| f.name == "Iif").unwrap();
+        assert_eq!(iif.aliases, ["If".to_string()]);
+        assert_eq!(iif.definition.metadata.category, "Logic");
+        assert_eq!(registry.list().iter().map(|f| 1 + f.aliases.len()).sum::<usize>(), registry.len());
+
+        // once overriden, an alias is its own function
+        let overridden = registry.clone().with_function("If", FunctionDeterminism::Deterministic, f_twice);
+        let names = overridden.list().iter().map(|f| f.name).collect::<Vec<_>>();
+        assert!(names.contains(&"Iif"));
+        assert!(names.contains(&"If"));
+        assert!(overridden.list().into_iter().find(|f| f.name == "Iif").unwrap().aliases.is_empty());
+        assert_eq!(overridden.list().iter().map(|f| 1 + f.aliases.len()).sum::<usize>(), overridden.len());
+
+        // once removed, an alias isn't listed anymore
+        let removed = registry.clone().without_function("If");
+        let iif = removed.list().into_iter().find(|f| f.name == "Iif").unwrap();
+        assert!(iif.aliases.is_empty());
+        assert_eq!(removed.list().iter().map(|f| 1 + f.aliases.len()).sum::<usize>(), removed.len());
+
+        // without its canonical name, an alias is listed alone
+        let without_canonical = registry.without_function("Iif");
+        let if_function = without_canonical.list().into_iter().find(|f| f.name == "If").unwrap();
+        assert!(if_function.aliases.is_empty());
+    }
+
+    #[test]
+    fn builtins_are_described() {
+        for function in FunctionRegistry::builtins().list() {
+            let definition = function.definition;
+            let parameters = definition.signature.parameters.len();
+            assert!(!definition.metadata.description.is_empty(), "{}", function.name);
+            assert_eq!(definition.metadata.name, function.name);
+            assert!(parameters >= definition.arity.min, "{}", function.name);
+            assert!(definition.arity.max.is_none_or(|max| max == parameters), "{}", function.name);
+            for (i, parameter) in definition.signature.parameters.iter().enumerate() {
+                assert!(parameter.default_value.is_none() || definition.is_optional_parameter(i), "{}", function.name);
+            }
+        }
+    }
+
+    #[test]
+    fn without_pack() {
+        let registry = FunctionRegistry::builtins().without_pack(FunctionPack::Dates);
+        assert!(!registry.contains("Now"));
+        assert_eq!(exec_with(&registry, "Len(\"abc\") + Abs(-1)"), Ok("4".to_string()));
+        assert_eq!(exec_with(&registry, "Year(Date(\"2020-01-01\"))"), Err("Unable to find the function named 'Year'".to_string()));
+        assert!(FunctionRegistry::new().is_empty());
+    }
+}
@@ -0,0 +1,421 @@
+use crate::expressions::*;
+use crate::functions::{check_result_type, f_operators, f_unary_operators, operand_conversion, OperandConversion};
+use crate::registry::FunctionRegistry;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use unicase::UniCase;
+
+// The types known before the execution. A number is an integer or a decimal, as both are changed to each other.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ExprType {
+    Any = 0,
+    Null = 1,
+    Text = 2,
+    Boolean = 3,
+    Number = 4,
+    Date = 5,
+    TimeSpan = 6,
+    Array = 7,
+    // a parameter like `x => x > 10`, never a value
+    Lambda = 8,
+    // named fields, as `x.Price`
+    Record = 9,
+}
+
+impl ExprType {
+    // The type of both, a null is only the lack of a value
+    pub fn common(self, other: ExprType) -> ExprType {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (ExprType::Null, t) | (t, ExprType::Null) => t,
+            _ => ExprType::Any,
+        }
+    }
+
+    // Whether a value of this type can never be given where `expected` is, as every value of this type fails to change to it.
+    // A text may be a number or a date, and anything can be a text or a boolean.
+    pub fn never_converts_to(self, expected: ExprType) -> bool {
+        match expected {
+            _ if self == ExprType::Lambda => !matches!(expected, ExprType::Lambda | ExprType::Any),
+            ExprType::Lambda => self != ExprType::Any,
+            ExprType::Number => matches!(self, ExprType::Boolean | ExprType::Date | ExprType::TimeSpan | ExprType::Array | ExprType::Record),
+            ExprType::Date => matches!(self, ExprType::Boolean | ExprType::Number | ExprType::TimeSpan | ExprType::Array | ExprType::Record),
+            ExprType::Array => !matches!(self, ExprType::Array | ExprType::Any | ExprType::Null),
+            ExprType::Record => !matches!(self, ExprType::Record | ExprType::Any | ExprType::Null),
+            _ => false,
+        }
+    }
+
+    fn article(self) -> &'static str {
+        match self {
+            ExprType::Any | ExprType::Array => "an",
+            _ => "a",
+        }
+    }
+}
+
+impl fmt::Display for ExprType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExprType::Any => write!(f, "any"),
+            ExprType::Null => write!(f, "null"),
+            ExprType::Text => write!(f, "text"),
+            ExprType::Boolean => write!(f, "boolean"),
+            ExprType::Number => write!(f, "number"),
+            ExprType::Date => write!(f, "date"),
+            ExprType::TimeSpan => write!(f, "timespan"),
+            ExprType::Array => write!(f, "array"),
+            ExprType::Lambda => write!(f, "lambda"),
+            ExprType::Record => write!(f, "record"),
+        }
+    }
+}
+
+// from the number of a type given by the host
+impl TryFrom<u32> for ExprType {
+    type Error = String;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ExprType::Any),
+            1 => Ok(ExprType::Null),
+            2 => Ok(ExprType::Text),
+            3 => Ok(ExprType::Boolean),
+            4 => Ok(ExprType::Number),
+            5 => Ok(ExprType::Date),
+            6 => Ok(ExprType::TimeSpan),
+            7 => Ok(ExprType::Array),
+            8 => Ok(ExprType::Lambda),
+            9 => Ok(ExprType::Record),
+            _ => Err(format!("Unknown type {}", value)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReturnType {
+    Fixed(ExprType),
+    // the common type of the parameters from this index, as `Iif` or `FirstNotNull` return one of them
+    CommonOfParameters(usize),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionParameter {
+    pub name: String,
+    pub parameter_type: ExprType,
+    // what an optional parameter is when not given, as it would be written in an expression
+    pub default_value: Option<String>,
+}
+
+impl FunctionParameter {
+    pub fn new(name: &str, parameter_type: ExprType) -> FunctionParameter {
+        FunctionParameter {
+            name: name.to_string(),
+            parameter_type,
+            default_value: None,
+        }
+    }
+
+    pub fn with_default(name: &str, parameter_type: ExprType, default_value: &str) -> FunctionParameter {
+        FunctionParameter {
+            default_value: Some(default_value.to_string()),
+            ..FunctionParameter::new(name, parameter_type)
+        }
+    }
+}
+
+// The parameters, by position : the last one is also the type of the following parameters.
+// The ones from the minimum count of the function arity are optional.
+// The default one is for the functions without known types, any parameters and any result.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FunctionSignature {
+    pub parameters: Vec<FunctionParameter>,
+    pub returns: ReturnType,
+}
+
+impl Default for FunctionSignature {
+    fn default() -> Self {
+        FunctionSignature::new(vec![], ExprType::Any)
+    }
+}
+
+impl FunctionSignature {
+    pub fn new(parameters: Vec<FunctionParameter>, returns: ExprType) -> FunctionSignature {
+        FunctionSignature {
+            parameters,
+            returns: ReturnType::Fixed(returns),
+        }
+    }
+
+    pub fn common_of(parameters: Vec<FunctionParameter>, from: usize) -> FunctionSignature {
+        FunctionSignature {
+            parameters,
+            returns: ReturnType::CommonOfParameters(from),
+        }
+    }
+
+    pub fn parameter_type(&self, index: usize) -> ExprType {
+        self.parameters.get(index).or_else(|| self.parameters.last()).map_or(ExprType::Any, |p| p.parameter_type)
+    }
+
+    pub fn return_type(&self, parameters_types: &[ExprType]) -> ExprType {
+        match self.returns {
+            ReturnType::Fixed(t) => t,
+            ReturnType::CommonOfParameters(from) => parameters_types.iter().skip(from).fold(ExprType::Null, |common, t| common.common(*t)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeInference {
+    pub result_type: ExprType,
+    // the type errors, and the constants that fail, in the order they appear in the expression
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+// Infers the type of a prepared expression from the signatures of the registry functions.
+// The identifiers are matched to `identifier_types` as to their values, the ones not declared can be anything.
+// Only the definite errors are reported : the parameters whose type never converts to the expected one,
+// the constants that fail to convert, with the message of the execution, and the failing constants always executed.
+pub fn infer_expr_type(expr: &ExprAndIdentifiers, registry: &FunctionRegistry, identifier_types: &HashMap<String, ExprType>) -> TypeInference {
+    let slot_types = expr
+        .identifiers_names
+        .iter()
+        .map(|name| {
+            let declared = match expr.identifier_case {
+                IdentifierCase::Insensitive => identifier_types.iter().find(|(n, _)| UniCase::new(n.as_str()) == UniCase::new(name.as_str())).map(|(_, t)| t),
+                IdentifierCase::Sensitive => identifier_types.get(name),
+            };
+            declared.copied().unwrap_or(ExprType::Any)
+        })
+        .collect();
+    let mut checker = TypeChecker {
+        funcs: registry.functions(),
+        slot_types,
+        diagnostics: Vec::new(),
+        maybe_skipped: false,
+    };
+    let result_type = checker.infer(&expr.expr, None);
+    TypeInference {
+        result_type,
+        diagnostics: checker.diagnostics,
+    }
+}
+
+struct TypeChecker<'a> {
+    funcs: &'a FunctionImplList,
+    slot_types: Vec<ExprType>,
+    diagnostics: Vec<Diagnostic>,
+    // in a sub-expression that may not be executed, as a branch or a function parameter
+    maybe_skipped: bool,
+}
+
+impl TypeChecker<'_> {
+    fn infer(&mut self, expr: &SharedExpr, span: Option<Span>) -> ExprType {
+        match expr.as_ref() {
+            Expr::Str(_) => ExprType::Text,
+            Expr::Boolean(_) => ExprType::Boolean,
+            Expr::Num(_) | Expr::Int(_) => ExprType::Number,
+            Expr::Null => ExprType::Null,
+            Expr::Date(_) => ExprType::Date,
+            Expr::TimeSpan(_) => ExprType::TimeSpan,
+            Expr::Failed(error) => {
+                if !self.maybe_skipped {
+                    self.diagnostics.push(Diagnostic {
+                        kind: DiagnosticKind::AlwaysFails,
+                        message: error.message.clone(),
+                        span: error.span.or(span),
+                    });
+                }
+                ExprType::Any
+            }
+            Expr::Identifier(_) => ExprType::Any,
+            Expr::PreparedIdentifier(_, slot) => self.slot_types.get(*slot).copied().unwrap_or(ExprType::Any),
+            Expr::PreparedFunctionCall(name, parameters, implementation) if prepared_call_definition(self.funcs, name, implementation).is_some() => {
+                let signature = prepared_call_definition(self.funcs, name, implementation).unwrap().signature.clone();
+                let types = parameters
+                    .iter()
+                    .enumerate()
+                    .map(|(i, p)| {
+                        let actual = self.infer_maybe_skipped(p, span);
+                        self.check(p, actual, signature.parameter_type(i), span, || format!("The parameter {} of {}", i + 1, name));
+                        actual
+                    })
+                    .collect::<Vec<_>>();
+                signature.return_type(&types)
+            }
+            // unknown or removed since the preparation, nothing is known of the result
+            Expr::FunctionCall(_, parameters) | Expr::PreparedFunctionCall(_, parameters, _) => {
+                for p in parameters {
+                    self.infer_maybe_skipped(p, span);
+                }
+                ExprType::Any
+            }
+            Expr::PreparedBinaryOperator(left, right, op, op_impl) if op_impl.implementation_type() == type_of(&f_operators) => {
+                let left_type = self.infer(left, span);
+                let right_type = match op {
+                    AssocOp::LAnd | AssocOp::LOr | AssocOp::NullCoalescing => self.infer_maybe_skipped(right, span),
+                    _ => self.infer(right, span),
+                };
+                let expected = match operand_conversion(*op) {
+                    OperandConversion::Number | OperandConversion::Decimal => ExprType::Number,
+                    OperandConversion::TextOrNumber => ExprType::Text,
+                    OperandConversion::Bool => ExprType::Boolean,
+                    OperandConversion::Unchanged => ExprType::Any,
+                };
+                self.check(left, left_type, expected, span, || format!("The left operand of '{}'", op));
+                self.check(right, right_type, expected, span, || format!("The right operand of '{}'", op));
+                match op {
+                    AssocOp::Add | AssocOp::Subtract | AssocOp::Multiply | AssocOp::Divide | AssocOp::Modulus => ExprType::Number,
+                    AssocOp::NullCoalescing => left_type.common(right_type),
+                    _ => ExprType::Boolean,
+                }
+            }
+            Expr::PreparedUnaryOperator(operand, op, op_impl) if op_impl.implementation_type() == type_of(&f_unary_operators) => {
+                let operand_type = self.infer(operand, span);
+                match op {
+                    UnaryOp::Not => ExprType::Boolean,
+                    UnaryOp::Negate | UnaryOp::Plus => {
+                        self.check(operand, operand_type, ExprType::Number, span, || format!("The operand of '{}'", op));
+                        ExprType::Number
+                    }
+                }
+            }
+            // the host operators can work on anything
+            Expr::BinaryOperator(left, right, _) | Expr::PreparedBinaryOperator(left, right, _, _) => {
+                self.infer_maybe_skipped(left, span);
+                self.infer_maybe_skipped(right, span);
+                ExprType::Any
+            }
+            Expr::UnaryOperator(operand, _) | Expr::PreparedUnaryOperator(operand, _, _) => {
+                self.infer_maybe_skipped(operand, span);
+                ExprType::Any
+            }
+            Expr::Ternary(condition, when_true, when_false) => {
+                self.infer(condition, span);
+                let when_true = self.infer_maybe_skipped(when_true, span);
+                when_true.common(self.infer_maybe_skipped(when_false, span))
+            }
+            Expr::Array(items) => {
+                for item in items {
+                    self.infer(item, span);
+                }
+                ExprType::Array
+            }
+            Expr::Index(e, index) => {
+                let (e_type, index_type) = (self.infer(e, span), self.infer(index, span));
+                self.check(e, e_type, ExprType::Array, span, || "The indexed value".to_string());
+                self.check(index, index_type, ExprType::Number, span, || "The index".to_string());
+                ExprType::Any
+            }
+            Expr::Member(e, name) => {
+                let e_type = self.infer(e, span);
+                self.check(e, e_type, ExprType::Record, span, || format!("The value read by '.{}'", name));
+                ExprType::Any
+            }
+            // its parameter can be anything, as the items of an array
+            Expr::Lambda(_, body) => {
+                self.infer_maybe_skipped(body, span);
+                ExprType::Lambda
+            }
+            Expr::Spanned(e, span) => self.infer(e, Some(*span)),
+        }
+    }
+
+    // the functions and the host operators decide when to execute their parameters, if ever, as `Iif` or `&&`
+    fn infer_maybe_skipped(&mut self, expr: &SharedExpr, span: Option<Span>) -> ExprType {
+        let maybe_skipped = std::mem::replace(&mut self.maybe_skipped, true);
+        let expr_type = self.infer(expr, span);
+        self.maybe_skipped = maybe_skipped;
+        expr_type
+    }
+
+    // a constant is checked by converting it as the execution would, the error is located on the constant
+    fn check<F: FnOnce() -> String>(&mut self, expr: &SharedExpr, actual: ExprType, expected: ExprType, span: Option<Span>, what: F) {
+        let (message, span) = match expr.without_span() {
+            Expr::Failed(_) => (None, span),
+            e if e.is_constant() => (
+                exec_expr(expr, &IdentifierValues::new()).and_then(|value| check_result_type(&value, expected)).err().map(|e| e.message),
+                expr.span().or(span),
+            ),
+            _ if actual.never_converts_to(expected) => (Some(format!("{} should be {} {}, not {} {}", what(), expected.article(), expected, actual.article(), actual)), span),
+            _ => (None, span),
+        };
+        if let Some(message) = message {
+            self.diagnostics.push(Diagnostic {
+                kind: DiagnosticKind::TypeMismatch,
+                message,
+                span,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use test_case::test_case;
+
+    #[test_case("1 + 2 * x", &[] => (ExprType::Number, vec![]))]
+    #[test_case("\"a\" * x", &[] => (ExprType::Number, vec!["TypeMismatch 0..3 The value 'a' is not a number.".to_string()]))]
+    #[test_case("Year(\"hello\")", &[] => (ExprType::Any, vec!["AlwaysFails 0..13 The value 'hello' is not a date.".to_string()]))]
+    #[test_case("Year(flag)", &[("flag", ExprType::Boolean)] => (ExprType::Number, vec!["TypeMismatch 0..10 The parameter 1 of Year should be a date, not a boolean".to_string()]))]
+    #[test_case("Year(FLAG)", &[("flag", ExprType::Text)] => (ExprType::Number, vec![]))]
+    #[test_case("-d + 1", &[("d", ExprType::Date)] => (ExprType::Number, vec!["TypeMismatch 0..2 The operand of '-' should be a number, not a date".to_string()]))]
+    #[test_case("Now() > 1", &[] => (ExprType::Boolean, vec!["TypeMismatch 0..9 The left operand of '>' should be a number, not a date".to_string()]))]
+    #[test_case("Left(name, 2) + 1", &[("name", ExprType::Text)] => (ExprType::Number, vec![]))]
+    #[test_case("Iif(x > 1, \"big\", null)", &[] => (ExprType::Text, vec![]))]
+    #[test_case("x > 1 ? 1 : \"small\"", &[] => (ExprType::Any, vec![]))]
+    #[test_case("x ?? 0", &[("x", ExprType::Number)] => (ExprType::Number, vec![]))]
+    #[test_case("DateDiff(Now(), d)", &[("d", ExprType::Number)] => (ExprType::TimeSpan, vec!["TypeMismatch 0..18 The parameter 2 of DateDiff should be a date, not a number".to_string()]))]
+    #[test_case("Split(s, \",\")[1]", &[("s", ExprType::Text)] => (ExprType::Any, vec![]))]
+    #[test_case("items[0] + [1, 2][d]", &[("items", ExprType::Number), ("d", ExprType::Date)] => (ExprType::Number, vec!["TypeMismatch 0..8 The indexed value should be an array, not a number".to_string(), "TypeMismatch 11..20 The index should be a number, not a date".to_string()]))]
+    #[test_case("order.Price * 2 + d.Year", &[("order", ExprType::Record), ("d", ExprType::Date)] => (ExprType::Number, vec!["TypeMismatch 18..24 The value read by '.Year' should be a record, not a date".to_string()]))]
+    #[test_case("Abs(order)", &[("order", ExprType::Record)] => (ExprType::Number, vec!["TypeMismatch 0..10 The parameter 1 of Abs should be a number, not a record".to_string()]))]
+    #[test_case("Abs(new[] { 1 })", &[] => (ExprType::Any, vec!["AlwaysFails 0..16 The value '[1]' is not a number.".to_string()]))]
+    #[test_case("Iif(true, 1, 1/0)", &[] => (ExprType::Number, vec![]))]
+    #[test_case("x ? 1 : Year(\"a\")", &[] => (ExprType::Any, vec![]))]
+    #[test_case("x ?? Year(\"a\")", &[] => (ExprType::Any, vec![]))]
+    #[test_case("Abs(x) + 1/0", &[] => (ExprType::Number, vec!["AlwaysFails 9..12 Couldn't divide 1 by 0".to_string()]))]
+    #[test_case("Abs(new[] { x })", &[] => (ExprType::Number, vec!["TypeMismatch 0..16 The parameter 1 of Abs should be a number, not an array".to_string()]))]
+    #[test_case("items.Where(x => x > 1).Count() + Abs(x => x)", &[("items", ExprType::Array)] => (ExprType::Number, vec!["TypeMismatch 34..45 The parameter 1 of Abs should be a number, not a lambda".to_string()]))]
+    #[test_case("items.Where(1)", &[] => (ExprType::Array, vec!["TypeMismatch 12..13 The value '1' is not a lambda.".to_string()]))]
+    #[test_case("$\"{Abs(x)} {Unknown(Date(x))}\"", &[("x", ExprType::Boolean)] => (ExprType::Text, vec!["TypeMismatch 3..9 The parameter 1 of Abs should be a number, not a boolean".to_string()]))]
+    fn infer_type(expression: &str, identifier_types: &[(&str, ExprType)]) -> (ExprType, Vec<String>) {
+        let registry = FunctionRegistry::builtins();
+        let expr = prepare_expr_and_identifiers(parse_expr(expression).unwrap(), &registry, Arc::new(f_operators), Arc::new(f_unary_operators));
+        let identifier_types = identifier_types.iter().map(|(name, t)| (name.to_string(), *t)).collect();
+        let inference = infer_expr_type(&expr, &registry, &identifier_types);
+        let diagnostics = inference
+            .diagnostics
+            .iter()
+            .map(|d| format!("{:?} {} {}", d.kind, d.span.map_or("?".to_string(), |s| format!("{}..{}", s.start, s.end)), d.message))
+            .collect();
+        (inference.result_type, diagnostics)
+    }
+
+    #[test]
+    fn host_signatures() {
+        let registry = FunctionRegistry::builtins()
+            .with_definition(
+                "Discount",
+                FunctionDefinition::new(
+                    FunctionDeterminism::Deterministic,
+                    FunctionArity::exactly(1),
+                    FunctionSignature::new(vec![FunctionParameter::new("price", ExprType::Number)], ExprType::Number),
+                    Arc::new(|_: &SliceSharedExpr, _: &IdentifierValues| Ok(ExprResult::Int(1))),
+                ),
+            )
+            .with_function("Untyped", FunctionDeterminism::Deterministic, |_: &SliceSharedExpr, _: &IdentifierValues| Ok(ExprResult::Null));
+        let expr = prepare_expr_and_identifiers(parse_expr("Discount(Untyped(Now())) + Discount(Now())").unwrap(), &registry, Arc::new(f_operators), Arc::new(f_unary_operators));
+        let inference = infer_expr_type(&expr, &registry, &HashMap::new());
+        assert_eq!(inference.result_type, ExprType::Number);
+        assert_eq!(
+            inference.diagnostics.iter().map(|d| d.message.as_str()).collect::<Vec<_>>(),
+            vec!["The parameter 1 of Discount should be a number, not a date"]
+        );
+    }
+}
@@ -31,7 +31,7 @@ namespace csharp_expr_rs.Tests
                     using (var expr = new Expression(expression))
                     {
                         var result = expr.Execute(new Dictionary<string, string>());
-                        _output.WriteLine(result.content);
+                        _output.WriteLine($"{result.content}");
                         result.content.ShouldBe(expectedResult);
                     }
                 }
@@ -54,7 +54,7 @@ namespace csharp_expr_rs.Tests
                 try
                 {
                     var result = expression.Execute(new Dictionary<string, string>());
-                    _output.WriteLine(result.content);
+                    _output.WriteLine($"{result.content}");
                     result.content.ShouldBe("123");
                 }
                 finally
@@ -92,7 +92,7 @@ namespace csharp_expr_rs.Tests
 
                 using (var expression = new Expression("test"))
                 {
-                    (bool is_error, string content) result;
+                    (bool is_error, object content) result;
                     try
                     {
                         result = expression.Execute(new Dictionary<string, string>() { { "test", "42" } });
@@ -148,5 +148,280 @@ namespace csharp_expr_rs.Tests
             //}
             //}
         }
+
+        [Theory]
+        [InlineData("42", 42L)]
+        [InlineData("1 == 1", true)]
+        [InlineData("null", null)]
+        [InlineData("\"text\"", "text")]
+        public void Exec_typed_result(string expression, object expected)
+        {
+            using (var expr = new Expression(expression))
+            {
+                var (is_error, content) = expr.Execute(new Dictionary<string, string>());
+                is_error.ShouldBeFalse();
+                content.ShouldBe(expected);
+            }
+        }
+
+        [Fact]
+        public void Exec_typed_decimal_and_date()
+        {
+            using (var expr = new Expression("-12.50 * 2"))
+            {
+                expr.Execute(new Dictionary<string, string>()).content.ShouldBe(-25.000m);
+            }
+            using (var expr = new Expression("DateAddDays(Date(\"2020-02-03\"), 1)"))
+            {
+                expr.Execute(new Dictionary<string, string>()).content.ShouldBe(new DateTime(2020, 2, 4));
+            }
+        }
+
+        [Fact]
+        public void Exec_with_typed_identifier_values()
+        {
+            using (var expr = new Expression("vip ? price * qty : DateAddDays(since, qty)"))
+            {
+                var values = new Dictionary<string, object> { { "vip", true }, { "price", 12.5m }, { "qty", 3 }, { "since", new DateTime(2020, 2, 3) } };
+                expr.Execute(values).content.ShouldBe(37.5m);
+
+                values["vip"] = false;
+                expr.Execute(values).content.ShouldBe(new DateTime(2020, 2, 6));
+            }
+        }
+
+        [Fact]
+        public void Exec_from_many_threads()
+        {
+            using (var expr = new Expression("Len(name) * factor"))
+            {
+                var results = Enumerable.Range(0, 64)
+                    .AsParallel()
+                    .Select(i => expr.Execute(new Dictionary<string, object> { { "name", "abc" }, { "factor", i } }))
+                    .ToList();
+                results.ShouldAllBe(r => !r.is_error);
+                results.Select(r => Convert.ToInt64(r.content)).OrderBy(r => r).ShouldBe(Enumerable.Range(0, 64).Select(i => 3L * i));
+            }
+        }
+
+        [Fact]
+        public void Exec_many_rows()
+        {
+            using (var expr = new Expression("price / qty"))
+            {
+                var columns = new Dictionary<string, IReadOnlyList<object>>
+                {
+                    { "price", new object[] { 10m, 9m, "7.5" } },
+                    { "qty", new object[] { 4, 0, 3 } },
+                };
+                var results = expr.ExecuteMany(columns, 3);
+                results.Length.ShouldBe(3);
+                results[0].ShouldBe((false, (object)2.5m));
+                results[1].is_error.ShouldBeTrue();
+                results[2].ShouldBe((false, (object)2.5m));
+            }
+        }
+
+        [Fact]
+        public void Exec_by_slots()
+        {
+            using (var expr = new Expression("Concat(label, qty * price, label)"))
+            {
+                expr.Identifiers.ShouldBe(new[] { "label", "qty", "price" });
+                expr.ExecuteBySlots(new object[] { "x", 3, 1.5m }).ShouldBe((false, (object)"x4.5x"));
+                expr.ExecuteBySlots(new object[] { "x", 3 }).is_error.ShouldBeTrue();
+            }
+        }
+
+        [Fact]
+        public void Exec_arrays()
+        {
+            using (var expr = new Expression("In(qty, quantities) ? Split(labels, \",\")[qty] : quantities"))
+            {
+                expr.ExecuteBySlots(new object[] { 1, new List<int> { 1, 2 }, "a,b" }).ShouldBe((false, (object)"b"));
+                var (is_error, content) = expr.ExecuteBySlots(new object[] { 3, new object[] { 1, "x", new[] { 2.5m } }, "" });
+                is_error.ShouldBeFalse();
+                content.ShouldBe(new object[] { 1L, "x", new object[] { 2.5m } });
+            }
+            HostFunctions.Register("Pair", true, 1, 1, args => new[] { args[0], args[0] });
+            try
+            {
+                using (var expr = new Expression("Concat(Pair(new[] { 1, 2 })[1], Pair(\"z\"))"))
+                {
+                    expr.Execute(new Dictionary<string, object>()).ShouldBe((false, (object)"12zz"));
+                }
+            }
+            finally
+            {
+                HostFunctions.Unregister("Pair");
+            }
+        }
+
+        [Fact]
+        public void Exec_lambdas()
+        {
+            using (var expr = new Expression("prices.Where(p => p > min).Sum(p => p * 2)"))
+            {
+                expr.Identifiers.ShouldBe(new[] { "prices", "min" });
+                expr.ExecuteBySlots(new object[] { new[] { 5m, 12.5m, 20m }, 10 }).ShouldBe((false, (object)65m));
+                expr.ExecuteBySlots(new object[] { null, 10 }).ShouldBe((false, (object)0L));
+            }
+        }
+
+        [Fact]
+        public void Exec_records()
+        {
+            using (var expr = new Expression("lines.Where(x => x.Price > 10).Sum(x => x.Price)"))
+            {
+                var lines = new[]
+                {
+                    new Dictionary<string, object> { { "Name", "nut" }, { "Price", 5m } },
+                    new Dictionary<string, object> { { "Name", "screw" }, { "Price", 12.5m } },
+                    new Dictionary<string, object> { { "Name", "bolt" }, { "Price", 20 } },
+                };
+                expr.ExecuteBySlots(new object[] { lines }).ShouldBe((false, (object)32.5m));
+            }
+            using (var expr = new Expression("lines[0]"))
+            {
+                var (is_error, content) = expr.ExecuteBySlots(new object[] { new[] { new Dictionary<string, object> { { "Name", "nut" }, { "Tags", new[] { "a" } } } } });
+                is_error.ShouldBeFalse();
+                var fields = content.ShouldBeOfType<Dictionary<string, object>>();
+                fields.Keys.ShouldBe(new[] { "Name", "Tags" });
+                fields["Name"].ShouldBe("nut");
+                fields["Tags"].ShouldBe(new object[] { "a" });
+            }
+        }
+
+        [Theory]
+        [InlineData(ExpressionOptions.None)]
+        [InlineData(ExpressionOptions.Compiled)]
+        public void Exec_compiled(ExpressionOptions options)
+        {
+            using (var expr = new Expression("qty > 2 ? Concat(Left(label, qty), qty * 2) : Left(label)", options))
+            {
+                expr.ExecuteBySlots(new object[] { 3, "abcdef" }).ShouldBe((false, (object)"abc6"));
+                expr.ExecuteBySlots(new object[] { 1, "abcdef" }).ShouldBe((true, (object)"Function Left should have exactly 2 parameters"));
+            }
+        }
+
+        [Fact]
+        public void Identifiers_case()
+        {
+            var values = new Dictionary<string, object> { { "Qty", 3 } };
+            using (var expr = new Expression("qty + QTY"))
+            {
+                expr.Identifiers.ShouldBe(new[] { "qty" });
+                expr.Execute(values).ShouldBe((false, (object)6L));
+            }
+            using (var expr = new Expression("qty + QTY", ExpressionOptions.CaseSensitiveIdentifiers))
+            {
+                expr.Identifiers.ShouldBe(new[] { "qty", "QTY" });
+                expr.Execute(values).is_error.ShouldBeTrue();
+            }
+        }
+
+        [Fact]
+        public void Infer_type()
+        {
+            using (var expr = new Expression("Year(Date(d)) + \"a\" * Len(name)"))
+            {
+                var (resultType, diagnostics) = expr.InferType(new Dictionary<string, ExpressionType> { { "d", ExpressionType.Number }, { "name", ExpressionType.Text } });
+                resultType.ShouldBe(ExpressionType.Number);
+                diagnostics.Select(d => (d.Kind, d.Message, d.Start, d.Length)).ShouldBe(new[]
+                {
+                    (DiagnosticKind.TypeMismatch, "The parameter 1 of Date should be a date, not a number", 5, 7),
+                    (DiagnosticKind.TypeMismatch, "The value 'a' is not a number.", 16, 3),
+                });
+            }
+        }
+
+        [Fact]
+        public void Infer_type_of_unknown_identifier_type()
+        {
+            using (var expr = new Expression("d + 1"))
+            {
+                var ex = Should.Throw<ExpressionInvokeException>(() => expr.InferType(new Dictionary<string, ExpressionType> { { "d", (ExpressionType)42 } }));
+                ex.Message.ShouldBe("ffi_infer_type: Unknown type 42 for the identifier 'd'");
+            }
+        }
+
+        [Fact]
+        public void Strict_prepare()
+        {
+            using (var expr = new Expression("Left(\"abc\") + Unknown()"))
+            {
+                expr.Execute(new Dictionary<string, object>()).is_error.ShouldBeTrue();
+            }
+            var ex = Should.Throw<ExpressionParsingException>(() => new Expression("Left(\"abc\") + Unknown()", ExpressionOptions.Strict));
+            ex.Message.ShouldBe("Function Left should have exactly 2 parameters; Unable to find the function named 'Unknown'");
+        }
+
+        [Theory]
+        [InlineData(MissingIdentifierPolicy.Error, true, "Unable to find value for identifier named 'missing'")]
+        [InlineData(MissingIdentifierPolicy.Null, false, "true/")]
+        [InlineData(MissingIdentifierPolicy.Default, false, "false/5")]
+        public void Missing_identifiers(MissingIdentifierPolicy policy, bool isError, string expected)
+        {
+            using (var expr = new Expression("$\"{IsNull(missing)}/{missing}\""))
+            {
+                expr.Execute(new Dictionary<string, object>(), policy, 5).ShouldBe((isError, (object)expected));
+                expr.ExecuteBySlots(new object[0], policy, 5).ShouldBe((isError, (object)expected));
+                expr.ExecuteMany(new Dictionary<string, IReadOnlyList<object>>(), 2, policy, 5).ShouldBe(new[] { (isError, (object)expected), (isError, (object)expected) });
+            }
+        }
+
+        [Fact]
+        public void Exec_host_function()
+        {
+            HostFunctions.Register("GetCustomerTier", false, 1, 1, args => (long)args[0] > 10 ? "gold" : "silver");
+            try
+            {
+                using (var expression = new Expression("GetCustomerTier(42) + \"/\" + GetCustomerTier(1)"))
+                {
+                    expression.IsDeterministic.ShouldBeFalse();
+                    var (is_error, content) = expression.Execute(new Dictionary<string, string>());
+                    is_error.ShouldBeFalse();
+                    content.ShouldBe("gold/silver");
+                }
+            }
+            finally
+            {
+                HostFunctions.Unregister("GetCustomerTier");
+            }
+        }
+
+        [Fact]
+        public void Exec_unregistered_host_function()
+        {
+            HostFunctions.Register("Temporary", true, 0, 0, args => "here");
+            using (var expression = new Expression("Temporary()"))
+            {
+                expression.Execute(new Dictionary<string, string>()).ShouldBe((false, (object)"here"));
+                HostFunctions.Unregister("Temporary").ShouldBeTrue();
+                GC.Collect();
+                GC.WaitForPendingFinalizers();
+                var (is_error, content) = expression.Execute(new Dictionary<string, string>());
+                is_error.ShouldBeTrue();
+                content.ShouldBe("The host function 'Temporary' is no longer registered");
+            }
+        }
+
+        [Fact]
+        public void List_functions()
+        {
+            HostFunctions.Register("Twice", true, 1, 1, args => args[0]);
+            try
+            {
+                var json = HostFunctions.ListAsJson();
+                json.ShouldStartWith("[{\"name\":\"Abs\",");
+                json.ShouldContain("{\"name\":\"Iif\",\"aliases\":[\"If\"],\"category\":\"Logic\",");
+                json.ShouldContain("{\"name\":\"decimals\",\"type\":\"number\",\"default\":\"2\"}");
+                json.ShouldContain("{\"name\":\"Twice\",\"aliases\":[],\"category\":\"Host\",");
+            }
+            finally
+            {
+                HostFunctions.Unregister("Twice");
+            }
+        }
     }
 }
@@ -0,0 +1,46 @@
+use csharp_expr::bytecode::{compile_expr, exec_compiled_expr};
+use csharp_expr::expressions::*;
+use csharp_expr::registry::{FunctionPack, FunctionRegistry};
+use csharp_expr::types::{infer_expr_type, ExprType};
+use csharp_expr::{f_operators, f_unary_operators};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+fn prepare(expression: &str, registry: &FunctionRegistry) -> ExprAndIdentifiers {
+    prepare_expr_and_identifiers(parse_expr(expression).unwrap(), registry, Arc::new(f_operators), Arc::new(f_unary_operators))
+}
+
+fn values() -> IdentifierValues {
+    let mut values = IdentifierValues::new();
+    values.insert("qty".into(), Box::new(|| Ok(ExprResult::Int(3))));
+    values.insert("label".into(), Box::new(|| Ok(ExprResult::Str("x".to_string().into()))));
+    values
+}
+
+#[test]
+fn prepare_and_execute() {
+    let expr = prepare("Concat(Upper(label), qty * 2 + 0.5, qty == 3.0 ? \"!\" : \"?\")", &FunctionRegistry::builtins());
+    assert_eq!(expr.identifiers_names, vec!["label".to_string(), "qty".to_string()]);
+    assert!(expr.diagnostics.is_empty());
+    assert_eq!(exec_expr(&expr.expr, &values()).map(|r| r.to_string()).map_err(|e| e.message), Ok("X6.5!".to_string()));
+}
+
+#[test]
+fn execute_compiled() {
+    let expr = prepare("qty > 2 && Len(label) == 1 ? -qty : qty", &FunctionRegistry::builtins());
+    let compiled = compile_expr(&expr.expr);
+    assert_eq!(exec_compiled_expr(&compiled, &values()).map(|r| r.to_string()).map_err(|e| e.message), Ok("-3".to_string()));
+
+    let expr = expr.compile();
+    assert!(expr.compiled.is_some());
+    assert_eq!(exec_prepared_expr(&expr, &values()).map(|r| r.to_string()).map_err(|e| e.message), Ok("-3".to_string()));
+}
+
+#[test]
+fn registry_and_types() {
+    let registry = FunctionRegistry::new().with_pack(FunctionPack::Math);
+    let expr = prepare("Round(qty / 2, 1) + Len(label)", &registry);
+    assert_eq!(expr.diagnostics.iter().map(|d| d.kind).collect::<Vec<_>>(), vec![DiagnosticKind::UnknownFunction]);
+    let inference = infer_expr_type(&prepare("Round(qty / 2, 1)", &registry), &registry, &HashMap::new());
+    assert_eq!(inference.result_type, ExprType::Number);
+}